@@ -0,0 +1,37 @@
+#!/bin/sh
+# Benchmark of the line scanner of diffr on a generated diff.
+#
+# Usage: benches/scan.sh [HUNKS [RUNS]]
+#
+# Writes a unified diff of HUNKS hunks (450000 by default, about 240 MB)
+# to target/bench-scan.diff, the same on every run, then times diffr on
+# it with --bench, over RUNS runs (3 by default). The line scan is timed
+# both with the scanner of diffr and with BufRead::read_until.
+
+set -eu
+
+hunks=${1:-450000}
+runs=${2:-3}
+cd "$(dirname "$0")/.."
+diff=target/bench-scan.diff
+
+cargo build --release
+mkdir -p target
+awk -v hunks="$hunks" 'BEGIN {
+    for (i = 0; i < hunks; i++) {
+        if (i % 100 == 0) {
+            file = "src/module_" int(i / 100) ".rs"
+            printf "diff --git a/%s b/%s\n--- a/%s\n+++ b/%s\n", file, file, file, file
+        }
+        line = (i % 100) * 10 + 1
+        printf "@@ -%d,7 +%d,7 @@ fn function_%d() {\n", line, line, i
+        printf "     let context_before = compute_value(%d, \"first context line\");\n", i
+        printf "     let another_context = context_before.saturating_add(%d);\n", i % 7
+        printf "-    let removed_value = old_function(%d, another_context, \"old text\");\n", i
+        printf "-    println!(\"removed line number %d with some words\", removed_value);\n", i
+        printf "+    let added_value = new_function(%d, another_context, \"new text\");\n", i
+        printf "+    println!(\"added line number %d with other words\", added_value);\n", i
+        printf "     let context_after = finish(context_before, %d);\n", i % 13
+    }
+}' > "$diff"
+target/release/diffr --bench "$diff" --bench-runs "$runs"
@@ -5,7 +5,8 @@
 //! Variations](http://www.xmailserver.org/diff2.pdf).
 //!
 //! The main entrypoint is `diff`, which allows to compute the longest
-//! common subsequence between two sequences of byte slices.
+//! common subsequence between two sequences of byte slices. `edits`
+//! turns it into the edit operations between the sequences.
 
 use std::collections::hash_map::DefaultHasher;
 use std::convert::TryFrom;
@@ -153,7 +154,7 @@ impl<'a> DiffInput<'a> {
 }
 
 /// Hash the given bytes.
-fn hash_slice(data: &[u8]) -> u64 {
+pub fn hash_slice(data: &[u8]) -> u64 {
     let mut s = DefaultHasher::new();
     s.write(data);
     s.finish()
@@ -437,6 +438,61 @@ pub fn diff(input: &DiffInput, v: &mut Vec<isize>, dst: &mut Vec<Snake>) {
     diff_rec(input, v, dst)
 }
 
+/// The kind of an edit operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditKind {
+    /// Tokens are added.
+    Insert,
+    /// Tokens are removed.
+    Delete,
+    /// Tokens are removed, and others are added in their place.
+    Replace,
+}
+
+/// An edit operation between the sequences of a [DiffInput].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub kind: EditKind,
+    /// The range of the indices of the removed tokens; it is empty for
+    /// an insertion, and starts at the position of the insertion.
+    pub removed: (usize, usize),
+    /// The range of the indices of the added tokens; it is empty for a
+    /// deletion, and starts at the position of the deletion.
+    pub added: (usize, usize),
+}
+
+/// Compute the edit operations between the sequences of `input` from
+/// `lcs`, their longest common subsequence computed by `diff`: the
+/// tokens between two snakes are an edit operation.
+pub fn edits(input: &DiffInput, lcs: &[Snake]) -> Vec<Edit> {
+    let mut edits = vec![];
+    let mut push = |(x0, x1): (isize, isize), (y0, y1): (isize, isize)| {
+        let kind = match (x0 < x1, y0 < y1) {
+            (false, false) => return,
+            (false, true) => EditKind::Insert,
+            (true, false) => EditKind::Delete,
+            (true, true) => EditKind::Replace,
+        };
+        edits.push(Edit {
+            kind,
+            removed: (to_usize(x0), to_usize(x1)),
+            added: (to_usize(y0), to_usize(y1)),
+        });
+    };
+    let mut x = input.removed.start_index;
+    let mut y = input.added.start_index;
+    for snake in lcs {
+        push((x, snake.x0), (y, snake.y0));
+        x = x.max(snake.x0 + snake.len);
+        y = y.max(snake.y0 + snake.len);
+    }
+    push(
+        (x, input.removed.one_past_end_index),
+        (y, input.added.one_past_end_index),
+    );
+    edits
+}
+
 fn diff_rec(input: &DiffInput, v: &mut Vec<isize>, dst: &mut Vec<Snake>) {
     let n = to_isize(input.n());
     fn trivial_diff(tok: &Tokenization) -> bool {
@@ -531,8 +587,9 @@ fn to_usize(input: isize) -> usize {
     usize::try_from(input).unwrap()
 }
 
+/// The classes of bytes that `tokenize` groups in tokens.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
-enum TokenKind {
+pub enum TokenKind {
     Other,
     Word,
     Spaces,
@@ -562,7 +619,9 @@ pub fn tokenize(src: &[u8], ofs: usize, tokens: &mut Vec<HashedSpan>) {
     push(lo, src.len());
 }
 
-fn classify_byte(b: u8) -> TokenKind {
+/// The class of the byte `b`: the words and the spaces are runs of
+/// bytes, any other byte is a token of its own.
+pub fn classify_byte(b: u8) -> TokenKind {
     match b {
         b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' => TokenKind::Word,
         b'\t' | b' ' => TokenKind::Spaces,
@@ -599,3 +599,35 @@ fn issue15_2() {
         b"+        --include '+ */' \r\n",
     )
 }
+
+#[test]
+fn edits_test() {
+    let check =
+        |removed: &[u8], added: &[u8], expected: &[(EditKind, (usize, usize), (usize, usize))]| {
+            let removed_tokens = really_tokenize(removed);
+            let added_tokens = really_tokenize(added);
+            let input = DiffInput {
+                added: Tokenization::new(added, &added_tokens),
+                removed: Tokenization::new(removed, &removed_tokens),
+            };
+            let mut lcs = vec![];
+            diff(&input, &mut vec![], &mut lcs);
+            let expected = mk_vec(expected.iter().map(|&(kind, removed, added)| Edit {
+                kind,
+                removed,
+                added,
+            }));
+            assert_eq!(expected, edits(&input, &lcs));
+        };
+    check(
+        b"a b c",
+        b"a d c e",
+        &[
+            (EditKind::Replace, (2, 3), (2, 3)),
+            (EditKind::Insert, (5, 5), (5, 7)),
+        ],
+    );
+    check(b"a b", b"a", &[(EditKind::Delete, (1, 3), (1, 1))]);
+    check(b"", b"x", &[(EditKind::Insert, (0, 0), (0, 1))]);
+    check(b"same", b"same", &[]);
+}
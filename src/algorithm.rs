@@ -0,0 +1,259 @@
+//! The algorithms computing the tokens shared by the removed and the
+//! added lines of a hunk, for --algorithm.
+//!
+//! Myers' algorithm finds a longest common subsequence of tokens, but
+//! the matched tokens are often frequent ones, such as blanks or
+//! brackets, that interleave the highlighted spans. The patience and
+//! histogram algorithms first anchor the diff on rare tokens, and only
+//! compute the LCS between the anchors.
+
+use diffr_lib::{DiffInput, HashedSpan, Snake, Tokenization};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Myers,
+    /// Anchored on the tokens occurring once on both sides.
+    Patience,
+    /// Anchored on the least frequent tokens of the removed side.
+    Histogram,
+}
+
+/// The maximal number of occurrences of a token used as an anchor by
+/// the histogram algorithm.
+const MAX_HISTOGRAM_CHAIN: usize = 64;
+
+struct Context<'a> {
+    algorithm: Algorithm,
+    data: &'a [u8],
+    removed: &'a [HashedSpan],
+    added: &'a [HashedSpan],
+    v: &'a mut Vec<isize>,
+    scratch: Vec<Snake>,
+}
+
+impl<'a> Context<'a> {
+    fn removed(&self, x: usize) -> &'a [u8] {
+        let token = self.removed[x];
+        &self.data[token.lo..token.hi]
+    }
+
+    fn added(&self, y: usize) -> &'a [u8] {
+        let token = self.added[y];
+        &self.data[token.lo..token.hi]
+    }
+
+    fn same(&self, x: usize, y: usize) -> bool {
+        self.removed[x].hash == self.added[y].hash && self.removed(x) == self.added(y)
+    }
+
+    /// Diffs the removed tokens x0..x1 against the added tokens y0..y1.
+    fn diff(&mut self, (x0, x1): (usize, usize), (y0, y1): (usize, usize), dst: &mut Vec<Snake>) {
+        // the common prefix and suffix are shared
+        let mut prefix = 0;
+        while x0 + prefix < x1 && y0 + prefix < y1 && self.same(x0 + prefix, y0 + prefix) {
+            prefix += 1;
+        }
+        let mut suffix = 0;
+        while x0 + prefix < x1 - suffix
+            && y0 + prefix < y1 - suffix
+            && self.same(x1 - suffix - 1, y1 - suffix - 1)
+        {
+            suffix += 1;
+        }
+        push(dst, x0, y0, prefix);
+        let (x0, y0) = (x0 + prefix, y0 + prefix);
+        let (x1, y1) = (x1 - suffix, y1 - suffix);
+        if x0 < x1 && y0 < y1 {
+            let anchors = match self.algorithm {
+                Algorithm::Myers => None,
+                Algorithm::Patience => self.patience_anchors((x0, x1), (y0, y1)),
+                Algorithm::Histogram => self.histogram_anchor((x0, x1), (y0, y1)),
+            };
+            match anchors {
+                Some(anchors) => {
+                    let (mut x, mut y) = (x0, y0);
+                    for (ax, ay, len) in anchors {
+                        self.diff((x, ax), (y, ay), dst);
+                        push(dst, ax, ay, len);
+                        x = ax + len;
+                        y = ay + len;
+                    }
+                    self.diff((x, x1), (y, y1), dst);
+                }
+                None => self.myers((x0, x1), (y0, y1), dst),
+            }
+        }
+        push(dst, x1, y1, suffix);
+    }
+
+    fn myers(&mut self, (x0, x1): (usize, usize), (y0, y1): (usize, usize), dst: &mut Vec<Snake>) {
+        let input = DiffInput {
+            removed: Tokenization::new(self.data, &self.removed[x0..x1]),
+            added: Tokenization::new(self.data, &self.added[y0..y1]),
+        };
+        diffr_lib::diff(&input, self.v, &mut self.scratch);
+        for snake in &self.scratch {
+            push(
+                dst,
+                x0 + snake.x0 as usize,
+                y0 + snake.y0 as usize,
+                snake.len as usize,
+            );
+        }
+    }
+
+    /// The longest increasing sequence of the tokens occurring once on
+    /// both sides, None if there is no such token.
+    fn patience_anchors(
+        &self,
+        (x0, x1): (usize, usize),
+        (y0, y1): (usize, usize),
+    ) -> Option<Vec<(usize, usize, usize)>> {
+        // occurrences and position on each side
+        let mut counts: HashMap<&[u8], (usize, usize, usize, usize)> = HashMap::new();
+        for x in x0..x1 {
+            let entry = counts.entry(self.removed(x)).or_default();
+            entry.0 += 1;
+            entry.1 = x;
+        }
+        for y in y0..y1 {
+            if let Some(entry) = counts.get_mut(self.added(y)) {
+                entry.2 += 1;
+                entry.3 = y;
+            }
+        }
+        let mut unique: Vec<(usize, usize)> = counts
+            .values()
+            .filter(|&&(nx, _, ny, _)| nx == 1 && ny == 1)
+            .map(|&(_, x, _, y)| (x, y))
+            .collect();
+        if unique.is_empty() {
+            return None;
+        }
+        unique.sort_unstable();
+
+        // patience sorting: `piles[k]` is the index in `unique` of the
+        // smallest end of an increasing sequence of length k + 1
+        let mut piles: Vec<usize> = vec![];
+        let mut previous = vec![None; unique.len()];
+        for (i, &(_, y)) in unique.iter().enumerate() {
+            let k = piles.partition_point(|&j| unique[j].1 < y);
+            previous[i] = k.checked_sub(1).map(|k| piles[k]);
+            if k == piles.len() {
+                piles.push(i);
+            } else {
+                piles[k] = i;
+            }
+        }
+        let mut anchors = vec![];
+        let mut i = piles.last().cloned();
+        while let Some(j) = i {
+            let (x, y) = unique[j];
+            anchors.push((x, y, 1));
+            i = previous[j];
+        }
+        anchors.reverse();
+        Some(anchors)
+    }
+
+    /// The longest run of shared tokens containing the least frequent
+    /// removed token, None if no token occurs few enough times.
+    fn histogram_anchor(
+        &self,
+        (x0, x1): (usize, usize),
+        (y0, y1): (usize, usize),
+    ) -> Option<Vec<(usize, usize, usize)>> {
+        let mut occurrences: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        for x in x0..x1 {
+            occurrences.entry(self.removed(x)).or_default().push(x);
+        }
+        // the anchor, with the occurrences of its least frequent token
+        let mut best: Option<(usize, usize, usize, usize)> = None;
+        let mut y = y0;
+        while y < y1 {
+            let mut next = y + 1;
+            let positions = match occurrences.get(self.added(y)) {
+                Some(positions) if positions.len() <= MAX_HISTOGRAM_CHAIN => positions,
+                _ => {
+                    y = next;
+                    continue;
+                }
+            };
+            for &x in positions {
+                if !self.same(x, y) {
+                    continue;
+                }
+                let (mut lo_x, mut lo_y) = (x, y);
+                while x0 < lo_x && y0 < lo_y && self.same(lo_x - 1, lo_y - 1) {
+                    lo_x -= 1;
+                    lo_y -= 1;
+                }
+                let (mut hi_x, mut hi_y) = (x + 1, y + 1);
+                while hi_x < x1 && hi_y < y1 && self.same(hi_x, hi_y) {
+                    hi_x += 1;
+                    hi_y += 1;
+                }
+                let count = (lo_x..hi_x)
+                    .map(|x| occurrences[self.removed(x)].len())
+                    .min()
+                    .unwrap_or(usize::MAX);
+                let len = hi_x - lo_x;
+                let better = match best {
+                    Some((_, _, best_len, best_count)) => {
+                        count < best_count || count == best_count && best_len < len
+                    }
+                    None => true,
+                };
+                if better {
+                    best = Some((lo_x, lo_y, len, count));
+                }
+                next = next.max(hi_y);
+            }
+            y = next;
+        }
+        best.map(|(x, y, len, _)| vec![(x, y, len)])
+    }
+}
+
+/// Appends a snake to `dst`, merging it with the last one when they
+/// are contiguous.
+pub fn push(dst: &mut Vec<Snake>, x0: usize, y0: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let (x0, y0, len) = (x0 as isize, y0 as isize, len as isize);
+    match dst.last_mut() {
+        Some(last) if last.x0 + last.len == x0 && last.y0 + last.len == y0 => last.len += len,
+        _ => dst.push(Snake { x0, y0, len }),
+    }
+}
+
+/// Computes the tokens shared by `removed` and `added` into `dst`,
+/// with `algorithm`.
+pub fn diff(
+    algorithm: Algorithm,
+    data: &[u8],
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+    v: &mut Vec<isize>,
+    dst: &mut Vec<Snake>,
+) {
+    if algorithm == Algorithm::Myers {
+        let input = DiffInput {
+            removed: Tokenization::new(data, removed),
+            added: Tokenization::new(data, added),
+        };
+        diffr_lib::diff(&input, v, dst);
+        return;
+    }
+    dst.clear();
+    let mut context = Context {
+        algorithm,
+        data,
+        removed,
+        added,
+        v,
+        scratch: vec![],
+    };
+    context.diff((0, removed.len()), (0, added.len()), dst);
+}
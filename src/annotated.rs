@@ -0,0 +1,71 @@
+//! Rendering of the output of diffr with the refined spans between
+//! brackets, for --output=annotated.
+
+use std::io::{self, Write};
+
+use super::hunks::{self, LineKind, Part};
+
+/// The markers around the refined spans of the removed lines.
+pub const REMOVED_MARKERS: (&str, &str) = ("[-", "-]");
+
+/// The markers around the refined spans of the added lines.
+pub const ADDED_MARKERS: (&str, &str) = ("{+", "+}");
+
+/// Writes the content of `line`, with its refined spans between `open`
+/// and `close`.
+fn write_annotated<W: Write>(
+    line: &hunks::Line,
+    (open, close): (&str, &str),
+    out: &mut W,
+) -> io::Result<()> {
+    let content = String::from_utf8_lossy(&line.content);
+    let mut spans = line.spans.iter().peekable();
+    for (i, c) in content.chars().enumerate() {
+        if spans.peek().is_some_and(|&&(lo, _)| lo == i) {
+            out.write_all(open.as_bytes())?;
+        }
+        write!(out, "{}", c)?;
+        if spans.next_if(|&&(_, hi)| hi == i + 1).is_some() {
+            out.write_all(close.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `line` with its prefix: the refined spans of the removed
+/// lines are between "[-" and "-]", and the ones of the added lines
+/// between "{+" and "+}".
+pub fn write_line<W: Write>(line: &hunks::Line, out: &mut W) -> io::Result<()> {
+    match line.kind {
+        LineKind::Context => {
+            out.write_all(b" ")?;
+            out.write_all(&line.content)?;
+        }
+        LineKind::Removed => {
+            out.write_all(b"-")?;
+            write_annotated(line, REMOVED_MARKERS, out)?;
+        }
+        LineKind::Added => {
+            out.write_all(b"+")?;
+            write_annotated(line, ADDED_MARKERS, out)?;
+        }
+        LineKind::NoNewline => out.write_all(b"\\ No newline at end of file")?,
+    }
+    out.write_all(b"\n")
+}
+
+/// Writes `parts` as a plain unified diff, where the lines of the hunks
+/// are written by `write_line`.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    for part in parts {
+        match part {
+            Part::Lines(lines) => out.write_all(lines)?,
+            Part::Hunk(hunk) => {
+                for line in &hunk.lines {
+                    write_line(line, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
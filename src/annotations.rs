@@ -0,0 +1,276 @@
+//! Messages displayed under the lines of the diff, for --annotations.
+//!
+//! The annotations are read from a JSON array of objects, such as the
+//! diagnostics of a linter:
+//!
+//! [{"path": "src/main.rs", "new_line": 12, "severity": "warning",
+//!   "message": "unused variable: `x`"}]
+//!
+//! Each annotation is displayed under the line `new_line` of the new
+//! version of the file `path`, if the diff shows it.
+//!
+//! The results of static analyzers are also read from SARIF logs, for
+//! --annotations-sarif, under the first line of their region.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use termcolor::WriteColor;
+
+use super::json::{self, Value};
+use super::{output, AppConfig};
+
+/// The severity of an annotation, which selects its face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    /// The severity named `name`; the unknown ones, such as "help" or
+    /// "info", are notes.
+    fn from_name(name: &str) -> Severity {
+        match &name.to_ascii_lowercase()[..] {
+            "error" | "fatal" => Severity::Error,
+            "warning" | "warn" => Severity::Warning,
+            _ => Severity::Note,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The annotations of the lines of the files, by path and line number.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    lines: HashMap<(String, usize), Vec<Annotation>>,
+}
+
+/// The path of a file as in the diff, without a leading "./".
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./")
+}
+
+/// The path of the artifact `uri` of a SARIF log: a "file:" URI is
+/// made relative to the current directory, where the paths of the diff
+/// are, and the escapes of the URI are decoded.
+fn sarif_path(uri: &str) -> String {
+    let path = match uri.strip_prefix("file://") {
+        Some(path) => path.strip_prefix("localhost").unwrap_or(path),
+        None => uri.strip_prefix("file:").unwrap_or(uri),
+    };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => {
+                decoded.push(escaped);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8_lossy(&decoded).into_owned();
+    let cwd = std::env::current_dir().ok();
+    let relative = cwd
+        .as_ref()
+        .and_then(|cwd| cwd.to_str())
+        .and_then(|cwd| path.strip_prefix(cwd))
+        .and_then(|path| path.strip_prefix('/'));
+    match relative {
+        Some(relative) => relative.to_owned(),
+        None => path,
+    }
+}
+
+impl Annotations {
+    /// Reads the annotations of the JSON file `path`.
+    pub fn read(path: &str) -> Result<Annotations, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Annotations::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Reads the results of the SARIF log `path`.
+    pub fn read_sarif(path: &str) -> Result<Annotations, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Annotations::parse_sarif(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses the JSON array of annotations `text`.
+    pub fn parse(text: &str) -> Result<Annotations, String> {
+        let entries = match json::parse(text)? {
+            Value::Array(entries) => entries,
+            _ => return Err("expected an array of annotations".to_owned()),
+        };
+        let mut annotations = Annotations::default();
+        for (i, entry) in entries.iter().enumerate() {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .ok_or_else(|| format!("annotation {}: no '{}'", i + 1, name))
+            };
+            let invalid = |name: &str| format!("annotation {}: invalid '{}'", i + 1, name);
+            let path = field("path")?.as_str().ok_or_else(|| invalid("path"))?;
+            let line = field("new_line")?
+                .as_usize()
+                .filter(|&line| 0 < line)
+                .ok_or_else(|| invalid("new_line"))?;
+            let message = field("message")?
+                .as_str()
+                .ok_or_else(|| invalid("message"))?;
+            let severity = match entry.get("severity") {
+                None | Some(Value::Null) => Severity::Note,
+                Some(severity) => {
+                    Severity::from_name(severity.as_str().ok_or_else(|| invalid("severity"))?)
+                }
+            };
+            annotations
+                .lines
+                .entry((normalize(path).to_owned(), line))
+                .or_default()
+                .push(Annotation {
+                    severity,
+                    message: message.to_owned(),
+                });
+        }
+        Ok(annotations)
+    }
+
+    /// Parses the SARIF log `text`: each result is annotated, with the
+    /// id of its rule, on the first line of the region of each of its
+    /// physical locations. The results without such a location, such as
+    /// those about a whole file, are ignored.
+    pub fn parse_sarif(text: &str) -> Result<Annotations, String> {
+        let log = json::parse(text)?;
+        let runs = match log.get("runs") {
+            Some(Value::Array(runs)) => runs,
+            _ => return Err("expected a SARIF log, with 'runs'".to_owned()),
+        };
+        let mut annotations = Annotations::default();
+        for run in runs {
+            let results = match run.get("results") {
+                Some(Value::Array(results)) => results,
+                _ => continue,
+            };
+            for result in results {
+                // the level of a result is a warning by default
+                let severity = match result.get("level").and_then(Value::as_str) {
+                    Some("error") => Severity::Error,
+                    Some("note") | Some("none") => Severity::Note,
+                    _ => Severity::Warning,
+                };
+                let text = result
+                    .get("message")
+                    .and_then(|message| message.get("text").or_else(|| message.get("markdown")))
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let message = match result.get("ruleId").and_then(Value::as_str) {
+                    Some(rule) => format!("{} [{}]", text, rule),
+                    None => text.to_owned(),
+                };
+                let locations = match result.get("locations") {
+                    Some(Value::Array(locations)) => &locations[..],
+                    _ => &[],
+                };
+                for location in locations {
+                    let location = match location.get("physicalLocation") {
+                        Some(location) => location,
+                        None => continue,
+                    };
+                    let uri = location
+                        .get("artifactLocation")
+                        .and_then(|artifact| artifact.get("uri"))
+                        .and_then(Value::as_str);
+                    let line = location
+                        .get("region")
+                        .and_then(|region| region.get("startLine"))
+                        .and_then(Value::as_usize)
+                        .filter(|&line| 0 < line);
+                    if let (Some(uri), Some(line)) = (uri, line) {
+                        annotations
+                            .lines
+                            .entry((normalize(&sarif_path(uri)).to_owned(), line))
+                            .or_default()
+                            .push(Annotation {
+                                severity,
+                                message: message.clone(),
+                            });
+                    }
+                }
+            }
+        }
+        Ok(annotations)
+    }
+
+    /// Adds the annotations of `other`, after those of the same lines.
+    pub fn extend(&mut self, other: Annotations) {
+        for (key, annotations) in other.lines {
+            self.lines.entry(key).or_default().extend(annotations);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The annotations of the line `line` of the new version of `path`.
+    pub fn get(&self, path: &str, line: usize) -> &[Annotation] {
+        self.lines
+            .get(&(normalize(path).to_owned(), line))
+            .map_or(&[], |annotations| &annotations[..])
+    }
+}
+
+/// Writes `annotation` as callout lines, painted with the face of its
+/// severity, after the gutter written by `output_gutter` for each line.
+pub fn output_callout<Stream, Gutter>(
+    annotation: &Annotation,
+    mut output_gutter: Gutter,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+    Gutter: FnMut(&mut Stream) -> io::Result<()>,
+{
+    let face = match annotation.severity {
+        Severity::Error => &config.annotation_error_face,
+        Severity::Warning => &config.annotation_warning_face,
+        Severity::Note => &config.annotation_note_face,
+    };
+    for (i, line) in annotation.message.lines().enumerate() {
+        output_gutter(out)?;
+        let callout = if i == 0 {
+            format!(" └ {}: {}\n", annotation.severity.name(), line)
+        } else {
+            format!("   {}\n", line)
+        };
+        output(callout.as_bytes(), face, out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Checking whether the hunks of a patch apply to the files on disk,
+//! for --apply-preview.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use termcolor::WriteColor;
+
+use super::{output, AppConfig, HunkHeader};
+
+/// The maximal number of context lines ignored at each end of a hunk
+/// to find where it applies, as patch does by default.
+const MAX_FUZZ: usize = 2;
+
+/// How a hunk applies to its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Clean,
+    /// The hunk applies this number of lines away from its position.
+    Offset(isize),
+    /// The hunk applies once this number of context lines are ignored
+    /// at each end, and with the offset.
+    Fuzz(usize, isize),
+    /// The hunk does not apply, for this reason.
+    Rejected(&'static str),
+}
+
+impl Status {
+    /// The note written after the hunk, if it does not apply cleanly.
+    fn note(&self) -> Option<String> {
+        match *self {
+            Status::Clean => None,
+            Status::Offset(offset) => Some(format!("applies with offset {:+}", offset)),
+            Status::Fuzz(fuzz, 0) => Some(format!("applies with fuzz {}", fuzz)),
+            Status::Fuzz(fuzz, offset) => Some(format!(
+                "applies with fuzz {} and offset {:+}",
+                fuzz, offset
+            )),
+            Status::Rejected(reason) => Some(format!("rejected: {}", reason)),
+        }
+    }
+}
+
+/// Strips the line end of `line`.
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Finds where `old` appears in `file`, the nearest to `start` first.
+/// Returns the offset from `start`.
+fn find(file: &[Vec<u8>], old: &[&[u8]], start: usize) -> Option<isize> {
+    if file.len() < old.len() {
+        return None;
+    }
+    let last = file.len() - old.len();
+    let matches = |at: usize| {
+        old.iter()
+            .zip(&file[at..])
+            .all(|(old, line)| trim_newline(old) == trim_newline(line))
+    };
+    let nearest = start.min(last);
+    (0..=last).find_map(|distance| {
+        let candidates = [nearest.checked_add(distance), nearest.checked_sub(distance)];
+        candidates
+            .iter()
+            .flatten()
+            .find(|&&at| at <= last && matches(at))
+            .map(|&at| at as isize - start as isize)
+    })
+}
+
+/// Checks the hunks against the files of a directory, keeping the
+/// files read.
+#[derive(Default)]
+pub struct Checker {
+    files: HashMap<String, Option<Vec<Vec<u8>>>>,
+}
+
+impl Checker {
+    /// Checks how the hunk `header` of `path` applies in `dir`.
+    /// `old_path` is None for the new files, and `lines` are the lines
+    /// of the hunk with their prefix.
+    pub fn check<'a, Lines>(
+        &mut self,
+        dir: &str,
+        (old_path, path): (Option<&str>, Option<&str>),
+        header: &HunkHeader,
+        lines: Lines,
+    ) -> Status
+    where
+        Lines: Iterator<Item = &'a [u8]>,
+    {
+        let old_path = match old_path {
+            Some(old_path) => old_path,
+            None if path.is_some_and(|path| Path::new(dir).join(path).exists()) => {
+                return Status::Rejected("the new file already exists")
+            }
+            None => return Status::Clean,
+        };
+        let file = self.files.entry(old_path.to_owned()).or_insert_with(|| {
+            let data = fs::read(Path::new(dir).join(old_path)).ok()?;
+            let lines = data.split_inclusive(|&b| b == b'\n').map(<[u8]>::to_vec);
+            Some(lines.collect())
+        });
+        let file = match file {
+            Some(file) => file,
+            None => return Status::Rejected("the file is missing"),
+        };
+        // the lines of the old file, and whether they are context
+        let old = lines
+            .filter_map(|line| match line.first() {
+                Some(b' ') => Some((&line[1..], true)),
+                Some(b'-') => Some((&line[1..], false)),
+                // an empty context line whose space was stripped
+                Some(b'\n') => Some((line, true)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // the index of the first old line; a hunk without old lines
+        // starts after the line of its header
+        let start = if header.old_len == 0 {
+            header.old_start
+        } else {
+            header.old_start.saturating_sub(1)
+        };
+        let leading = old.iter().take_while(|(_, context)| *context).count();
+        let trailing = old.iter().rev().take_while(|(_, context)| *context).count();
+        for fuzz in 0..=MAX_FUZZ.min(leading.max(trailing)) {
+            let (lo, hi) = (fuzz.min(leading), old.len() - fuzz.min(trailing));
+            let lines = old[lo..hi]
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>();
+            match find(file, &lines, start + lo) {
+                Some(0) if fuzz == 0 => return Status::Clean,
+                Some(offset) if fuzz == 0 => return Status::Offset(offset),
+                Some(offset) => return Status::Fuzz(fuzz, offset),
+                None => (),
+            }
+        }
+        Status::Rejected("the context does not match")
+    }
+}
+
+/// Writes the note of `status` after a hunk, if it does not apply
+/// cleanly.
+pub fn output_note<Stream>(status: &Status, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let note = match status.note() {
+        Some(note) => note,
+        None => return Ok(()),
+    };
+    let face = match status {
+        Status::Rejected(_) => &config.apply_rejected_face,
+        _ => &config.apply_warning_face,
+    };
+    output(format!("{}\n", note).as_bytes(), face, out)
+}
@@ -0,0 +1,67 @@
+//! Benchmark of the processing of a saved diff, for --bench.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, SystemTime};
+use termcolor::Ansi;
+
+use super::{elapsed, first_after_escape, process_diff, scan, AppConfig, Timings};
+
+/// Splits `data` in lines with `read_line` and classifies them by
+/// their first byte, as the main loop does. Returns the number of
+/// changed lines found and the time spent.
+fn scan_lines<ReadLine>(data: &[u8], read_line: ReadLine) -> io::Result<(usize, Duration)>
+where
+    ReadLine: Fn(&mut &[u8], &mut Vec<u8>) -> io::Result<usize>,
+{
+    let start = Some(SystemTime::now());
+    let mut input = data;
+    let mut line = vec![];
+    let mut changed = 0;
+    while read_line(&mut input, &mut line)? != 0 {
+        if let Some(b'+') | Some(b'-') = first_after_escape(&line) {
+            changed += 1;
+        }
+        line.clear();
+    }
+    Ok((changed, elapsed(&start)))
+}
+
+/// Processes the diff in the file at `path` `runs` times, and reports
+/// the average time of each phase on the standard output.
+pub fn run(config: AppConfig, path: &str, runs: u32) -> io::Result<()> {
+    let start = Some(SystemTime::now());
+    let data = fs::read(path)?;
+    let read = elapsed(&start);
+    let mut total = Timings::default();
+    for _ in 0..runs {
+        let stats = process_diff(
+            config.clone(),
+            &mut &data[..],
+            &mut Ansi::new(io::sink()),
+            false,
+        )?;
+        total.add(&stats.timings);
+    }
+    let (changed, scan_time) = scan_lines(&data, |input, line| scan::read_line(input, line))?;
+    let (_, bytewise_time) = scan_lines(&data, |input, line| input.read_until(b'\n', line))?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(
+        out,
+        "{}: {} bytes, {} lines, read in {:.3} ms",
+        path,
+        data.len(),
+        scan::count_lines(&data),
+        read.as_secs_f64() * 1000.0
+    )?;
+    writeln!(
+        out,
+        "line scan of {} changed lines (ms): {:.3}, {:.3} with read_until",
+        changed,
+        scan_time.as_secs_f64() * 1000.0,
+        bytewise_time.as_secs_f64() * 1000.0
+    )?;
+    writeln!(out, "average of {} runs:", runs)?;
+    write!(out, "{}", total.div(runs.max(1)))
+}
@@ -0,0 +1,220 @@
+//! Placeholders of the binary files of the diff, for --binary.
+//!
+//! The lines of a "GIT binary patch" are replaced by a line giving the
+//! sizes of the file, read from its "literal" blocks: the first block
+//! holds the new file, and the second the old one.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use termcolor::{HyperlinkSpec, WriteColor};
+
+use super::{output, AppConfig, OutputFormat};
+
+/// How the binary files of the diff are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryMode {
+    /// A placeholder line with the sizes of the file.
+    Summary,
+    /// The lines of the input, unchanged.
+    Raw,
+    /// A placeholder line, followed by the image in the HTML output
+    /// if the file is found locally.
+    Embed,
+}
+
+/// The largest image embedded in the HTML output.
+const MAX_EMBEDDED_SIZE: u64 = 1 << 20;
+
+const BASE85_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Returns true if `line` is a line of data of a binary patch: its
+/// length as a letter, followed by groups of 5 base85 characters.
+fn is_data_line(line: &[u8]) -> bool {
+    match line.split_first() {
+        Some((first, data)) => {
+            first.is_ascii_alphabetic()
+                && !data.is_empty()
+                && data.len() % 5 == 0
+                && data.iter().all(|b| BASE85_ALPHABET.contains(b))
+        }
+        None => false,
+    }
+}
+
+/// The last line read of a binary patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Last {
+    Start,
+    Block,
+    Data,
+    Blank,
+}
+
+/// A "GIT binary patch" being read.
+pub struct Patch {
+    path: Option<String>,
+    /// The size of the file of each block, None for a "delta" block.
+    sizes: Vec<Option<u64>>,
+    last: Last,
+}
+
+impl Patch {
+    pub fn new(path: Option<String>) -> Self {
+        Patch {
+            path,
+            sizes: vec![],
+            last: Last::Start,
+        }
+    }
+
+    /// Reads the line `stripped`; returns false if it is not part of the
+    /// patch, which is then complete.
+    pub fn push(&mut self, stripped: &[u8]) -> bool {
+        let line = stripped.strip_suffix(b"\n").unwrap_or(stripped);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let can_start_block = matches!(self.last, Last::Start | Last::Blank);
+        self.last = if let (Some(size), true) = (line.strip_prefix(b"literal "), can_start_block) {
+            let size = std::str::from_utf8(size)
+                .ok()
+                .and_then(|size| size.parse().ok());
+            self.sizes.push(size);
+            Last::Block
+        } else if line.starts_with(b"delta ") && can_start_block {
+            self.sizes.push(None);
+            Last::Block
+        } else if matches!(self.last, Last::Block | Last::Data) && is_data_line(line) {
+            Last::Data
+        } else if self.last == Last::Data && line.is_empty() {
+            Last::Blank
+        } else {
+            return false;
+        };
+        true
+    }
+
+    /// Writes the placeholder of the patch.
+    pub fn write<Stream>(&self, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let size = |i: usize| match self.sizes.get(i) {
+            Some(Some(size)) => format_size(*size),
+            _ => "?".to_owned(),
+        };
+        let change = format!("{} -> {}", size(1), size(0));
+        write_summary(self.path.as_deref(), &change, config, out)
+    }
+}
+
+/// The change described by a "Binary files A and B differ" line.
+pub fn marker_change(stripped: &[u8]) -> &'static str {
+    let line = stripped.strip_suffix(b"\n").unwrap_or(stripped);
+    if line.starts_with(b"Binary files /dev/null ") {
+        "added"
+    } else if line.ends_with(b" and /dev/null differ") {
+        "deleted"
+    } else {
+        "changed"
+    }
+}
+
+/// Formats a size in bytes, with a binary unit.
+pub fn format_size(size: u64) -> String {
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut units = ["KiB", "MiB", "GiB", "TiB"].iter();
+    while 1024.0 <= value && units.len() > 1 {
+        value /= 1024.0;
+        units.next();
+    }
+    format!("{:.1} {}", value, units.next().unwrap())
+}
+
+/// Writes the placeholder line of the binary file `path`, with the
+/// 'binary' face.
+pub fn write_summary<Stream>(
+    path: Option<&str>,
+    change: &str,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let text = format!("binary file {}: {}", path.unwrap_or("?"), change);
+    let image = match path {
+        Some(path) if config.binary == BinaryMode::Embed && change != "deleted" => {
+            embedded_image(config, Path::new(path))
+        }
+        _ => None,
+    };
+    // the HTML output displays the image that follows a data url
+    if let Some(url) = &image {
+        out.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+    }
+    output(text.as_bytes(), &config.binary_face, out)?;
+    if image.is_some() {
+        out.set_hyperlink(&HyperlinkSpec::close())?;
+    }
+    out.write_all(b"\n")
+}
+
+/// The media type of the images that browsers display.
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let media_type = match &extension[..] {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    };
+    Some(media_type)
+}
+
+/// The data url of the image `path`, if it is found from the current
+/// directory and the output is HTML.
+fn embedded_image(config: &AppConfig, path: &Path) -> Option<String> {
+    if !matches!(
+        config.output,
+        OutputFormat::Html | OutputFormat::MarkdownHtml
+    ) {
+        return None;
+    }
+    let media_type = media_type(path)?;
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || MAX_EMBEDDED_SIZE < metadata.len() {
+        return None;
+    }
+    let data = fs::read(path).ok()?;
+    Some(format!("data:{};base64,{}", media_type, base64(&data)))
+}
+
+/// Encodes `data` in base64, with padding.
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
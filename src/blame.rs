@@ -0,0 +1,149 @@
+//! Annotation of the removed lines with the commit that introduced
+//! them, for --blame.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{Command, Stdio};
+use termcolor::WriteColor;
+
+use super::{git_diff, output, AppConfig};
+
+/// The number of characters of the author names in the gutter.
+const AUTHOR_WIDTH: usize = 12;
+
+/// The number of characters of the abbreviated hashes.
+const HASH_WIDTH: usize = 7;
+
+/// The commit that introduced a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub hash: String,
+    pub author: String,
+}
+
+/// Parses the output of `git blame --porcelain`: the annotation of
+/// each line of the file, by line number from 1.
+pub fn parse_porcelain(porcelain: &str) -> Vec<Option<Annotation>> {
+    let mut hashes: Vec<Option<&str>> = vec![];
+    let mut authors: HashMap<&str, &str> = HashMap::new();
+    let mut current = None;
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            // the content of the line
+            continue;
+        }
+        let mut words = line.split(' ');
+        let first = words.next().unwrap_or_default();
+        if first.len() == 40 && first.bytes().all(|b| b.is_ascii_hexdigit()) {
+            let final_line = words.nth(1).and_then(|line| line.parse::<usize>().ok());
+            if let Some(final_line) = final_line.filter(|&line| 0 < line) {
+                if hashes.len() < final_line {
+                    hashes.resize(final_line, None);
+                }
+                hashes[final_line - 1] = Some(first);
+            }
+            current = Some(first);
+        } else if let (Some(hash), Some(author)) = (current, line.strip_prefix("author ")) {
+            authors.entry(hash).or_insert(author);
+        }
+    }
+    hashes
+        .into_iter()
+        .map(|hash| {
+            let hash = hash?;
+            Some(Annotation {
+                hash: hash[..HASH_WIDTH].to_owned(),
+                author: authors.get(hash).copied().unwrap_or_default().to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// The revision of the old side of the diff: the first revision of
+/// --git, or HEAD.
+fn old_revision(config: &AppConfig) -> String {
+    let revisions = config.git_revisions.as_deref().unwrap_or_default();
+    let old = revisions.split("..").next().unwrap_or_default().trim();
+    if old.is_empty() {
+        "HEAD".to_owned()
+    } else {
+        old.to_owned()
+    }
+}
+
+/// The annotations of the files of the diff, computed on demand.
+#[derive(Default)]
+pub struct Blame {
+    root: Option<Option<String>>,
+    files: HashMap<String, Vec<Option<Annotation>>>,
+}
+
+impl Blame {
+    /// The annotations of the lines of the old version of `path`, None
+    /// if git blame does not know it.
+    fn annotations(&mut self, path: &str, config: &AppConfig) -> Option<&[Option<Annotation>]> {
+        let root = self
+            .root
+            .get_or_insert_with(git_diff::repository_root)
+            .as_deref()?;
+        let annotations = self.files.entry(path.to_owned()).or_insert_with(|| {
+            let output = Command::new("git")
+                .args(["blame", "--porcelain", &old_revision(config), "--", path])
+                .current_dir(root)
+                .stderr(Stdio::null())
+                .output();
+            match output {
+                Ok(output) if output.status.success() => {
+                    parse_porcelain(&String::from_utf8_lossy(&output.stdout))
+                }
+                _ => vec![],
+            }
+        });
+        Some(&annotations[..]).filter(|annotations| !annotations.is_empty())
+    }
+
+    /// Returns true if git blame knows the old version of `path`, so
+    /// that the lines of its hunks have a gutter.
+    pub fn is_known(&mut self, path: &str, config: &AppConfig) -> bool {
+        self.annotations(path, config).is_some()
+    }
+
+    /// The annotation of the line `line` of the old version of
+    /// `path`, None if git blame does not know it.
+    pub fn annotation(
+        &mut self,
+        path: &str,
+        line: usize,
+        config: &AppConfig,
+    ) -> Option<&Annotation> {
+        self.annotations(path, config)?
+            .get(line.checked_sub(1)?)?
+            .as_ref()
+    }
+}
+
+/// Writes the gutter of --blame: the abbreviated hash and the author
+/// of `annotation`, or blanks.
+pub fn output_gutter<Stream>(
+    annotation: Option<&Annotation>,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let gutter = match annotation {
+        Some(annotation) => {
+            let author: String = annotation.author.chars().take(AUTHOR_WIDTH).collect();
+            format!(
+                "{:hash$} {:author$} ",
+                annotation.hash,
+                author,
+                hash = HASH_WIDTH,
+                author = AUTHOR_WIDTH
+            )
+        }
+        None => " ".repeat(HASH_WIDTH + AUTHOR_WIDTH + 2),
+    };
+    output(gutter.as_bytes(), &config.blame_face, out)
+}
@@ -0,0 +1,57 @@
+//! Refinement of a block of changed lines as a single stream of words,
+//! for --refine-block.
+//!
+//! When a paragraph is reflowed, the words move from a line to another
+//! and the line breaks and indentation change around them. The blank
+//! tokens are left out of the comparison, so that the words are
+//! matched whatever the layout of the lines; the blanks between two
+//! words matched in a row are shared when both sides have as many.
+
+use diffr_lib::{HashedSpan, Snake};
+
+use super::algorithm::{self, Algorithm};
+
+/// The tokens of `tokens` that are not blank, with their indices.
+fn words(data: &[u8], tokens: &[HashedSpan]) -> (Vec<HashedSpan>, Vec<usize>) {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !data[token.lo..token.hi].iter().all(u8::is_ascii_whitespace))
+        .map(|(i, token)| (*token, i))
+        .unzip()
+}
+
+/// Computes the tokens shared by `removed` and `added` into `dst`,
+/// comparing their words with `algorithm`.
+pub fn diff(
+    algorithm: Algorithm,
+    data: &[u8],
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+    v: &mut Vec<isize>,
+    dst: &mut Vec<Snake>,
+) {
+    let (removed_words, removed_index) = words(data, removed);
+    let (added_words, added_index) = words(data, added);
+    let mut snakes = vec![];
+    algorithm::diff(
+        algorithm,
+        data,
+        (&removed_words, &added_words),
+        v,
+        &mut snakes,
+    );
+    dst.clear();
+    for snake in &snakes {
+        let (x0, y0) = (snake.x0 as usize, snake.y0 as usize);
+        for k in 0..snake.len as usize {
+            let (x, y) = (removed_index[x0 + k], added_index[y0 + k]);
+            if 0 < k {
+                let (px, py) = (removed_index[x0 + k - 1], added_index[y0 + k - 1]);
+                if x - px == y - py {
+                    algorithm::push(dst, px + 1, py + 1, x - px - 1);
+                }
+            }
+            algorithm::push(dst, x, y, 1);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Display of the bytes that are not printable text.
+
+use std::io::{self, Write};
+use termcolor::{ColorSpec, HyperlinkSpec, WriteColor};
+
+fn is_unprintable(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x1b')
+}
+
+/// Writes `buf`, replacing the bytes that are not valid UTF-8 and the
+/// control characters by hexadecimal escapes such as `\xff`.
+fn write_escaped<Out: Write>(buf: &[u8], out: &mut Out) -> io::Result<()> {
+    for chunk in buf.utf8_chunks() {
+        let valid = chunk.valid();
+        let mut lo = 0;
+        for (i, c) in valid.char_indices().filter(|&(_, c)| is_unprintable(c)) {
+            out.write_all(&valid.as_bytes()[lo..i])?;
+            write!(out, "\\x{:02x}", u32::from(c))?;
+            lo = i + c.len_utf8();
+        }
+        out.write_all(&valid.as_bytes()[lo..])?;
+        for b in chunk.invalid() {
+            write!(out, "\\x{:02x}", b)?;
+        }
+    }
+    Ok(())
+}
+
+/// A stream that escapes the bytes written, except the escape codes.
+pub struct EscapeBytes<W> {
+    inner: W,
+}
+
+impl<W> EscapeBytes<W> {
+    pub fn new(inner: W) -> Self {
+        EscapeBytes { inner }
+    }
+}
+
+impl<W: Write> Write for EscapeBytes<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write_escaped(buf, &mut self.inner)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: WriteColor> WriteColor for EscapeBytes<W> {
+    fn supports_color(&self) -> bool {
+        self.inner.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.inner.set_color(spec)
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.inner.reset()
+    }
+
+    fn is_synchronous(&self) -> bool {
+        self.inner.is_synchronous()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> io::Result<()> {
+        self.inner.set_hyperlink(link)
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        self.inner.supports_hyperlinks()
+    }
+}
+
+/// Returns true if `line` replaces the hunks of a binary file.
+pub fn is_binary_marker(line: &[u8]) -> bool {
+    line.starts_with(b"Binary files ") || line.starts_with(b"GIT binary patch")
+}
@@ -0,0 +1,198 @@
+//! A cache of the refinement of the hunks on disk, for --cache.
+//!
+//! Each entry is a file named after a hash of the tokens of a hunk and
+//! of the options of the refinement, holding the shared spans found,
+//! a line "x0 y0 len" per span.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use diffr_lib::{HashedSpan, Snake};
+
+use super::AppConfig;
+
+/// Changes when the format of the entries or the refinement changes,
+/// so that the entries of older versions are not read.
+const VERSION: u32 = 1;
+
+/// The maximal size of the cache by default, in MiB.
+pub const DEFAULT_MAX_SIZE_MIB: u64 = 64;
+
+/// The directory of the cache: 'diffr' in the cache directory of the
+/// XDG base directory specification, or of Windows.
+fn cache_dir() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let base = var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| var("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("diffr"))
+}
+
+/// Computes the name of the entry of the refinement of the tokens
+/// `removed` and `added` of `data`.
+fn key(
+    config: &AppConfig,
+    data: &[u8],
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+) -> String {
+    // two hashes with different seeds make collisions unlikely
+    let hash = |seed: u64| {
+        let mut hasher = DefaultHasher::new();
+        (seed, VERSION).hash(&mut hasher);
+        format!("{:?}", config.algorithm).hash(&mut hasher);
+        let options = (config.refine_block, config.line_pairing, config.slider);
+        let threshold = config.pairing_threshold.map(f64::to_bits);
+        (options, threshold, config.ignore_case).hash(&mut hasher);
+        data.hash(&mut hasher);
+        for tokens in [removed, added] {
+            tokens.len().hash(&mut hasher);
+            for token in tokens {
+                (token.lo, token.hi, token.hash).hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", hash(0), hash(1))
+}
+
+/// Parses the entry `text` of tokens of `sizes` into `dst`; returns
+/// false if it is malformed.
+fn parse(text: &str, sizes: (usize, usize), dst: &mut Vec<Snake>) -> bool {
+    dst.clear();
+    for line in text.lines() {
+        let mut fields = line.split(' ').map(|field| field.parse::<usize>().ok());
+        let (x0, y0, len) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(Some(x0)), Some(Some(y0)), Some(Some(len)), None) => (x0, y0, len),
+            _ => return false,
+        };
+        if sizes.0 < x0 + len || sizes.1 < y0 + len {
+            return false;
+        }
+        dst.push(Snake {
+            x0: x0 as isize,
+            y0: y0 as isize,
+            len: len as isize,
+        });
+    }
+    true
+}
+
+/// The cache of the refinement; it does nothing without --cache.
+#[derive(Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+    max_size: u64,
+    written: bool,
+}
+
+impl Cache {
+    /// Opens the cache of at most `max_size` bytes in its directory, if
+    /// `max_size` is set.
+    pub fn new(max_size: Option<u64>) -> Self {
+        match max_size {
+            Some(max_size) => Cache::with_dir(cache_dir(), max_size),
+            None => Cache::default(),
+        }
+    }
+
+    pub fn with_dir(dir: Option<PathBuf>, max_size: u64) -> Self {
+        Cache {
+            dir,
+            max_size,
+            written: false,
+        }
+    }
+
+    /// The name of the entry of the refinement of the tokens of `data`,
+    /// None without --cache.
+    pub fn key(
+        &self,
+        config: &AppConfig,
+        data: &[u8],
+        tokens: (&[HashedSpan], &[HashedSpan]),
+    ) -> Option<String> {
+        self.dir.as_ref().map(|_| key(config, data, tokens))
+    }
+
+    /// Reads into `dst` the refinement of the entry `key`, of tokens of
+    /// `sizes`. Returns false if it is not in the cache.
+    pub fn get(&self, key: &str, sizes: (usize, usize), dst: &mut Vec<Snake>) -> bool {
+        let path = match &self.dir {
+            Some(dir) => dir.join(key),
+            None => return false,
+        };
+        let found = match fs::read_to_string(&path) {
+            Ok(text) => parse(&text, sizes, dst),
+            Err(_) => false,
+        };
+        if found {
+            // the entries used recently are evicted last
+            if let Ok(file) = File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+        }
+        found
+    }
+
+    /// Stores the refinement `snakes` in the entry `key`. The cache is
+    /// best effort: the errors are ignored.
+    pub fn put(&mut self, key: &str, snakes: &[Snake]) {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let text = snakes
+            .iter()
+            .map(|snake| format!("{} {} {}\n", snake.x0, snake.y0, snake.len))
+            .collect::<String>();
+        // the entry is renamed once complete, for the concurrent readers
+        let tmp = dir.join(format!("{}.{}.tmp", key, std::process::id()));
+        let written = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&tmp, text))
+            .and_then(|_| fs::rename(&tmp, dir.join(key)));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        self.written = true;
+    }
+
+    /// Removes the entries used the least recently until the cache
+    /// fits in its maximal size, if entries were stored.
+    pub fn evict(&mut self) {
+        let dir = match &self.dir {
+            Some(dir) if self.written => dir,
+            _ => return,
+        };
+        self.written = false;
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut entries = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let metadata = entry
+                    .metadata()
+                    .ok()
+                    .filter(|metadata| metadata.is_file())?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((modified, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        entries.sort_unstable();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
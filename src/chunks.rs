@@ -0,0 +1,86 @@
+//! Splitting of the long hunks into chunks, for --split-hunks.
+//!
+//! A hunk is split after the context lines that end a logical block:
+//! the blank lines, and the lines whose closing brace balances the
+//! braces opened since the start of the chunk.
+
+/// A chunk of a hunk, after its first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    /// The index of the first line of the chunk in the hunk.
+    pub start: usize,
+    pub old_len: usize,
+    pub new_len: usize,
+}
+
+/// The change of the brace depth over `content`.
+fn brace_delta(content: &[u8]) -> isize {
+    content.iter().fold(0, |depth, &b| match b {
+        b'{' => depth + 1,
+        b'}' => depth - 1,
+        _ => depth,
+    })
+}
+
+fn is_context(first: u8) -> bool {
+    first != b'-' && first != b'+' && first != b'\\'
+}
+
+/// Returns true if the line `lo..hi` of `data` is an empty context line.
+fn is_blank_context(data: &[u8], (lo, hi): (usize, usize)) -> bool {
+    is_context(data[lo]) && data[lo + 1..hi].iter().all(u8::is_ascii_whitespace)
+}
+
+/// Splits the hunk of the lines `lines` of `data` into chunks of at
+/// least `min_lines` lines, and writes in `dst` the chunks after the
+/// first one; `dst` is left empty if the hunk is not split.
+pub fn split<I>(data: &[u8], lines: I, min_lines: usize, dst: &mut Vec<Chunk>)
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    dst.clear();
+    let lines: Vec<_> = lines.into_iter().collect();
+    let mut chunk = Chunk {
+        start: 0,
+        old_len: 0,
+        new_len: 0,
+    };
+    let mut depth = 0;
+    for (i, &(lo, hi)) in lines.iter().enumerate() {
+        let first = data[lo];
+        let content = data.get(lo + 1..hi).unwrap_or_default();
+        match first {
+            b'-' => chunk.old_len += 1,
+            b'+' => chunk.new_len += 1,
+            b'\\' => continue,
+            _ => {
+                chunk.old_len += 1;
+                chunk.new_len += 1;
+            }
+        }
+        if first != b'-' {
+            depth += brace_delta(content);
+        }
+        let ends_block = is_blank_context(data, (lo, hi))
+            || is_context(first) && depth <= 0 && content.contains(&b'}');
+        // the chunk goes on with the blank lines after its block, and
+        // with the "\\ No newline at end of file" of its last line
+        let boundary = match lines.get(i + 1) {
+            Some(&next) => ends_block && data[next.0] != b'\\' && !is_blank_context(data, next),
+            None => false,
+        };
+        if boundary && min_lines <= i + 1 - chunk.start {
+            dst.push(chunk);
+            chunk = Chunk {
+                start: i + 1,
+                old_len: 0,
+                new_len: 0,
+            };
+            depth = 0;
+        }
+    }
+    if !dst.is_empty() {
+        dst.push(chunk);
+        dst.remove(0);
+    }
+}
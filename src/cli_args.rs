@@ -1,9 +1,18 @@
-use super::AppConfig;
+use super::{
+    cache, filter, ignore, profile, Algorithm, AppConfig, Background, BinaryMode, CommitFormat,
+    DebugFormat, Granularity, Lang, LineNumberStyle, OutputFormat, RedactStyle, RenameFormat,
+    SortFiles, Theme, Timestamps, DEFAULT_COLLAPSE_CONTEXT, DEFAULT_MAX_REFINE_COST,
+};
+use atty::{is, Stream};
 use clap::{App, AppSettings, Arg, ArgMatches};
+use regex::bytes::Regex;
+use std::env;
+use std::ffi::OsString;
 use std::fmt::Display;
 use std::fmt::{Error as FmtErr, Formatter};
+use std::io;
 use std::str::FromStr;
-use termcolor::{Color, ColorSpec, ParseColorError};
+use termcolor::{Color, ColorChoice, ColorSpec, ParseColorError, WriteColor};
 
 const ABOUT: &str = "
 diffr adds word-level diff on top of unified diffs.
@@ -14,10 +23,115 @@ diffr reads from standard input and write to standard output.
 
     Typical usage is for interactive use of diff:
     diff -u <file1> <file2> | diffr
-    git show | diffr";
+    git show | diffr
+    diffr --git <rev1>..<rev2> [-- <path>...]
+    diffr 0001-first.patch 0002-second.patch
+
+    Options can also be given in the DIFFR_OPTIONS environment variable,
+    before the ones of the command line, and in profiles selected with
+    --profile.";
+
+pub const ENV_OPTIONS: &str = "DIFFR_OPTIONS";
+pub const FLAG_PROFILE: &str = "--profile";
 
 pub const FLAG_DEBUG: &str = "--debug";
+pub const FLAG_BENCH: &str = "--bench";
+pub const FLAG_BENCH_RUNS: &str = "--bench-runs";
+pub const FLAG_EMIT_TEST_EXPECTATION: &str = "--emit-test-expectation";
 pub const FLAG_COLOR: &str = "--colors";
+pub const FLAG_KEEP_INPUT_COLORS: &str = "--keep-input-colors";
+pub const FLAG_PAGER: &str = "--pager";
+pub const FLAG_TUI: &str = "--tui";
+pub const FLAG_NAVIGATE: &str = "--navigate";
+pub const FLAG_NAVIGATE_MARKER: &str = "--navigate-marker";
+pub const FLAG_NAVIGATE_PROMPT: &str = "--navigate-prompt";
+pub const FLAG_LINE_NUMBERS: &str = "--line-numbers";
+pub const FLAG_LINE_NUMBERS_SEPARATOR: &str = "--line-numbers-separator";
+pub const FLAG_LINE_NUMBERS_WIDTH: &str = "--line-numbers-width";
+pub const FLAG_LINE_NUMBERS_FORMAT: &str = "--line-numbers-format";
+pub const FLAG_HYPERLINKS: &str = "--hyperlinks";
+pub const FLAG_HYPERLINK_FORMAT: &str = "--hyperlink-format";
+pub const FLAG_REPO_URL: &str = "--repo-url";
+pub const FLAG_PATH: &str = "--path";
+pub const FLAG_EXCLUDE: &str = "--exclude";
+pub const FLAG_GENERATED: &str = "--generated";
+pub const FLAG_EXPAND_GENERATED: &str = "--expand-generated";
+pub const FLAG_STATS: &str = "--stats";
+pub const FLAG_DIFFSTAT: &str = "--diffstat";
+pub const FLAG_REFINE_THRESHOLD: &str = "--refine-threshold";
+pub const FLAG_MAX_REFINE_COST: &str = "--max-refine-cost";
+pub const FLAG_CACHE: &str = "--cache";
+pub const FLAG_ASCII_TOKENIZE: &str = "--ascii-tokenize";
+pub const FLAG_INVALID_BYTES: &str = "--invalid-bytes";
+pub const FLAG_TABS: &str = "--tabs";
+pub const FLAG_WORD_DIFF: &str = "--word-diff";
+pub const FLAG_COLORS_FROM_GIT: &str = "--colors-from-git";
+pub const FLAG_COLOR_WHEN: &str = "--color";
+pub const FLAG_MAX_LINE_LENGTH: &str = "--max-line-length";
+pub const FLAG_TRUNCATION_MARKER: &str = "--truncation-marker";
+pub const FLAG_RULER: &str = "--ruler";
+pub const FLAG_NO_SLIDER: &str = "--no-slider";
+pub const FLAG_NO_LINE_PAIRING: &str = "--no-line-pairing";
+pub const FLAG_PAIRING_THRESHOLD: &str = "--pairing-threshold";
+pub const FLAG_REFINE_BLOCK: &str = "--refine-block";
+pub const FLAG_REFINE: &str = "--refine";
+pub const FLAG_REFINE_CHAR_WIDTH: &str = "--refine-char-width";
+pub const FLAG_IGNORE_MATCHING_LINES: &str = "--ignore-matching-lines";
+pub const FLAG_GREP: &str = "--grep";
+pub const FLAG_IGNORE_TOKEN: &str = "--ignore-token";
+pub const FLAG_REDACT: &str = "--redact";
+pub const FLAG_REDACT_STYLE: &str = "--redact-style";
+pub const FLAG_BLAME: &str = "--blame";
+pub const FLAG_LENIENT: &str = "--lenient";
+pub const FLAG_STRICT: &str = "--strict";
+pub const FLAG_GIT: &str = "--git";
+pub const FLAG_CONTEXT: &str = "--context";
+pub const FLAG_RESOLVE_CONTEXT: &str = "--resolve-context";
+pub const ARG_GIT_PATHS: &str = "git-paths";
+pub const ARG_INPUTS: &str = "inputs";
+pub const FLAG_COLLAPSE_CONTEXT: &str = "--collapse-context";
+pub const FLAG_ONLY_CHANGES: &str = "--only-changes";
+pub const FLAG_MERGE_HUNKS: &str = "--merge-hunks";
+pub const FLAG_SPLIT_HUNKS: &str = "--split-hunks";
+pub const FLAG_ANNOTATIONS: &str = "--annotations";
+pub const FLAG_ANNOTATIONS_SARIF: &str = "--annotations-sarif";
+pub const FLAG_INDENT_CHANGED: &str = "--indent-changed";
+pub const FLAG_INDENT_DELTA: &str = "--indent-delta";
+pub const FLAG_RELOCATED: &str = "--relocated";
+pub const FLAG_LANG: &str = "--lang";
+pub const FLAG_IGNORE_CASE: &str = "--ignore-case";
+pub const FLAG_ALGORITHM: &str = "--algorithm";
+pub const FLAG_OUTPUT: &str = "--output";
+pub const FLAG_EXIT_CODE: &str = "--exit-code";
+pub const FLAG_METRICS_FILE: &str = "--metrics-file";
+pub const FLAG_QUIET: &str = "--quiet";
+pub const FLAG_OUTPUT_FILE: &str = "--output-file";
+pub const FLAG_SPLIT_OUTPUT: &str = "--split-output";
+pub const FLAG_TEE: &str = "--tee";
+pub const FLAG_COMMIT_FORMAT: &str = "--commit-format";
+pub const FLAG_COMPACT_HEADERS: &str = "--compact-headers";
+pub const FLAG_RENAME_FORMAT: &str = "--rename-format";
+pub const FLAG_LIGHT: &str = "--light";
+pub const FLAG_THEME: &str = "--theme";
+pub const FLAG_THEME_FILE: &str = "--theme-file";
+pub const FLAG_ACCESSIBILITY: &str = "--accessibility";
+pub const FLAG_TIMESTAMPS: &str = "--timestamps";
+pub const FLAG_SORT_FILES: &str = "--sort-files";
+pub const FLAG_STRIP_PREFIX: &str = "--strip-prefix";
+pub const FLAG_SHOW_CR: &str = "--show-cr";
+pub const FLAG_SHOW_NONPRINTING: &str = "--show-nonprinting";
+pub const FLAG_BINARY: &str = "--binary";
+pub const FLAG_DARK: &str = "--dark";
+pub const FLAG_HELP_COLORS: &str = "--help-colors";
+pub const FLAG_DUMP_THEME: &str = "--dump-theme";
+pub const FLAG_MAN_PAGE: &str = "--man-page";
+pub const FLAG_PREVIEW_COLORS: &str = "--preview-colors";
+pub const FLAG_SERVE: &str = "--serve";
+pub const FLAG_WATCH: &str = "--watch";
+pub const FLAG_CONFLICTS: &str = "--conflicts";
+pub const FLAG_INPUT: &str = "--input";
+pub const FLAG_THREE_WAY: &str = "--three-way";
+pub const FLAG_APPLY_PREVIEW: &str = "--apply-preview";
 
 #[derive(Debug, Clone, Copy)]
 pub enum FaceName {
@@ -25,6 +139,36 @@ pub enum FaceName {
     RefineAdded,
     Removed,
     RefineRemoved,
+    LineNumber,
+    NoNewline,
+    Folded,
+    CaseChanged,
+    Commit,
+    CommitMeta,
+    Mode,
+    Rename,
+    Submodule,
+    Ignored,
+    NumberChanged,
+    Blame,
+    ApplyWarning,
+    ApplyRejected,
+    SearchMatch,
+    IndentChanged,
+    CarriageReturn,
+    Nonprinting,
+    FunctionContext,
+    Binary,
+    Masked,
+    Relocated,
+    MailHeader,
+    Message,
+    Ruler,
+    Overlong,
+    AnnotationError,
+    AnnotationWarning,
+    AnnotationNote,
+    ChunkHeader,
 }
 
 impl EnumString for FaceName {
@@ -35,6 +179,36 @@ impl EnumString for FaceName {
             ("refine-added", RefineAdded),
             ("removed", Removed),
             ("refine-removed", RefineRemoved),
+            ("line-number", LineNumber),
+            ("no-newline", NoNewline),
+            ("folded", Folded),
+            ("case-changed", CaseChanged),
+            ("commit", Commit),
+            ("commit-meta", CommitMeta),
+            ("mode", Mode),
+            ("rename", Rename),
+            ("submodule", Submodule),
+            ("ignored", Ignored),
+            ("number-changed", NumberChanged),
+            ("blame", Blame),
+            ("apply-warning", ApplyWarning),
+            ("apply-rejected", ApplyRejected),
+            ("search-match", SearchMatch),
+            ("indent-changed", IndentChanged),
+            ("carriage-return", CarriageReturn),
+            ("nonprinting", Nonprinting),
+            ("function-context", FunctionContext),
+            ("binary", Binary),
+            ("masked", Masked),
+            ("relocated", Relocated),
+            ("mail-header", MailHeader),
+            ("message", Message),
+            ("ruler", Ruler),
+            ("overlong", Overlong),
+            ("annotation-error", AnnotationError),
+            ("annotation-warning", AnnotationWarning),
+            ("annotation-note", AnnotationNote),
+            ("chunk-header", ChunkHeader),
         ]
     }
 }
@@ -47,22 +221,215 @@ impl Display for FaceName {
             RefineAdded => write!(f, "refine-added"),
             Removed => write!(f, "removed"),
             RefineRemoved => write!(f, "refine-removed"),
+            LineNumber => write!(f, "line-number"),
+            NoNewline => write!(f, "no-newline"),
+            Folded => write!(f, "folded"),
+            CaseChanged => write!(f, "case-changed"),
+            Commit => write!(f, "commit"),
+            CommitMeta => write!(f, "commit-meta"),
+            Mode => write!(f, "mode"),
+            Rename => write!(f, "rename"),
+            Submodule => write!(f, "submodule"),
+            Ignored => write!(f, "ignored"),
+            NumberChanged => write!(f, "number-changed"),
+            Blame => write!(f, "blame"),
+            ApplyWarning => write!(f, "apply-warning"),
+            ApplyRejected => write!(f, "apply-rejected"),
+            SearchMatch => write!(f, "search-match"),
+            IndentChanged => write!(f, "indent-changed"),
+            CarriageReturn => write!(f, "carriage-return"),
+            Nonprinting => write!(f, "nonprinting"),
+            FunctionContext => write!(f, "function-context"),
+            Binary => write!(f, "binary"),
+            Masked => write!(f, "masked"),
+            Relocated => write!(f, "relocated"),
+            MailHeader => write!(f, "mail-header"),
+            Message => write!(f, "message"),
+            Ruler => write!(f, "ruler"),
+            Overlong => write!(f, "overlong"),
+            AnnotationError => write!(f, "annotation-error"),
+            AnnotationWarning => write!(f, "annotation-warning"),
+            AnnotationNote => write!(f, "annotation-note"),
+            ChunkHeader => write!(f, "chunk-header"),
         }
     }
 }
 
 impl FaceName {
-    fn get_face_mut<'a, 'b>(&'a self, config: &'b mut super::AppConfig) -> &'b mut ColorSpec {
+    fn get_face_mut<'b>(&self, config: &'b mut super::AppConfig) -> &'b mut ColorSpec {
         use FaceName::*;
         match self {
             Added => &mut config.added_face,
             RefineAdded => &mut config.refine_added_face,
             Removed => &mut config.removed_face,
             RefineRemoved => &mut config.refine_removed_face,
+            LineNumber => &mut config.line_number_face,
+            NoNewline => &mut config.no_newline_face,
+            Folded => &mut config.folded_face,
+            CaseChanged => &mut config.case_changed_face,
+            Commit => &mut config.commit_face,
+            CommitMeta => &mut config.commit_meta_face,
+            Mode => &mut config.mode_face,
+            Rename => &mut config.rename_face,
+            Submodule => &mut config.submodule_face,
+            Ignored => &mut config.ignored_face,
+            NumberChanged => &mut config.number_changed_face,
+            Blame => &mut config.blame_face,
+            ApplyWarning => &mut config.apply_warning_face,
+            ApplyRejected => &mut config.apply_rejected_face,
+            SearchMatch => &mut config.search_match_face,
+            IndentChanged => &mut config.indent_changed_face,
+            CarriageReturn => &mut config.carriage_return_face,
+            Nonprinting => &mut config.nonprinting_face,
+            FunctionContext => &mut config.function_context_face,
+            Binary => &mut config.binary_face,
+            Masked => &mut config.masked_face,
+            Relocated => &mut config.relocated_face,
+            MailHeader => &mut config.mail_header_face,
+            Message => &mut config.message_face,
+            Ruler => &mut config.ruler_face,
+            Overlong => &mut config.overlong_face,
+            AnnotationError => &mut config.annotation_error_face,
+            AnnotationWarning => &mut config.annotation_warning_face,
+            AnnotationNote => &mut config.annotation_note_face,
+            ChunkHeader => &mut config.chunk_header_face,
         }
     }
 }
 
+impl EnumString for LineNumberStyle {
+    fn data() -> &'static [(&'static str, Self)] {
+        use LineNumberStyle::*;
+        &[
+            ("compact", Compact),
+            ("aligned", Aligned),
+            ("both", Both),
+            ("right-only", RightOnly),
+        ]
+    }
+}
+
+impl EnumString for Lang {
+    fn data() -> &'static [(&'static str, Self)] {
+        use Lang::*;
+        &[
+            ("auto", Auto),
+            ("rust", Rust),
+            ("python", Python),
+            ("c", C),
+            ("json", Json),
+            ("prose", Prose),
+        ]
+    }
+}
+
+impl EnumString for Theme {
+    fn data() -> &'static [(&'static str, Self)] {
+        use Theme::*;
+        &[
+            ("default", Default),
+            ("deuteranopia", Deuteranopia),
+            ("protanopia", Protanopia),
+            ("tritanopia", Tritanopia),
+        ]
+    }
+}
+
+impl EnumString for Timestamps {
+    fn data() -> &'static [(&'static str, Self)] {
+        use Timestamps::*;
+        &[("keep", Keep), ("strip", Strip), ("normalize", Normalize)]
+    }
+}
+
+impl EnumString for Granularity {
+    fn data() -> &'static [(&'static str, Self)] {
+        use Granularity::*;
+        &[("word", Word), ("char", Char), ("auto", Auto)]
+    }
+}
+
+impl EnumString for DebugFormat {
+    fn data() -> &'static [(&'static str, Self)] {
+        use DebugFormat::*;
+        &[("text", Text), ("json", Json)]
+    }
+}
+
+impl EnumString for RedactStyle {
+    fn data() -> &'static [(&'static str, Self)] {
+        use RedactStyle::*;
+        &[("block", Block), ("hash", Hash)]
+    }
+}
+
+impl EnumString for BinaryMode {
+    fn data() -> &'static [(&'static str, Self)] {
+        use BinaryMode::*;
+        &[("summary", Summary), ("raw", Raw), ("embed", Embed)]
+    }
+}
+
+impl EnumString for SortFiles {
+    fn data() -> &'static [(&'static str, Self)] {
+        use SortFiles::*;
+        &[
+            ("path", Path),
+            ("changes", Changes),
+            ("extension", Extension),
+        ]
+    }
+}
+
+impl EnumString for Algorithm {
+    fn data() -> &'static [(&'static str, Self)] {
+        use Algorithm::*;
+        &[
+            ("myers", Myers),
+            ("patience", Patience),
+            ("histogram", Histogram),
+        ]
+    }
+}
+
+impl EnumString for CommitFormat {
+    fn data() -> &'static [(&'static str, Self)] {
+        use CommitFormat::*;
+        &[
+            ("full", Full),
+            ("short", Short),
+            ("oneline", Oneline),
+            ("none", Hidden),
+        ]
+    }
+}
+
+impl EnumString for RenameFormat {
+    fn data() -> &'static [(&'static str, Self)] {
+        use RenameFormat::*;
+        &[("full", Full), ("compact", Compact)]
+    }
+}
+
+impl EnumString for OutputFormat {
+    fn data() -> &'static [(&'static str, Self)] {
+        use OutputFormat::*;
+        &[
+            ("terminal", Terminal),
+            ("markdown", Markdown),
+            ("markdown-html", MarkdownHtml),
+            ("svg", Svg),
+            ("html", Html),
+            ("quickfix", Quickfix),
+            ("sexp", Sexp),
+            ("json", Json),
+            ("json-stream", JsonStream),
+            ("annotated", Annotated),
+            ("patch", Patch),
+        ]
+    }
+}
+
 // custom parsing of Option<Color>
 struct ColorOpt(Option<Color>);
 
@@ -80,6 +447,34 @@ impl FromStr for ColorOpt {
     }
 }
 
+/// The grammar of the values of --colors.
+macro_rules! color_spec_grammar {
+    () => {
+        "color_spec = face-name + ':' + attributes
+attributes = <empty>
+           | attribute + ':' + attributes
+attribute  = ('foreground' | 'background') + ':' + color
+           | (<empty> | 'no') + ('bold' | 'intense' | 'underline'
+                                | 'dim' | 'italic' | 'strikethrough')
+           | 'none'
+color      = 'none'
+           | [0-255]
+           | [0-255] + ',' + [0-255] + ',' + [0-255]
+           | ('black', 'blue', 'green', 'red',
+              'cyan', 'magenta', 'yellow', 'white')
+"
+    };
+}
+
+/// The examples of color_spec of --help-colors.
+const COLOR_SPEC_EXAMPLES: &[&str] = &[
+    "refine-added:background:blue:bold",
+    "added:foreground:28:nobold",
+    "removed:foreground:215,0,0",
+    "commit:none:underline",
+    "line-number:dim:italic",
+];
+
 trait EnumString: Copy {
     fn data() -> &'static [(&'static str, Self)];
 }
@@ -113,6 +508,9 @@ enum AttributeName {
     Bold(bool),
     Intense(bool),
     Underline(bool),
+    Dimmed(bool),
+    Italic(bool),
+    Strikethrough(bool),
     Reset,
 }
 
@@ -128,6 +526,12 @@ impl EnumString for AttributeName {
             ("nointense", Intense(false)),
             ("underline", Underline(true)),
             ("nounderline", Underline(false)),
+            ("dim", Dimmed(true)),
+            ("nodim", Dimmed(false)),
+            ("italic", Italic(true)),
+            ("noitalic", Italic(false)),
+            ("strikethrough", Strikethrough(true)),
+            ("nostrikethrough", Strikethrough(false)),
             ("none", Reset),
         ]
     }
@@ -170,8 +574,6 @@ impl FromStr for AttributeName {
     }
 }
 
-fn ignore<T>(_: T) {}
-
 fn parse_color_attributes<'a, Values>(
     config: &mut AppConfig,
     mut values: Values,
@@ -199,6 +601,9 @@ where
             Bold(bold) => ignore(face.set_bold(bold)),
             Intense(intense) => ignore(face.set_intense(intense)),
             Underline(underline) => ignore(face.set_underline(underline)),
+            Dimmed(dimmed) => ignore(face.set_dimmed(dimmed)),
+            Italic(italic) => ignore(face.set_italic(italic)),
+            Strikethrough(strikethrough) => ignore(face.set_strikethrough(strikethrough)),
             Reset => *face = Default::default(),
         }
     }
@@ -222,62 +627,2186 @@ where
     Ok(())
 }
 
+/// Returns true if `name` is the name of a face, such as "added".
+pub fn is_face_name(name: &str) -> bool {
+    FaceName::data()
+        .iter()
+        .any(|&(face_name, _)| face_name == name)
+}
+
+/// The names of the faces, with their faces in `config`.
+pub fn faces(config: &AppConfig) -> Vec<(&'static str, ColorSpec)> {
+    let mut config = config.clone();
+    FaceName::data()
+        .iter()
+        .map(|&(name, face_name)| (name, face_name.get_face_mut(&mut config).clone()))
+        .collect()
+}
+
+/// Sets the faces of `config` to `faces`, as returned by `faces`.
+pub fn set_faces(config: &mut AppConfig, faces: &[(&'static str, ColorSpec)]) {
+    for (&(_, face_name), (_, face)) in FaceName::data().iter().zip(faces) {
+        *face_name.get_face_mut(config) = face.clone();
+    }
+}
+
+/// Writes the help of --help-colors: the faces with their colors in
+/// `config`, the grammar of --colors, and examples painted with the
+/// colors they set.
+pub fn write_colors_help<Stream>(config: &AppConfig, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let width = COLOR_SPEC_EXAMPLES
+        .iter()
+        .map(|spec| spec.len())
+        .max()
+        .unwrap_or(0);
+    writeln!(out, "The faces, with their current colors:")?;
+    writeln!(out)?;
+    for &(name, face_name) in FaceName::data() {
+        let face = face_name.get_face_mut(&mut config.clone()).clone();
+        write!(out, "    ")?;
+        super::output(name.as_bytes(), &face, out)?;
+        writeln!(out)?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "The syntax of the values of {} is the following:",
+        FLAG_COLOR
+    )?;
+    writeln!(out)?;
+    write!(out, color_spec_grammar!())?;
+    writeln!(out)?;
+    writeln!(out, "For example:")?;
+    writeln!(out)?;
+    for spec in COLOR_SPEC_EXAMPLES {
+        let mut example = config.clone();
+        let face = match parse_color_args(&mut example, std::iter::once(*spec)) {
+            Ok(()) => {
+                let face_name = spec.split(':').next().and_then(|name| tryparse(name).ok());
+                face_name.map(|face_name: FaceName| face_name.get_face_mut(&mut example).clone())
+            }
+            Err(_) => None,
+        };
+        write!(out, "    {:width$}  ", spec, width = width)?;
+        super::output(b"sample", &face.unwrap_or_default(), out)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+pub fn line_numbers(matches: &ArgMatches) -> Option<LineNumberStyle> {
+    if !matches.is_present(FLAG_LINE_NUMBERS) && !matches.is_present(FLAG_LINE_NUMBERS_FORMAT) {
+        return None;
+    }
+    match matches.value_of(FLAG_LINE_NUMBERS) {
+        Some(style) => tryparse(style).ok(),
+        None => Some(LineNumberStyle::Compact),
+    }
+}
+
+/// Selects whether colors are written, with the conventions of the
+/// CLICOLOR_FORCE and NO_COLOR environment variables for 'auto'.
+pub fn color_choice(matches: &ArgMatches) -> ColorChoice {
+    let is_set = |var| env::var_os(var).is_some_and(|value| !value.is_empty() && value != "0");
+    match matches.value_of(FLAG_COLOR_WHEN) {
+        Some("never") => ColorChoice::Never,
+        Some("auto") if is_set("CLICOLOR_FORCE") => ColorChoice::Always,
+        Some("auto") if is_set("NO_COLOR") || !is(Stream::Stdout) => ColorChoice::Never,
+        Some("auto") => ColorChoice::Auto,
+        _ => ColorChoice::Always,
+    }
+}
+
+/// Selects whether colors are written to the file of --output-file
+/// or --tee, which is not a terminal.
+pub fn file_colors(matches: &ArgMatches) -> bool {
+    match matches.value_of(FLAG_COLOR_WHEN) {
+        Some("never") => false,
+        Some("auto") => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty() || value == "0")
+        }
+        _ => true,
+    }
+}
+
+/// The file written with --output-file or --tee, and whether the
+/// output is also displayed.
+pub fn output_file(matches: &ArgMatches) -> Option<(String, bool)> {
+    match (
+        matches.value_of(FLAG_OUTPUT_FILE),
+        matches.value_of(FLAG_TEE),
+    ) {
+        (Some(path), _) => Some((path.to_owned(), false)),
+        (None, Some(path)) => Some((path.to_owned(), true)),
+        (None, None) => None,
+    }
+}
+
+/// Collects the values of a flag that may be provided multiple
+/// times.
+pub fn values(matches: &ArgMatches, flag: &str) -> Vec<String> {
+    match matches.values_of(flag) {
+        Some(values) => values.map(str::to_owned).collect(),
+        None => vec![],
+    }
+}
+
+/// The globs of the generated files: the built-in ones and the ones
+/// of --generated, unless --expand-generated is given.
+pub fn generated_paths(matches: &ArgMatches) -> Vec<String> {
+    if matches.is_present(FLAG_EXPAND_GENERATED) {
+        return vec![];
+    }
+    let defaults = filter::DEFAULT_GENERATED
+        .iter()
+        .map(|&glob| glob.to_owned());
+    defaults.chain(values(matches, FLAG_GENERATED)).collect()
+}
+
+/// The diff files of --three-way, the last ones if the option is
+/// given several times.
+pub fn three_way(matches: &ArgMatches) -> Option<(String, String)> {
+    let mut files = matches.values_of(FLAG_THREE_WAY)?.rev().map(str::to_owned);
+    let theirs = files.next()?;
+    Some((files.next()?, theirs))
+}
+
+/// The patch and the directory of --apply-preview.
+pub fn apply_preview(matches: &ArgMatches) -> Option<(String, String)> {
+    let mut values = matches.values_of(FLAG_APPLY_PREVIEW)?.map(str::to_owned);
+    let patch = values.next()?;
+    Some((patch, values.next().unwrap_or_else(|| ".".to_owned())))
+}
+
+/// The command of --watch: the arguments after '--', or an empty
+/// command to run git diff with --git. Checks that the arguments after
+/// '--' are used.
+pub fn watch_command(matches: &ArgMatches) -> Result<Option<Vec<String>>, String> {
+    let args = values(matches, ARG_GIT_PATHS);
+    match (matches.is_present(FLAG_WATCH), matches.is_present(FLAG_GIT)) {
+        (true, true) => Ok(Some(vec![])),
+        (true, false) if args.is_empty() => Err(format!(
+            "{} needs a command after '--', or --git",
+            FLAG_WATCH
+        )),
+        (true, false) => Ok(Some(args)),
+        (false, false) if !args.is_empty() => Err(format!(
+            "the arguments after '--' need {} or {}",
+            FLAG_GIT, FLAG_WATCH
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Compiles the regexes given to a flag that may be provided multiple
+/// times.
+pub fn regexes(matches: &ArgMatches, flag: &str) -> Result<Vec<Regex>, regex::Error> {
+    values(matches, flag)
+        .iter()
+        .map(|pattern| Regex::new(pattern))
+        .collect()
+}
+
+pub fn output_format(matches: &ArgMatches) -> OutputFormat {
+    if matches.is_present(FLAG_EMIT_TEST_EXPECTATION) {
+        return OutputFormat::Expectation;
+    }
+    matches
+        .value_of(FLAG_OUTPUT)
+        .and_then(|format| tryparse(format).ok())
+        .unwrap_or(OutputFormat::Terminal)
+}
+
+/// Parses the name of an output format, as the value of --output.
+pub fn parse_output_format(name: &str) -> Result<OutputFormat, String> {
+    tryparse(name)
+}
+
+pub fn commit_format(matches: &ArgMatches) -> CommitFormat {
+    matches
+        .value_of(FLAG_COMMIT_FORMAT)
+        .and_then(|format| tryparse(format).ok())
+        .unwrap_or(CommitFormat::Full)
+}
+
+pub fn rename_format(matches: &ArgMatches) -> RenameFormat {
+    matches
+        .value_of(FLAG_RENAME_FORMAT)
+        .and_then(|format| tryparse(format).ok())
+        .unwrap_or(RenameFormat::Full)
+}
+
+pub fn background(matches: &ArgMatches) -> Option<Background> {
+    if matches.is_present(FLAG_LIGHT) {
+        Some(Background::Light)
+    } else if matches.is_present(FLAG_DARK) {
+        Some(Background::Dark)
+    } else {
+        None
+    }
+}
+
+pub fn theme(matches: &ArgMatches) -> Theme {
+    matches
+        .value_of(FLAG_THEME)
+        .and_then(|theme| tryparse(theme).ok())
+        .unwrap_or(Theme::Default)
+}
+
+pub fn timestamps(matches: &ArgMatches) -> Timestamps {
+    matches
+        .value_of(FLAG_TIMESTAMPS)
+        .and_then(|timestamps| tryparse(timestamps).ok())
+        .unwrap_or(Timestamps::Keep)
+}
+
+pub fn refine_granularity(matches: &ArgMatches) -> Granularity {
+    matches
+        .value_of(FLAG_REFINE)
+        .and_then(|granularity| tryparse(granularity).ok())
+        .unwrap_or(Granularity::Word)
+}
+
+pub fn debug_format(matches: &ArgMatches) -> DebugFormat {
+    matches
+        .value_of(FLAG_DEBUG)
+        .and_then(|format| tryparse(format).ok())
+        .unwrap_or(DebugFormat::Text)
+}
+
+pub fn redact_style(matches: &ArgMatches) -> RedactStyle {
+    matches
+        .value_of(FLAG_REDACT_STYLE)
+        .and_then(|style| tryparse(style).ok())
+        .unwrap_or(RedactStyle::Block)
+}
+
+pub fn binary(matches: &ArgMatches) -> BinaryMode {
+    matches
+        .value_of(FLAG_BINARY)
+        .and_then(|mode| tryparse(mode).ok())
+        .unwrap_or(BinaryMode::Summary)
+}
+
+pub fn sort_files(matches: &ArgMatches) -> Option<SortFiles> {
+    matches
+        .value_of(FLAG_SORT_FILES)
+        .and_then(|order| tryparse(order).ok())
+}
+
+pub fn algorithm(matches: &ArgMatches) -> Algorithm {
+    matches
+        .value_of(FLAG_ALGORITHM)
+        .and_then(|algorithm| tryparse(algorithm).ok())
+        .unwrap_or(Algorithm::Myers)
+}
+
+pub fn lang(matches: &ArgMatches) -> Option<Lang> {
+    matches
+        .value_of(FLAG_LANG)
+        .and_then(|lang| tryparse(lang).ok())
+}
+
+/// The maximal number of context lines displayed in a row, if
+/// --collapse-context is given.
+pub fn collapse_context(matches: &ArgMatches) -> Option<usize> {
+    if !matches.is_present(FLAG_COLLAPSE_CONTEXT) {
+        return None;
+    }
+    Some(parsed_value(matches, FLAG_COLLAPSE_CONTEXT).unwrap_or(DEFAULT_COLLAPSE_CONTEXT))
+}
+
+/// Parses the value of a flag, that was checked by a validator.
+pub fn parsed_value<T: FromStr>(matches: &ArgMatches, flag: &str) -> Option<T> {
+    matches.value_of(flag).and_then(|value| value.parse().ok())
+}
+
+/// The maximal cost of refining a hunk, None if unlimited.
+pub fn max_refine_cost(matches: &ArgMatches) -> Option<usize> {
+    match parsed_value(matches, FLAG_MAX_REFINE_COST) {
+        Some(0) => None,
+        Some(cost) => Some(cost),
+        None => Some(DEFAULT_MAX_REFINE_COST),
+    }
+}
+
+/// The maximal size of the cache of --cache in bytes, None without
+/// cache.
+pub fn cache(matches: &ArgMatches) -> Option<u64> {
+    if !matches.is_present(FLAG_CACHE) {
+        return None;
+    }
+    let mib = parsed_value(matches, FLAG_CACHE).unwrap_or(cache::DEFAULT_MAX_SIZE_MIB);
+    Some(mib << 20)
+}
+
+fn is_number(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("'{}' is not a number", value)),
+    }
+}
+
+fn is_tab_width(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if 0 < n => Ok(()),
+        _ if value == "keep" => Ok(()),
+        _ => Err(format!(
+            "'{}' is neither a positive number nor 'keep'",
+            value
+        )),
+    }
+}
+
+fn is_score(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(score) if (0.0..=1.0).contains(&score) => Ok(()),
+        _ => Err(format!("'{}' is not a score between 0 and 1", value)),
+    }
+}
+
+fn is_percentage(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if n <= 100 => Ok(()),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", value)),
+    }
+}
+
+/// Splits `input` in words like a POSIX shell: words are separated by
+/// whitespace, and quotes and backslashes escape the characters.
+pub fn split_shell_words(input: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                let escaped = chars.next().ok_or("trailing backslash")?;
+                word.get_or_insert_with(String::new).push(escaped)
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_owned()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c)
+                            }
+                            None => return Err("unterminated double quote".to_owned()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_owned()),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The arguments of the program, after the ones read from the
+/// DIFFR_OPTIONS environment variable and the ones of the selected
+/// profile.
+fn args() -> Vec<OsString> {
+    let mut args = env::args_os().collect::<Vec<_>>();
+    let mut nwords = 0;
+    if let Some(options) = env::var_os(ENV_OPTIONS) {
+        let options = options.to_string_lossy();
+        match split_shell_words(&options) {
+            Ok(words) => {
+                nwords = words.len();
+                ignore(args.splice(1..1, words.into_iter().map(OsString::from)))
+            }
+            Err(err) => {
+                eprintln!("error parsing {}: {}", ENV_OPTIONS, err);
+                std::process::exit(-1)
+            }
+        }
+    }
+    // the command line takes precedence over the profile
+    match profile::options(&args[1..]) {
+        Ok(words) => {
+            let at = 1 + nwords;
+            ignore(args.splice(at..at, words.into_iter().map(OsString::from)))
+        }
+        Err(err) => {
+            eprintln!("error reading the profile: {}", err);
+            std::process::exit(-1)
+        }
+    }
+    args
+}
+
 pub fn get_matches() -> ArgMatches<'static> {
+    app().get_matches_from(args())
+}
+
+/// The command line interface of diffr, also rendered by --man-page.
+pub fn app() -> App<'static, 'static> {
     App::new("diffr")
         .setting(AppSettings::UnifiedHelpMessage)
+        .setting(AppSettings::AllArgsOverrideSelf)
         .version("0.1.2")
         .author("Nathan Moreau <nathan.moreau@m4x.org>")
         .about(ABOUT)
         .usage(USAGE)
-        .arg(Arg::with_name(FLAG_DEBUG).long(FLAG_DEBUG).hidden(true))
         .arg(
-            Arg::with_name(FLAG_COLOR)
-                .long(FLAG_COLOR)
-                .value_name("COLOR_SPEC")
+            Arg::with_name(FLAG_DEBUG)
+                .long(FLAG_DEBUG)
+                .value_name("FORMAT")
                 .takes_value(true)
-                .multiple(true)
-                .number_of_values(1)
-                .help("Configure color settings.")
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["text", "json"])
+                .hidden(true),
+        )
+        .arg(
+            Arg::with_name(FLAG_BENCH)
+                .long(FLAG_BENCH)
+                .value_name("FILE")
+                .takes_value(true)
+                .hidden(true),
+        )
+        .arg(
+            Arg::with_name(FLAG_BENCH_RUNS)
+                .long(FLAG_BENCH_RUNS)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .requires(FLAG_BENCH)
+                .hidden(true),
+        )
+        .arg(
+            Arg::with_name(FLAG_EMIT_TEST_EXPECTATION)
+                .long(FLAG_EMIT_TEST_EXPECTATION)
+                .conflicts_with(FLAG_OUTPUT)
+                .hidden(true),
+        )
+        .arg(
+            Arg::with_name(FLAG_KEEP_INPUT_COLORS)
+                .long(FLAG_KEEP_INPUT_COLORS)
+                .help("Keep the colors of the input.")
                 .long_help(
-                    "Configure color settings for console ouput.
+                    "Keep the colors of the input.
 
-There are four faces to customize:
-+----------------+--------------+----------------+
-|  line prefix   |      +       |       -        |
-+----------------+--------------+----------------+
-| common segment |    added     |    removed     |
-| unique segment | refine-added | refine-removed |
-+----------------+--------------+----------------+
+By default, escape sequences found in the input are removed before
+processing. With this flag, lines that are not refined are written
+unchanged, and the colors of added and removed lines are used
+instead of the added and removed faces.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_PAGER)
+                .long(FLAG_PAGER)
+                .value_name("PAGER")
+                .takes_value(true)
+                .possible_values(&["internal", "none"])
+                .help("Display the output in a pager.")
+                .long_help(
+                    "Display the output in a pager.
 
-The customization allows
-- to change the foreground or background color;
-- to set or unset the attributes 'bold', 'intense', 'underline';
-- to clear all attributes.
+With 'internal', the output is displayed in a built-in pager when
+standard output is a terminal. The following keys are available:
+- 'j', 'k', up and down arrows scroll by one line;
+- space, 'b', page up and page down scroll by one page;
+- 'g' and 'G' jump to the start and the end of the output;
+- 'n' and 'p' jump to the next and previous file or hunk;
+- '/' searches forward, an empty search repeats the last one;
+- 'q' quits.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_TUI)
+                .long(FLAG_TUI)
+                .conflicts_with(FLAG_PAGER)
+                .help("Browse the diff with folded files and hunks.")
+                .long_help(
+                    "Browse the diff with folded files and hunks.
 
-Customization is done passing a color_spec argument.
-This flag may be provided multiple times.
+When standard output is a terminal, files and hunks are displayed
+as a tree. Hunks are folded, and their unchanged lines are folded
+when they are unfolded. The following keys are available:
+- 'j', 'k', up and down arrows move the cursor;
+- space, enter and tab fold or unfold the node under the cursor;
+- 'l' and 'h' unfold and fold the node under the cursor;
+- 'E' and 'C' unfold and fold all nodes;
+- 'q' quits.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_NAVIGATE)
+                .long(FLAG_NAVIGATE)
+                .conflicts_with(FLAG_TUI)
+                .help("Mark the files and hunks to jump between them in less.")
+                .long_help(
+                    "Mark the files and hunks to jump between them in less.
 
-The syntax is the following:
+The '+++' lines of the files and the '@@' lines of the hunks are
+preceded by a marker, 'Δ' by default, so that after searching '^Δ'
+in less, 'n' and 'N' jump from file to file and from hunk to hunk.
+The marker is not written with --pager=internal, which jumps between
+files and hunks with its own keys.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_NAVIGATE_MARKER)
+                .long(FLAG_NAVIGATE_MARKER)
+                .value_name("MARKER")
+                .takes_value(true)
+                .help("Mark the files and hunks with MARKER [default: 'Δ']."),
+        )
+        .arg(
+            Arg::with_name(FLAG_NAVIGATE_PROMPT)
+                .long(FLAG_NAVIGATE_PROMPT)
+                .help("Display options of less for the markers of --navigate.")
+                .long_help(
+                    "Display options of less for the markers of --navigate.
 
-color_spec = face-name + ':' + attributes
-attributes = <empty>
-           | attribute + ':' + attributes
-attribute  = ('foreground' | 'background') + ':' + color
-           | (<empty> | 'no') + ('bold' | 'intense' | 'underline')
-           | 'none'
-color      = 'none'
-           | [0-255]
-           | [0-255] + ',' + [0-255] + ',' + [0-255]
-           | ('black', 'blue', 'green', 'red',
-              'cyan', 'magenta', 'yellow', 'white')
+The options are written for the LESS environment variable: they make
+less keep the colors, search the first marker, and display the keys
+that jump between the markers in its prompt, as in:
+    LESS=\"$(diffr --navigate-prompt)\" git -c core.pager='diffr --navigate | less' log -p",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_LINE_NUMBERS)
+                .long(FLAG_LINE_NUMBERS)
+                .value_name("STYLE")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .possible_values(&["compact", "aligned", "both", "right-only"])
+                .help("Display line numbers.")
+                .long_help(
+                    "Display line numbers.
+
+The line numbers in the old and the new file are displayed side by
+side in a gutter in front of the lines of each hunk; a number is left
+blank if the line does not exist in the file.
+
+With 'compact' (the default), the gutter is as narrow as possible for
+each hunk. With 'aligned', it is at least 4 digits wide. With 'both',
+the old and the new columns are each as narrow as possible, so that
+the new numbers are not padded for the old ones. With 'right-only',
+only the line numbers in the new file are displayed, and the removed
+lines have a blank gutter.
+
+The hunks whose header has a function context, such as 'fn main()',
+start with a breadcrumb made of the path of the file and the function
+context.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_LINE_NUMBERS_SEPARATOR)
+                .long(FLAG_LINE_NUMBERS_SEPARATOR)
+                .value_name("SEPARATOR")
+                .takes_value(true)
+                .help("Separate line numbers from the lines with SEPARATOR [default: ' | ']."),
+        )
+        .arg(
+            Arg::with_name(FLAG_LINE_NUMBERS_WIDTH)
+                .long(FLAG_LINE_NUMBERS_WIDTH)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Make the columns of line numbers at least N digits wide."),
+        )
+        .arg(
+            Arg::with_name(FLAG_LINE_NUMBERS_FORMAT)
+                .long(FLAG_LINE_NUMBERS_FORMAT)
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .conflicts_with(FLAG_LINE_NUMBERS_SEPARATOR)
+                .help("Display line numbers in a gutter formatted with TEMPLATE.")
+                .long_help(
+                    "Display line numbers in a gutter formatted with TEMPLATE.
+
+'{old}' is replaced by the line number in the old file, and '{new}' by
+the line number in the new file, or by blanks if the line does not
+exist in the file. The numbers are aligned on the right in columns as
+wide as with the style of --line-numbers, unless an alignment, '<',
+'^' or '>', and a width follow a colon, as in '{old:>4} {new:<4} │ '.
+'{{' and '}}' stand for braces; the other fields and the unclosed
+braces are errors. The template replaces the separator of
+--line-numbers-separator.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_HYPERLINKS)
+                .long(FLAG_HYPERLINKS)
+                .help("Make line numbers and file headers hyperlinks.")
+                .long_help(
+                    "Make line numbers and file headers hyperlinks.
+
+Line numbers (or hunk headers without --line-numbers) and '+++' file
+headers are made into OSC 8 terminal hyperlinks to the corresponding
+line of the new file. Paths are relative to the current directory.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_HYPERLINK_FORMAT)
+                .long(FLAG_HYPERLINK_FORMAT)
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .requires(FLAG_HYPERLINKS)
+                .help("Format hyperlinks with TEMPLATE [default: 'file://{path}#{line}'].")
+                .long_help(
+                    "Format hyperlinks with TEMPLATE [default: 'file://{path}#{line}'].
+
+'{path}' is replaced by the absolute path of the file, and '{line}'
+by the line number. For example, 'vscode://file{path}:{line}' opens
+the line in Visual Studio Code.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REPO_URL)
+                .long(FLAG_REPO_URL)
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .help("Link line numbers and file headers to the hosted source at TEMPLATE.")
+                .long_help(
+                    "Link line numbers and file headers to the hosted source at TEMPLATE.
+
+The links are made as with --hyperlinks, in the terminal and in the
+HTML output, which becomes a page to share for review. '{path}' is
+replaced by the path of the file in the repository, '{line}' by the
+line number, and '{commit}' by the hash of the commit displayed by git
+log or git show, or HEAD. For example:
+
+    https://github.com/org/repo/blob/{commit}/{path}#L{line}",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_PATH)
+                .long(FLAG_PATH)
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only display the files matching GLOB.")
+                .long_help(
+                    "Only display the files matching GLOB.
+
+In GLOB, '*' matches any sequence of characters but '/', '**' any
+sequence of characters, and '?' any character. A GLOB without '/'
+is matched against the file name only, for instance '*.rs'.
+A summary line is displayed in place of each skipped file.
+This flag may be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_EXCLUDE)
+                .long(FLAG_EXCLUDE)
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Do not display the files matching GLOB.")
+                .long_help(
+                    "Do not display the files matching GLOB.
+
+The syntax of GLOB is described in the help of --path.
+This flag may be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_GENERATED)
+                .long(FLAG_GENERATED)
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Collapse the files matching GLOB, as generated files.")
+                .long_help(
+                    "Collapse the files matching GLOB, as generated files.
+
+The generated files, such as the lockfiles and the minified sources,
+are displayed as a single line with their numbers of added and
+removed lines, painted with the 'folded' face:
+
+    generated: Cargo.lock +120 -80
+
+The files named Cargo.lock, package-lock.json, npm-shrinkwrap.json,
+yarn.lock, pnpm-lock.yaml, poetry.lock, Pipfile.lock, Gemfile.lock,
+composer.lock, go.sum and flake.lock, and the files matching *.min.js
+and *.min.css, are collapsed without this flag. The syntax of GLOB is
+described in the help of --path. The output formats of --output that
+describe the diff, such as 'patch' or 'json', keep the generated
+files. This flag may be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_EXPAND_GENERATED)
+                .long(FLAG_EXPAND_GENERATED)
+                .help("Display the generated files instead of collapsing them.")
+                .long_help(
+                    "Display the generated files instead of collapsing them.
+
+The files of --generated, and the built-in generated files, are then
+displayed as the other files.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_STATS)
+                .long(FLAG_STATS)
+                .help("Display statistics after the diff.")
+                .long_help(
+                    "Display statistics after the diff.
+
+For each file, then for the whole diff, display the number of hunks,
+of added and removed lines, and of refined added and removed tokens,
+which tells how dense the changes are.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_DIFFSTAT)
+                .long(FLAG_DIFFSTAT)
+                .help("Display a histogram of the changed lines before the diff.")
+                .long_help(
+                    "Display a histogram of the changed lines before the diff.
+
+Like 'git diff --stat', display the number of changed lines of each
+file with a bar, colored with the added and removed faces.
+The whole input is read before anything is displayed.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_QUIET)
+                .long(FLAG_QUIET)
+                .help("Do not display the diff.")
+                .long_help(
+                    "Do not display the diff.
+
+The hunks are still refined, so that --stats and --diffstat display
+the same summary as without this flag, and --exit-code gives the
+same status; nothing else is displayed.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_EXIT_CODE)
+                .long(FLAG_EXIT_CODE)
+                .help("Exit with 1 if the input has changes, 0 otherwise.")
+                .long_help(
+                    "Exit with 1 if the input has changes, 0 otherwise.
+
+Like 'git diff --exit-code', the exit status tells whether the input
+contained hunks or binary file changes. If a hunk header could not
+be parsed, the exit status is 2.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_METRICS_FILE)
+                .long(FLAG_METRICS_FILE)
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with_all(&[FLAG_BENCH, FLAG_WATCH, FLAG_SERVE])
+                .help("Write the size of the changes to PATH.")
+                .long_help(
+                    "Write the size of the changes to PATH.
+
+Once the diff is rendered, the numbers of files, hunks, added and
+removed lines, tokens and refined tokens of the whole diff, and the
+time elapsed, are written to PATH, so that CI pipelines can record
+them without parsing the diff again. A PATH ending with '.prom' is
+written in the text format of Prometheus, and other ones as a JSON
+object.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REFINE_THRESHOLD)
+                .long(FLAG_REFINE_THRESHOLD)
+                .value_name("PERCENT")
+                .takes_value(true)
+                .validator(is_percentage)
+                .help("Do not refine lines that changed by more than PERCENT.")
+                .long_help(
+                    "Do not refine lines that changed by more than PERCENT.
+
+When more than PERCENT of the characters of an added or removed line
+would be highlighted, the line is displayed with the added or removed
+face only, as highlighting almost everything is just noise.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_MAX_REFINE_COST)
+                .long(FLAG_MAX_REFINE_COST)
+                .value_name("COST")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Do not refine hunks costing more than COST [default: 100000000].")
+                .long_help(
+                    "Do not refine hunks costing more than COST [default: 100000000].
+
+The cost of a hunk is the number of its added tokens times the number
+of its removed tokens. Refining huge hunks, such as minified or
+generated files, takes a long time: above COST, the lines of the hunk
+are displayed with the added and removed faces only. With --debug, the
+skipped hunks are reported on stderr. 0 removes the limit.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_CACHE)
+                .long(FLAG_CACHE)
+                .value_name("MAX_SIZE")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .validator(is_number)
+                .help("Cache the refinement of the hunks on disk [default: 64 MiB].")
+                .long_help(
+                    "Cache the refinement of the hunks on disk [default: 64 MiB].
+
+The refinement of each hunk is stored in the 'diffr' directory of
+$XDG_CACHE_HOME, ~/.cache by default, and read again when the same
+hunk is refined with the same options, such as when the same large
+diff is displayed again with --watch or by git. Once the hunks are
+refined, the entries used the least recently are removed until the
+cache takes at most MAX_SIZE MiB.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ASCII_TOKENIZE)
+                .long(FLAG_ASCII_TOKENIZE)
+                .help("Split lines in words byte per byte.")
+                .long_help(
+                    "Split lines in words byte per byte.
+
+By default, non-ASCII text is split in words at Unicode word boundaries,
+so that multi-byte characters are highlighted as a whole. With this
+flag, only ASCII letters, digits and underscores form words, and other
+bytes are compared one by one, which is faster.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_INVALID_BYTES)
+                .long(FLAG_INVALID_BYTES)
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["passthrough", "hex"])
+                .help("Display bytes that are not text as is, or escaped [default: passthrough].")
+                .long_help(
+                    "Display bytes that are not text as is, or escaped [default: passthrough].
+
+With 'passthrough', the input bytes are written unchanged, whatever
+their encoding. With 'hex', bytes that are not valid UTF-8 and control
+characters, such as NUL, are written as hexadecimal escapes like
+'\\xff'.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_TABS)
+                .long(FLAG_TABS)
+                .value_name("WIDTH")
+                .takes_value(true)
+                .validator(is_tab_width)
+                .help("Expand tabs to WIDTH columns in hunks [default: keep].")
+                .long_help(
+                    "Expand tabs to WIDTH columns in hunks [default: keep].
+
+Tabs of the lines of hunks are replaced by spaces up to the next
+multiple of WIDTH columns, counted from the character after the
+leading '+', '-' or ' ', so that the lines stay aligned whatever the
+width of the line numbers. With 'keep', tabs are written unchanged.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_WORD_DIFF)
+                .long(FLAG_WORD_DIFF)
+                .conflicts_with(FLAG_LINE_NUMBERS)
+                .help("Merge the removed and added lines of hunks.")
+                .long_help(
+                    "Merge the removed and added lines of hunks.
+
+Like 'git diff --word-diff=color', each block of changed lines is
+written once, without the leading '+' and '-': the removed words are
+written with the removed face, and the added words with the added face.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_PROFILE)
+                .long(FLAG_PROFILE)
+                .value_name("NAME")
+                .takes_value(true)
+                .help("Use the options of the profile NAME of the configuration file.")
+                .long_help(
+                    "Use the options of the profile NAME of the configuration file.
+
+The configuration file is 'diffr/config' in $XDG_CONFIG_HOME, or in
+~/.config, or in %APPDATA% on Windows. Each profile is a section of
+options, written as in DIFFR_OPTIONS:
+
+    [profile.review]
+    --line-numbers --hyperlinks
+
+    [profile.presentation]
+    --theme deuteranopia --line-numbers aligned
+    --max-line-length 100
+
+The options of the profile come after the ones of DIFFR_OPTIONS and
+before the ones of the command line. Without this flag, the profile is
+named by the DIFFR_PROFILE environment variable, if set.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COLORS_FROM_GIT)
+                .long(FLAG_COLORS_FROM_GIT)
+                .help("Read the faces from the configuration of git.")
+                .long_help(
+                    "Read the faces from the configuration of git.
+
+The added and removed faces are read from 'color.diff.new' and
+'color.diff.old', using the color syntax of git. Each face can also be
+set with a 'diffr.<face-name>' variable, using the syntax of --colors:
+
+    git config diffr.refine-added background:blue:bold
+
+--colors takes precedence over the configuration of git.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COLOR_WHEN)
+                .long(FLAG_COLOR_WHEN)
+                .value_name("WHEN")
+                .takes_value(true)
+                .possible_values(&["always", "auto", "never"])
+                .help(
+                    "Write colors always, never, or when writing to a terminal [default: always].",
+                )
+                .long_help(
+                    "Write colors always, never, or when writing to a terminal [default: always].
+
+Colors are written by default, as diffr usually writes to a pager. With
+'auto', colors are written when the output is a terminal, or when the
+CLICOLOR_FORCE environment variable is set, but not when the NO_COLOR
+environment variable is set. With 'never', the escape codes of
+--keep-input-colors and --hyperlinks are not written either.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_MAX_LINE_LENGTH)
+                .long(FLAG_MAX_LINE_LENGTH)
+                .value_name("COLUMNS")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Truncate the lines of hunks after COLUMNS columns.")
+                .long_help(
+                    "Truncate the lines of hunks after COLUMNS columns.
+
+The lines of hunks longer than COLUMNS, counting the leading '+', '-'
+or ' ', are truncated and followed by the truncation marker. The
+truncated ends of lines are not refined, but the lines are still
+counted by --stats and --diffstat.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_TRUNCATION_MARKER)
+                .long(FLAG_TRUNCATION_MARKER)
+                .value_name("MARKER")
+                .takes_value(true)
+                .requires(FLAG_MAX_LINE_LENGTH)
+                .help("Mark truncated lines with MARKER [default: '…']."),
+        )
+        .arg(
+            Arg::with_name(FLAG_RULER)
+                .long(FLAG_RULER)
+                .value_name("COLUMN")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Draw a guide after the column COLUMN of the lines of hunks.")
+                .long_help(
+                    "Draw a guide after the column COLUMN of the lines of hunks.
+
+The guide, a '│' painted with the 'ruler' face (dim by default), is
+drawn after the column COLUMN of the content of the lines of hunks,
+not counting the leading '+', '-' or ' ' nor the gutter, so that the
+lines longer than a limit stand out; it is not drawn on these lines.
+With the 'overlong' face set, e.g. with --colors
+overlong:background:red, the characters of the added lines beyond
+COLUMN are painted with it.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COLLAPSE_CONTEXT)
+                .long(FLAG_COLLAPSE_CONTEXT)
+                .value_name("N")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .validator(is_number)
+                .help("Fold the runs of more than N context lines [default: 6].")
+                .long_help(
+                    "Fold the runs of more than N context lines [default: 6].
+
+Inside hunks, the runs of more than N unchanged lines are replaced
+by a marker giving the number of hidden lines, displayed with the
+'folded' face. At most N lines of each run are kept next to the
+changes; a single hidden line is displayed instead of its marker.
+The value must be given as --collapse-context=N.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ONLY_CHANGES)
+                .long(FLAG_ONLY_CHANGES)
+                .help("Hide the context lines of the hunks.")
+                .long_help(
+                    "Hide the context lines of the hunks.
+
+Only the headers of the files and hunks, and the added and removed
+lines, are displayed, with their line numbers. The refinement of the
+changed lines is not affected. This gives a dense view of large
+diffs, such as the updates of dependencies.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_MERGE_HUNKS)
+                .long(FLAG_MERGE_HUNKS)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .conflicts_with(FLAG_APPLY_PREVIEW)
+                .help("Merge the hunks separated by fewer than N unchanged lines.")
+                .long_help(
+                    "Merge the hunks separated by fewer than N unchanged lines.
+
+The hunks of a file separated by fewer than N lines that are not part
+of the diff are displayed as a single hunk, with a header covering
+them all, and these lines folded, as with --collapse-context, so that
+the related changes read as a block. The changed lines of the merged
+hunks are refined together.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SPLIT_HUNKS)
+                .long(FLAG_SPLIT_HUNKS)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .conflicts_with(FLAG_WORD_DIFF)
+                .help("Split the hunks into chunks of at least N lines.")
+                .long_help(
+                    "Split the hunks into chunks of at least N lines.
+
+A long hunk is displayed as chunks of at least N lines, each after a
+header with its line ranges, so that it is reviewed a screen at a time.
+A chunk ends after an unchanged line that ends a block: a blank line,
+or a line whose closing brace balances the braces opened in the chunk.
+The hunks are still refined as a whole, and the output formats that
+describe the diff are not split.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ANNOTATIONS)
+                .long(FLAG_ANNOTATIONS)
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Display the messages of the JSON file FILE under their lines.")
+                .long_help(
+                    "Display the messages of the JSON file FILE under their lines.
+
+FILE holds an array of annotations, such as the diagnostics of a
+linter, converted to objects such as:
+
+{\"path\": \"src/main.rs\", \"new_line\": 12, \"severity\": \"warning\",
+ \"message\": \"unused variable: `x`\"}
+
+The message of an annotation is displayed under the line 'new_line'
+of the new version of the file 'path', if the diff shows it, painted
+with the 'annotation-error', 'annotation-warning' or 'annotation-note'
+face after its 'severity'. The severities other than 'error' and
+'warning', and a missing one, are notes.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ANNOTATIONS_SARIF)
+                .long(FLAG_ANNOTATIONS_SARIF)
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Display the results of the SARIF log FILE under their lines.")
+                .long_help(
+                    "Display the results of the SARIF log FILE under their lines.
+
+The results of static analyzers in the SARIF format are displayed as
+with --annotations, with the id of their rule, under the first line of
+the region of each of their locations. The 'file:' URIs of the
+locations are made relative to the current directory. The level of a
+result selects its face; 'none' is displayed as a note. This option
+can be combined with --annotations.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_INDENT_CHANGED)
+                .long(FLAG_INDENT_CHANGED)
+                .help("Subdue the lines whose indentation only changed.")
+                .long_help(
+                    "Subdue the lines whose indentation only changed.
+
+In each block of removed lines followed by added lines, the removed
+and added lines that differ only by their leading spaces and tabs,
+as when a block is re-indented, are paired in order. They are not
+refined: their content is painted with the 'indent-changed' face
+(dim by default).",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_INDENT_DELTA)
+                .long(FLAG_INDENT_DELTA)
+                .requires(FLAG_INDENT_CHANGED)
+                .help("Follow the re-indented lines by the change of indentation.")
+                .long_help(
+                    "Follow the re-indented lines by the change of indentation.
+
+The added lines whose indentation only changed are followed by the
+change, such as '→ +4 spaces' or '→ 1 tab to 4 spaces'.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_RELOCATED)
+                .long(FLAG_RELOCATED)
+                .help("Subdue the lines moved unchanged within their hunk.")
+                .long_help(
+                    "Subdue the lines moved unchanged within their hunk.
+
+The removed and added lines of a hunk with the same content, as when
+blocks of lines are reordered, are paired. They are not refined:
+their content is painted with the 'relocated' face (dim by default),
+so that the review focuses on the lines that really changed. The
+blank lines are never paired.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_LANG)
+                .long(FLAG_LANG)
+                .value_name("LANG")
+                .takes_value(true)
+                .possible_values(&["auto", "rust", "python", "c", "json", "prose"])
+                .help("Tokenize the hunks with the rules of LANG.")
+                .long_help(
+                    "Tokenize the hunks with the rules of LANG.
+
+With 'rust', 'python' and 'c', operators such as '::', '->' or '+='
+are single tokens. With 'json', string literals are single tokens.
+With 'prose', words joined by an apostrophe or a hyphen are single
+tokens. With 'auto', the rules are selected from the extension of
+each file. For the files without known extension, such as scripts,
+they are selected from the shebang of the first line of the file, or
+from a modeline of Emacs ('-*- mode: python -*-') or Vim
+('vim: set ft=python:'), once read in the diff. Other files are
+tokenized as usual.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_IGNORE_CASE)
+                .long(FLAG_IGNORE_CASE)
+                .help("Match the tokens that differ only by case.")
+                .long_help(
+                    "Match the tokens that differ only by case.
+
+The tokens are compared in lower case when refining hunks, so that
+'SELECT' and 'select' are shared. Only ASCII letters are folded. The
+tokens differing by case are painted like other shared tokens, unless
+the 'case-changed' face is set with --colors.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ALGORITHM)
+                .long(FLAG_ALGORITHM)
+                .value_name("ALGORITHM")
+                .takes_value(true)
+                .possible_values(&["myers", "patience", "histogram"])
+                .help("Refine the hunks with ALGORITHM [default: myers].")
+                .long_help(
+                    "Refine the hunks with ALGORITHM [default: myers].
+
+'myers' computes a longest common subsequence of the tokens of the
+removed and added lines, which may match frequent tokens such as
+blanks or brackets far from their origin. 'patience' first matches
+the tokens occurring once on both sides, and 'histogram' the runs
+of shared tokens containing the least frequent ones; the remaining
+tokens are then compared with 'myers' between those anchors.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_OUTPUT)
+                .long(FLAG_OUTPUT)
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&[
+                    "terminal",
+                    "markdown",
+                    "markdown-html",
+                    "svg",
+                    "html",
+                    "quickfix",
+                    "sexp",
+                    "json",
+                    "json-stream",
+                    "annotated",
+                    "patch",
+                ])
+                .help("Write the output in FORMAT [default: terminal].")
+                .long_help(
+                    "Write the output in FORMAT [default: terminal].
+
+With 'markdown', each file is written as a fenced 'diff' code block,
+without faces, ready to paste in a comment. With 'markdown-html', each
+file is written as a <pre> block where the faces are <span> elements
+with inline styles, for the platforms that allow inline HTML. With
+'svg', the output is an image of a terminal with a monospace font,
+where each character takes one cell. With 'html', the output is a
+standalone HTML page where the faces are <span> elements. With
+'quickfix', each block of changed lines is written as a line
+'path:line: summary', where line is a line of the new file, for the
+quickfix list of Vim or the compilation mode of Emacs. With 'sexp',
+each hunk is written on a line as an s-expression
+
+    (hunk :old-path \"a.rs\" :path \"a.rs\" :old-start 1 :old-len 1
+     :new-start 1 :new-len 1
+     :lines ((removed \"a b\" (2 . 3)) (added \"a c\" (2 . 3))))
+
+where the refined spans of each line follow its content, as character
+offsets, for editors that apply their own faces. With 'json', the hunks
+are written as an array of objects with the same fields; the refined
+spans are also given as byte offsets in the UTF-8 content, and as
+display columns, where tabs stop every 8 columns and the wide East
+Asian characters take 2 columns. Each hunk also has its 'edits', the
+'insert', 'delete' and 'replace' operations of the refinement that turn
+its removed lines into its added lines, with their old and new text and
+its range, as the index of a line of the hunk and a character offset,
+at its start and at its end. With 'json-stream', a line of JSON is
+written for each event as soon as the diff is read up to it, for the
+frontends that display huge diffs progressively: 'file-start' with the
+paths of a file, 'hunk-start' with the header of a hunk, 'line' with
+the kind, the old and new line numbers and the content of a line, and
+'span' after a line for each of its refined spans, in characters, in
+bytes and in columns, then 'file-end'. With 'annotated', the diff is written
+without faces, and the refined spans are written between '[-' and '-]'
+in the removed lines and between '{+' and '+}' in the added lines, for
+the terminals without colors and for tests. With 'patch', the lines of
+the diff are written as in the input, without faces nor the
+transformations that only change how they are displayed, such as the
+gutters, the folds, --merge-hunks or --strip-prefix, so that the
+output applies with 'git apply' or 'patch' as the input does, when
+diffr is inserted in a pipeline; --redact is an error. The files and
+hunks filtered out, by --path or --grep for instance, are still left
+out.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COMMIT_FORMAT)
+                .long(FLAG_COMMIT_FORMAT)
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["full", "short", "oneline", "none"])
+                .help("Display the headers of commits in FORMAT [default: full].")
+                .long_help(
+                    "Display the headers of commits in FORMAT [default: full].
+
+In the output of 'git log -p', each commit starts with a header made
+of a 'commit' line, metadata lines such as 'Author:' and 'Date:', and
+the indented message. With 'full', the header is displayed as read.
+With 'short', only the 'commit' line, the metadata lines other than
+dates, and the first line of the message are displayed. With
+'oneline', the abbreviated hash and the first line of the message
+are displayed on a single line. With 'none', the header is hidden.
+
+The patches of 'git format-patch' are mails: their header is made of
+a 'From <hash>' line, mail headers such as 'Subject:', and the message
+up to the '---' line; the header of each mail is displayed as the one
+of a commit, and its '-- ' signature is hidden.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COMPACT_HEADERS)
+                .long(FLAG_COMPACT_HEADERS)
+                .help("Summarize the mode changes and renames on one line.")
+                .long_help(
+                    "Summarize the mode changes and renames on one line.
+
+The extended header lines of each file, such as 'old mode' and 'new
+mode', or 'similarity index', 'rename from' and 'rename to', are
+replaced by a single line such as 'mode 100644 => 100755' or 'rename
+a.c => b.c (90%)'.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SHOW_CR)
+                .long(FLAG_SHOW_CR)
+                .help("Display the carriage returns of the line ends as '^M'.")
+                .long_help(
+                    "Display the carriage returns of the line ends as '^M'.
+
+The carriage returns of the '\\r\\n' line ends of the hunks are never
+refined: a line is diffed as the same line with a '\\n' line end.
+With this flag, they are displayed as '^M' with the 'carriage-return'
+face (dim by default), so that the changes of line ends are visible.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SHOW_NONPRINTING)
+                .long(FLAG_SHOW_NONPRINTING)
+                .help("Display the control and invisible characters of the hunks.")
+                .long_help(
+                    "Display the control and invisible characters of the hunks.
+
+The control characters are displayed as '^X', such as '^[' for a lone
+escape or '^?' for a delete, and the invisible Unicode characters as
+'<U+XXXX>', such as '<U+200B>' for a zero width space, '<U+FEFF>' for
+a byte order mark or '<U+202E>' for a bidirectional control. They are
+painted with the 'nonprinting' face (bold yellow by default), so that
+the lines changed only by such characters do not look identical.
+Tabs, line ends and the escape codes of colored input are kept.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_BINARY)
+                .long(FLAG_BINARY)
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["summary", "raw", "embed"])
+                .help("Summarize, keep or embed the binary files [default: summary].")
+                .long_help(
+                    "Summarize, keep or embed the binary files [default: summary].
+
+With 'summary', the 'Binary files A and B differ' lines and the
+encoded lines of the patches of git diff --binary are replaced by a
+line such as 'binary file logo.png: 1.2 KiB -> 3.4 KiB', painted with
+the 'binary' face (bold magenta by default). The sizes are only known
+from the 'literal' blocks of the patches. With 'raw', the lines are
+kept. With 'embed', the images found from the current directory, up
+to 1 MiB, are also displayed below their line in the HTML output.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_TIMESTAMPS)
+                .long(FLAG_TIMESTAMPS)
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["keep", "strip", "normalize"])
+                .help("Keep, strip or normalize the timestamps of the files [default: keep].")
+                .long_help(
+                    "Keep, strip or normalize the timestamps of the files [default: keep].
+
+The '---' and '+++' lines of the output of diff -u end with the
+modification times of the files, which differ on every run. With
+'strip', they are removed; with 'normalize', they are replaced by
+the epoch, so that stored outputs can be compared.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SORT_FILES)
+                .long(FLAG_SORT_FILES)
+                .value_name("ORDER")
+                .takes_value(true)
+                .possible_values(&["path", "changes", "extension"])
+                .help("Display the files of the diff in ORDER.")
+                .long_help(
+                    "Display the files of the diff in ORDER.
+
+The whole diff is read before it is displayed. With 'path', the files
+are sorted by path; with 'changes', the files with the most added and
+removed lines come first; with 'extension', the files are grouped by
+extension, in the order of the diff otherwise. The lines before the
+first file stay first, and the files of each commit of the output of
+git log are sorted separately.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_STRIP_PREFIX)
+                .long(FLAG_STRIP_PREFIX)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Remove the N leading components of the paths of the files.")
+                .long_help(
+                    "Remove the N leading components of the paths of the files.
+
+As the -p option of patch, the paths of the '---' and '+++' lines
+lose their N leading directories: with 1, 'a/src/main.rs' becomes
+'src/main.rs'. The last component of a path is always kept.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_RENAME_FORMAT)
+                .long(FLAG_RENAME_FORMAT)
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["full", "compact"])
+                .help("Display the renames and copies in FORMAT [default: full].")
+                .long_help(
+                    "Display the renames and copies in FORMAT [default: full].
+
+With 'full', the 'similarity index', 'rename from' and 'rename to'
+lines of a renamed file are displayed as read. With 'compact', they
+are replaced by a single line where the common leading and trailing
+directories of the paths are factored out, like in the diffstat of
+git, such as 'rename src/{old => new}/lib.rs (90%)'. The paths of
+--compact-headers are displayed in the same way.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_OUTPUT_FILE)
+                .long(FLAG_OUTPUT_FILE)
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with(FLAG_TEE)
+                .help("Write the output to the file at PATH.")
+                .long_help(
+                    "Write the output to the file at PATH.
+
+Unless --output is given, the format is selected from the extension
+of PATH: 'html' for .html and .htm, 'svg' for .svg, 'markdown' for
+.md, and 'terminal' otherwise. The file has colors unless
+--color=never is given, or --color=auto and NO_COLOR is set.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SPLIT_OUTPUT)
+                .long(FLAG_SPLIT_OUTPUT)
+                .value_name("DIR")
+                .takes_value(true)
+                .conflicts_with_all(&[FLAG_OUTPUT_FILE, FLAG_TEE, FLAG_SERVE, FLAG_WATCH])
+                .help("Write the output of each file of the diff to its own file in DIR.")
+                .long_help(
+                    "Write the output of each file of the diff to its own file in DIR.
+
+Each file is named after the path of the file of the diff, where the
+characters other than letters, digits, '.', '-' and '_' are replaced by
+'_', such as 'src_main.rs.html' for src/main.rs, with the extension of
+the format of --output: '.diff' for 'terminal', '.html' for 'html'...
+The lines before a file, such as the header of a commit, are written
+with it. With --output=html, an index.html page links to the files,
+with their numbers of added and removed lines. The files have colors
+unless --color=never is given, or --color=auto and NO_COLOR is set.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_TEE)
+                .long(FLAG_TEE)
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Write the output to the file at PATH, and display it.")
+                .long_help(
+                    "Write the output to the file at PATH, and display it.
+
+The file is written like with --output-file, and the output is also
+displayed as without this flag.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_NO_SLIDER)
+                .long(FLAG_NO_SLIDER)
+                .help("Do not move refined segments to natural boundaries.")
+                .long_help(
+                    "Do not move refined segments to natural boundaries.
+
+When words are only added or only removed, several segments are
+often equivalent: adding 'g(x), ' before 'g(y)' may highlight
+'x), g('. By default, the segment with matching brackets and quotes,
+and surrounded by blanks, is highlighted. This flag highlights the
+segment found by the LCS algorithm instead.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_NO_LINE_PAIRING)
+                .long(FLAG_NO_LINE_PAIRING)
+                .help("Do not pair similar lines before refining hunks.")
+                .long_help(
+                    "Do not pair similar lines before refining hunks.
+
+By default, each removed line is paired with the most similar added
+line, keeping their order, when they share at least half of their
+tokens; the tokens of paired lines are compared together, and the
+lines between the pairs as a block. This flag compares all the
+removed tokens of a hunk with all its added tokens instead, which
+may match the tokens of unrelated lines.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_PAIRING_THRESHOLD)
+                .long(FLAG_PAIRING_THRESHOLD)
+                .value_name("SCORE")
+                .takes_value(true)
+                .validator(is_score)
+                .conflicts_with_all(&[FLAG_NO_LINE_PAIRING, FLAG_REFINE_BLOCK])
+                .help("Pair the lines at least SCORE similar, and leave the others unrefined.")
+                .long_help(
+                    "Pair the lines at least SCORE similar, and leave the others unrefined.
+
+The similarity of two lines is the proportion of the bytes of their
+non-blank tokens that they share, in the same order, between 0 and 1;
+by default, the lines are paired from 0.5. With this flag, in the hunks whose numbers of removed
+and added lines differ, the lines without a counterpart at least SCORE
+similar are not compared, and are displayed without refinement, so
+that the changed lines stand out from the new and deleted ones.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REFINE_BLOCK)
+                .long(FLAG_REFINE_BLOCK)
+                .help("Refine each block of changed lines as a single stream of words.")
+                .long_help(
+                    "Refine each block of changed lines as a single stream of words.
+
+The removed lines of each run of changed lines are compared with its
+added lines as a whole, ignoring the blanks, and the shared words are
+mapped back to their lines. This helps when a change is split across
+wrapped lines, such as a reflowed paragraph of a comment, where the
+words move from a line to another. The lines are not paired, and
+the blanks between two shared words are shared when both sides have
+as many.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REFINE)
+                .long(FLAG_REFINE)
+                .value_name("GRANULARITY")
+                .takes_value(true)
+                .possible_values(&["word", "char", "auto"])
+                .help("Refine the words or the characters of the changed lines [default: word].")
+                .long_help(
+                    "Refine the words or the characters of the changed lines [default: word].
+
+With 'word', the tokens compared are words, numbers, blanks and
+punctuation marks. With 'char', they are the characters, so that a
+one-character edit is highlighted alone, at the cost of a slower
+refinement and of highlights scattered over the unrelated lines.
+With 'auto', the hunks whose changed lines are all at most
+--refine-char-width characters long are refined by character, and
+the other ones by word.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REFINE_CHAR_WIDTH)
+                .long(FLAG_REFINE_CHAR_WIDTH)
+                .value_name("WIDTH")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Refine by character the hunks with lines of at most WIDTH characters [default: 40].")
+                .long_help(
+                    "Refine by character the hunks with lines of at most WIDTH characters [default: 40].
+
+With --refine=auto, the hunks whose changed lines are all at most
+WIDTH characters long, without their +/- prefix, are refined by
+character.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_IGNORE_MATCHING_LINES)
+                .long(FLAG_IGNORE_MATCHING_LINES)
+                .value_name("REGEX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("De-emphasize the hunks whose changed lines all match REGEX.")
+                .long_help(
+                    "De-emphasize the hunks whose changed lines all match REGEX.
+
+Like the -I option of GNU diff, the hunks whose added and removed
+lines all match one of the given regexes, such as timestamps, version
+strings or generated identifiers, are not refined: their changed
+lines are painted with the 'ignored' face (dim by default). The
+regexes are matched against the lines without their +/- prefix, with
+the syntax of the regex crate (https://docs.rs/regex). This flag may
+be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_GREP)
+                .long(FLAG_GREP)
+                .value_name("REGEX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Display only the hunks with a changed line matching REGEX.")
+                .long_help(
+                    "Display only the hunks with a changed line matching REGEX.
+
+The hunks whose added or removed lines match none of the given
+regexes are dropped, along with the headers of the files left
+without hunks. The matches in the changed lines are painted with the
+'search-match' face (bold black on yellow by default). The syntax of
+the regexes is the one of --ignore-matching-lines. This flag may be
+provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_IGNORE_TOKEN)
+                .long(FLAG_IGNORE_TOKEN)
+                .value_name("REGEX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Compare the tokens matching REGEX as equal.")
+                .long_help(
+                    "Compare the tokens matching REGEX as equal.
+
+The tokens of the changed lines inside a match of one of the given
+regexes, such as UUIDs, hashes or timestamps, are compared as equal
+during the refinement, so that the generated identifiers do not
+dominate the highlights; the matches are painted with the 'masked'
+face (dim by default). The syntax of the regexes is the one of
+--ignore-matching-lines. This flag may be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REDACT)
+                .long(FLAG_REDACT)
+                .value_name("REGEX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Replace the text matching REGEX in the output.")
+                .long_help(
+                    "Replace the text matching REGEX in the output.
+
+The matches of the given regexes in each line of the output, headers
+included, are replaced as --redact-style says, after the refinement:
+the structure of the diff and the highlights of the hunks are kept,
+so that a diff of private code can be shared, e.g. in a bug report.
+The syntax of the regexes is the one of --ignore-matching-lines. This
+flag may be provided multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_REDACT_STYLE)
+                .long(FLAG_REDACT_STYLE)
+                .value_name("STYLE")
+                .takes_value(true)
+                .possible_values(&["block", "hash"])
+                .help("Replace the matches of --redact by blocks or by hashes [default: block].")
+                .long_help(
+                    "Replace the matches of --redact by blocks or by hashes [default: block].
+
+With 'block', each character of a match is replaced by a '█', so
+that the highlights keep their place in the line. With 'hash', a match
+is replaced by '#' and 8 hexadecimal digits hashed from its text, so
+that the equal matches, such as the occurrences of an identifier, can
+be told apart from the other ones.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_BLAME)
+                .long(FLAG_BLAME)
+                .help("Annotate the removed lines with the commit that introduced them.")
+                .long_help(
+                    "Annotate the removed lines with the commit that introduced them.
+
+A gutter column, painted with the 'blame' face (dim by default), shows
+the abbreviated hash and the author of the commit that last changed
+each removed line, from 'git blame' on the old version of the file:
+the first revision given to --git, or HEAD. The other lines have a
+blank gutter. The files unknown to the git repository of the current
+directory, such as the untracked files, have no gutter.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_LENIENT)
+                .long(FLAG_LENIENT)
+                .help("Find the end of hunks from the line counts of their headers.")
+                .long_help(
+                    "Find the end of hunks from the line counts of their headers.
+
+The output of some tools (svn diff, hg diff, quilt, diff -u of several
+files) has no 'diff' line between files, so the '---' line of a file
+may be taken for a removed line of the previous hunk. With this flag,
+a hunk ends after the number of lines given by its header, and empty
+lines inside a hunk, left by tools stripping trailing whitespace, are
+taken as empty context lines.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_STRICT)
+                .long(FLAG_STRICT)
+                .help("Fail on the hunks that do not match their headers.")
+                .long_help(
+                    "Fail on the hunks that do not match their headers.
+
+A hunk whose numbers of old and new lines differ from the counts of its
+header, such as a hunk of a patch edited by hand, is reported on the
+standard error with the line of its header in the input, and is
+displayed without refinement, since its lines may be paired wrongly;
+the next hunks are displayed as usual. With this flag, such a hunk is
+an error, and diffr stops at it.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_GIT)
+                .long(FLAG_GIT)
+                .value_name("REVISIONS")
+                .takes_value(true)
+                .help("Display the output of git diff REVISIONS.")
+                .long_help(
+                    "Display the output of git diff REVISIONS, such as 'main..topic'.
+
+Runs git diff in place of reading the standard input. The globs of
+--path and --exclude are forwarded to git as pathspecs, as well as
+the paths given after '--'.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_CONTEXT)
+                .long(FLAG_CONTEXT)
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_number)
+                .help("Display N lines of context around changes.")
+                .long_help(
+                    "Display N lines of context around changes.
+
+The hunks of the input are re-sliced: the context lines farther than
+N lines from a change are dropped, and a hunk is split where more
+than 2N context lines separate two changes. Lines missing from the
+input cannot be added back, unless --resolve-context is given. With
+--git, N is given to git diff.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_RESOLVE_CONTEXT)
+                .long(FLAG_RESOLVE_CONTEXT)
+                .requires(FLAG_CONTEXT)
+                .help("Read the context lines missing from the input from git.")
+                .long_help(
+                    "Read the context lines missing from the input from git.
+
+With --context N, the hunks with fewer than N context lines around
+their changes are extended with the lines of their file, read with
+'git cat-file' from the blobs named by the 'index' line of its git
+header: the new blob, or the old one if git does not know it, as for
+the files of the working tree. The hunks are extended up to the hunks
+around them, and are left as read when git knows neither blob, or when
+the diff has no 'index' lines.",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_INPUTS)
+                .value_name("FILE")
+                .multiple(true)
+                .conflicts_with_all(&[
+                    FLAG_GIT,
+                    FLAG_SERVE,
+                    FLAG_WATCH,
+                    FLAG_PREVIEW_COLORS,
+                    FLAG_CONFLICTS,
+                ])
+                .help("The diff files read instead of the standard input.")
+                .long_help(
+                    "The diff files read instead of the standard input.
+
+The files are read as with --input, and rendered one after the
+other, each after a '==> FILE <==' line painted with the 'commit'
+face, as for the patches of a series exported by git format-patch.
+The state of the parser is reset between two files; the statistics
+of --stats cover all the files.",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_GIT_PATHS)
+                .value_name("PATH")
+                .multiple(true)
+                .last(true)
+                .help("Paths given to git diff, or the command of --watch, after '--'."),
+        )
+        .arg(
+            Arg::with_name(FLAG_HELP_COLORS)
+                .long(FLAG_HELP_COLORS)
+                .help("Display the faces with their colors, and the syntax of --colors.")
+                .long_help(
+                    "Display the faces with their colors, and the syntax of --colors.
+
+Each face is painted with its color, after the --colors,
+--colors-from-git and --light options given along, so that a
+color_spec can be previewed before being written in the
+configuration. The syntax of the color_spec is then displayed, with
+examples painted with the colors they set.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_DUMP_THEME)
+                .long(FLAG_DUMP_THEME)
+                .help("Write the faces as a theme file, instead of reading the input.")
+                .long_help(
+                    "Write the faces as a theme file, instead of reading the input.
+
+The faces are written as they would be used, after --light, --theme,
+--theme-file, --colors-from-git and --colors, in the TOML format read
+by --theme-file, and in the section 'faces' of the configuration file.
+Each face is written in full, from 'none', so that the file gives the
+same faces whatever the options it is loaded with.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_MAN_PAGE)
+                .long(FLAG_MAN_PAGE)
+                .help("Write the manual page of diffr, instead of reading the input.")
+                .long_help(
+                    "Write the manual page of diffr, instead of reading the input.
+
+The page is written in the roff format read by man, from this help:
+
+    diffr --man-page > diffr.1
+    man ./diffr.1",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_PREVIEW_COLORS)
+                .long(FLAG_PREVIEW_COLORS)
+                .conflicts_with(FLAG_GIT)
+                .help("Display a sample diff instead of reading the input.")
+                .long_help(
+                    "Display a sample diff instead of reading the input.
+
+The sample diff is rendered with the configuration given along, such
+as --colors, --light or --line-numbers, to preview the colors without
+piping a diff. It has a commit header, mode changes and a rename,
+refined hunks and a submodule.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_SERVE)
+                .long(FLAG_SERVE)
+                .conflicts_with_all(&[FLAG_GIT, FLAG_PREVIEW_COLORS, FLAG_OUTPUT_FILE])
+                .help("Render the diffs of framed requests read from the standard input.")
+                .long_help(
+                    "Render the diffs of framed requests read from the standard input.
+
+diffr stays resident and answers each request on the standard output,
+for editors and review tools that render many small diffs. A request is
+a line 'FORMAT LENGTH', where FORMAT is a format of --output, followed
+by LENGTH bytes of diff. The response is a line 'ok LENGTH' followed by
+LENGTH bytes of output, or 'error LENGTH' followed by LENGTH bytes of
+message; the diffs longer than 8 MiB are skipped with an error. The
+diffs are rendered with the other options given along;
+the colors of the 'terminal' format are written unless --color=never.
+diffr exits at the end of its input.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_WATCH)
+                .long(FLAG_WATCH)
+                .conflicts_with_all(&[
+                    FLAG_PAGER,
+                    FLAG_TUI,
+                    FLAG_SERVE,
+                    FLAG_PREVIEW_COLORS,
+                    FLAG_OUTPUT_FILE,
+                ])
+                .help("Render the diff of a command again each time the files change.")
+                .long_help(
+                    "Render the diff of a command again each time the files change.
+
+The command is given after '--', as in 'diffr --watch -- git diff
+--cached'; with --git, git diff is run. The files of the git repository
+of the current directory, or of the current directory outside of a
+repository, are watched with the notifications of the system, and the
+command is run again when one of them changes, is added or removed.
+The diff is then rendered on a clear screen. Hidden files and
+directories are not watched, except for the index and HEAD of git.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_CONFLICTS)
+                .long(FLAG_CONFLICTS)
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with_all(&[FLAG_GIT, FLAG_SERVE, FLAG_WATCH, FLAG_PREVIEW_COLORS])
+                .help("Display the conflicts of FILE, with the sides refined.")
+                .long_help(
+                    "Display the conflicts of FILE, with the sides refined.
+
+FILE has conflict markers, as left by a merge: the lines of our side,
+between '<<<<<<<' and '=======', are refined against the lines of their
+side, between '=======' and '>>>>>>>', and displayed as removed and
+added lines. With the diff3 style, the base side after '|||||||' is
+displayed unrefined. The lines outside of the conflicts are indented
+by a space. Each conflict counts as a hunk for --stats and
+--exit-code.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_INPUT)
+                .long(FLAG_INPUT)
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    FLAG_GIT,
+                    FLAG_SERVE,
+                    FLAG_WATCH,
+                    FLAG_PREVIEW_COLORS,
+                    FLAG_CONFLICTS,
+                ])
+                .help("Read the diff from FILE instead of the standard input.")
+                .long_help(
+                    "Read the diff from FILE instead of the standard input.
+
+FILE is mapped in memory where possible, and read in place line by
+line: the memory used for a huge patch does not grow with its size,
+unlike a patch piped to diffr. FILE must not be truncated while diffr
+runs.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_THREE_WAY)
+                .long(FLAG_THREE_WAY)
+                .value_names(&["OURS", "THEIRS"])
+                .number_of_values(2)
+                // as the values of the options are otherwise overridden
+                // by the last one
+                .multiple(true)
+                .conflicts_with_all(&[
+                    FLAG_GIT,
+                    FLAG_SERVE,
+                    FLAG_WATCH,
+                    FLAG_PREVIEW_COLORS,
+                    FLAG_CONFLICTS,
+                ])
+                .help("Display the diff files OURS and THEIRS of the same base together.")
+                .long_help(
+                    "Display the diff files OURS and THEIRS of the same base together.
+
+The changes of both diffs are merged by the lines of the base they
+replace. Each region of the base changed by either side is displayed
+in the diff3 style, as with --conflicts: our version, the base, then
+their version, where the side that did not change the region is
+marked as unchanged. Our version is refined against theirs, with the
+removed faces for our side and the added faces for their side. A few
+lines of the base are displayed around the regions, when the diffs
+have them.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_APPLY_PREVIEW)
+                .long(FLAG_APPLY_PREVIEW)
+                .value_name("PATCH")
+                .min_values(1)
+                .max_values(2)
+                // as the values of the options are otherwise overridden
+                // by the last one
+                .multiple(true)
+                .conflicts_with_all(&[
+                    FLAG_GIT,
+                    FLAG_SERVE,
+                    FLAG_WATCH,
+                    FLAG_PREVIEW_COLORS,
+                    FLAG_CONFLICTS,
+                    FLAG_THREE_WAY,
+                ])
+                .help("Display PATCH with notes on how its hunks apply to the files of DIR.")
+                .long_help(
+                    "Display PATCH with notes on how its hunks apply to the files of DIR.
+
+The hunks are checked against the files of DIR, the current directory
+by default, as 'git apply --check' or patch would: a note follows each
+hunk that applies with an offset, that applies once up to 2 context
+lines are ignored at its ends (fuzz), or that does not apply. The
+notes are painted with the 'apply-warning' and 'apply-rejected' faces.
+diffr exits with status 1 if some hunks do not apply.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_LIGHT)
+                .long(FLAG_LIGHT)
+                .conflicts_with(FLAG_DARK)
+                .help("Use the default colors for light backgrounds.")
+                .long_help(
+                    "Use the default colors for light backgrounds.
+
+By default, the background of the terminal is guessed from the
+COLORFGBG environment variable, set by some terminals, and is assumed
+dark otherwise. The colors for light backgrounds use darker
+foregrounds, and pale backgrounds for the unique segments. The faces
+given with --colors or --colors-from-git apply on top of them.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_DARK)
+                .long(FLAG_DARK)
+                .help("Use the default colors for dark backgrounds.")
+                .long_help(
+                    "Use the default colors for dark backgrounds.
+
+This is the default, unless the COLORFGBG environment variable tells
+that the background of the terminal is light.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_THEME)
+                .long(FLAG_THEME)
+                .value_name("THEME")
+                .takes_value(true)
+                .possible_values(&["default", "deuteranopia", "protanopia", "tritanopia"])
+                .help("Use the colors of THEME for the changed lines [default: default].")
+                .long_help(
+                    "Use the colors of THEME for the changed lines [default: default].
+
+The themes for color vision deficiencies replace the green and red of
+the added and removed lines, and of their refined segments, by colors
+that remain distinct: blue and orange with 'deuteranopia', blue and
+yellow with 'protanopia', teal and red with 'tritanopia'. They have
+variants for dark and light backgrounds. The faces given with
+--theme-file, --colors or --colors-from-git apply on top of them.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_THEME_FILE)
+                .long(FLAG_THEME_FILE)
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Use the faces of the theme file at PATH.")
+                .long_help(
+                    "Use the faces of the theme file at PATH.
+
+A theme file is a TOML file where the keys of the 'faces' table are
+the names of the faces, and their values are written with the syntax
+of --colors:
+
+    [faces]
+    added = \"none:foreground:green\"
+    refine-added = \"none:foreground:white:background:green:bold\"
+
+The keys of the other tables are ignored, so that a shared theme can
+tell its name or its author. The faces apply on top of --theme, and
+the faces given with --colors-from-git or --colors apply on top of
+them. --dump-theme writes such a file.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_ACCESSIBILITY)
+                .long(FLAG_ACCESSIBILITY)
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["symbols"])
+                .help("Tell the refined segments apart without colors.")
+                .long_help(
+                    "Tell the refined segments apart without colors.
+
+With 'symbols', the refined segments are underlined, and the lines of
+hunks are preceded by a gutter where '±' marks the changed lines that
+are partly refined, as opposed to the lines entirely added or
+removed.",
+                ),
+        )
+        .arg(
+            Arg::with_name(FLAG_COLOR)
+                .long(FLAG_COLOR)
+                .value_name("COLOR_SPEC")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Configure color settings.")
+                .long_help(concat!(
+                    "Configure color settings for console ouput.
+
+There are four faces to customize:
++----------------+--------------+----------------+
+|  line prefix   |      +       |       -        |
++----------------+--------------+----------------+
+| common segment |    added     |    removed     |
+| unique segment | refine-added | refine-removed |
++----------------+--------------+----------------+
+
+The 'line-number' face is used for the gutter displayed with
+--line-numbers, the 'no-newline' face (dim by default) for the
+'\\ No newline at end of file' markers, and the 'folded' face (dim by
+default) for the markers of --collapse-context. With --ignore-case,
+the 'case-changed' face, if set, paints the tokens that differ only
+by case. In the output of 'git log -p', the 'commit' face (yellow by
+default) paints the first line of the headers of commits, and the
+'commit-meta' face their metadata lines, such as 'Author:'. The
+'mode' face (cyan by default) paints the mode changes of files, the
+'rename' face (cyan by default) their renames and copies, and the
+'submodule' face (magenta by default) the 'Submodule' lines. With
+--ignore-matching-lines, the 'ignored' face (dim by default) paints
+the changed lines of the ignored hunks. The 'number-changed' face, if
+set, paints the numbers replaced by other numbers, such as the
+version bumps, instead of the 'refine-added' and 'refine-removed'
+faces. With --blame, the 'blame' face (dim by default) paints
+the gutter of the commits of the removed lines. With --apply-preview,
+the 'apply-warning' face (yellow by default) paints the notes of the
+hunks that apply with an offset or fuzz, and the 'apply-rejected' face
+(bold red by default) the notes of the hunks that do not apply.
+With --grep, the 'search-match' face (bold black on yellow by
+default) paints the matches in the changed lines. With
+--indent-changed, the 'indent-changed' face (dim by default) paints
+the lines whose indentation only changed. With --show-cr, the
+'carriage-return' face (dim by default) paints the '^M' markers.
+With --show-nonprinting, the 'nonprinting' face (bold yellow by
+default) paints the escaped nonprinting characters. The
+'function-context' face (none by default) paints the function context
+that follows the hunk headers, such as 'fn main()', and its breadcrumb
+above the hunks with --line-numbers. The 'binary' face (bold magenta
+by default) paints the placeholders of the binary files. With
+--ignore-token, the 'masked' face (dim by default) paints the masked
+tokens of the changed lines. With --relocated, the 'relocated' face
+(dim by default) paints the lines moved unchanged within their hunk.
+With --ruler, the 'ruler' face (dim by default) paints the guide, and
+the 'overlong' face, if set, the end of the added lines beyond it.
+With --split-hunks, the 'chunk-header' face (dim by default) paints the
+headers of the chunks.
+With --annotations, the 'annotation-error' (bold red by default),
+'annotation-warning' (bold yellow by default) and 'annotation-note'
+(cyan by default) faces paint the messages.
+In the patches of 'git format-patch', the 'mail-header' face (bold by
+default) paints the mail headers, and the 'message' face, if set, the
+commit message.
 
+The customization allows
+- to change the foreground or background color;
+- to set or unset the attributes 'bold', 'intense', 'underline',
+  'dim', 'italic', 'strikethrough';
+- to clear all attributes.
+
+Customization is done passing a color_spec argument.
+This flag may be provided multiple times.
+
+The syntax is the following:
+
+",
+                    color_spec_grammar!(),
+                    "
 For example, the color_spec
 
     'refine-added:background:blue:bold'
 
 sets the color of unique added segments with
-a blue background, written with a bold font.",
-                ),
+a blue background, written with a bold font.
+
+The sections 'faces.GLOB' of the configuration file of --profile set
+the faces of the files whose path matches GLOB, as with --path, on top
+of the other faces. Each section is a list of color_specs:
+
+    [faces.*.md]
+    refine-added:italic refine-removed:italic
+
+    [faces.Cargo.lock]
+    added:dim removed:dim
+
+The section 'faces' sets the faces of all the files, after --theme and
+before --theme-file, as the table of a theme file of --dump-theme:
+
+    [faces]
+    added = \"none:foreground:green\"
+
+With --help-colors, the faces are displayed with their current
+colors, and example color_specs with the colors they set.",
+                )),
         )
-        .get_matches()
 }
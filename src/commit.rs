@@ -0,0 +1,227 @@
+//! Display of the commit headers of the output of `git log -p`, and of
+//! the mail headers of the patches of `git format-patch`.
+//!
+//! A mail starts with a "From <sha> <date>" line, followed by the mail
+//! headers, a blank line and the commit message, up to a "---" line;
+//! its "-- " signature, after the diff, is skipped.
+
+use std::io;
+use termcolor::{ColorSpec, WriteColor};
+
+use super::{output, output_unrefined, AppConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitFormat {
+    /// The header as read.
+    Full,
+    /// The commit, author and subject lines.
+    Short,
+    /// The abbreviated hash and the subject on a single line.
+    Oneline,
+    /// No header.
+    Hidden,
+}
+
+/// Returns true if `line`, without escape codes, starts the header of
+/// a commit, or of a mail.
+pub fn is_commit_start(line: &[u8]) -> bool {
+    line.starts_with(b"commit ") || is_mail_start(line)
+}
+
+/// Returns true if `line` is the "From <sha> <date>" line that starts a
+/// mail of git format-patch.
+fn is_mail_start(line: &[u8]) -> bool {
+    match line.strip_prefix(b"From ") {
+        Some(rest) => {
+            40 < rest.len() && rest[..40].iter().all(u8::is_ascii_hexdigit) && rest[40] == b' '
+        }
+        None => false,
+    }
+}
+
+/// The hash of the commit started by `line`.
+pub fn sha(line: &[u8]) -> Option<String> {
+    let rest = line
+        .strip_prefix(b"commit ")
+        .or_else(|| line.strip_prefix(b"From "))?;
+    rest.split(|b| b.is_ascii_whitespace())
+        .next()
+        .map(|sha| String::from_utf8_lossy(sha).into_owned())
+}
+
+/// Returns true if `line` is the "-- " line that starts the signature
+/// of a mail.
+pub fn is_signature(line: &[u8]) -> bool {
+    matches!(line, b"-- " | b"-- \n" | b"-- \r\n")
+}
+
+/// Returns true if `line` is the "---" line that ends the commit
+/// message of a mail, before its diffstat.
+fn is_scissors(line: &[u8]) -> bool {
+    matches!(line, b"---" | b"---\n" | b"---\r\n")
+}
+
+/// Returns true if `line` is a metadata line such as "Author: ...".
+pub fn is_meta(line: &[u8]) -> bool {
+    match line.iter().position(|&b| b == b':') {
+        Some(i) => 0 < i && line[..i].iter().all(u8::is_ascii_alphabetic),
+        None => false,
+    }
+}
+
+fn is_date(line: &[u8]) -> bool {
+    line.starts_with(b"Date:")
+        || line.starts_with(b"AuthorDate:")
+        || line.starts_with(b"CommitDate:")
+}
+
+/// The lines of a commit header, buffered until its end is known.
+pub struct CommitHeader {
+    /// The lines as read, and without escape codes.
+    lines: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Whether the header is the one of a mail.
+    mail: bool,
+}
+
+impl CommitHeader {
+    pub fn new(raw: &[u8], stripped: &[u8]) -> Self {
+        CommitHeader {
+            lines: vec![(raw.to_vec(), stripped.to_vec())],
+            mail: is_mail_start(stripped),
+        }
+    }
+
+    /// Returns true if the header is the one of a mail.
+    pub fn is_mail(&self) -> bool {
+        self.mail
+    }
+
+    /// Adds `line` to the header if it belongs to it: metadata lines,
+    /// and the indented lines and blank lines of the message, or the
+    /// lines of a mail up to its "---" line. Returns false otherwise.
+    pub fn push(&mut self, raw: &[u8], stripped: &[u8]) -> bool {
+        let belongs = if self.mail {
+            !is_scissors(stripped) && !stripped.starts_with(b"diff ") && !is_mail_start(stripped)
+        } else {
+            stripped.trim_ascii().is_empty() || stripped.starts_with(b"    ") || is_meta(stripped)
+        };
+        if belongs {
+            self.lines.push((raw.to_vec(), stripped.to_vec()));
+        }
+        belongs
+    }
+
+    /// The index of the first line of the message of a mail, after the
+    /// blank line that ends its headers.
+    fn body_start(&self) -> usize {
+        self.lines
+            .iter()
+            .position(|(_, stripped)| stripped.trim_ascii().is_empty())
+            .unwrap_or(self.lines.len())
+    }
+
+    /// The first line of the message, as read and without escape
+    /// codes; the "Subject:" line of a mail.
+    fn subject(&self) -> Option<&(Vec<u8>, Vec<u8>)> {
+        self.lines.iter().find(|(_, stripped)| {
+            if self.mail {
+                stripped.starts_with(b"Subject:")
+            } else {
+                stripped.starts_with(b"    ")
+            }
+        })
+    }
+
+    /// The face of the line `i`, None for the lines displayed as read.
+    fn face<'a>(&self, i: usize, config: &'a AppConfig) -> Option<&'a ColorSpec> {
+        if i == 0 {
+            Some(&config.commit_face)
+        } else if !self.mail {
+            is_meta(&self.lines[i].1).then_some(&config.commit_meta_face)
+        } else if i < self.body_start() {
+            Some(&config.mail_header_face)
+        } else {
+            Some(&config.message_face)
+        }
+    }
+
+    fn output_line<Stream>(&self, i: usize, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let (raw, stripped) = &self.lines[i];
+        let face = match self.face(i, config) {
+            Some(face) => face,
+            None => return output_unrefined(raw, config.keep_input_colors, out),
+        };
+        if config.keep_input_colors {
+            return output_unrefined(raw, true, out);
+        }
+        output(stripped, face, out)
+    }
+
+    /// Writes the header in the format of --commit-format.
+    pub fn write<Stream>(&self, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        match config.commit_format {
+            CommitFormat::Full => {
+                for i in 0..self.lines.len() {
+                    self.output_line(i, config, out)?;
+                }
+            }
+            CommitFormat::Short if self.mail => {
+                // the headers of a mail hold its subject
+                for i in 0..self.body_start() {
+                    if !is_date(&self.lines[i].1) {
+                        self.output_line(i, config, out)?;
+                    }
+                }
+                output(b"\n", &ColorSpec::new(), out)?;
+            }
+            CommitFormat::Short => {
+                let metadata = self
+                    .lines
+                    .iter()
+                    .take_while(|(_, stripped)| !stripped.starts_with(b"    "))
+                    .count();
+                for i in 0..metadata {
+                    let stripped = &self.lines[i].1;
+                    if !is_date(stripped) && !stripped.trim_ascii().is_empty() {
+                        self.output_line(i, config, out)?;
+                    }
+                }
+                // the subject follows the metadata
+                if metadata < self.lines.len() {
+                    output(b"\n", &ColorSpec::new(), out)?;
+                    self.output_line(metadata, config, out)?;
+                    output(b"\n", &ColorSpec::new(), out)?;
+                }
+            }
+            CommitFormat::Oneline => {
+                let hash = sha(&self.lines[0].1).unwrap_or_default();
+                // the date of the "From" line of a mail is not a decoration
+                let decoration = match self.mail {
+                    true => &[][..],
+                    false => self.lines[0].1["commit ".len() + hash.len()..].trim_ascii_end(),
+                };
+                let mut line = hash.as_bytes()[..hash.len().min(7)].to_vec();
+                line.extend_from_slice(decoration);
+                output(&line, &config.commit_face, out)?;
+                let mut subject = b" ".to_vec();
+                if let Some((_, stripped)) = self.subject() {
+                    let stripped = match self.mail {
+                        true => &stripped[b"Subject:".len()..],
+                        false => &stripped[..],
+                    };
+                    subject.extend_from_slice(stripped.trim_ascii());
+                }
+                subject.push(b'\n');
+                output(&subject, &ColorSpec::new(), out)?;
+            }
+            CommitFormat::Hidden => (),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Display of the conflicts of a file with conflict markers, for
+//! --conflicts.
+//!
+//! The lines of our side of each conflict are refined as removed
+//! lines against the lines of their side, as added lines. The base
+//! side of the diff3 style is displayed between them, unrefined.
+
+use std::io::{self, BufRead};
+use termcolor::{ColorSpec, WriteColor};
+
+use super::{output, scan, stats, AppConfig, DiffStats, HunkBuffer, Layout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    /// "<<<<<<< ours"
+    Ours,
+    /// "||||||| base", with the diff3 style.
+    Base,
+    /// "======="
+    Separator,
+    /// ">>>>>>> theirs"
+    End,
+}
+
+/// Parses a conflict marker: 7 marker characters, followed by a space
+/// or the end of the line.
+fn parse_marker(line: &[u8]) -> Option<Marker> {
+    let marker = match line.get(..7)? {
+        b"<<<<<<<" => Marker::Ours,
+        b"|||||||" => Marker::Base,
+        b"=======" => Marker::Separator,
+        b">>>>>>>" => Marker::End,
+        _ => return None,
+    };
+    match line.get(7) {
+        None | Some(b' ') | Some(b'\n') | Some(b'\r') => Some(marker),
+        _ => None,
+    }
+}
+
+/// Prepends `prefix` to `line`.
+fn prefixed(prefix: u8, line: &[u8]) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(line.len() + 1);
+    prefixed.push(prefix);
+    prefixed.extend_from_slice(line);
+    prefixed
+}
+
+/// Displays the file with conflict markers read from `input`; each
+/// conflict is counted as a hunk.
+pub fn process<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    let path = config.conflicts.clone().unwrap_or_default();
+    let mut hunk_buffer = HunkBuffer {
+        path: Some(path.clone()),
+        config,
+        ..HunkBuffer::default()
+    };
+    hunk_buffer.stats.start_file(path);
+    // the last marker read, in a conflict
+    let mut section: Option<Marker> = None;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        scan::read_line(input, &mut line)?;
+        if line.is_empty() {
+            break;
+        }
+        match (section, parse_marker(&line)) {
+            (None, Some(Marker::Ours)) => {
+                hunk_buffer.stats.current().hunks += 1;
+                output(&line, &ColorSpec::default(), out)?;
+                section = Some(Marker::Ours);
+            }
+            (Some(Marker::Ours), Some(Marker::Base))
+            | (Some(Marker::Ours), Some(Marker::Separator))
+            | (Some(Marker::Base), Some(Marker::Separator)) => {
+                // the marker lines have no prefix: they are displayed
+                // as read
+                hunk_buffer.push_context(&line);
+                section = parse_marker(&line);
+            }
+            (Some(Marker::Separator), Some(Marker::End)) => {
+                hunk_buffer.process(out)?;
+                output(&line, &ColorSpec::default(), out)?;
+                section = None;
+            }
+            (Some(Marker::Ours), _) => hunk_buffer.push_removed(&prefixed(b'-', &line)),
+            (Some(Marker::Base), _) => hunk_buffer.push_context(&prefixed(b' ', &line)),
+            (Some(Marker::Separator), _) => hunk_buffer.push_added(&prefixed(b'+', &line)),
+            _ => {
+                let line = prefixed(b' ', &line);
+                let line = Layout::new(&hunk_buffer.config).apply(&line);
+                output(&line, &ColorSpec::default(), out)?;
+            }
+        }
+    }
+    // a conflict without end marker
+    hunk_buffer.process(out)?;
+    if hunk_buffer.config.stats {
+        stats::output_stats(&hunk_buffer.stats, out)?;
+    }
+    Ok(hunk_buffer.stats)
+}
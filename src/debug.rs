@@ -0,0 +1,130 @@
+//! Diagnostics of the refinement of each hunk, for --debug.
+//!
+//! A line is written on the standard error per refined hunk, either as
+//! space separated 'key=value' fields:
+//!
+//! hunk path="src/main.rs" header="-1,2 +1,3" lines=-2+3 tokens=-9+14 ...
+//!
+//! or as a JSON object with --debug=json.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use super::json;
+use super::stats::Timings;
+
+/// The format of the diagnostics of --debug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugFormat {
+    Text,
+    Json,
+}
+
+/// The diagnostics of the refinement of a hunk.
+pub struct HunkReport<'a> {
+    pub path: Option<&'a str>,
+    /// The old start and length, and the new start and length.
+    pub header: Option<(usize, usize, usize, usize)>,
+    /// The numbers of removed and added lines.
+    pub lines: (usize, usize),
+    /// The numbers of removed and added tokens.
+    pub tokens: (usize, usize),
+    /// The cost of the LCS, compared to --max-refine-cost.
+    pub cost: usize,
+    /// The number of tokens shared by the removed and added lines.
+    pub shared: usize,
+    pub algorithm: String,
+    /// How the tokens are compared: "tokens", "pairing" or "block".
+    pub mode: &'static str,
+    /// Why the hunk is not refined, if it is not.
+    pub skipped: Option<&'static str>,
+    /// Whether the refinement is read from the cache.
+    pub cached: bool,
+    pub lcs: Duration,
+    pub total: Duration,
+}
+
+impl HunkReport<'_> {
+    /// Writes the report as a line of `format`.
+    pub fn write<W: Write>(&self, format: DebugFormat, out: &mut W) -> io::Result<()> {
+        match format {
+            DebugFormat::Text => self.write_text(out),
+            DebugFormat::Json => self.write_json(out),
+        }
+    }
+
+    fn header(&self) -> Option<String> {
+        self.header
+            .map(|(a, b, c, d)| format!("-{},{} +{},{}", a, b, c, d))
+    }
+
+    fn write_text<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"hunk path=")?;
+        json::write_string(self.path.unwrap_or_default().as_bytes(), out)?;
+        out.write_all(b" header=")?;
+        json::write_string(self.header().unwrap_or_default().as_bytes(), out)?;
+        writeln!(
+            out,
+            " lines=-{}+{} tokens=-{}+{} cost={} shared={} algorithm={} mode={} skipped={} \
+             cached={} lcs_us={} total_us={}",
+            self.lines.0,
+            self.lines.1,
+            self.tokens.0,
+            self.tokens.1,
+            self.cost,
+            self.shared,
+            self.algorithm,
+            self.mode,
+            self.skipped.unwrap_or("none"),
+            self.cached,
+            self.lcs.as_micros(),
+            self.total.as_micros()
+        )
+    }
+
+    fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let write_option = |value: Option<&str>, out: &mut W| match value {
+            Some(value) => json::write_string(value.as_bytes(), out),
+            None => out.write_all(b"null"),
+        };
+        out.write_all(b"{\"path\":")?;
+        write_option(self.path, out)?;
+        out.write_all(b",\"header\":")?;
+        write_option(self.header().as_deref(), out)?;
+        write!(
+            out,
+            ",\"removed_lines\":{},\"added_lines\":{},\"removed_tokens\":{},\
+             \"added_tokens\":{},\"cost\":{},\"shared_tokens\":{},\"algorithm\":\"{}\",\
+             \"mode\":\"{}\",\"skipped\":",
+            self.lines.0,
+            self.lines.1,
+            self.tokens.0,
+            self.tokens.1,
+            self.cost,
+            self.shared,
+            self.algorithm,
+            self.mode
+        )?;
+        write_option(self.skipped, out)?;
+        writeln!(
+            out,
+            ",\"cached\":{},\"lcs_us\":{},\"total_us\":{}}}",
+            self.cached,
+            self.lcs.as_micros(),
+            self.total.as_micros()
+        )
+    }
+}
+
+/// Writes the timings of the phases of the processing as a JSON object.
+pub fn write_timings<W: Write>(timings: &Timings, out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "{{\"parse_us\":{},\"tokenize_us\":{},\"lcs_us\":{},\"emit_us\":{},\"total_us\":{}}}",
+        timings.parse().as_micros(),
+        timings.tokenize.as_micros(),
+        timings.lcs.as_micros(),
+        timings.emit.as_micros(),
+        timings.total.as_micros()
+    )
+}
@@ -0,0 +1,44 @@
+//! Rendering of the refined spans of the hunks as annotated text, for
+//! --emit-test-expectation and the golden tests of tests/golden.
+
+use std::io::{self, Write};
+
+use super::annotated;
+use super::hunks::{self, Part};
+
+/// Writes the hunks of `parts` as a header line per hunk:
+///
+/// @@ -1,2 +1,2 @@ a.rs
+///
+/// followed by the lines of the hunk, where the refined spans of the
+/// removed lines are between "[-" and "-]", and the ones of the added
+/// lines between "{+" and "+}":
+///
+/// -let [-x-] = 1;
+/// +let {+y+} = 1;
+///
+/// The header names the old path and the new path if they differ. The
+/// output only depends on the refinement, so that its changes are
+/// reviewed as the changes of text files.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    for hunk in hunks::hunks(parts) {
+        let header = hunk.header;
+        write!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            header.old_start, header.old_len, header.new_start, header.new_len
+        )?;
+        match (&hunk.old_path, &hunk.path) {
+            (Some(old_path), Some(path)) if old_path != path => {
+                write!(out, " {} -> {}", old_path, path)?
+            }
+            (_, Some(path)) | (Some(path), None) => write!(out, " {}", path)?,
+            (None, None) => {}
+        }
+        out.write_all(b"\n")?;
+        for line in &hunk.lines {
+            annotated::write_line(line, out)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Display of the extended header lines of git diffs, such as mode
+//! changes and renames.
+
+use std::io;
+use std::mem;
+use termcolor::{ColorSpec, WriteColor};
+
+use super::{output, output_unrefined, AppConfig};
+
+/// An extended header line, with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extended<'a> {
+    OldMode(&'a [u8]),
+    NewMode(&'a [u8]),
+    NewFileMode(&'a [u8]),
+    DeletedFileMode(&'a [u8]),
+    RenameFrom(&'a [u8]),
+    RenameTo(&'a [u8]),
+    CopyFrom(&'a [u8]),
+    CopyTo(&'a [u8]),
+    Similarity(&'a [u8]),
+    Dissimilarity(&'a [u8]),
+    /// A "Submodule <path> <range>:" line of --submodule=log.
+    Submodule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameFormat {
+    /// The lines as read.
+    Full,
+    /// A single line such as "rename src/{a => b}/lib.rs (90%)".
+    Compact,
+}
+
+/// A line prefix, and the constructor of the line with its value.
+type Prefix<'a> = (&'static [u8], fn(&'a [u8]) -> Extended<'a>);
+
+/// Parses `line`, without escape codes, as an extended header line.
+pub fn parse<'a>(line: &'a [u8]) -> Option<Extended<'a>> {
+    use Extended::*;
+    let line = line.trim_ascii_end();
+    let prefixes: [Prefix<'a>; 10] = [
+        (b"old mode ", OldMode),
+        (b"new mode ", NewMode),
+        (b"new file mode ", NewFileMode),
+        (b"deleted file mode ", DeletedFileMode),
+        (b"rename from ", RenameFrom),
+        (b"rename to ", RenameTo),
+        (b"copy from ", CopyFrom),
+        (b"copy to ", CopyTo),
+        (b"similarity index ", Similarity),
+        (b"dissimilarity index ", Dissimilarity),
+    ];
+    for (prefix, kind) in prefixes {
+        if let Some(value) = line.strip_prefix(prefix) {
+            return Some(kind(value));
+        }
+    }
+    if line.starts_with(b"Submodule ") {
+        return Some(Submodule);
+    }
+    None
+}
+
+/// Returns true if `line` is summarized rather than written as read,
+/// with --compact-headers or --rename-format=compact.
+pub fn is_summarized(line: &Extended, config: &AppConfig) -> bool {
+    use Extended::*;
+    match line {
+        Submodule => false,
+        RenameFrom(_) | RenameTo(_) | CopyFrom(_) | CopyTo(_) | Similarity(_)
+        | Dissimilarity(_) => {
+            config.compact_headers || config.rename_format == RenameFormat::Compact
+        }
+        _ => config.compact_headers,
+    }
+}
+
+/// Writes the paths of a rename like git does, with their common
+/// leading and trailing directories factored out, as in
+/// "src/{a => b}/lib.rs".
+pub fn compact_paths(from: &[u8], to: &[u8]) -> Vec<u8> {
+    // the common leading directories, up to the last slash
+    let mut prefix = 0;
+    for (i, (a, b)) in from.iter().zip(to).enumerate() {
+        if a != b {
+            break;
+        }
+        if *a == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // the common trailing directories, from their first slash, not
+    // overlapping the prefix
+    let mut suffix = 0;
+    let mut len = 0;
+    while prefix + len < from.len().min(to.len())
+        && from[from.len() - len - 1] == to[to.len() - len - 1]
+    {
+        len += 1;
+        if from[from.len() - len] == b'/' {
+            suffix = len;
+        }
+    }
+    let mut line = vec![];
+    if prefix + suffix == 0 {
+        line.extend_from_slice(from);
+        line.extend_from_slice(b" => ");
+        line.extend_from_slice(to);
+        return line;
+    }
+    line.extend_from_slice(&from[..prefix]);
+    line.push(b'{');
+    line.extend_from_slice(&from[prefix..from.len() - suffix]);
+    line.extend_from_slice(b" => ");
+    line.extend_from_slice(&to[prefix..to.len() - suffix]);
+    line.push(b'}');
+    line.extend_from_slice(&from[from.len() - suffix..]);
+    line
+}
+
+/// The face of an extended header line.
+pub fn face<'a>(line: &Extended, config: &'a AppConfig) -> &'a ColorSpec {
+    use Extended::*;
+    match line {
+        OldMode(_) | NewMode(_) | NewFileMode(_) | DeletedFileMode(_) => &config.mode_face,
+        Submodule => &config.submodule_face,
+        _ => &config.rename_face,
+    }
+}
+
+/// Writes an extended header line with its face; `raw` is the line as
+/// read, and `stripped` without escape codes.
+pub fn output_line<Stream>(
+    raw: &[u8],
+    stripped: &[u8],
+    line: &Extended,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if config.keep_input_colors {
+        return output_unrefined(raw, true, out);
+    }
+    output(stripped, face(line, config), out)
+}
+
+/// The extended header lines of a file, summarized on a single line
+/// with --compact-headers, or the renames and copies only with
+/// --rename-format=compact.
+#[derive(Debug, Default)]
+pub struct Summary {
+    mode: Option<(Vec<u8>, Vec<u8>)>,
+    file_mode: Option<(&'static str, Vec<u8>)>,
+    rename: Option<(&'static str, Vec<u8>, Vec<u8>)>,
+    similarity: Option<(&'static str, Vec<u8>)>,
+}
+
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none()
+            && self.file_mode.is_none()
+            && self.rename.is_none()
+            && self.similarity.is_none()
+    }
+
+    fn rename(&mut self, kind: &'static str) -> &mut (&'static str, Vec<u8>, Vec<u8>) {
+        self.rename.get_or_insert_with(|| (kind, vec![], vec![]))
+    }
+
+    /// Adds an extended header line; the "Submodule" lines are not
+    /// summarized.
+    pub fn push(&mut self, line: &Extended) {
+        use Extended::*;
+        match *line {
+            OldMode(mode) => self.mode.get_or_insert_with(Default::default).0 = mode.to_vec(),
+            NewMode(mode) => self.mode.get_or_insert_with(Default::default).1 = mode.to_vec(),
+            NewFileMode(mode) => self.file_mode = Some(("new file", mode.to_vec())),
+            DeletedFileMode(mode) => self.file_mode = Some(("deleted file", mode.to_vec())),
+            RenameFrom(path) => self.rename("rename").1 = path.to_vec(),
+            RenameTo(path) => self.rename("rename").2 = path.to_vec(),
+            CopyFrom(path) => self.rename("copy").1 = path.to_vec(),
+            CopyTo(path) => self.rename("copy").2 = path.to_vec(),
+            Similarity(index) => self.similarity = Some(("", index.to_vec())),
+            Dissimilarity(index) => self.similarity = Some(("dissimilarity ", index.to_vec())),
+            Submodule => (),
+        }
+    }
+
+    /// Writes the summary, and forgets it.
+    pub fn write<Stream>(&mut self, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let summary = mem::take(self);
+        if summary.is_empty() {
+            return Ok(());
+        }
+        let mut parts: Vec<Vec<u8>> = vec![];
+        if let Some((kind, from, to)) = &summary.rename {
+            let mut part = format!("{} ", kind).into_bytes();
+            match config.rename_format {
+                RenameFormat::Full => {
+                    part.extend_from_slice(from);
+                    part.extend_from_slice(b" => ");
+                    part.extend_from_slice(to);
+                }
+                RenameFormat::Compact => part.extend_from_slice(&compact_paths(from, to)),
+            }
+            if let Some((kind, index)) = &summary.similarity {
+                part.extend_from_slice(format!(" ({}", kind).as_bytes());
+                part.extend_from_slice(index);
+                part.push(b')');
+            }
+            parts.push(part);
+        }
+        if let Some((kind, mode)) = &summary.file_mode {
+            let mut part = format!("{} mode ", kind).into_bytes();
+            part.extend_from_slice(mode);
+            parts.push(part);
+        }
+        if let Some((old, new)) = &summary.mode {
+            let mut part = b"mode ".to_vec();
+            part.extend_from_slice(old);
+            part.extend_from_slice(b" => ");
+            part.extend_from_slice(new);
+            parts.push(part);
+        }
+        match &summary.similarity {
+            // a rewrite of --break-rewrites
+            Some((kind, index)) if summary.rename.is_none() => {
+                let kind = if kind.is_empty() { "similarity " } else { kind };
+                let mut part = kind.as_bytes().to_vec();
+                part.extend_from_slice(index);
+                parts.push(part);
+            }
+            _ => (),
+        }
+        let mut line = parts.join(&b", "[..]);
+        line.push(b'\n');
+        let face = if summary.rename.is_some() {
+            &config.rename_face
+        } else {
+            &config.mode_face
+        };
+        output(&line, face, out)
+    }
+}
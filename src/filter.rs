@@ -0,0 +1,213 @@
+//! Selection of the files displayed, based on their paths.
+
+use std::mem;
+
+use super::{parse_git_path, parse_new_path, parse_old_path};
+
+/// Returns true if `path` matches the glob `pattern`.
+///
+/// `*` matches any sequence of characters but `/`, `**` any sequence
+/// of characters and `?` any character; `**/` also matches no
+/// directory at all. Patterns without any `/` are matched against the
+/// file name only.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let path = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    glob_match(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            // "src/**/*.rs" matches "src/main.rs"
+            let no_directory = rest.first() == Some(&b'/') && glob_match(&rest[1..], text);
+            no_directory || (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let max = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+            (0..=max).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && glob_match(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// The generated files collapsed by default, such as lockfiles and
+/// minified sources.
+pub const DEFAULT_GENERATED: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Pipfile.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "flake.lock",
+    "*.min.js",
+    "*.min.css",
+];
+
+/// The result of filtering a line outside of hunks.
+pub enum Filtered {
+    /// A line to display.
+    Line(Vec<u8>),
+    /// The path of a file that is not displayed.
+    Skipped(String),
+    /// A generated file that is collapsed, with its numbers of added
+    /// and removed lines.
+    Generated(Collapsed),
+}
+
+/// A generated file whose lines are counted rather than displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collapsed {
+    pub path: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Buffers the header of each file until its path is known, and
+/// decides whether the file is displayed.
+#[derive(Default)]
+pub struct FileFilter {
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    generated: Vec<String>,
+    pending: Vec<Vec<u8>>,
+    pending_path: Option<String>,
+    skipping: bool,
+    /// The generated file being skipped, until its end.
+    collapsed: Option<Collapsed>,
+}
+
+impl FileFilter {
+    pub fn new(includes: &[String], excludes: &[String], generated: &[String]) -> Self {
+        FileFilter {
+            includes: includes.to_vec(),
+            excludes: excludes.to_vec(),
+            generated: generated.to_vec(),
+            ..FileFilter::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.includes.is_empty() || !self.excludes.is_empty() || !self.generated.is_empty()
+    }
+
+    /// Counts a line of a hunk skipped while `is_skipping`, whose kind
+    /// is `kind`.
+    pub fn count(&mut self, kind: Option<u8>) {
+        if let Some(collapsed) = &mut self.collapsed {
+            match kind {
+                Some(b'+') => collapsed.added += 1,
+                Some(b'-') => collapsed.removed += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Ends the generated file being skipped, if any.
+    fn end_collapsed(&mut self, dst: &mut Vec<Filtered>) {
+        dst.extend(self.collapsed.take().map(Filtered::Generated));
+    }
+
+    /// Returns true while the lines of the current file are skipped.
+    pub fn is_skipping(&self) -> bool {
+        self.skipping
+    }
+
+    fn accepts(&self, path: &str) -> bool {
+        (self.includes.is_empty() || self.includes.iter().any(|p| matches_path(p, path)))
+            && !self.excludes.iter().any(|p| matches_path(p, path))
+    }
+
+    fn decide(&mut self, dst: &mut Vec<Filtered>) {
+        let lines = mem::take(&mut self.pending);
+        match self.pending_path.take() {
+            Some(path) if !self.accepts(&path) => {
+                self.skipping = true;
+                dst.push(Filtered::Skipped(path));
+            }
+            Some(path) if self.generated.iter().any(|p| matches_path(p, &path)) => {
+                self.skipping = true;
+                self.collapsed = Some(Collapsed {
+                    path,
+                    added: 0,
+                    removed: 0,
+                });
+            }
+            _ => {
+                self.skipping = false;
+                dst.extend(lines.into_iter().map(Filtered::Line));
+            }
+        }
+    }
+
+    fn update_path(&mut self, line: &[u8]) {
+        if let Some(rest) = line.strip_prefix(b"diff --git ") {
+            self.pending_path = parse_git_path(rest);
+        } else if let Some(rest) = line.strip_prefix(b"--- ") {
+            if let Some(path) = parse_old_path(rest) {
+                self.pending_path = Some(path);
+            }
+        } else if let Some(rest) = line.strip_prefix(b"+++ ") {
+            if let Some(path) = parse_new_path(rest) {
+                self.pending_path = Some(path);
+            }
+        }
+    }
+
+    /// Filters a line that is not part of the body of a hunk; `raw`
+    /// is the line as read, and `stripped` without escape codes.
+    /// Returns true if the lines outside of the diff are displayed as
+    /// pushed: no file header is buffered, and no file is skipped.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && !self.skipping && self.collapsed.is_none()
+    }
+
+    pub fn push(&mut self, raw: &[u8], stripped: &[u8]) -> Vec<Filtered> {
+        let mut result = vec![];
+        let starts_file = stripped.starts_with(b"diff ")
+            || stripped.starts_with(b"--- ") && self.pending.is_empty();
+        if starts_file {
+            self.end_collapsed(&mut result);
+        }
+        if starts_file && !self.pending.is_empty() {
+            // the previous file has no hunk
+            self.decide(&mut result);
+        }
+        if starts_file || !self.pending.is_empty() {
+            self.update_path(stripped);
+            self.pending.push(raw.to_vec());
+            if stripped.starts_with(b"+++ ") || stripped.starts_with(b"@@") {
+                self.decide(&mut result);
+            }
+        } else if self.skipping && (stripped.starts_with(b"@@") || stripped.starts_with(b"\\")) {
+            // other hunks of a skipped file
+        } else {
+            self.skipping = false;
+            self.end_collapsed(&mut result);
+            result.push(Filtered::Line(raw.to_vec()));
+        }
+        result
+    }
+
+    /// Flushes the lines buffered at the end of the input.
+    pub fn finish(&mut self) -> Vec<Filtered> {
+        let mut result = vec![];
+        self.end_collapsed(&mut result);
+        if !self.pending.is_empty() {
+            self.decide(&mut result);
+        }
+        result
+    }
+}
@@ -0,0 +1,129 @@
+//! Folding of long runs of context lines, for --collapse-context, of
+//! all of them, for --only-changes, and of the unchanged lines between
+//! the hunks merged by --merge-hunks.
+
+/// How a line of a hunk is displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fold {
+    Shown,
+    /// The first hidden line of a run, displayed as a marker for the
+    /// given number of hidden lines.
+    Marker(usize),
+    Hidden,
+}
+
+/// Folds the run of context lines `start..end`, where `at_end` is
+/// true if the run ends the hunk. Returns true if the last line of
+/// the run is hidden.
+fn fold_run(folds: &mut [Fold], start: usize, end: usize, at_end: bool, max: usize) -> bool {
+    // a marker for a single line would not save any line
+    if end - start <= max + 1 {
+        return false;
+    }
+    let (before, after) = match (start == 0, at_end) {
+        (true, true) => (0, 0),
+        (true, false) => (0, max),
+        (false, true) => (max, 0),
+        (false, false) => (max / 2, max - max / 2),
+    };
+    let hidden = start + before..end - after;
+    folds[hidden.start] = Fold::Marker(hidden.len());
+    for fold in &mut folds[hidden.start + 1..hidden.end] {
+        *fold = Fold::Hidden;
+    }
+    after == 0
+}
+
+/// Folds the runs of more than `max + 1` context lines of a hunk,
+/// given the first byte of each of its lines, into `folds`.
+///
+/// At most `max` lines of each run are kept, next to the changes: the
+/// end of a run at the start of the hunk, the start of a run at the
+/// end of the hunk, and both ends of the runs between changes.
+pub fn fold_context<Kinds>(kinds: Kinds, max: usize, folds: &mut Vec<Fold>)
+where
+    Kinds: Iterator<Item = u8>,
+{
+    folds.clear();
+    // the start of the current run of context lines
+    let mut start = None;
+    for (i, kind) in kinds.enumerate() {
+        if kind == b' ' || kind == b'\n' {
+            start.get_or_insert(i);
+            folds.push(Fold::Shown);
+            continue;
+        }
+        let last_hidden = match start.take() {
+            Some(start) => fold_run(folds, start, i, false, max),
+            None => false,
+        };
+        // a "\ No newline at end of file" marker of a hidden line
+        if kind == b'\\' && last_hidden {
+            folds.push(Fold::Hidden);
+        } else {
+            folds.push(Fold::Shown);
+        }
+    }
+    if let Some(start) = start {
+        let end = folds.len();
+        fold_run(folds, start, end, true, max);
+    }
+}
+
+/// Hides all the context lines of a hunk, given the first byte of each
+/// of its lines, into `folds`.
+pub fn hide_context<Kinds>(kinds: Kinds, folds: &mut Vec<Fold>)
+where
+    Kinds: Iterator<Item = u8>,
+{
+    folds.clear();
+    let mut last_hidden = false;
+    for kind in kinds {
+        let hidden = match kind {
+            b' ' | b'\n' => true,
+            // a "\ No newline at end of file" marker of a hidden line
+            b'\\' => last_hidden,
+            _ => false,
+        };
+        folds.push(if hidden { Fold::Hidden } else { Fold::Shown });
+        last_hidden = hidden;
+    }
+}
+
+/// Folds the unchanged lines `gaps` between the hunks merged by
+/// --merge-hunks, given the first byte of each line of the hunk, into
+/// `folds`; each gap is displayed as a marker if `markers` is set,
+/// merged with the folded context lines around it.
+pub fn fold_gaps<Kinds>(kinds: Kinds, gaps: &[(usize, usize)], markers: bool, folds: &mut Vec<Fold>)
+where
+    Kinds: Iterator<Item = u8>,
+{
+    let kinds = kinds.collect::<Vec<_>>();
+    folds.resize(kinds.len(), Fold::Shown);
+    for &(start, len) in gaps {
+        for fold in &mut folds[start..start + len] {
+            *fold = Fold::Hidden;
+        }
+    }
+    if !markers {
+        return;
+    }
+    let mut i = 0;
+    while i < folds.len() {
+        if folds[i] == Fold::Shown {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut nlines = 0;
+        while i < folds.len() && folds[i] != Fold::Shown {
+            // a "\ No newline at end of file" marker is not a line
+            if kinds[i] != b'\\' {
+                nlines += 1;
+            }
+            folds[i] = Fold::Hidden;
+            i += 1;
+        }
+        folds[start] = Fold::Marker(nlines);
+    }
+}
@@ -0,0 +1,105 @@
+//! Faces read from the configuration of git.
+
+use std::process::Command;
+use termcolor::{Color, ColorSpec};
+
+use super::{cli_args, ignore, AppConfig};
+
+/// Parses a color, as written in the configuration of git.
+fn parse_git_color(word: &str) -> Option<(Option<Color>, bool)> {
+    let (name, intense) = match word.strip_prefix("bright") {
+        Some(name) => (name, true),
+        None => (word, false),
+    };
+    let color = match name {
+        "normal" | "default" => None,
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "magenta" => Some(Color::Magenta),
+        "cyan" => Some(Color::Cyan),
+        "white" => Some(Color::White),
+        _ if name.len() == 7 && name.starts_with('#') => {
+            let component = |i| u8::from_str_radix(&name[i..i + 2], 16).ok();
+            Some(Color::Rgb(component(1)?, component(3)?, component(5)?))
+        }
+        _ => Some(Color::Ansi256(word.parse().ok()?)),
+    };
+    Some((color, intense))
+}
+
+/// Parses a color value of git, such as "bold red" or "#ff0000 ul".
+pub fn parse_git_face(value: &str) -> Result<ColorSpec, String> {
+    let mut face = ColorSpec::new();
+    let mut ncolors = 0;
+    for word in value.split_whitespace() {
+        let word = word.to_ascii_lowercase();
+        let (attribute, enable) = match word.strip_prefix("no") {
+            Some(attribute) => (attribute.trim_start_matches('-'), false),
+            None => (&word[..], true),
+        };
+        match attribute {
+            "bold" => ignore(face.set_bold(enable)),
+            "dim" => ignore(face.set_dimmed(enable)),
+            "italic" => ignore(face.set_italic(enable)),
+            "ul" | "underline" => ignore(face.set_underline(enable)),
+            "strike" => ignore(face.set_strikethrough(enable)),
+            // not supported by terminals consistently
+            "blink" | "reverse" => (),
+            _ => match parse_git_color(&word) {
+                Some((color, intense)) if ncolors == 0 => {
+                    face.set_fg(color).set_intense(intense);
+                    ncolors += 1;
+                }
+                Some((color, _)) if ncolors == 1 => {
+                    face.set_bg(color);
+                    ncolors += 1;
+                }
+                _ => return Err(format!("unexpected git color: {}", value)),
+            },
+        }
+    }
+    Ok(face)
+}
+
+/// Applies the faces found in the output of `git config --list`.
+///
+/// The git slots `color.diff.new` and `color.diff.old` give the added
+/// and removed faces; the `diffr.<face-name>` variables are written
+/// with the syntax of --colors, such as `background:blue:bold`. The
+/// variables that do not name a face are ignored with a warning.
+pub fn apply_git_config(config: &mut AppConfig, list: &str) -> Result<(), String> {
+    for line in list.lines() {
+        let (key, value) = match line.find('=') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+        match key {
+            "color.diff.new" => config.added_face = parse_git_face(value)?,
+            "color.diff.old" => config.removed_face = parse_git_face(value)?,
+            _ => {
+                if let Some(face_name) = key.strip_prefix("diffr.") {
+                    if !cli_args::is_face_name(face_name) {
+                        eprintln!("ignoring git config {}: unknown face name", key);
+                        continue;
+                    }
+                    let color_spec = format!("{}:{}", face_name, value);
+                    cli_args::parse_color_args(config, Some(&color_spec[..]).into_iter())
+                        .map_err(|err| format!("in git config {}: {}", key, err))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies the faces configured in git.
+pub fn read_git_colors(config: &mut AppConfig) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(["config", "--list"])
+        .output()
+        .map_err(|err| format!("could not run git config: {}", err))?;
+    apply_git_config(config, &String::from_utf8_lossy(&output.stdout))
+}
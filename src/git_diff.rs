@@ -0,0 +1,91 @@
+//! Running git diff, for --git.
+
+use std::io;
+use std::process::{Child, Command, Stdio};
+
+use super::AppConfig;
+
+/// Converts a glob of --path or --exclude to a pathspec of git.
+fn pathspec(glob: &str, exclude: bool) -> String {
+    let magic = if exclude { "exclude,glob" } else { "glob" };
+    if glob.contains('/') {
+        format!(":({}){}", magic, glob)
+    } else {
+        // globs without '/' match the file name
+        format!(":({})**/{}", magic, glob)
+    }
+}
+
+/// The arguments of git diff for `config`.
+pub fn git_diff_args(config: &AppConfig) -> Vec<String> {
+    let mut args = vec!["diff".to_owned(), "--no-ext-diff".to_owned()];
+    if config.keep_input_colors {
+        args.push("--color=always".to_owned());
+    } else {
+        args.push("--no-color".to_owned());
+    }
+    if let Some(context) = config.context {
+        args.push(format!("-U{}", context));
+    }
+    args.extend(config.git_revisions.clone());
+    args.push("--".to_owned());
+    args.extend(config.git_paths.iter().cloned());
+    let includes = config
+        .include_paths
+        .iter()
+        .map(|glob| pathspec(glob, false));
+    args.extend(includes);
+    if !config.exclude_paths.is_empty() {
+        if config.include_paths.is_empty() && config.git_paths.is_empty() {
+            // git needs a positive pathspec along the excluded ones
+            args.push(".".to_owned());
+        }
+        let excludes = config.exclude_paths.iter().map(|glob| pathspec(glob, true));
+        args.extend(excludes);
+    }
+    args
+}
+
+/// The root of the git repository of the current directory, as the
+/// paths of the diff are relative to it.
+pub fn repository_root() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let root = String::from_utf8(output.stdout).ok()?;
+    Some(root.trim_end().to_owned())
+}
+
+/// The content of the blob `hash`, None if git does not know it, as
+/// the blobs of the files of the working tree.
+pub fn cat_blob(hash: &str) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .args(["cat-file", "blob", hash])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(output.stdout)
+}
+
+/// Starts git diff, with its output piped.
+pub fn spawn(config: &AppConfig) -> io::Result<Child> {
+    Command::new("git")
+        .args(git_diff_args(config))
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(), format!("could not run git diff: {}", err)))
+}
+
+/// Waits for the end of git diff.
+pub fn wait(mut child: Child) -> io::Result<()> {
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("git diff failed: {}", status)))
+    }
+}
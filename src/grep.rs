@@ -0,0 +1,192 @@
+//! Selection of the hunks displayed, based on their changed lines, for
+//! --grep.
+
+use std::io::{self, BufRead, Read};
+use std::mem;
+
+use regex::bytes::Regex;
+
+use super::{first_after_escape, parse_hunk_header, strip_escape_codes, HunkRemaining};
+
+/// Returns true if the changed line `line`, with its +/- prefix and
+/// without escape codes, matches one of `regexes`.
+pub fn is_match(regexes: &[Regex], line: &[u8]) -> bool {
+    let line = line.get(1..).unwrap_or_default();
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    regexes.iter().any(|regex| regex.is_match(line))
+}
+
+/// Collects in `dst` the spans of the matches of `regexes` in the line
+/// `data[lo..hi]`, with its +/- prefix.
+pub fn match_spans(
+    regexes: &[Regex],
+    data: &[u8],
+    (lo, hi): (usize, usize),
+    dst: &mut Vec<(usize, usize)>,
+) {
+    let line = &data[lo + 1..hi];
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    find_spans(regexes, line, lo + 1, dst);
+}
+
+/// Collects in `dst` the spans of the matches of `regexes` in `text`,
+/// shifted by `offset`, sorted and merged.
+pub fn find_spans(regexes: &[Regex], text: &[u8], offset: usize, dst: &mut Vec<(usize, usize)>) {
+    let start = dst.len();
+    for regex in regexes {
+        let mut at = 0;
+        while let Some(found) = regex.find_at(text, at) {
+            let (match_lo, match_hi) = (found.start(), found.end());
+            if match_lo < match_hi {
+                dst.push((offset + match_lo, offset + match_hi));
+            }
+            if text.len() <= match_hi {
+                break;
+            }
+            at = match_hi.max(match_lo + 1);
+        }
+    }
+    // the matches of several regexes are merged
+    dst[start..].sort_unstable();
+    let mut w = start;
+    for r in start..dst.len() {
+        let (span_lo, span_hi) = dst[r];
+        if start < w && span_lo <= dst[w - 1].1 {
+            dst[w - 1].1 = dst[w - 1].1.max(span_hi);
+        } else {
+            dst[w] = (span_lo, span_hi);
+            w += 1;
+        }
+    }
+    dst.truncate(w);
+}
+
+/// The input, with only the hunks that have a changed line matching
+/// one of the regexes, and the headers of their files.
+pub struct Grep<'a, Input> {
+    input: Input,
+    regexes: &'a [Regex],
+    lenient: bool,
+    // the lines read since the last hunk, such as the headers of a
+    // file, written before its first hunk that matches
+    pending: Vec<u8>,
+    // the current hunk, and whether one of its changed lines matches
+    hunk: Vec<u8>,
+    remaining: Option<HunkRemaining>,
+    in_hunk: bool,
+    matched: bool,
+    line: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<'a, Input> Grep<'a, Input> {
+    pub fn new(input: Input, regexes: &'a [Regex], lenient: bool) -> Self {
+        Grep {
+            input,
+            regexes,
+            lenient,
+            pending: vec![],
+            hunk: vec![],
+            remaining: None,
+            in_hunk: false,
+            matched: false,
+            line: vec![],
+            output: vec![],
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        let first = first_after_escape(line);
+        let after_hunk = self.in_hunk;
+        if self.in_hunk {
+            let kind = match &mut self.remaining {
+                Some(remaining) => remaining.count(first),
+                None => first,
+            };
+            match kind {
+                Some(b'+') | Some(b'-') => {
+                    if !self.matched {
+                        let mut stripped = vec![];
+                        strip_escape_codes(line, &mut stripped);
+                        self.matched = is_match(self.regexes, &stripped);
+                    }
+                    self.hunk.extend_from_slice(line);
+                    return;
+                }
+                Some(b' ') | Some(b'\\') => {
+                    self.hunk.extend_from_slice(line);
+                    return;
+                }
+                _ => self.flush(),
+            }
+        }
+        let mut stripped = vec![];
+        strip_escape_codes(line, &mut stripped);
+        match parse_hunk_header(&stripped) {
+            Some(header) => {
+                self.in_hunk = true;
+                self.matched = false;
+                self.hunk.extend_from_slice(line);
+                self.remaining = if self.lenient {
+                    Some(HunkRemaining::from(header))
+                } else {
+                    None
+                };
+            }
+            None => {
+                if after_hunk {
+                    // the headers of the next file start
+                    self.pending.clear();
+                }
+                self.pending.extend_from_slice(line);
+            }
+        }
+    }
+
+    /// Writes the current hunk if it matches, after the pending lines.
+    fn flush(&mut self) {
+        if self.matched {
+            self.output.append(&mut self.pending);
+            self.output.append(&mut self.hunk);
+        }
+        self.hunk.clear();
+        self.in_hunk = false;
+    }
+}
+
+impl<Input: BufRead> Read for Grep<'_, Input> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<Input: BufRead> BufRead for Grep<'_, Input> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.output.len() && !self.eof {
+            self.output.clear();
+            self.pos = 0;
+            let mut line = mem::take(&mut self.line);
+            line.clear();
+            if self.input.read_until(b'\n', &mut line)? == 0 {
+                self.eof = true;
+                self.flush();
+            } else {
+                self.push_line(&line);
+            }
+            self.line = line;
+        }
+        Ok(&self.output[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
@@ -0,0 +1,66 @@
+//! Rewriting of the "---" and "+++" lines of the files, for
+//! --timestamps and --strip-prefix.
+
+use super::AppConfig;
+
+/// What is done with the timestamps of the "---" and "+++" lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamps {
+    Keep,
+    Strip,
+    /// The timestamps are replaced by the epoch, as diff writes for
+    /// missing files.
+    Normalize,
+}
+
+const EPOCH: &[u8] = b"1970-01-01 00:00:00.000000000 +0000";
+
+/// Removes the `n` leading components of `path`, as the -p option of
+/// patch; the last component is always kept.
+fn strip_components(path: &[u8], n: usize) -> &[u8] {
+    let mut path = path;
+    for _ in 0..n {
+        match path.iter().position(|&b| b == b'/') {
+            Some(i) => path = &path[i + 1..],
+            None => break,
+        }
+    }
+    path
+}
+
+/// Rewrites the "---" or "+++" line `line`, without escape codes, with
+/// the options of `config`. Returns None if the line is unchanged.
+pub fn rewrite(line: &[u8], config: &AppConfig) -> Option<Vec<u8>> {
+    if config.timestamps == Timestamps::Keep && config.strip_prefix.is_none() {
+        return None;
+    }
+    let (marker, rest) = line.split_at(4.min(line.len()));
+    if marker != b"--- " && marker != b"+++ " {
+        return None;
+    }
+    let end = rest.strip_suffix(b"\n").unwrap_or(rest);
+    let end = end.strip_suffix(b"\r").unwrap_or(end);
+    let newline = &rest[end.len()..];
+    // the path may be followed by a tab and a timestamp
+    let (path, timestamp) = match end.iter().position(|&b| b == b'\t') {
+        Some(i) => (&end[..i], Some(&end[i + 1..])),
+        None => (end, None),
+    };
+    let path = match config.strip_prefix {
+        Some(n) if path != b"/dev/null" => strip_components(path, n),
+        _ => path,
+    };
+    let timestamp = match (config.timestamps, timestamp) {
+        (Timestamps::Strip, _) | (_, None) => None,
+        (Timestamps::Normalize, Some(_)) => Some(EPOCH),
+        (Timestamps::Keep, timestamp) => timestamp,
+    };
+    let mut rewritten = marker.to_vec();
+    rewritten.extend_from_slice(path);
+    if let Some(timestamp) = timestamp {
+        rewritten.push(b'\t');
+        rewritten.extend_from_slice(timestamp);
+    }
+    rewritten.extend_from_slice(newline);
+    Some(rewritten).filter(|rewritten| rewritten != line)
+}
@@ -0,0 +1,225 @@
+//! Rendering of the output of diffr as HTML.
+
+use std::io::{self, Write};
+use termcolor::{Color, ColorSpec};
+
+use super::{apply_sgr, skip_escape_code};
+
+/// The 16 colors of the palette of xterm.
+const XTERM_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// The components of the color `n` of the 256 colors palette.
+fn ansi256_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => XTERM_COLORS[n as usize],
+        16..=231 => {
+            let level = |i: u8| if i == 0 { 0 } else { 55 + 40 * i };
+            let i = n - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + 10 * (n - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// The CSS color of `color`.
+pub fn css_color(color: &Color, intense: bool) -> String {
+    let index = match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White => 7,
+        _ => 0,
+    };
+    let (r, g, b) = match *color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Ansi256(n) => ansi256_rgb(n),
+        _ => XTERM_COLORS[index + if intense { 8 } else { 0 }],
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The CSS style of `face`, empty for the default face.
+pub fn style(face: &ColorSpec) -> String {
+    let mut style = vec![];
+    if let Some(fg) = face.fg() {
+        style.push(format!("color:{}", css_color(fg, face.intense())));
+    }
+    if let Some(bg) = face.bg() {
+        style.push(format!(
+            "background-color:{}",
+            css_color(bg, face.intense())
+        ));
+    }
+    if face.bold() {
+        style.push("font-weight:bold".to_owned());
+    }
+    if face.dimmed() {
+        style.push("opacity:0.6".to_owned());
+    }
+    if face.italic() {
+        style.push("font-style:italic".to_owned());
+    }
+    let decorations = [
+        (face.underline(), "underline"),
+        (face.strikethrough(), "line-through"),
+    ];
+    let decorations = decorations
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, decoration)| *decoration)
+        .collect::<Vec<_>>();
+    if !decorations.is_empty() {
+        style.push(format!("text-decoration:{}", decorations.join(" ")));
+    }
+    style.join(";")
+}
+
+/// Writes `text`, with the special characters of HTML escaped.
+pub fn write_escaped<W: Write>(text: &[u8], out: &mut W) -> io::Result<()> {
+    for &b in text {
+        match b {
+            b'&' => out.write_all(b"&amp;")?,
+            b'<' => out.write_all(b"&lt;")?,
+            b'>' => out.write_all(b"&gt;")?,
+            b'"' => out.write_all(b"&quot;")?,
+            _ => out.write_all(&[b])?,
+        }
+    }
+    Ok(())
+}
+
+/// Splits a line of the output of diffr in runs of text of the same
+/// face, according to its SGR escape codes. Other escape codes are
+/// dropped.
+pub fn runs(line: &[u8]) -> Vec<(ColorSpec, &[u8])> {
+    let mut runs = vec![];
+    let mut face = ColorSpec::default();
+    let mut i = 0;
+    while i < line.len() {
+        if let Some(nbytes) = skip_escape_code(&line[i..]) {
+            if line[i + nbytes - 1] == b'm' {
+                apply_sgr(&line[i + 2..i + nbytes - 1], &mut face);
+            }
+            i += nbytes;
+            continue;
+        }
+        let len = line[i..]
+            .iter()
+            .skip(1)
+            .position(|&b| b == b'\x1b')
+            .map_or(line.len() - i, |len| len + 1);
+        runs.push((face.clone(), &line[i..i + len]));
+        i += len;
+    }
+    runs
+}
+
+/// Writes `rendered`, the output of diffr, as a standalone HTML page.
+pub fn write_page<W: Write>(rendered: &[u8], out: &mut W) -> io::Result<()> {
+    out.write_all(
+        b"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>diffr</title>\n</head>\n<body>\n<pre>",
+    )?;
+    for line in rendered.split_inclusive(|&b| b == b'\n') {
+        let (line, newline) = match line.strip_suffix(b"\n") {
+            Some(line) => (line, &b"\n"[..]),
+            None => (line, &b""[..]),
+        };
+        write_line(line, out)?;
+        out.write_all(newline)?;
+    }
+    out.write_all(b"</pre>\n</body>\n</html>\n")
+}
+
+/// Finds the OSC 8 hyperlinks of a line of the output of diffr: the
+/// offset of each escape code, and the url it opens, or None if it
+/// closes the link.
+pub fn links(line: &[u8]) -> Vec<(usize, Option<&[u8]>)> {
+    let mut links = vec![];
+    let mut i = 0;
+    while i < line.len() {
+        match skip_escape_code(&line[i..]) {
+            Some(nbytes) => {
+                let code = &line[i..i + nbytes];
+                if let Some(body) = code.strip_prefix(b"\x1b]8;") {
+                    let body = body.strip_suffix(b"\x07").unwrap_or(body);
+                    let body = body.strip_suffix(b"\x1b\\").unwrap_or(body);
+                    // the parameters are before the url
+                    let url = body.splitn(2, |&b| b == b';').nth(1).unwrap_or_default();
+                    links.push((i, Some(url).filter(|url| !url.is_empty())));
+                }
+                i += nbytes;
+            }
+            None => i += 1,
+        }
+    }
+    links
+}
+
+/// Closes the link to `url`; the images of data urls, such as the
+/// ones of --binary=embed, are displayed after it.
+fn close_link<W: Write>(url: &[u8], out: &mut W) -> io::Result<()> {
+    out.write_all(b"</a>")?;
+    if url.starts_with(b"data:image/") {
+        out.write_all(b"<br><img src=\"")?;
+        write_escaped(url, out)?;
+        out.write_all(b"\" alt=\"\">")?;
+    }
+    Ok(())
+}
+
+/// Writes a line of the output of diffr, translating its SGR escape
+/// codes to `<span>` elements, and its hyperlinks to `<a>` elements.
+pub fn write_line<W: Write>(line: &[u8], out: &mut W) -> io::Result<()> {
+    let mut links = links(line).into_iter().peekable();
+    let mut open = None;
+    for (face, text) in runs(line) {
+        let offset = text.as_ptr() as usize - line.as_ptr() as usize;
+        while let Some((_, url)) = links.next_if(|(i, _)| *i < offset) {
+            if let Some(open) = open {
+                close_link(open, out)?;
+            }
+            open = url;
+            if let Some(url) = url {
+                out.write_all(b"<a href=\"")?;
+                write_escaped(url, out)?;
+                out.write_all(b"\">")?;
+            }
+        }
+        match style(&face) {
+            style if style.is_empty() => write_escaped(text, out)?,
+            style => {
+                write!(out, "<span style=\"{}\">", style)?;
+                write_escaped(text, out)?;
+                out.write_all(b"</span>")?;
+            }
+        }
+    }
+    match open {
+        Some(url) => close_link(url, out),
+        None => Ok(()),
+    }
+}
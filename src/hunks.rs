@@ -0,0 +1,362 @@
+//! The hunks of a diff with their refined spans, recorded while the
+//! diff is processed, for the output formats that describe the diff
+//! rather than paint it.
+
+use diffr_lib::{DiffInput, EditKind, HashedSpan, LineSplit, Snake, Tokenization};
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use unicode_width::UnicodeWidthChar;
+
+use super::{
+    is_too_different, split_crlf, strip_escape_codes, AppConfig, HunkBuffer, HunkHeader, Span,
+};
+
+/// A part of a diff, as recorded.
+#[derive(Debug)]
+pub enum Part {
+    /// Lines outside of the hunks, as read without escape codes.
+    Lines(Vec<u8>),
+    Hunk(Hunk),
+}
+
+/// The hunks of `parts`.
+pub fn hunks(parts: &[Part]) -> impl Iterator<Item = &Hunk> {
+    parts.iter().filter_map(|part| match part {
+        Part::Hunk(hunk) => Some(hunk),
+        Part::Lines(_) => None,
+    })
+}
+
+/// The receiver of the parts of a diff, as they are processed.
+pub trait Record {
+    fn record(&mut self, part: Part) -> io::Result<()>;
+}
+
+impl Record for Vec<Part> {
+    fn record(&mut self, part: Part) -> io::Result<()> {
+        self.push(part);
+        Ok(())
+    }
+}
+
+/// The receiver of the parts of the diff processed with a
+/// configuration, shared with the configurations derived from it.
+#[derive(Clone)]
+pub struct Recorder(Rc<RefCell<dyn Record>>);
+
+impl Recorder {
+    pub fn new(record: Rc<RefCell<dyn Record>>) -> Self {
+        Recorder(record)
+    }
+
+    pub fn record(&self, part: Part) -> io::Result<()> {
+        self.0.borrow_mut().record(part)
+    }
+
+    /// Records `lines`, as read, without their escape codes.
+    pub fn record_lines(&self, lines: &[u8]) -> io::Result<()> {
+        let mut stripped = Vec::with_capacity(lines.len());
+        strip_escape_codes(lines, &mut stripped);
+        self.record(Part::Lines(stripped))
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Recorder")
+    }
+}
+
+/// The configuration to process the diff without rendering it, its
+/// parts being recorded in `recorder`: the options that change the
+/// lines of the input before they are refined are dropped, so that
+/// the parts are the ones of the input, and the text written from them
+/// is redacted instead of the rendering.
+pub fn record_config(config: AppConfig, recorder: Recorder) -> AppConfig {
+    AppConfig {
+        merge_hunks: None,
+        word_diff: false,
+        keep_input_colors: false,
+        max_line_length: None,
+        generated_paths: vec![],
+        redact: vec![],
+        recorder: Some(recorder),
+        ..config
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Removed,
+    Added,
+    /// A "\ No newline at end of file" line.
+    NoNewline,
+}
+
+/// A line of a hunk.
+#[derive(Debug)]
+pub struct Line {
+    pub kind: LineKind,
+    /// The content of the line, after its +/- prefix.
+    pub content: Vec<u8>,
+    /// The refined spans of the content, in characters.
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// The columns between the tab stops, to compute the display columns
+/// of the refined spans.
+pub const TAB_WIDTH: usize = 8;
+
+impl Line {
+    /// The offsets in bytes and in display columns of the characters
+    /// of the content, and of its end: tabs stop every `TAB_WIDTH`
+    /// columns, and the wide characters take 2 columns.
+    fn offsets(&self) -> (Vec<usize>, Vec<usize>) {
+        let content = String::from_utf8_lossy(&self.content);
+        let (mut bytes, mut columns) = (vec![], vec![]);
+        let mut column = 0;
+        for (i, c) in content.char_indices() {
+            bytes.push(i);
+            columns.push(column);
+            column += match c {
+                '\t' => TAB_WIDTH - column % TAB_WIDTH,
+                c => c.width().unwrap_or(0),
+            };
+        }
+        bytes.push(content.len());
+        columns.push(column);
+        (bytes, columns)
+    }
+
+    /// The refined spans, in bytes of the UTF-8 content and in display
+    /// columns.
+    pub fn mapped_spans(&self) -> (Vec<Span>, Vec<Span>) {
+        let (bytes, columns) = self.offsets();
+        let map = |offsets: &[usize]| {
+            let at = |i: usize| offsets[i.min(offsets.len() - 1)];
+            self.spans
+                .iter()
+                .map(|&(lo, hi)| (at(lo), at(hi)))
+                .collect()
+        };
+        (map(&bytes), map(&columns))
+    }
+}
+
+#[derive(Debug)]
+pub struct Hunk {
+    pub old_path: Option<String>,
+    pub path: Option<String>,
+    pub header: HunkHeader,
+    pub lines: Vec<Line>,
+    /// The edit operations turning the removed lines into the added
+    /// lines, if the hunk is refined.
+    pub edits: Vec<TextEdit>,
+}
+
+/// A position in a hunk: the index of a line in its lines, and a
+/// character offset in the content of the line.
+pub type Position = (usize, usize);
+
+/// An edit operation of a hunk, with the text of its tokens.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    pub kind: EditKind,
+    /// The start and the end of the removed text, None for an
+    /// insertion.
+    pub old_range: Option<(Position, Position)>,
+    /// The removed text, empty for an insertion.
+    pub old: Vec<u8>,
+    /// The start and the end of the added text, None for a deletion.
+    pub new_range: Option<(Position, Position)>,
+    /// The added text, empty for a deletion.
+    pub new: Vec<u8>,
+}
+
+impl Hunk {
+    /// The text of the lines of `kind` between `start` and `end`, with
+    /// a newline between the lines.
+    fn text(&self, (start, end): (Position, Position), kind: LineKind) -> Vec<u8> {
+        let mut text = vec![];
+        for (i, line) in self.lines.iter().enumerate().take(end.0 + 1).skip(start.0) {
+            if line.kind != kind {
+                continue;
+            }
+            if i != start.0 {
+                text.push(b'\n');
+            }
+            let lo = if i == start.0 { start.1 } else { 0 };
+            let hi = if i == end.0 { end.1 } else { usize::MAX };
+            let content = String::from_utf8_lossy(&line.content);
+            let chars = content.chars().take(hi).skip(lo).collect::<String>();
+            text.extend_from_slice(chars.as_bytes());
+        }
+        text
+    }
+
+    /// Adds the edit operation of `kind` between the removed text
+    /// `old_range` and the added text `new_range`.
+    pub fn push_edit(
+        &mut self,
+        kind: EditKind,
+        old_range: Option<(Position, Position)>,
+        new_range: Option<(Position, Position)>,
+    ) {
+        let edit = TextEdit {
+            kind,
+            old_range,
+            old: old_range.map_or(vec![], |range| self.text(range, LineKind::Removed)),
+            new_range,
+            new: new_range.map_or(vec![], |range| self.text(range, LineKind::Added)),
+        };
+        self.edits.push(edit);
+    }
+}
+
+/// The position in the hunk of the byte `offset` of `data`, where the
+/// lines of the hunk start at `starts`.
+fn position(data: &[u8], starts: &[usize], offset: usize) -> Position {
+    let i = starts.partition_point(|&start| start <= offset).max(1) - 1;
+    // the +/- prefix is not part of the content
+    let content = data.get(starts[i] + 1..offset).unwrap_or_default();
+    (i, String::from_utf8_lossy(content).chars().count())
+}
+
+/// The hunk of `header` whose lines are `lines`, recorded once it is
+/// processed. If it is refined, `lcs` are the tokens shared by its
+/// removed and added `tokens`: the refined spans are the parts of the
+/// changed lines that are not shared, and the edit operations are
+/// the tokens between two shared spans. The `masked` spans of
+/// --ignore-token are neither refined nor edited. The changed lines
+/// without shared tokens are not refined if `unpaired_unrefined` is
+/// set.
+pub fn refined(
+    (header, old_path, path): (HunkHeader, &Option<String>, &Option<String>),
+    lines: &LineSplit,
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+    (lcs, masked): (Option<&[Snake]>, &[Span]),
+    unpaired_unrefined: bool,
+    config: &AppConfig,
+) -> Hunk {
+    let data = lines.data();
+    let mut hunk = Hunk {
+        old_path: old_path.clone(),
+        path: path.clone(),
+        header,
+        lines: vec![],
+        edits: vec![],
+    };
+    let (mut ishared_removed, mut ishared_added) = (0, 0);
+    let mut segments = vec![];
+    for (lo, hi) in lines.iter() {
+        let kind = match data[lo] {
+            b'-' => LineKind::Removed,
+            b'+' => LineKind::Added,
+            b'\\' => LineKind::NoNewline,
+            _ => LineKind::Context,
+        };
+        // the empty context lines of --lenient have no prefix
+        let content_lo = (lo + 1).min(hi);
+        let content_hi = match data[content_lo..hi].strip_suffix(b"\n") {
+            Some(content) => content_lo + content.len(),
+            None => hi,
+        };
+        let content = match kind {
+            LineKind::NoNewline => vec![],
+            _ => data[content_lo..content_hi].to_vec(),
+        };
+        let mut spans = vec![];
+        let shared = match kind {
+            LineKind::Removed => Some((removed, &mut ishared_removed, false)),
+            LineKind::Added => Some((added, &mut ishared_added, true)),
+            _ => None,
+        };
+        if let (Some(lcs), Some((toks, ishared, is_plus))) = (lcs, shared) {
+            let shared = lcs
+                .iter()
+                .skip(*ishared)
+                .map(|s| HunkBuffer::shared_span(s, toks, is_plus));
+            *ishared += HunkBuffer::shared_segments(shared, &(lo, hi), &mut segments);
+            // the carriage return is not refined
+            let (data_hi, crlf) = split_crlf(data, (lo, hi));
+            let text_hi = if crlf { data_hi } else { content_hi };
+            // as displayed, the lines without counterpart and the lines
+            // too different are not refined
+            let unpaired = unpaired_unrefined && segments.is_empty();
+            let refined = !unpaired && !is_too_different(config, data, (lo, data_hi), &segments);
+            let chars = |offset: usize| position(data, &[lo], offset).1;
+            let mut unrefined = segments.clone();
+            unrefined.extend(
+                masked
+                    .iter()
+                    .filter(|&&(masked_lo, masked_hi)| masked_lo < hi && lo < masked_hi),
+            );
+            unrefined.sort_unstable();
+            unrefined.push((text_hi, text_hi));
+            let mut y = content_lo;
+            for &(shared_lo, shared_hi) in &unrefined {
+                let shared_lo = shared_lo.min(text_hi);
+                if refined && y < shared_lo {
+                    spans.push((chars(y), chars(shared_lo)));
+                }
+                y = y.max(shared_hi);
+            }
+        }
+        hunk.lines.push(Line {
+            kind,
+            content,
+            spans,
+        });
+    }
+    let lcs = match lcs {
+        Some(lcs) => lcs,
+        None => return hunk,
+    };
+    let starts = lines.iter().map(|(lo, _)| lo).collect::<Vec<_>>();
+    let is_masked = |tok: &HashedSpan| {
+        let i = masked.partition_point(|&(_, hi)| hi <= tok.lo);
+        masked
+            .get(i)
+            .is_some_and(|&(lo, hi)| lo <= tok.lo && tok.hi <= hi)
+    };
+    // the text of an edit starts and ends on a line of its side
+    let is_edge = |tok: &HashedSpan| is_masked(tok) || data[tok.lo..tok.hi] == *b"\n";
+    // the ranges of text of the tokens `lo..hi` of `toks`, without the
+    // masked tokens and the newlines at their ends
+    let range = |toks: &[HashedSpan], (mut lo, mut hi): (usize, usize)| {
+        while lo < hi && is_edge(&toks[lo]) {
+            lo += 1;
+        }
+        while lo < hi && is_edge(&toks[hi - 1]) {
+            hi -= 1;
+        }
+        if hi <= lo {
+            return None;
+        }
+        let start = toks[lo].lo;
+        let end = toks[hi - 1].hi;
+        let end = end - usize::from(data[start..end].ends_with(b"\n"));
+        let range = (position(data, &starts, start), position(data, &starts, end));
+        (range.0 != range.1).then_some(range)
+    };
+    let input = DiffInput {
+        removed: Tokenization::new(data, removed),
+        added: Tokenization::new(data, added),
+    };
+    for edit in diffr_lib::edits(&input, lcs) {
+        let old_range = range(removed, edit.removed);
+        let new_range = range(added, edit.added);
+        // an edit of newlines only has no text
+        let kind = match (old_range, new_range) {
+            (None, None) => continue,
+            (Some(_), None) => EditKind::Delete,
+            (None, Some(_)) => EditKind::Insert,
+            (Some(_), Some(_)) => edit.kind,
+        };
+        hunk.push_edit(kind, old_range, new_range);
+    }
+    hunk
+}
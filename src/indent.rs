@@ -0,0 +1,113 @@
+//! Detection of the lines whose indentation only changed, for
+//! --indent-changed.
+
+use super::Span;
+
+/// Splits `line`, without its +/- prefix, in its indentation and the
+/// rest, without the line end.
+fn split_indent(line: &[u8]) -> (&[u8], &[u8]) {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let len = line
+        .iter()
+        .position(|&b| b != b' ' && b != b'\t')
+        .unwrap_or(line.len());
+    line.split_at(len)
+}
+
+/// Returns true if the lines `removed` and `added`, with their +/-
+/// prefix, differ only by their indentation.
+fn is_indent_change(removed: &[u8], added: &[u8]) -> bool {
+    let (removed_indent, removed) = split_indent(&removed[1..]);
+    let (added_indent, added) = split_indent(&added[1..]);
+    !removed.is_empty() && removed == added && removed_indent != added_indent
+}
+
+/// Pairs each removed line of a block of changed lines with the next
+/// added line whose indentation only differs, given their indices in
+/// `spans`. The pairs keep the order of the lines.
+fn pair_block(
+    data: &[u8],
+    spans: &[Span],
+    (removed, added): (&[usize], &[usize]),
+    pairs: &mut [Option<Span>],
+) {
+    let line = |i: usize| &data[spans[i].0..spans[i].1];
+    // the first added line that can still be paired
+    let mut next = 0;
+    for &i in removed {
+        let found = added[next..]
+            .iter()
+            .position(|&j| is_indent_change(line(i), line(j)));
+        if let Some(k) = found {
+            let j = added[next + k];
+            pairs[i] = Some(spans[j]);
+            pairs[j] = Some(spans[i]);
+            next += k + 1;
+        }
+    }
+}
+
+/// Pairs the lines of a hunk whose indentation only changed, in each
+/// block of removed lines followed by added lines. Collects in `pairs`
+/// the span of the line paired with each line, if any.
+pub fn pair_lines<Lines>(data: &[u8], lines: Lines, pairs: &mut Vec<Option<Span>>)
+where
+    Lines: Iterator<Item = Span>,
+{
+    pairs.clear();
+    let spans = lines.collect::<Vec<_>>();
+    pairs.resize(spans.len(), None);
+    // the indices of the removed and added lines of the current block
+    let (mut removed, mut added) = (vec![], vec![]);
+    for (i, &(lo, _)) in spans.iter().enumerate() {
+        match data[lo] {
+            b'-' => {
+                if !added.is_empty() {
+                    pair_block(data, &spans, (&removed, &added), pairs);
+                    removed.clear();
+                    added.clear();
+                }
+                removed.push(i);
+            }
+            b'+' => added.push(i),
+            // "\ No newline at end of file"
+            b'\\' => (),
+            _ => {
+                pair_block(data, &spans, (&removed, &added), pairs);
+                removed.clear();
+                added.clear();
+            }
+        }
+    }
+    pair_block(data, &spans, (&removed, &added), pairs);
+}
+
+/// Describes an indentation, such as "2 tabs" or "1 tab, 4 spaces".
+fn describe(indent: &[u8]) -> String {
+    let tabs = indent.iter().filter(|&&b| b == b'\t').count();
+    let spaces = indent.len() - tabs;
+    let plural = |n: usize, name: &str| format!("{} {}{}", n, name, if n == 1 { "" } else { "s" });
+    match (tabs, spaces) {
+        (0, 0) => "no indentation".to_owned(),
+        (0, _) => plural(spaces, "space"),
+        (_, 0) => plural(tabs, "tab"),
+        _ => format!("{}, {}", plural(tabs, "tab"), plural(spaces, "space")),
+    }
+}
+
+/// The change of indentation from the line `old` to the line `new`,
+/// with their +/- prefix, such as "→ +4 spaces".
+pub fn delta(old: &[u8], new: &[u8]) -> String {
+    let (old, _) = split_indent(&old[1..]);
+    let (new, _) = split_indent(&new[1..]);
+    let is_only = |indent: &[u8], b: u8| indent.iter().all(|&c| c == b);
+    for (b, name) in [(b' ', "space"), (b'\t', "tab")] {
+        if is_only(old, b) && is_only(new, b) {
+            let n = new.len() as isize - old.len() as isize;
+            let plural = if n.abs() == 1 { "" } else { "s" };
+            return format!("→ {:+} {}{}", n, name, plural);
+        }
+    }
+    format!("→ {} to {}", describe(old), describe(new))
+}
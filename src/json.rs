@@ -0,0 +1,543 @@
+//! Rendering of the output of diffr as JSON, for --output=json and
+//! --output=json-stream, and parsing of the JSON files given to diffr.
+
+use diffr_lib::EditKind;
+use std::io::{self, Write};
+
+use super::hunks::{self, LineKind, Part, Record};
+use super::{parse_git_path, parse_new_path, parse_old_path};
+
+/// Writes `text` as a JSON string; invalid UTF-8 is replaced.
+pub fn write_string<W: Write>(text: &[u8], out: &mut W) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in String::from_utf8_lossy(text).chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+fn write_path<W: Write>(path: &Option<String>, out: &mut W) -> io::Result<()> {
+    match path {
+        Some(path) => write_string(path.as_bytes(), out),
+        None => out.write_all(b"null"),
+    }
+}
+
+fn kind_name(kind: LineKind) -> &'static str {
+    match kind {
+        LineKind::Removed => "removed",
+        LineKind::Added => "added",
+        LineKind::Context => "context",
+        LineKind::NoNewline => "no-newline",
+    }
+}
+
+fn write_number<W: Write>(number: Option<usize>, out: &mut W) -> io::Result<()> {
+    match number {
+        Some(number) => write!(out, "{}", number),
+        None => out.write_all(b"null"),
+    }
+}
+
+fn write_line<W: Write>(line: &hunks::Line, out: &mut W) -> io::Result<()> {
+    write!(out, "{{\"kind\":\"{}\",\"content\":", kind_name(line.kind))?;
+    write_string(&line.content, out)?;
+    let (bytes, columns) = line.mapped_spans();
+    write_spans("refined", &line.spans, out)?;
+    write_spans("refined_bytes", &bytes, out)?;
+    write_spans("refined_columns", &columns, out)?;
+    out.write_all(b"}")
+}
+
+fn write_spans<W: Write>(key: &str, spans: &[(usize, usize)], out: &mut W) -> io::Result<()> {
+    write!(out, ",\"{}\":[", key)?;
+    for (i, (lo, hi)) in spans.iter().enumerate() {
+        if 0 < i {
+            out.write_all(b",")?;
+        }
+        write!(out, "[{},{}]", lo, hi)?;
+    }
+    out.write_all(b"]")
+}
+
+fn write_edit<W: Write>(edit: &hunks::TextEdit, out: &mut W) -> io::Result<()> {
+    let op = match edit.kind {
+        EditKind::Insert => "insert",
+        EditKind::Delete => "delete",
+        EditKind::Replace => "replace",
+    };
+    write!(out, "{{\"op\":\"{}\",\"old\":", op)?;
+    write_string(&edit.old, out)?;
+    write_range("old_range", edit.old_range, out)?;
+    out.write_all(b",\"new\":")?;
+    write_string(&edit.new, out)?;
+    write_range("new_range", edit.new_range, out)?;
+    out.write_all(b"}")
+}
+
+fn write_range<W: Write>(
+    key: &str,
+    range: Option<(hunks::Position, hunks::Position)>,
+    out: &mut W,
+) -> io::Result<()> {
+    match range {
+        Some(((lo_line, lo), (hi_line, hi))) => write!(
+            out,
+            ",\"{}\":[[{},{}],[{},{}]]",
+            key, lo_line, lo, hi_line, hi
+        ),
+        None => write!(out, ",\"{}\":null", key),
+    }
+}
+
+/// Writes the hunks of `parts`, recorded while the diff is processed,
+/// as an array of hunks:
+///
+/// [{"old_path":"a.rs","path":"a.rs","old_start":1,"old_len":1,
+///   "new_start":1,"new_len":1,"lines":[
+///   {"kind":"removed","content":"a b","refined":[[2,3]],
+///    "refined_bytes":[[2,3]],"refined_columns":[[2,3]]},
+///   {"kind":"added","content":"a c","refined":[[2,3]],
+///    "refined_bytes":[[2,3]],"refined_columns":[[2,3]]}],
+///   "edits":[{"op":"replace","old":"b","old_range":[[0,2],[0,3]],
+///   "new":"c","new_range":[[1,2],[1,3]]}]}]
+///
+/// with a hunk per line. The refined spans are pairs of character
+/// offsets in the content of the lines, and the same spans in bytes
+/// and in display columns. The edits turn the removed lines of the
+/// hunk into its added lines: they are the tokens between two tokens
+/// shared by the refinement. Their ranges are pairs of an index in the
+/// lines of the hunk and a character offset in its content, null on
+/// the side without text.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    out.write_all(b"[")?;
+    for (i, hunk) in hunks::hunks(parts).enumerate() {
+        if 0 < i {
+            out.write_all(b",")?;
+        }
+        out.write_all(b"\n{\"old_path\":")?;
+        write_path(&hunk.old_path, out)?;
+        out.write_all(b",\"path\":")?;
+        write_path(&hunk.path, out)?;
+        let header = hunk.header;
+        write!(
+            out,
+            ",\"old_start\":{},\"old_len\":{},\"new_start\":{},\"new_len\":{},\"lines\":[",
+            header.old_start, header.old_len, header.new_start, header.new_len
+        )?;
+        for (i, line) in hunk.lines.iter().enumerate() {
+            if 0 < i {
+                out.write_all(b",")?;
+            }
+            write_line(line, out)?;
+        }
+        out.write_all(b"],\"edits\":[")?;
+        for (i, edit) in hunk.edits.iter().enumerate() {
+            if 0 < i {
+                out.write_all(b",")?;
+            }
+            write_edit(edit, out)?;
+        }
+        out.write_all(b"]}")?;
+    }
+    out.write_all(b"\n]\n")
+}
+
+/// Writes the parts of a diff as they are recorded, as a line of JSON
+/// per event:
+///
+/// {"event":"file-start","old_path":"a.rs","path":"a.rs"}
+/// {"event":"hunk-start","old_start":1,"old_len":1,"new_start":1,"new_len":1}
+/// {"event":"line","kind":"removed","old_line":1,"new_line":null,"content":"a b"}
+/// {"event":"span","start":2,"end":3,"start_byte":2,"end_byte":3,"start_column":2,"end_column":3}
+/// {"event":"line","kind":"added","old_line":null,"new_line":1,"content":"a c"}
+/// {"event":"span","start":2,"end":3,"start_byte":2,"end_byte":3,"start_column":2,"end_column":3}
+/// {"event":"file-end","path":"a.rs"}
+///
+/// The span events follow the line they refine. A file starts at its
+/// '+++' header, or at its first hunk without one, and ends at the
+/// next file or at `finish`.
+pub struct EventStream<W: Write> {
+    out: W,
+    old_path: Option<String>,
+    path: Option<String>,
+    in_file: bool,
+}
+
+impl<W: Write> EventStream<W> {
+    pub fn new(out: W) -> Self {
+        EventStream {
+            out,
+            old_path: None,
+            path: None,
+            in_file: false,
+        }
+    }
+
+    /// Ends the current file.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.end_file()?;
+        self.out.flush()
+    }
+
+    fn start_file(&mut self) -> io::Result<()> {
+        if self.in_file {
+            return Ok(());
+        }
+        self.in_file = true;
+        self.out
+            .write_all(b"{\"event\":\"file-start\",\"old_path\":")?;
+        write_path(&self.old_path, &mut self.out)?;
+        self.out.write_all(b",\"path\":")?;
+        write_path(&self.path, &mut self.out)?;
+        self.out.write_all(b"}\n")
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        if !self.in_file {
+            return Ok(());
+        }
+        self.in_file = false;
+        self.out.write_all(b"{\"event\":\"file-end\",\"path\":")?;
+        write_path(&self.path, &mut self.out)?;
+        self.out.write_all(b"}\n")
+    }
+
+    /// Writes the events of a line outside of the hunks, without its
+    /// newline: the headers of the files start and end them.
+    fn header_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if let Some(line) = line.strip_prefix(b"diff --git ") {
+            self.end_file()?;
+            self.old_path = None;
+            self.path = parse_git_path(line);
+        } else if let Some(line) = line.strip_prefix(b"--- ") {
+            self.end_file()?;
+            self.old_path = parse_old_path(line);
+        } else if let Some(line) = line.strip_prefix(b"+++ ") {
+            self.path = parse_new_path(line);
+            self.start_file()?;
+        }
+        Ok(())
+    }
+
+    fn hunk(&mut self, hunk: &hunks::Hunk) -> io::Result<()> {
+        self.start_file()?;
+        let header = hunk.header;
+        writeln!(
+            self.out,
+            "{{\"event\":\"hunk-start\",\"old_start\":{},\"old_len\":{},\
+             \"new_start\":{},\"new_len\":{}}}",
+            header.old_start, header.old_len, header.new_start, header.new_len
+        )?;
+        let (mut old, mut new) = (header.old_start, header.new_start);
+        for line in &hunk.lines {
+            let (old_line, new_line) = match line.kind {
+                LineKind::Removed => (Some(old), None),
+                LineKind::Added => (None, Some(new)),
+                LineKind::Context => (Some(old), Some(new)),
+                LineKind::NoNewline => (None, None),
+            };
+            old += old_line.is_some() as usize;
+            new += new_line.is_some() as usize;
+            self.write_line(line, old_line, new_line)?;
+        }
+        Ok(())
+    }
+
+    fn write_line(
+        &mut self,
+        line: &hunks::Line,
+        old_line: Option<usize>,
+        new_line: Option<usize>,
+    ) -> io::Result<()> {
+        let out = &mut self.out;
+        write!(
+            out,
+            "{{\"event\":\"line\",\"kind\":\"{}\"",
+            kind_name(line.kind)
+        )?;
+        out.write_all(b",\"old_line\":")?;
+        write_number(old_line, out)?;
+        out.write_all(b",\"new_line\":")?;
+        write_number(new_line, out)?;
+        out.write_all(b",\"content\":")?;
+        write_string(&line.content, out)?;
+        out.write_all(b"}\n")?;
+        let (bytes, columns) = line.mapped_spans();
+        for ((span, bytes), columns) in line.spans.iter().zip(&bytes).zip(&columns) {
+            writeln!(
+                out,
+                "{{\"event\":\"span\",\"start\":{},\"end\":{},\"start_byte\":{},\
+                 \"end_byte\":{},\"start_column\":{},\"end_column\":{}}}",
+                span.0, span.1, bytes.0, bytes.1, columns.0, columns.1
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the events of `part`.
+    pub fn write_part(&mut self, part: &Part) -> io::Result<()> {
+        match part {
+            Part::Lines(lines) => {
+                for line in lines.split(|&b| b == b'\n') {
+                    self.header_line(line)?;
+                }
+                Ok(())
+            }
+            Part::Hunk(hunk) => self.hunk(hunk),
+        }
+    }
+}
+
+impl<W: Write> Record for EventStream<W> {
+    fn record(&mut self, part: Part) -> io::Result<()> {
+        self.write_part(&part)
+    }
+}
+
+/// Writes `parts` as the events of `EventStream`.
+pub fn write_stream<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    let mut stream = EventStream::new(out);
+    for part in parts {
+        stream.write_part(part)?;
+    }
+    stream.finish()
+}
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// The members of an object, in their order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The value of a number that is a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Value::Number(n) if 0.0 <= n && n.fract() == 0.0 && n <= usize::MAX as f64 => {
+                Some(n as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses the JSON document `text`.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_blanks();
+    if parser.pos < parser.text.len() {
+        return Err(parser.error("unexpected data after the value"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn skip_blanks(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips the blanks, then `b` if it is next.
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_blanks();
+        let found = self.text.get(self.pos) == Some(&b);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), String> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", char::from(b))))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_blanks();
+        match self.text.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.text.get(self.pos) {
+            self.pos += 1;
+        }
+        let number = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        match number.parse() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+
+    /// Parses the 4 hexadecimal digits of a "\u" escape.
+    fn code_unit(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).unwrap_or_default();
+        let unit = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(unit)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut text = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.text.get(self.pos) {
+                if b == b'"' || b == b'\\' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // the text is split at ASCII characters only
+            text.push_str(std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default());
+            match self.text.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some(_) => self.pos += 1,
+                None => return Err(self.error("unterminated string")),
+            }
+            let escaped = self.text.get(self.pos).copied();
+            self.pos += 1;
+            let c = match escaped {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    let mut unit = self.code_unit()?;
+                    // a surrogate pair
+                    if (0xd800..0xdc00).contains(&unit) && self.text[self.pos..].starts_with(b"\\u")
+                    {
+                        let pos = self.pos;
+                        self.pos += 2;
+                        let low = self.code_unit()?;
+                        if (0xdc00..0xe000).contains(&low) {
+                            unit = 0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00);
+                        } else {
+                            self.pos = pos;
+                        }
+                    }
+                    char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("invalid escape"));
+                }
+            };
+            text.push(c);
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        if self.eat(b']') {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat(b']') {
+                return Ok(Value::Array(values));
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect(b'{')?;
+        let mut members = vec![];
+        if self.eat(b'}') {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_blanks();
+            let name = self.string()?;
+            self.expect(b':')?;
+            members.push((name, self.value()?));
+            if self.eat(b'}') {
+                return Ok(Value::Object(members));
+            }
+            self.expect(b',')?;
+        }
+    }
+}
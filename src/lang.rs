@@ -0,0 +1,202 @@
+//! Tokenization rules tuned for some languages, for --lang.
+
+use diffr_lib::{hash_slice, HashedSpan};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    /// Selected from the extension of each file, or from its shebang
+    /// or modeline.
+    Auto,
+    Rust,
+    Python,
+    C,
+    Json,
+    Prose,
+}
+
+const RUST_OPERATORS: &[&[u8]] = &[
+    b"..=", b"...", b"<<=", b">>=", b"::", b"->", b"=>", b"==", b"!=", b"<=", b">=", b"&&", b"||",
+    b"..", b"+=", b"-=", b"*=", b"/=", b"%=", b"^=", b"&=", b"|=", b"<<", b">>",
+];
+
+const PYTHON_OPERATORS: &[&[u8]] = &[
+    b"**=", b"//=", b">>=", b"<<=", b"...", b"**", b"//", b"->", b"==", b"!=", b"<=", b">=", b":=",
+    b"+=", b"-=", b"*=", b"/=", b"%=", b"@=", b"&=", b"|=", b"^=", b"<<", b">>",
+];
+
+const C_OPERATORS: &[&[u8]] = &[
+    b"<<=", b">>=", b"...", b"->", b"++", b"--", b"==", b"!=", b"<=", b">=", b"&&", b"||", b"<<",
+    b">>", b"+=", b"-=", b"*=", b"/=", b"%=", b"&=", b"|=", b"^=", b"::", b"##",
+];
+
+impl Lang {
+    /// The language of the file at `path`, for `Lang::Auto`.
+    pub fn resolve(self, path: Option<&str>) -> Option<Lang> {
+        if self != Lang::Auto {
+            return Some(self);
+        }
+        let extension = path?.rsplit('/').next()?.rsplit_once('.')?.1;
+        match extension {
+            "rs" => Some(Lang::Rust),
+            "py" | "pyi" => Some(Lang::Python),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "java" | "js" | "ts"
+            | "go" => Some(Lang::C),
+            "json" => Some(Lang::Json),
+            "md" | "markdown" | "txt" | "rst" | "adoc" | "org" => Some(Lang::Prose),
+            _ => None,
+        }
+    }
+
+    /// The language of a name of Emacs mode, Vim filetype or
+    /// interpreter.
+    fn from_name(name: &str) -> Option<Lang> {
+        match name.to_ascii_lowercase().trim_end_matches("-mode") {
+            "rust" | "rust-script" => Some(Lang::Rust),
+            "python" | "pypy" | "pypy3" => Some(Lang::Python),
+            name if name.starts_with("python") => Some(Lang::Python),
+            "c" | "c++" | "cpp" | "java" | "javascript" | "js" | "typescript" | "go" | "node"
+            | "deno" | "bun" => Some(Lang::C),
+            "json" => Some(Lang::Json),
+            "markdown" | "text" | "rst" | "org" | "asciidoc" => Some(Lang::Prose),
+            _ => None,
+        }
+    }
+
+    fn operators(self) -> &'static [&'static [u8]] {
+        match self {
+            Lang::Rust => RUST_OPERATORS,
+            Lang::Python => PYTHON_OPERATORS,
+            Lang::C => C_OPERATORS,
+            _ => &[],
+        }
+    }
+}
+
+/// Merges the tokens `tokens[start..]` of a line according to the
+/// rules of `lang`:
+/// - the operators of Rust, Python and C-like languages, such as `::`
+///   or `->`, are single tokens;
+/// - the string literals of JSON are single tokens;
+/// - in prose, words joined by an apostrophe or a hyphen, such as
+///   "don't", are single tokens.
+pub fn merge_tokens(src: &[u8], tokens: &mut Vec<HashedSpan>, start: usize, lang: Lang) {
+    let text = |token: &HashedSpan| &src[token.lo..token.hi];
+    // the tokens are merged in place, the merged tokens being written
+    // at `w`
+    let mut w = start;
+    let mut i = start;
+    while i < tokens.len() {
+        let lo = tokens[i].lo;
+        let n = match lang {
+            Lang::Json if text(&tokens[i]) == b"\"" => {
+                // up to the closing quote, if any
+                let mut j = i + 1;
+                while j < tokens.len() && text(&tokens[j]) != b"\"" {
+                    j += if text(&tokens[j]) == b"\\" { 2 } else { 1 };
+                }
+                if j < tokens.len() {
+                    j + 1 - i
+                } else {
+                    1
+                }
+            }
+            Lang::Prose => {
+                let is_word = |j: usize| {
+                    tokens
+                        .get(j)
+                        .is_some_and(|token| text(token).iter().all(u8::is_ascii_alphanumeric))
+                };
+                let mut j = i;
+                while is_word(j)
+                    && matches!(tokens.get(j + 1).map(text), Some(b"'") | Some(b"-"))
+                    && is_word(j + 2)
+                {
+                    j += 2;
+                }
+                j + 1 - i
+            }
+            _ => lang
+                .operators()
+                .iter()
+                .find(|op| {
+                    let end = i + op.len() - 1;
+                    end < tokens.len()
+                        && tokens[end].hi == lo + op.len()
+                        && src[lo..tokens[end].hi] == ***op
+                })
+                .map_or(1, |op| op.len()),
+        };
+        let n = n.min(tokens.len() - i);
+        let hi = tokens[i + n - 1].hi;
+        tokens[w] = if n == 1 {
+            tokens[i]
+        } else {
+            HashedSpan {
+                lo,
+                hi,
+                hash: hash_slice(&src[lo..hi]),
+            }
+        };
+        w += 1;
+        i += n;
+    }
+    tokens.truncate(w);
+}
+
+/// The interpreter of a shebang line, such as "python3" for
+/// "#!/usr/bin/env python3".
+fn shebang_interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program != "env" {
+        return Some(program);
+    }
+    // the options of env, such as "-S"
+    words.find(|word| !word.starts_with('-'))
+}
+
+/// The mode of an Emacs modeline, such as "-*- mode: python -*-" or
+/// "-*- python -*-".
+fn emacs_mode(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    if !vars.contains(':') {
+        return Some(vars.trim());
+    }
+    vars.split(';').find_map(|var| {
+        let (name, value) = var.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| value.trim())
+    })
+}
+
+/// The filetype of a Vim modeline, such as "vim: set ft=python:" or
+/// "vi: filetype=python".
+fn vim_filetype(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let i = line.find(marker)?;
+        let preceded = line[..i].ends_with(char::is_whitespace) || i == 0;
+        preceded.then(|| i + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+}
+
+/// The language of a file from a line of its content: its shebang,
+/// when the line is the `first` one of the file, or a modeline of
+/// Emacs or Vim.
+pub fn detect(line: &[u8], first: bool) -> Option<Lang> {
+    let line = std::str::from_utf8(line).ok()?;
+    if first && line.starts_with("#!") {
+        return shebang_interpreter(line).and_then(Lang::from_name);
+    }
+    emacs_mode(line)
+        .or_else(|| vim_filetype(line))
+        .and_then(Lang::from_name)
+}
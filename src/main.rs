@@ -1,46 +1,781 @@
 use atty::{is, Stream};
-use std::io::{self, BufRead};
-use std::time::SystemTime;
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 use termcolor::{
-    Color,
-    Color::{Green, Red, White},
-    ColorChoice, ColorSpec, StandardStream, WriteColor,
+    Ansi, Buffer, Color,
+    Color::{Black, Cyan, Green, Magenta, Red, White, Yellow},
+    ColorChoice, ColorSpec, HyperlinkSpec, NoColor, StandardStream, WriteColor,
 };
+use unicode_width::UnicodeWidthChar;
 
-use diffr_lib::{DiffInput, HashedSpan, LineSplit, Snake, Tokenization};
+use algorithm::Algorithm;
+use annotations::Annotations;
+use binary::BinaryMode;
+use blame::Blame;
+use bytes::EscapeBytes;
+use chunks::Chunk;
+use commit::{CommitFormat, CommitHeader};
+use debug::DebugFormat;
+use diffr_lib::{HashedSpan, LineSplit, Snake};
+use extended::RenameFormat;
+use filter::{FileFilter, Filtered};
+use fold::Fold;
+use grep::Grep;
+use headers::Timestamps;
+use lang::Lang;
+use palette::{Background, Theme};
+use redact::{Redact, RedactStyle};
+use reslice::Reslice;
+use sort::SortFiles;
+use stats::{DiffStats, Timings};
+use tokenize::Granularity;
 
+mod algorithm;
+mod annotated;
+mod annotations;
+mod apply;
+mod bench;
+mod binary;
+mod blame;
+mod block;
+mod bytes;
+mod cache;
+mod chunks;
 mod cli_args;
+mod commit;
+mod conflicts;
+mod debug;
+mod expectation;
+mod extended;
+mod filter;
+mod fold;
+mod git_config;
+mod git_diff;
+mod grep;
+mod headers;
+mod html;
+mod hunks;
+mod indent;
+mod json;
+mod lang;
+mod man;
+mod markdown;
+mod mmap;
+mod nonprinting;
+mod pager;
+mod pairing;
+mod palette;
+mod patch;
+mod preview;
+mod profile;
+mod quickfix;
+mod redact;
+mod relocated;
+mod reslice;
+mod scan;
+mod server;
+mod sexp;
+mod slider;
+mod sort;
+mod split;
+mod stats;
+mod svg;
+mod theme_file;
+mod threeway;
+mod tokenize;
+mod tui;
+mod watch;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AppConfig {
     debug: bool,
+    /// The format of the diagnostics of --debug.
+    debug_format: DebugFormat,
+    /// Measure the time spent in each phase of the processing.
+    profile: bool,
+    keep_input_colors: bool,
+    internal_pager: bool,
+    tui: bool,
+    line_numbers: Option<LineNumberStyle>,
+    line_number_separator: String,
+    /// The minimum width of the columns of the gutter, with
+    /// --line-numbers-width.
+    line_number_width: usize,
+    /// The template of the gutter, with --line-numbers-format.
+    line_number_format: Option<Vec<GutterPart>>,
+    hyperlinks: bool,
+    hyperlink_format: String,
+    /// The template of --repo-url, the links to the hosted source.
+    repo_url: Option<String>,
+    include_paths: Vec<String>,
+    exclude_paths: Vec<String>,
+    /// The globs of the generated files, which are collapsed.
+    generated_paths: Vec<String>,
+    stats: bool,
+    diffstat: bool,
+    quiet: bool,
+    refine_threshold: Option<usize>,
+    max_refine_cost: Option<usize>,
+    /// The maximal size of the cache of --cache, in bytes.
+    cache: Option<u64>,
+    ascii_tokenize: bool,
+    escape_bytes: bool,
+    tabs: Option<usize>,
+    word_diff: bool,
+    color: ColorChoice,
+    max_line_length: Option<usize>,
+    truncation_marker: String,
+    /// The column of the content of the lines after which the guide of
+    /// --ruler is drawn.
+    ruler: Option<usize>,
+    /// The hunks separated by fewer unchanged lines are merged, with
+    /// --merge-hunks.
+    merge_hunks: Option<usize>,
+    /// The hunks are split into chunks of at least that many lines, with
+    /// --split-hunks.
+    split_hunks: Option<usize>,
+    /// The messages displayed under the lines, with --annotations.
+    annotations: Annotations,
+    /// The faces of the files matching a glob, from the configuration
+    /// file.
+    file_faces: Vec<profile::FileFaces>,
+    /// The marker of the files and hunks, with --navigate.
+    navigate: Option<String>,
+    slider: bool,
+    line_pairing: bool,
+    /// The minimal similarity of paired lines of --pairing-threshold.
+    pairing_threshold: Option<f64>,
+    refine_block: bool,
+    /// The size of the tokens refined, for --refine.
+    refine_granularity: Granularity,
+    /// The width of the lines refined by character with --refine=auto.
+    refine_char_width: usize,
+    /// The receiver of the hunks, recorded for the output formats that
+    /// describe them.
+    recorder: Option<hunks::Recorder>,
+    blame: bool,
+    /// The hunks whose changed lines all match one of these are not
+    /// refined, and painted with the ignored face.
+    ignore_matching_lines: Vec<Regex>,
+    /// The regexes of --grep.
+    grep: Vec<Regex>,
+    /// The regexes of the tokens compared as equal, for --ignore-token.
+    ignore_tokens: Vec<Regex>,
+    /// The regexes of the text replaced in the output, for --redact.
+    redact: Vec<Regex>,
+    redact_style: RedactStyle,
+    lenient: bool,
+    /// A hunk whose lines do not match the counts of its header is an
+    /// error, with --strict.
+    strict: bool,
+    git_revisions: Option<String>,
+    git_paths: Vec<String>,
+    context: Option<usize>,
+    /// The context lines missing from the input are read from the blobs
+    /// of git, with --resolve-context.
+    resolve_context: bool,
+    collapse_context: Option<usize>,
+    only_changes: bool,
+    indent_changed: bool,
+    /// Whether the lines moved within their hunk are subdued, for
+    /// --relocated.
+    relocated: bool,
+    indent_delta: bool,
+    /// Whether the changed lines that are partly refined are marked by
+    /// a symbol, for --accessibility=symbols.
+    symbols: bool,
+    timestamps: Timestamps,
+    sort_files: Option<SortFiles>,
+    /// The number of leading components removed from the paths of the
+    /// files, with --strip-prefix.
+    strip_prefix: Option<usize>,
+    show_cr: bool,
+    show_nonprinting: bool,
+    binary: BinaryMode,
+    lang: Option<Lang>,
+    ignore_case: bool,
+    algorithm: Algorithm,
+    output: OutputFormat,
+    output_file: Option<(String, bool)>,
+    file_colors: bool,
     added_face: ColorSpec,
     refine_added_face: ColorSpec,
     removed_face: ColorSpec,
     refine_removed_face: ColorSpec,
+    line_number_face: ColorSpec,
+    no_newline_face: ColorSpec,
+    folded_face: ColorSpec,
+    case_changed_face: ColorSpec,
+    commit_face: ColorSpec,
+    commit_meta_face: ColorSpec,
+    commit_format: CommitFormat,
+    mode_face: ColorSpec,
+    rename_face: ColorSpec,
+    submodule_face: ColorSpec,
+    ignored_face: ColorSpec,
+    number_changed_face: ColorSpec,
+    blame_face: ColorSpec,
+    apply_warning_face: ColorSpec,
+    apply_rejected_face: ColorSpec,
+    search_match_face: ColorSpec,
+    indent_changed_face: ColorSpec,
+    carriage_return_face: ColorSpec,
+    nonprinting_face: ColorSpec,
+    function_context_face: ColorSpec,
+    binary_face: ColorSpec,
+    masked_face: ColorSpec,
+    relocated_face: ColorSpec,
+    mail_header_face: ColorSpec,
+    message_face: ColorSpec,
+    ruler_face: ColorSpec,
+    overlong_face: ColorSpec,
+    annotation_error_face: ColorSpec,
+    annotation_warning_face: ColorSpec,
+    annotation_note_face: ColorSpec,
+    chunk_header_face: ColorSpec,
+    compact_headers: bool,
+    rename_format: RenameFormat,
+    /// Render the sample diff of --preview-colors instead of the input.
+    preview_colors: bool,
+    /// Answer the requests of --serve instead of reading a diff.
+    serve: bool,
+    /// Stop when the standard output is closed, such as by a pager
+    /// that quits, while the output is rendered in a buffer.
+    stop_on_closed_stdout: bool,
+    /// The command of --watch, empty to run git diff.
+    watch: Option<Vec<String>>,
+    /// The file with conflict markers of --conflicts.
+    conflicts: Option<String>,
+    /// The diff files of --input and of the command line, read instead
+    /// of the standard input.
+    inputs: Vec<String>,
+    /// The directory of --split-output.
+    split_output: Option<String>,
+    /// The diff files of our side and their side of --three-way.
+    three_way: Option<(String, String)>,
+    /// The patch and the directory of --apply-preview.
+    apply_preview: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumberStyle {
+    /// The gutter is as narrow as possible for each hunk.
+    Compact,
+    /// The gutter is at least 4 digits wide, so that most hunks have
+    /// the same gutter width.
+    Aligned,
+    /// The old and new columns are each as narrow as possible.
+    Both,
+    /// Only the line numbers in the new file are displayed.
+    RightOnly,
+}
+
+/// The widths of the old and new columns of the gutter of a hunk; the
+/// old column is not displayed if its width is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GutterWidth {
+    old: usize,
+    new: usize,
+}
+
+/// The alignment of a line number in the gutter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A part of the template of --line-numbers-format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GutterPart {
+    Text(String),
+    /// The line number of the new file, or of the old one, with its
+    /// alignment and the width of its column if given.
+    Number {
+        new: bool,
+        align: Align,
+        width: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Text with escape codes.
+    Terminal,
+    /// A fenced code block per file.
+    Markdown,
+    /// A `<pre>` block per file, with the faces as inline HTML.
+    MarkdownHtml,
+    /// An image of a terminal.
+    Svg,
+    /// A standalone HTML page.
+    Html,
+    /// A `path:line: summary` line per block of changed lines.
+    Quickfix,
+    /// An s-expression per hunk, with the refined spans of its lines.
+    Sexp,
+    /// An array of the hunks, with the refined spans of their lines.
+    Json,
+    /// A line of JSON per file, hunk, line and refined span, written as
+    /// the diff is read.
+    JsonStream,
+    /// The diff without faces, with the refined spans between brackets.
+    Annotated,
+    /// The lines of the input, without faces, as a patch to apply.
+    Patch,
+    /// The lines of the hunks, with their refined spans between
+    /// markers, for --emit-test-expectation.
+    Expectation,
+}
+
+impl OutputFormat {
+    /// The extension of the files written in the format.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Terminal => "diff",
+            OutputFormat::Html => "html",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Markdown | OutputFormat::MarkdownHtml => "md",
+            OutputFormat::Quickfix => "txt",
+            OutputFormat::Sexp => "sexp",
+            OutputFormat::Json => "json",
+            OutputFormat::JsonStream => "jsonl",
+            OutputFormat::Annotated => "txt",
+            OutputFormat::Patch => "patch",
+            OutputFormat::Expectation => "expected",
+        }
+    }
+
+    /// Whether the format describes the hunks recorded while the diff is
+    /// processed, rather than its rendering.
+    fn is_recorded(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Terminal
+                | OutputFormat::Html
+                | OutputFormat::Svg
+                | OutputFormat::Markdown
+                | OutputFormat::MarkdownHtml
+        )
+    }
+
+    /// The format of a file written with --output-file, from the
+    /// extension of its path.
+    fn from_path(path: &str) -> OutputFormat {
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("html") | Some("htm") => OutputFormat::Html,
+            Some("svg") => OutputFormat::Svg,
+            Some("md") => OutputFormat::Markdown,
+            Some("patch") => OutputFormat::Patch,
+            Some("jsonl") => OutputFormat::JsonStream,
+            _ => OutputFormat::Terminal,
+        }
+    }
+}
+
+/// The default cost above which hunks are not refined; the cost of a
+/// hunk is the product of its numbers of added and removed tokens.
+const DEFAULT_MAX_REFINE_COST: usize = 100_000_000;
+
+/// The default number of context lines displayed in a row with
+/// --collapse-context.
+const DEFAULT_COLLAPSE_CONTEXT: usize = 6;
+/// The width of the lines refined by character with --refine=auto.
+const DEFAULT_REFINE_CHAR_WIDTH: usize = 40;
+
+/// The default marker of the files and hunks with --navigate.
+const NAVIGATE_MARKER: &str = "Δ";
+
+/// The options of less for the markers of --navigate, written for the
+/// LESS environment variable: the marker is searched, and the prompt
+/// displays the keys jumping between the markers.
+fn navigate_prompt(marker: &str) -> String {
+    let escaped = |special: &str| {
+        let mut escaped = String::new();
+        for c in marker.chars() {
+            if special.contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+    format!(
+        "-R --pattern=^{} -Ps{} n/N\\: next/previous file or hunk\\.$",
+        escaped("\\^$.|?*+()[]{}"),
+        escaped("\\?:.%")
+    )
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         AppConfig {
             debug: false,
+            debug_format: DebugFormat::Text,
+            profile: false,
+            keep_input_colors: false,
+            internal_pager: false,
+            tui: false,
+            line_numbers: None,
+            line_number_separator: " | ".to_owned(),
+            line_number_width: 0,
+            line_number_format: None,
+            hyperlinks: false,
+            hyperlink_format: "file://{path}#{line}".to_owned(),
+            repo_url: None,
+            include_paths: vec![],
+            exclude_paths: vec![],
+            generated_paths: vec![],
+            stats: false,
+            diffstat: false,
+            quiet: false,
+            refine_threshold: None,
+            max_refine_cost: Some(DEFAULT_MAX_REFINE_COST),
+            cache: None,
+            ascii_tokenize: false,
+            escape_bytes: false,
+            tabs: None,
+            word_diff: false,
+            color: ColorChoice::Always,
+            max_line_length: None,
+            truncation_marker: "…".to_owned(),
+            ruler: None,
+            merge_hunks: None,
+            split_hunks: None,
+            annotations: Annotations::default(),
+            file_faces: vec![],
+            navigate: None,
+            slider: true,
+            line_pairing: true,
+            pairing_threshold: None,
+            refine_block: false,
+            refine_granularity: Granularity::Word,
+            refine_char_width: DEFAULT_REFINE_CHAR_WIDTH,
+            recorder: None,
+            blame: false,
+            ignore_matching_lines: vec![],
+            grep: vec![],
+            ignore_tokens: vec![],
+            redact: vec![],
+            redact_style: RedactStyle::Block,
+            lenient: false,
+            strict: false,
+            git_revisions: None,
+            git_paths: vec![],
+            context: None,
+            resolve_context: false,
+            collapse_context: None,
+            only_changes: false,
+            indent_changed: false,
+            relocated: false,
+            indent_delta: false,
+            symbols: false,
+            timestamps: Timestamps::Keep,
+            sort_files: None,
+            strip_prefix: None,
+            show_cr: false,
+            show_nonprinting: false,
+            binary: BinaryMode::Summary,
+            lang: None,
+            ignore_case: false,
+            algorithm: Algorithm::Myers,
+            output: OutputFormat::Terminal,
+            output_file: None,
+            file_colors: true,
             added_face: color_spec(Some(Green), None, false),
             refine_added_face: color_spec(Some(White), Some(Green), true),
             removed_face: color_spec(Some(Red), None, false),
             refine_removed_face: color_spec(Some(White), Some(Red), true),
+            line_number_face: color_spec(None, None, false),
+            no_newline_face: dimmed(),
+            folded_face: dimmed(),
+            case_changed_face: ColorSpec::new(),
+            commit_face: color_spec(Some(Yellow), None, false),
+            commit_meta_face: ColorSpec::new(),
+            commit_format: CommitFormat::Full,
+            mode_face: color_spec(Some(Cyan), None, false),
+            rename_face: color_spec(Some(Cyan), None, false),
+            submodule_face: color_spec(Some(Magenta), None, false),
+            ignored_face: dimmed(),
+            number_changed_face: ColorSpec::new(),
+            blame_face: dimmed(),
+            apply_warning_face: color_spec(Some(Yellow), None, false),
+            apply_rejected_face: color_spec(Some(Red), None, true),
+            search_match_face: color_spec(Some(Black), Some(Yellow), true),
+            indent_changed_face: dimmed(),
+            carriage_return_face: dimmed(),
+            nonprinting_face: color_spec(Some(Yellow), None, true),
+            function_context_face: ColorSpec::new(),
+            binary_face: color_spec(Some(Magenta), None, true),
+            masked_face: dimmed(),
+            relocated_face: dimmed(),
+            mail_header_face: color_spec(None, None, true),
+            message_face: ColorSpec::new(),
+            ruler_face: dimmed(),
+            overlong_face: ColorSpec::new(),
+            annotation_error_face: color_spec(Some(Color::Red), None, true),
+            annotation_warning_face: color_spec(Some(Color::Yellow), None, true),
+            annotation_note_face: color_spec(Some(Color::Cyan), None, false),
+            chunk_header_face: dimmed(),
+            compact_headers: false,
+            rename_format: RenameFormat::Full,
+            preview_colors: false,
+            serve: false,
+            stop_on_closed_stdout: false,
+            watch: None,
+            conflicts: None,
+            inputs: vec![],
+            split_output: None,
+            three_way: None,
+            apply_preview: None,
         }
     }
 }
 
 fn main() {
     let matches = cli_args::get_matches();
-    if is(Stream::Stdin) {
+    let reads_stdin = !matches.is_present(cli_args::FLAG_GIT)
+        && !matches.is_present(cli_args::FLAG_BENCH)
+        && !matches.is_present(cli_args::FLAG_HELP_COLORS)
+        && !matches.is_present(cli_args::FLAG_DUMP_THEME)
+        && !matches.is_present(cli_args::FLAG_MAN_PAGE)
+        && !matches.is_present(cli_args::FLAG_NAVIGATE_PROMPT)
+        && !matches.is_present(cli_args::FLAG_PREVIEW_COLORS)
+        && !matches.is_present(cli_args::FLAG_WATCH)
+        && !matches.is_present(cli_args::FLAG_CONFLICTS)
+        && !matches.is_present(cli_args::FLAG_THREE_WAY)
+        && !matches.is_present(cli_args::FLAG_APPLY_PREVIEW)
+        && !matches.is_present(cli_args::FLAG_INPUT)
+        && !matches.is_present(cli_args::ARG_INPUTS);
+    if is(Stream::Stdin) && reads_stdin {
         eprintln!("{}", matches.usage());
         std::process::exit(-1)
     }
 
-    let mut config = AppConfig::default();
-    config.debug = matches.is_present(cli_args::FLAG_DEBUG);
+    let mut config = AppConfig {
+        debug: matches.is_present(cli_args::FLAG_DEBUG),
+        debug_format: cli_args::debug_format(&matches),
+        profile: matches.is_present(cli_args::FLAG_DEBUG)
+            || matches.is_present(cli_args::FLAG_BENCH),
+        keep_input_colors: matches.is_present(cli_args::FLAG_KEEP_INPUT_COLORS),
+        internal_pager: matches.value_of(cli_args::FLAG_PAGER) == Some("internal"),
+        tui: matches.is_present(cli_args::FLAG_TUI),
+        line_numbers: cli_args::line_numbers(&matches),
+        hyperlinks: matches.is_present(cli_args::FLAG_HYPERLINKS),
+        repo_url: matches.value_of(cli_args::FLAG_REPO_URL).map(str::to_owned),
+        include_paths: cli_args::values(&matches, cli_args::FLAG_PATH),
+        exclude_paths: cli_args::values(&matches, cli_args::FLAG_EXCLUDE),
+        generated_paths: cli_args::generated_paths(&matches),
+        stats: matches.is_present(cli_args::FLAG_STATS),
+        diffstat: matches.is_present(cli_args::FLAG_DIFFSTAT),
+        quiet: matches.is_present(cli_args::FLAG_QUIET),
+        refine_threshold: cli_args::parsed_value(&matches, cli_args::FLAG_REFINE_THRESHOLD),
+        max_refine_cost: cli_args::max_refine_cost(&matches),
+        cache: cli_args::cache(&matches),
+        ascii_tokenize: matches.is_present(cli_args::FLAG_ASCII_TOKENIZE),
+        escape_bytes: matches.value_of(cli_args::FLAG_INVALID_BYTES) == Some("hex"),
+        tabs: cli_args::parsed_value(&matches, cli_args::FLAG_TABS),
+        word_diff: matches.is_present(cli_args::FLAG_WORD_DIFF),
+        color: cli_args::color_choice(&matches),
+        max_line_length: cli_args::parsed_value(&matches, cli_args::FLAG_MAX_LINE_LENGTH),
+        ruler: cli_args::parsed_value(&matches, cli_args::FLAG_RULER),
+        merge_hunks: cli_args::parsed_value(&matches, cli_args::FLAG_MERGE_HUNKS),
+        split_hunks: cli_args::parsed_value(&matches, cli_args::FLAG_SPLIT_HUNKS),
+        slider: !matches.is_present(cli_args::FLAG_NO_SLIDER),
+        line_pairing: !matches.is_present(cli_args::FLAG_NO_LINE_PAIRING),
+        pairing_threshold: cli_args::parsed_value(&matches, cli_args::FLAG_PAIRING_THRESHOLD),
+        refine_block: matches.is_present(cli_args::FLAG_REFINE_BLOCK),
+        refine_granularity: cli_args::refine_granularity(&matches),
+        redact_style: cli_args::redact_style(&matches),
+        refine_char_width: cli_args::parsed_value(&matches, cli_args::FLAG_REFINE_CHAR_WIDTH)
+            .unwrap_or(DEFAULT_REFINE_CHAR_WIDTH),
+        blame: matches.is_present(cli_args::FLAG_BLAME),
+        lenient: matches.is_present(cli_args::FLAG_LENIENT),
+        strict: matches.is_present(cli_args::FLAG_STRICT),
+        git_revisions: matches.value_of(cli_args::FLAG_GIT).map(str::to_owned),
+        git_paths: cli_args::values(&matches, cli_args::ARG_GIT_PATHS),
+        context: cli_args::parsed_value(&matches, cli_args::FLAG_CONTEXT),
+        resolve_context: matches.is_present(cli_args::FLAG_RESOLVE_CONTEXT),
+        collapse_context: cli_args::collapse_context(&matches),
+        only_changes: matches.is_present(cli_args::FLAG_ONLY_CHANGES),
+        indent_changed: matches.is_present(cli_args::FLAG_INDENT_CHANGED),
+        relocated: matches.is_present(cli_args::FLAG_RELOCATED),
+        indent_delta: matches.is_present(cli_args::FLAG_INDENT_DELTA),
+        symbols: matches.value_of(cli_args::FLAG_ACCESSIBILITY) == Some("symbols"),
+        timestamps: cli_args::timestamps(&matches),
+        sort_files: cli_args::sort_files(&matches),
+        strip_prefix: cli_args::parsed_value(&matches, cli_args::FLAG_STRIP_PREFIX),
+        show_cr: matches.is_present(cli_args::FLAG_SHOW_CR),
+        show_nonprinting: matches.is_present(cli_args::FLAG_SHOW_NONPRINTING),
+        binary: cli_args::binary(&matches),
+        lang: cli_args::lang(&matches),
+        ignore_case: matches.is_present(cli_args::FLAG_IGNORE_CASE),
+        algorithm: cli_args::algorithm(&matches),
+        output: cli_args::output_format(&matches),
+        output_file: cli_args::output_file(&matches),
+        commit_format: cli_args::commit_format(&matches),
+        compact_headers: matches.is_present(cli_args::FLAG_COMPACT_HEADERS),
+        rename_format: cli_args::rename_format(&matches),
+        preview_colors: matches.is_present(cli_args::FLAG_PREVIEW_COLORS),
+        serve: matches.is_present(cli_args::FLAG_SERVE),
+        conflicts: matches
+            .value_of(cli_args::FLAG_CONFLICTS)
+            .map(str::to_owned),
+        inputs: cli_args::values(&matches, cli_args::FLAG_INPUT)
+            .into_iter()
+            .chain(cli_args::values(&matches, cli_args::ARG_INPUTS))
+            .collect(),
+        split_output: matches
+            .value_of(cli_args::FLAG_SPLIT_OUTPUT)
+            .map(str::to_owned),
+        three_way: cli_args::three_way(&matches),
+        apply_preview: cli_args::apply_preview(&matches),
+        file_colors: cli_args::file_colors(&matches),
+        ..AppConfig::default()
+    };
+    if config.color == ColorChoice::Never {
+        // these are written as escape codes
+        config.keep_input_colors = false;
+        config.hyperlinks = false;
+    }
+    if let Some(format) = matches.value_of(cli_args::FLAG_HYPERLINK_FORMAT) {
+        config.hyperlink_format = format.to_owned();
+    }
+    if let Some(marker) = matches.value_of(cli_args::FLAG_TRUNCATION_MARKER) {
+        config.truncation_marker = marker.to_owned();
+    }
+    if matches.is_present(cli_args::FLAG_NAVIGATE) && !config.internal_pager {
+        let marker = matches.value_of(cli_args::FLAG_NAVIGATE_MARKER);
+        config.navigate = Some(marker.unwrap_or(NAVIGATE_MARKER).to_owned());
+    }
+    if let Some(separator) = matches.value_of(cli_args::FLAG_LINE_NUMBERS_SEPARATOR) {
+        config.line_number_separator = separator.to_owned();
+    }
+    if let Some(width) = cli_args::parsed_value(&matches, cli_args::FLAG_LINE_NUMBERS_WIDTH) {
+        config.line_number_width = width;
+    }
+    if let Some(template) = matches.value_of(cli_args::FLAG_LINE_NUMBERS_FORMAT) {
+        match parse_line_numbers_format(template) {
+            Ok(parts) => config.line_number_format = Some(parts),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(2)
+            }
+        }
+    }
+
+    let background = cli_args::background(&matches).or_else(palette::detect);
+    if background == Some(Background::Light) {
+        palette::set_light(&mut config);
+    }
+    let background = background.unwrap_or(Background::Dark);
+    palette::set_theme(&mut config, cli_args::theme(&matches), background);
+    match profile::faces() {
+        Ok(specs) => {
+            let specs = specs.iter().map(String::as_str);
+            if let Err(err) = cli_args::parse_color_args(&mut config, specs) {
+                eprintln!("in the faces of the configuration file: {}", err);
+                std::process::exit(-1)
+            }
+        }
+        Err(err) => {
+            eprintln!("error reading the faces: {}", err);
+            std::process::exit(-1)
+        }
+    }
+    if let Some(path) = matches.value_of(cli_args::FLAG_THEME_FILE) {
+        if let Err(err) = theme_file::read(path, &mut config) {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    if matches.is_present(cli_args::FLAG_COLORS_FROM_GIT) {
+        if let Err(err) = git_config::read_git_colors(&mut config) {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    match cli_args::watch_command(&matches) {
+        Ok(command) => config.watch = command,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    match cli_args::regexes(&matches, cli_args::FLAG_IGNORE_MATCHING_LINES) {
+        Ok(regexes) => config.ignore_matching_lines = regexes,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    match cli_args::regexes(&matches, cli_args::FLAG_GREP) {
+        Ok(regexes) => config.grep = regexes,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    match cli_args::regexes(&matches, cli_args::FLAG_IGNORE_TOKEN) {
+        Ok(regexes) => config.ignore_tokens = regexes,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+
+    let annotations = matches
+        .value_of(cli_args::FLAG_ANNOTATIONS)
+        .map(Annotations::read)
+        .into_iter()
+        .chain(
+            matches
+                .value_of(cli_args::FLAG_ANNOTATIONS_SARIF)
+                .map(Annotations::read_sarif),
+        );
+    for annotations in annotations {
+        match annotations {
+            Ok(annotations) => config.annotations.extend(annotations),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(-1)
+            }
+        }
+    }
+
+    match cli_args::regexes(&matches, cli_args::FLAG_REDACT) {
+        Ok(regexes) => config.redact = regexes,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
+    // the format of --output-file follows its extension, as in `render`
+    let file_format = config
+        .output_file
+        .as_ref()
+        .filter(|_| config.output == OutputFormat::Terminal)
+        .map(|(path, _)| OutputFormat::from_path(path));
+    for format in Some(config.output).into_iter().chain(file_format) {
+        if let Err(err) = patch::check_redact(&config, format) {
+            eprintln!("{}", err);
+            std::process::exit(-1)
+        }
+    }
 
     if let Some(values) = matches.values_of(cli_args::FLAG_COLOR) {
         if let Err(err) = cli_args::parse_color_args(&mut config, values) {
@@ -48,15 +783,116 @@ fn main() {
             std::process::exit(-1)
         }
     }
+    if config.symbols {
+        // the refined segments are told apart without colors
+        config.refine_added_face.set_underline(true);
+        config.refine_removed_face.set_underline(true);
+    }
+    match profile::file_faces() {
+        Ok(file_faces) => config.file_faces = file_faces,
+        Err(err) => {
+            eprintln!("error reading the file faces: {}", err);
+            std::process::exit(-1)
+        }
+    }
+    for file_faces in &config.file_faces {
+        let colors = file_faces.colors.iter().map(String::as_str);
+        if let Err(err) = cli_args::parse_color_args(&mut config.clone(), colors) {
+            eprintln!("in the faces of {}: {}", file_faces.glob, err);
+            std::process::exit(-1)
+        }
+    }
+
+    if matches.is_present(cli_args::FLAG_NAVIGATE_PROMPT) {
+        let marker = matches.value_of(cli_args::FLAG_NAVIGATE_MARKER);
+        let prompt = navigate_prompt(marker.unwrap_or(NAVIGATE_MARKER));
+        if let Err(err) = writeln!(io::stdout(), "{}", prompt) {
+            exit_on_error(&err);
+        }
+        return;
+    }
+
+    if matches.is_present(cli_args::FLAG_HELP_COLORS) {
+        let mut out = StandardStream::stdout(config.color);
+        if let Err(err) = cli_args::write_colors_help(&config, &mut out) {
+            exit_on_error(&err);
+        }
+        return;
+    }
+
+    if matches.is_present(cli_args::FLAG_DUMP_THEME) {
+        if let Err(err) = theme_file::write(&config, &mut io::stdout()) {
+            exit_on_error(&err);
+        }
+        return;
+    }
 
-    match try_main(config) {
-        Ok(()) => (),
-        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => (),
-        Err(ref err) => {
-            eprintln!("io error: {}", err);
+    if matches.is_present(cli_args::FLAG_MAN_PAGE) {
+        let mut help = vec![];
+        let result = cli_args::app()
+            .write_long_help(&mut help)
+            .map_err(|err| io::Error::other(err.to_string()))
+            .and_then(|()| man::write(&String::from_utf8_lossy(&help), &mut io::stdout()));
+        if let Err(err) = result {
+            exit_on_error(&err);
+        }
+        return;
+    }
+
+    let exit_code = matches.is_present(cli_args::FLAG_EXIT_CODE);
+    let apply_preview = config.apply_preview.is_some();
+    let start = SystemTime::now();
+    let result = match matches.value_of(cli_args::FLAG_BENCH) {
+        Some(path) => {
+            let runs = cli_args::parsed_value(&matches, cli_args::FLAG_BENCH_RUNS).unwrap_or(10);
+            bench::run(config, path, runs).map(|()| None)
+        }
+        None => try_main(config).map(Some),
+    };
+    if let (Ok(Some(stats)), Some(path)) = (&result, matches.value_of(cli_args::FLAG_METRICS_FILE))
+    {
+        let elapsed = elapsed(&Some(start));
+        if let Err(err) = stats::write_metrics_file(stats, elapsed, path) {
+            eprintln!("{}: {}", path, err);
             std::process::exit(-1)
         }
     }
+    match result {
+        Ok(Some(stats)) if exit_code => std::process::exit(stats.exit_code()),
+        Ok(Some(stats)) if apply_preview && 0 < stats.rejected_hunks => std::process::exit(1),
+        Ok(_) => (),
+        Err(ref err) => exit_on_error(err),
+    }
+}
+
+/// An input that cannot be parsed as a diff, with --strict.
+#[derive(Debug)]
+struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Exits after the error `err`; the standard output closed by a pager
+/// that quits early is not an error. An input that cannot be parsed
+/// exits with the status 2, as with --exit-code.
+fn exit_on_error(err: &io::Error) {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        return;
+    }
+    if let Some(err) = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<ParseError>())
+    {
+        eprintln!("parse error: {}", err);
+        std::process::exit(2)
+    }
+    eprintln!("io error: {}", err);
+    std::process::exit(-1)
 }
 
 fn now(debug: bool) -> Option<SystemTime> {
@@ -68,52 +904,625 @@ fn now(debug: bool) -> Option<SystemTime> {
 }
 
 fn duration_ms(time: &Option<SystemTime>) -> u128 {
+    elapsed(time).as_millis()
+}
+
+fn elapsed(time: &Option<SystemTime>) -> Duration {
     if let Some(time) = time {
         if let Ok(elapsed) = time.elapsed() {
-            elapsed.as_millis()
+            elapsed
         } else {
             // some non monotonically increasing clock
             // this is a short period of time anyway,
             // let us map it to 0
-            0
+            Duration::ZERO
         }
     } else {
-        0
+        Duration::ZERO
     }
 }
 
-fn try_main(config: AppConfig) -> io::Result<()> {
+fn try_main(config: AppConfig) -> io::Result<DiffStats> {
+    if config.preview_colors {
+        return render(config, &mut preview::SAMPLE_DIFF.as_bytes());
+    }
+    if config.three_way.is_some() {
+        return render(config, &mut io::empty());
+    }
+    if let Some((patch, _)) = &config.apply_preview {
+        let mut input = BufReader::new(File::open(patch)?);
+        return render(config, &mut input);
+    }
+    if let Some(path) = &config.conflicts {
+        let mut input = BufReader::new(File::open(path)?);
+        return render(config, &mut input);
+    }
+    if config.watch.is_some() {
+        watch::run(config)?;
+        return Ok(DiffStats::default());
+    }
+    if config.serve {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        server::run(&config, &mut stdin.lock(), &mut stdout.lock())?;
+        return Ok(DiffStats::default());
+    }
+    if config.git_revisions.is_some() {
+        let mut child = git_diff::spawn(&config)?;
+        let mut input = BufReader::new(child.stdout.take().expect("piped stdout"));
+        let stats = render(config, &mut input)?;
+        git_diff::wait(child)?;
+        return Ok(stats);
+    }
+    match &config.inputs[..] {
+        [] => {}
+        [path] => {
+            let file = File::open(path)?;
+            // the mapped file is read in place, line by line
+            return match mmap::Mmap::map(&file)? {
+                Some(map) => render(config, &mut &map[..]),
+                None => render(config, &mut BufReader::new(file)),
+            };
+        }
+        paths if config.split_output.is_some() => {
+            // the files are split by the paths of their diffs anyway
+            let mut data = vec![];
+            for path in paths {
+                data.extend(std::fs::read(path)?);
+            }
+            return render(config, &mut &data[..]);
+        }
+        // the files are read by process_inputs
+        _ => return render(config, &mut io::empty()),
+    }
     let stdin = io::stdin();
-    let stdout = StandardStream::stdout(ColorChoice::Always);
-    let mut buffer = vec![];
-    let mut hunk_buffer = HunkBuffer::default();
     let mut stdin = stdin.lock();
-    let mut stdout = stdout.lock();
+    render(config, &mut stdin)
+}
+
+/// Writes `rendered`, the output of diffr with escape codes, in
+/// `format`, which is not recorded; if `colors` is unset, the escape
+/// codes are dropped from the terminal format.
+fn write_rendered<Out: Write>(
+    format: OutputFormat,
+    rendered: &[u8],
+    colors: bool,
+    out: &mut Out,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Svg => svg::write(rendered, out),
+        OutputFormat::Html => html::write_page(rendered, out),
+        OutputFormat::Markdown => markdown::write(rendered, false, out),
+        OutputFormat::MarkdownHtml => markdown::write(rendered, true, out),
+        OutputFormat::Terminal if colors => out.write_all(rendered),
+        OutputFormat::Terminal => {
+            let mut plain = vec![];
+            strip_escape_codes(rendered, &mut plain);
+            out.write_all(&plain)
+        }
+        format => unreachable!("the {:?} output is written from the recorded parts", format),
+    }
+}
+
+/// Writes `parts`, the parts of the diff recorded by `record`, in
+/// `format`, which is recorded.
+fn write_recorded<Out: Write>(
+    format: OutputFormat,
+    parts: &[hunks::Part],
+    out: &mut Out,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Quickfix => quickfix::write(parts, out),
+        OutputFormat::Sexp => sexp::write(parts, out),
+        OutputFormat::Json => json::write(parts, out),
+        OutputFormat::JsonStream => json::write_stream(parts, out),
+        OutputFormat::Annotated => annotated::write(parts, out),
+        OutputFormat::Patch => patch::write(parts, out),
+        OutputFormat::Expectation => expectation::write(parts, out),
+        format => unreachable!("the {:?} output is written from the rendering", format),
+    }
+}
+
+/// Processes the diff from `input` without rendering it. Returns the
+/// collected statistics, and the parts of the diff recorded.
+fn record<Input>(config: AppConfig, input: &mut Input) -> io::Result<(DiffStats, Vec<hunks::Part>)>
+where
+    Input: BufRead,
+{
+    let recorded = Rc::new(RefCell::new(vec![]));
+    let config = hunks::record_config(config, hunks::Recorder::new(recorded.clone()));
+    let stats = process(config, input, &mut NoColor::new(io::sink()))?;
+    Ok((stats, recorded.take()))
+}
+
+/// Renders the diff from `input` in `format` into `out`: the recorded
+/// formats are written from the recorded hunks, with the matches of
+/// --redact replaced, and the others from the rendering, as
+/// `write_rendered` does. Returns the collected statistics.
+fn render_to<Input, Out>(
+    config: AppConfig,
+    format: OutputFormat,
+    colors: bool,
+    input: &mut Input,
+    out: &mut Out,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Out: Write,
+{
+    if !format.is_recorded() {
+        let mut rendered = Buffer::ansi();
+        let stats = process(config, input, &mut rendered)?;
+        write_rendered(format, rendered.as_slice(), colors, out)?;
+        return Ok(stats);
+    }
+    let (regexes, style) = (config.redact.clone(), config.redact_style);
+    let (stats, parts) = record(config, input)?;
+    let mut out = Redact::new(NoColor::new(out), regexes, style);
+    write_recorded(format, &parts, &mut out)?;
+    out.finish()?;
+    Ok(stats)
+}
+
+/// Renders the diff from `input` on the standard output, or in the
+/// pager, and in the file of --output-file. Returns the collected
+/// statistics.
+fn render<Input>(config: AppConfig, input: &mut Input) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+{
+    let format = config.output;
+    if let Some(dir) = &config.split_output {
+        return split::write(&config, format, dir, input);
+    }
+    if let Some((path, tee)) = config.output_file.clone() {
+        let file_format = match format {
+            OutputFormat::Terminal => OutputFormat::from_path(&path),
+            format => format,
+        };
+        let mut file = BufWriter::new(File::create(&path)?);
+        if file_format.is_recorded() && tee {
+            // the display is rendered apart, unless it is the file
+            let mut data = vec![];
+            input.read_to_end(&mut data)?;
+            let mut written = vec![];
+            let stats = render_to(
+                config.clone(),
+                file_format,
+                config.file_colors,
+                &mut &data[..],
+                &mut written,
+            )?;
+            file.write_all(&written)?;
+            file.flush()?;
+            if format == file_format {
+                io::stdout().lock().write_all(&written)?;
+            } else {
+                let mut rendered = Buffer::ansi();
+                process(config.clone(), &mut &data[..], &mut rendered)?;
+                display(&config, rendered.as_slice())?;
+            }
+            return Ok(stats);
+        }
+        if file_format.is_recorded() {
+            let stats = render_to(
+                config.clone(),
+                file_format,
+                config.file_colors,
+                input,
+                &mut file,
+            )?;
+            file.flush()?;
+            return Ok(stats);
+        }
+        let mut rendered = Buffer::ansi();
+        let stats = process(config.clone(), input, &mut rendered)?;
+        let rendered = rendered.into_inner();
+        write_rendered(file_format, &rendered, config.file_colors, &mut file)?;
+        file.flush()?;
+        if tee {
+            display(&config, &rendered)?;
+        }
+        return Ok(stats);
+    }
+    if format == OutputFormat::JsonStream {
+        // the events are written as the lines are rendered
+        let config = AppConfig {
+            stop_on_closed_stdout: true,
+            ..config
+        };
+        let out = Redact::new(
+            NoColor::new(io::stdout().lock()),
+            config.redact.clone(),
+            config.redact_style,
+        );
+        let stream = Rc::new(RefCell::new(json::EventStream::new(out)));
+        let config = hunks::record_config(config, hunks::Recorder::new(stream.clone()));
+        let stats = process(config, input, &mut NoColor::new(io::sink()))?;
+        stream.borrow_mut().finish()?;
+        return Ok(stats);
+    }
+    if format != OutputFormat::Terminal {
+        let config = AppConfig {
+            stop_on_closed_stdout: true,
+            ..config
+        };
+        let stdout = io::stdout();
+        return render_to(config, format, true, input, &mut stdout.lock());
+    }
+    if (config.internal_pager || config.tui) && is(Stream::Stdout) {
+        let tui = config.tui;
+        let mut rendered = match config.color {
+            ColorChoice::Never => Buffer::no_color(),
+            _ => Buffer::ansi(),
+        };
+        let stats = process(config, input, &mut rendered)?;
+        if tui {
+            tui::run(&rendered.into_inner())?
+        } else {
+            pager::run(&rendered.into_inner())?
+        }
+        Ok(stats)
+    } else {
+        let stdout = StandardStream::stdout(config.color);
+        let mut stdout = stdout.lock();
+        process(config, input, &mut stdout)
+    }
+}
+
+/// Displays `rendered`, the output of diffr with escape codes, as
+/// `render` does, for --tee.
+fn display(config: &AppConfig, rendered: &[u8]) -> io::Result<()> {
+    let colors = config.color != ColorChoice::Never;
+    if config.output == OutputFormat::Terminal
+        && (config.internal_pager || config.tui)
+        && is(Stream::Stdout)
+    {
+        let mut plain = vec![];
+        let rendered = if colors {
+            rendered
+        } else {
+            strip_escape_codes(rendered, &mut plain);
+            &plain
+        };
+        return if config.tui {
+            tui::run(rendered)
+        } else {
+            pager::run(rendered)
+        };
+    }
+    let stdout = io::stdout();
+    write_rendered(config.output, rendered, colors, &mut stdout.lock())
+}
+
+fn process<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if config.three_way.is_some() {
+        return threeway::process(config, out);
+    }
+    if config.conflicts.is_some() {
+        return conflicts::process(config, input, out);
+    }
+    if 1 < config.inputs.len() {
+        return process_inputs(config, out);
+    }
+    match config.context {
+        // the output of git diff --git has the right context already
+        Some(context) if config.git_revisions.is_none() => {
+            let mut input = Reslice::new(input, context, config.lenient);
+            if config.resolve_context {
+                input = input.resolve_context(git_diff::cat_blob);
+            }
+            process_sliced(config, &mut input, out)
+        }
+        _ => process_sliced(config, input, out),
+    }
+}
+
+/// Processes the diff files of `config.inputs` one after the other,
+/// each after a line naming it, with the state of the parser reset.
+fn process_inputs<Output>(config: AppConfig, out: &mut Output) -> io::Result<DiffStats>
+where
+    Output: WriteColor,
+{
+    let mut stats = DiffStats::default();
+    for path in &config.inputs {
+        let separator = format!("==> {} <==\n", path);
+        output(separator.as_bytes(), &config.commit_face, out)?;
+        let input_config = AppConfig {
+            inputs: vec![],
+            stats: false,
+            ..config.clone()
+        };
+        let file = File::open(path)?;
+        let input_stats = match mmap::Mmap::map(&file)? {
+            Some(map) => process(input_config, &mut &map[..], out)?,
+            None => process(input_config, &mut BufReader::new(file), out)?,
+        };
+        stats.append(input_stats);
+    }
+    if config.stats {
+        stats::output_stats(&stats, out)?;
+    }
+    Ok(stats)
+}
+
+fn process_sliced<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if let Some(order) = config.sort_files {
+        // the files are reordered once the whole diff is read
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+        let sorted = sort::sort_files(&data, order);
+        return process_sorted(config, &mut &sorted[..], out);
+    }
+    process_sorted(config, input, out)
+}
+
+fn process_sorted<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if !config.grep.is_empty() {
+        let regexes = config.grep.clone();
+        let mut input = Grep::new(input, &regexes, config.lenient);
+        return process_grepped(config, &mut input, out);
+    }
+    process_grepped(config, input, out)
+}
+
+fn process_grepped<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if !config.redact.is_empty() {
+        let mut out = Redact::new(out, config.redact.clone(), config.redact_style);
+        let stats = process_redacted(config, input, &mut out)?;
+        out.finish()?;
+        return Ok(stats);
+    }
+    process_redacted(config, input, out)
+}
+
+fn process_redacted<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if config.escape_bytes {
+        return process_escaped(config, input, &mut EscapeBytes::new(out));
+    }
+    process_escaped(config, input, out)
+}
+
+fn process_escaped<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    if config.quiet {
+        // the hunks are refined for the statistics, but not displayed
+        let quiet_config = AppConfig {
+            stats: false,
+            recorder: None,
+            ..config.clone()
+        };
+        let stats = process_diff(quiet_config, input, &mut NoColor::new(io::sink()), false)?;
+        if config.diffstat {
+            stats::output_diffstat(&stats, &config, out)?;
+        }
+        if config.stats {
+            stats::output_stats(&stats, out)?;
+        }
+        return Ok(stats);
+    }
+    if !config.diffstat {
+        return process_diff(config, input, out, false);
+    }
+    // the diffstat is displayed first: count the changed lines
+    // without refining the hunks, then process the input again
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+    let scan_config = AppConfig {
+        debug: false,
+        stats: false,
+        recorder: None,
+        ..config.clone()
+    };
+    let stats = process_diff(
+        scan_config,
+        &mut &data[..],
+        &mut NoColor::new(io::sink()),
+        true,
+    )?;
+    stats::output_diffstat(&stats, &config, out)?;
+    process_diff(config, &mut &data[..], out, false)
+}
+
+/// Processes the diff from `input`; if `count_only` is set, the
+/// changed lines are only counted. Returns the collected statistics.
+fn process_diff<Input, Output>(
+    config: AppConfig,
+    input: &mut Input,
+    out: &mut Output,
+    count_only: bool,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+    Output: WriteColor,
+{
+    let mut buffer = vec![];
+    let mut stripped = vec![];
+    let mut hunk_buffer = HunkBuffer {
+        count_only,
+        cache: cache::Cache::new(config.cache),
+        ..HunkBuffer::default()
+    };
+    let mut filter = FileFilter::new(
+        &config.include_paths,
+        &config.exclude_paths,
+        &config.generated_paths,
+    );
     let mut in_hunk = false;
 
     let mut time_computing_diff_ms = 0;
     let debug = config.debug;
+    let profile = config.profile;
+    let lenient = config.lenient;
+    let strict = config.strict;
+    // the line of the input being read, and the one of the header of
+    // the current hunk
+    let (mut input_line, mut header_line) = (0, 0);
+    let stop_on_closed_stdout = config.stop_on_closed_stdout;
+    // the lines outside of the diff are written as read
+    let passthrough = config.commit_format == CommitFormat::Full;
+    let mut passthrough_lines = 0;
+    let mut remaining: Option<HunkRemaining> = None;
+    // the lines of the current hunk not read yet, from its header
+    let mut expected: Option<HunkRemaining> = None;
     hunk_buffer.config = config;
-    let start = now(debug);
+    let start = now(profile);
 
     // process hunks
     loop {
-        stdin.read_until(b'\n', &mut buffer)?;
+        // the lines of the header of a mail have faces
+        let in_mail = hunk_buffer
+            .commit
+            .as_ref()
+            .is_some_and(CommitHeader::is_mail);
+        if passthrough
+            && !in_hunk
+            && filter.is_idle()
+            && hunk_buffer.binary_patch.is_none()
+            && !hunk_buffer.signature
+            && !in_mail
+        {
+            let available = input.fill_buf()?;
+            let len = scan::passthrough_len(available, hunk_buffer.commit.is_some());
+            if 0 < len {
+                hunk_buffer.finish_extended(out)?;
+                hunk_buffer.finish_commit(out)?;
+                if let Some(recorder) = &hunk_buffer.config.recorder {
+                    recorder.record_lines(&available[..len])?;
+                }
+                output_passthrough(&available[..len], hunk_buffer.config.keep_input_colors, out)?;
+                let nlines = scan::count_lines(&available[..len]);
+                input_line += nlines;
+                passthrough_lines += nlines;
+                input.consume(len);
+                continue;
+            }
+        }
+        scan::read_line(input, &mut buffer)?;
         if buffer.is_empty() {
             break;
         }
+        input_line += 1;
 
-        match (in_hunk, first_after_escape(&buffer)) {
+        let first = first_after_escape(&buffer);
+        let kind = match &mut remaining {
+            Some(remaining) if in_hunk => remaining.count(first),
+            _ => first,
+        };
+        let kind = match &mut expected {
+            // a "---" line after the lines of the hunk starts the next
+            // file, as in the output of diff -u of several files
+            Some(expected) if in_hunk && expected.is_empty() && kind == Some(b'-') => {
+                stripped.clear();
+                strip_escape_codes(&buffer, &mut stripped);
+                kind.filter(|_| !stripped.starts_with(b"--- "))
+            }
+            Some(expected) if in_hunk => {
+                expected.count(kind);
+                kind
+            }
+            _ => kind,
+        };
+        match (in_hunk, kind) {
+            (true, Some(b'+')) | (true, Some(b'-')) | (true, Some(b' ')) | (true, Some(b'\\'))
+                if filter.is_skipping() =>
+            {
+                filter.count(kind)
+            }
             (true, Some(b'+')) => hunk_buffer.push_added(&buffer),
             (true, Some(b'-')) => hunk_buffer.push_removed(&buffer),
-            (true, Some(b' ')) => add_raw_line(&mut hunk_buffer.lines, &buffer),
-            (_, other) => {
-                let start = now(debug);
+            (true, Some(b' ')) => hunk_buffer.push_context(&buffer),
+            // "\ No newline at end of file"
+            (true, Some(b'\\')) => hunk_buffer.push_context(&buffer),
+            _ => {
+                let start = now(profile);
+                stripped.clear();
+                strip_escape_codes(&buffer, &mut stripped);
+                let merged = in_hunk && hunk_buffer.merge_hunk(&stripped);
+                if in_hunk && !merged {
+                    if !filter.is_skipping() {
+                        hunk_buffer.check_counts(header_line, strict)?;
+                    }
+                    hunk_buffer.process(out)?;
+                    if stop_on_closed_stdout && is_stdout_closed() {
+                        // the rendering would be discarded
+                        return Err(io::ErrorKind::BrokenPipe.into());
+                    }
+                }
+                let header_start = now(profile);
+                in_hunk = first == Some(b'@');
+                expected = parse_hunk_header(&stripped)
+                    .filter(|_| in_hunk)
+                    .map(HunkRemaining::from);
                 if in_hunk {
-                    hunk_buffer.process(&mut stdout)?;
+                    header_line = input_line;
+                    // the hunks after a malformed one are refined again
+                    hunk_buffer.malformed = false;
                 }
-                in_hunk = other == Some(b'@');
-                output(&buffer, &ColorSpec::default(), &mut stdout)?;
+                remaining = match parse_hunk_header(&stripped) {
+                    // the lines of the hunks of a mail are counted, to
+                    // tell its signature from a removed line
+                    Some(header) if (lenient || hunk_buffer.mail) && in_hunk => {
+                        Some(HunkRemaining::from(header))
+                    }
+                    _ => None,
+                };
+                if merged {
+                    // the header is replaced by the one of the merged hunk
+                } else if filter.is_active() {
+                    let filtered = filter.push(&buffer, &stripped);
+                    output_filtered(&mut hunk_buffer, filtered, out)?;
+                } else {
+                    hunk_buffer.output_header_line(&buffer, &stripped, out)?;
+                }
+                hunk_buffer.timings.emit += elapsed(&header_start);
                 time_computing_diff_ms += duration_ms(&start);
             }
         }
@@ -121,14 +1530,283 @@ fn try_main(config: AppConfig) -> io::Result<()> {
     }
 
     // flush remaining hunk
-    hunk_buffer.process(&mut stdout)?;
-    if debug {
-        eprintln!("hunk processing time (ms): {}", time_computing_diff_ms);
-        eprintln!("total processing time (ms): {}", duration_ms(&start));
+    if in_hunk && !filter.is_skipping() {
+        hunk_buffer.check_counts(header_line, strict)?;
+    }
+    hunk_buffer.process(out)?;
+    output_filtered(&mut hunk_buffer, filter.finish(), out)?;
+    hunk_buffer.finish_header(out)?;
+    hunk_buffer.finish_binary(out)?;
+    hunk_buffer.finish_commit(out)?;
+    hunk_buffer.finish_extended(out)?;
+    hunk_buffer.cache.evict();
+    if hunk_buffer.config.stats {
+        stats::output_stats(&hunk_buffer.stats, out)?;
+    }
+    hunk_buffer.timings.total = elapsed(&start);
+    if debug {
+        match hunk_buffer.config.debug_format {
+            DebugFormat::Text => {
+                eprintln!("hunk processing time (ms): {}", time_computing_diff_ms);
+                eprintln!("total processing time (ms): {}", duration_ms(&start));
+                eprint!("{}", hunk_buffer.timings);
+                eprintln!("lines written as read: {}", passthrough_lines);
+            }
+            DebugFormat::Json => debug::write_timings(&hunk_buffer.timings, &mut io::stderr())?,
+        }
+    }
+    hunk_buffer.stats.timings = hunk_buffer.timings;
+    Ok(hunk_buffer.stats)
+}
+
+/// Writes `buf`, lines outside of the diff, at once; the output is the
+/// same as line by line.
+fn output_passthrough<Stream>(
+    buf: &[u8],
+    keep_input_colors: bool,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if keep_input_colors {
+        return output_raw(buf, out);
+    }
+    if out.supports_color() && out.is_synchronous() {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            output_unrefined(line, false, out)?;
+        }
+        return Ok(());
+    }
+    if out.supports_color() {
+        // the face is reset on each line, as by the escape codes of `out`
+        let mut lines = Ansi::new(Vec::with_capacity(buf.len()));
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            output_unrefined(line, false, &mut lines)?;
+        }
+        return out.write_all(&lines.into_inner());
+    }
+    match scan::find_escape(buf) {
+        Some(_) => {
+            let mut stripped = Vec::with_capacity(buf.len());
+            strip_escape_codes(buf, &mut stripped);
+            out.write_all(&stripped)
+        }
+        None => out.write_all(buf),
+    }
+}
+
+/// Returns true if the standard output is a pipe whose reader is gone,
+/// so that writing to it fails.
+#[cfg(unix)]
+fn is_stdout_closed() -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDOUT_FILENO,
+        events: 0,
+        revents: 0,
+    };
+    // SAFETY: `fd` is a valid array of one pollfd, and the timeout is 0.
+    let ready = unsafe { libc::poll(&mut fd, 1, 0) };
+    0 < ready && fd.revents & libc::POLLERR != 0
+}
+
+#[cfg(not(unix))]
+fn is_stdout_closed() -> bool {
+    false
+}
+
+fn output_filtered<Stream>(
+    hunk_buffer: &mut HunkBuffer,
+    filtered: Vec<Filtered>,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let mut stripped = vec![];
+    for item in filtered {
+        if !matches!(item, Filtered::Line(_)) {
+            // the summaries follow the header of their commit
+            hunk_buffer.finish_commit(out)?;
+        }
+        match item {
+            Filtered::Line(line) => {
+                stripped.clear();
+                strip_escape_codes(&line, &mut stripped);
+                hunk_buffer.output_header_line(&line, &stripped, out)?;
+            }
+            Filtered::Skipped(path) => {
+                let summary = format!("skipped: {}\n", path);
+                output(summary.as_bytes(), &ColorSpec::default(), out)?;
+            }
+            Filtered::Generated(collapsed) => {
+                let summary = format!(
+                    "generated: {} +{} -{}\n",
+                    collapsed.path, collapsed.added, collapsed.removed
+                );
+                output(summary.as_bytes(), &hunk_buffer.config.folded_face, out)?;
+            }
+        }
     }
     Ok(())
 }
 
+/// Returns true if the hunk `lines` of `data` is refined by character,
+/// with --refine.
+fn by_char(config: &AppConfig, data: &[u8], lines: &LineSplit) -> bool {
+    match config.refine_granularity {
+        Granularity::Word => false,
+        Granularity::Char => true,
+        Granularity::Auto => lines
+            .iter()
+            .filter(|&(lo, _)| data[lo] == b'-' || data[lo] == b'+')
+            .all(|(lo, hi)| {
+                let line = &data[lo + 1..hi];
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                String::from_utf8_lossy(line).chars().count() <= config.refine_char_width
+            }),
+    }
+}
+
+/// Computes the tokens shared by `removed` and `added` into `dst`.
+fn diff_tokens(
+    config: &AppConfig,
+    data: &[u8],
+    tokens: (&[HashedSpan], &[HashedSpan]),
+    v: &mut Vec<isize>,
+    dst: &mut Vec<Snake>,
+) {
+    if config.refine_block {
+        block::diff(config.algorithm, data, tokens, v, dst)
+    } else if config.line_pairing {
+        pairing::diff(
+            config.algorithm,
+            config.pairing_threshold,
+            data,
+            tokens,
+            v,
+            dst,
+        )
+    } else {
+        algorithm::diff(config.algorithm, data, tokens, v, dst)
+    }
+}
+
+/// Computes the tokens shared by `removed` and `added` into `dst`, a
+/// block of changed lines of `lines` at a time, for --refine-block.
+fn diff_blocks(
+    config: &AppConfig,
+    lines: &LineSplit,
+    data: &[u8],
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+    v: &mut Vec<isize>,
+    dst: &mut Vec<Snake>,
+) {
+    // the ends of the runs of changed lines
+    let mut block_ends = vec![];
+    let mut block_end = None;
+    for (line_start, line_end) in lines.iter() {
+        match lines.data()[line_start] {
+            b'-' | b'+' | b'\\' => block_end = Some(line_end),
+            _ => block_ends.extend(block_end.take()),
+        }
+    }
+    block_ends.extend(block_end);
+
+    dst.clear();
+    let mut snakes = vec![];
+    let (mut removed_lo, mut added_lo) = (0, 0);
+    for end in block_ends {
+        let removed_hi = removed_lo + removed[removed_lo..].partition_point(|tok| tok.lo < end);
+        let added_hi = added_lo + added[added_lo..].partition_point(|tok| tok.lo < end);
+        diff_tokens(
+            config,
+            data,
+            (&removed[removed_lo..removed_hi], &added[added_lo..added_hi]),
+            v,
+            &mut snakes,
+        );
+        for snake in &snakes {
+            algorithm::push(
+                dst,
+                removed_lo + snake.x0 as usize,
+                added_lo + snake.y0 as usize,
+                snake.len as usize,
+            );
+        }
+        removed_lo = removed_hi;
+        added_lo = added_hi;
+    }
+}
+
+/// Computes in `folds` how the lines of the hunk in `lines` are
+/// displayed, with --collapse-context or --only-changes.
+fn fold_lines(
+    config: &AppConfig,
+    lines: &LineSplit,
+    gaps: &[(usize, usize)],
+    folds: &mut Vec<Fold>,
+) {
+    let kinds = lines.iter().map(|(start, _)| lines.data()[start]);
+    match config.collapse_context {
+        _ if config.only_changes => fold::hide_context(kinds, folds),
+        Some(max) => fold::fold_context(kinds, max, folds),
+        None => folds.clear(),
+    }
+    if !gaps.is_empty() {
+        let kinds = lines.iter().map(|(start, _)| lines.data()[start]);
+        fold::fold_gaps(kinds, gaps, !config.only_changes, folds);
+    }
+}
+
+/// Returns true if the changed lines of the hunk in `lines` all match
+/// one of the regexes of --ignore-matching-lines.
+fn is_ignored(config: &AppConfig, lines: &LineSplit) -> bool {
+    if config.ignore_matching_lines.is_empty() {
+        return false;
+    }
+    let data = lines.data();
+    lines
+        .iter()
+        .all(|(line_start, line_end)| match data[line_start] {
+            b'-' | b'+' => {
+                let line = &data[line_start + 1..line_end];
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                let matches = |regex: &Regex| regex.is_match(line);
+                config.ignore_matching_lines.iter().any(matches)
+            }
+            _ => true,
+        })
+}
+
+/// Returns true if the changed line `data_lo..data_hi` is too different
+/// from the other lines for its refinement to help, with
+/// --refine-threshold: more of its content than the threshold is not
+/// in the shared `segments`.
+fn is_too_different(
+    config: &AppConfig,
+    data: &[u8],
+    (data_lo, data_hi): Span,
+    segments: &[(usize, usize)],
+) -> bool {
+    let threshold = match config.refine_threshold {
+        Some(threshold) => threshold,
+        None => return false,
+    };
+    let content = &data[data_lo + 1..data_hi];
+    let content_len = content.strip_suffix(b"\n").unwrap_or(content).len();
+    let shared_len = segments.iter().map(|(lo, hi)| hi - lo).sum::<usize>();
+    let refined_len = content_len.saturating_sub(shared_len);
+    0 < content_len && threshold * content_len < 100 * refined_len
+}
+
+fn dimmed() -> ColorSpec {
+    let mut face = ColorSpec::new();
+    face.set_dimmed(true);
+    face
+}
+
 fn color_spec(fg: Option<Color>, bg: Option<Color>, bold: bool) -> ColorSpec {
     let mut colorspec: ColorSpec = ColorSpec::default();
     colorspec.set_fg(fg);
@@ -137,6 +1815,9 @@ fn color_spec(fg: Option<Color>, bg: Option<Color>, bold: bool) -> ColorSpec {
     colorspec
 }
 
+/// A range of bytes of the data of a hunk.
+type Span = (usize, usize);
+
 #[derive(Default)]
 struct HunkBuffer {
     v: Vec<isize>,
@@ -144,25 +1825,199 @@ struct HunkBuffer {
     added_tokens: Vec<HashedSpan>,
     removed_tokens: Vec<HashedSpan>,
     lines: LineSplit,
+    input_faces: Vec<ColorSpec>,
+    // scratch buffers, reused across hunks
+    segments: Vec<(usize, usize)>,
+    case_changed: Vec<(usize, usize)>,
+    number_changed: Vec<(usize, usize)>,
+    search_matches: Vec<(usize, usize)>,
+    masked: Vec<(usize, usize)>,
+    indent_pairs: Vec<Option<Span>>,
+    relocated: Vec<bool>,
+    folds: Vec<Fold>,
+    /// The unchanged lines between the hunks merged by --merge-hunks:
+    /// the index of their first line, and their number.
+    gaps: Vec<(usize, usize)>,
+    /// The chunks of the hunk after its first one, with --split-hunks.
+    chunks: Vec<Chunk>,
+    /// The header of the hunk, written with its lines once the hunks
+    /// merged into it are read: as read, without escape codes, and the
+    /// line of its hyperlink.
+    pending_header: Option<(Vec<u8>, Vec<u8>, Option<usize>)>,
+    commit: Option<CommitHeader>,
+    /// The hash of the commit of the output of git log being read.
+    commit_sha: Option<String>,
+    /// Whether the input is a series of mails of git format-patch.
+    mail: bool,
+    /// Whether the signature of a mail is being skipped.
+    signature: bool,
+    /// The binary patch being read, replaced by a placeholder.
+    binary_patch: Option<binary::Patch>,
+    extended: extended::Summary,
+    blame: Blame,
+    apply: apply::Checker,
+    cache: cache::Cache,
+    header: Option<HunkHeader>,
+    /// The function context written after the header of the hunk.
+    function_context: Option<Vec<u8>>,
+    old_path: Option<String>,
+    path: Option<String>,
+    /// The language detected from the content of the file, with
+    /// --lang=auto.
+    content_lang: Option<Lang>,
+    stats: DiffStats,
+    timings: Timings,
+    count_only: bool,
+    /// Whether the current hunk does not match the counts of its
+    /// header; it is then not refined.
+    malformed: bool,
+    /// The faces of the configuration, without the file faces, once a
+    /// file is displayed with them.
+    base_faces: Option<Vec<(&'static str, ColorSpec)>>,
     config: AppConfig,
 }
 
+/// The line ranges of a hunk, as written in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HunkHeader {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+}
+
+/// The numbers of lines of a hunk not read yet, with --lenient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HunkRemaining {
+    old: usize,
+    new: usize,
+}
+
+impl From<HunkHeader> for HunkRemaining {
+    fn from(header: HunkHeader) -> Self {
+        HunkRemaining {
+            old: header.old_len,
+            new: header.new_len,
+        }
+    }
+}
+
+impl HunkRemaining {
+    fn is_empty(&self) -> bool {
+        self.old == 0 && self.new == 0
+    }
+
+    /// Counts a line of the hunk starting with `first`. Returns the
+    /// kind of the line, or None if the hunk ended before it.
+    fn count(&mut self, first: Option<u8>) -> Option<u8> {
+        let (kind, old, new) = match first? {
+            b'\\' => return first,
+            b'-' => (b'-', 1, 0),
+            b'+' => (b'+', 0, 1),
+            b' ' => (b' ', 1, 1),
+            // an empty context line whose space was stripped
+            b'\n' => (b' ', 1, 1),
+            _ => return None,
+        };
+        if self.old < old || self.new < new {
+            return None;
+        }
+        self.old -= old;
+        self.new -= new;
+        Some(kind)
+    }
+}
+
+impl HunkHeader {
+    /// The widths of the columns of the gutter of the hunk, at least
+    /// `min_width` wide.
+    fn gutter_width(&self, style: LineNumberStyle, min_width: usize) -> GutterWidth {
+        let old = (self.old_start + self.old_len)
+            .to_string()
+            .len()
+            .max(min_width);
+        let new = (self.new_start + self.new_len)
+            .to_string()
+            .len()
+            .max(min_width);
+        let (old, new) = match style {
+            LineNumberStyle::Compact => (old.max(new), old.max(new)),
+            LineNumberStyle::Aligned => (old.max(new).max(4), old.max(new).max(4)),
+            LineNumberStyle::Both => (old, new),
+            LineNumberStyle::RightOnly => (0, new),
+        };
+        GutterWidth { old, new }
+    }
+}
+
+/// Parses a line range, such as "12,3" or "12".
+fn parse_line_range(buf: &[u8]) -> Option<(usize, usize)> {
+    let buf = std::str::from_utf8(buf).ok()?;
+    let mut pieces = buf.splitn(2, ',');
+    let start = pieces.next()?.parse().ok()?;
+    let len = match pieces.next() {
+        Some(len) => len.parse().ok()?,
+        None => 1,
+    };
+    Some((start, len))
+}
+
+/// Parses a hunk header, such as "@@ -12,3 +12,4 @@".
+fn parse_hunk_header(buf: &[u8]) -> Option<HunkHeader> {
+    let buf = buf.strip_prefix(b"@@ -")?;
+    let mut pieces = buf.splitn(3, |&b| b == b' ');
+    let (old_start, old_len) = parse_line_range(pieces.next()?)?;
+    let (new_start, new_len) = parse_line_range(pieces.next()?.strip_prefix(b"+")?)?;
+    if !pieces.next()?.starts_with(b"@@") {
+        return None;
+    }
+    Some(HunkHeader {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+    })
+}
+
+/// Splits the hunk header `buf` in the header proper, up to the space
+/// after its second "@@", and the function context that follows, such
+/// as "fn main()", without its line end. Returns None without function
+/// context.
+fn split_function_context(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    parse_hunk_header(buf)?;
+    let end = 2 + buf[2..].windows(2).position(|w| w == b"@@")? + 2;
+    let context = buf[end..].strip_prefix(b" ")?;
+    let context = context.strip_suffix(b"\n").unwrap_or(context);
+    let context = context.strip_suffix(b"\r").unwrap_or(context);
+    if context.is_empty() {
+        return None;
+    }
+    Some((&buf[..end + 1], context))
+}
+
 impl HunkBuffer {
-    // Returns the number of completely printed snakes
-    fn paint_line<Stream, Positions>(
-        data: &[u8],
-        &(data_lo, data_hi): &(usize, usize),
-        no_highlight: &ColorSpec,
-        highlight: &ColorSpec,
+    /// The span in the data of the tokens of `snake` in `toks`, the
+    /// added tokens if `is_plus`.
+    fn shared_span(snake: &Snake, toks: &[HashedSpan], is_plus: bool) -> (usize, usize) {
+        let x0 = if is_plus { snake.y0 } else { snake.x0 };
+        let first = toks[x0 as usize].lo;
+        let last = toks[(x0 + snake.len - 1) as usize].hi;
+        (first, last)
+    }
+
+    /// Collects in `segments` the parts of the shared spans `shared`
+    /// that are in the line `data_lo..data_hi`. Returns the number of
+    /// spans that end before the line.
+    fn shared_segments<Positions>(
         shared: Positions,
-        out: &mut Stream,
-    ) -> io::Result<usize>
+        &(data_lo, data_hi): &(usize, usize),
+        segments: &mut Vec<(usize, usize)>,
+    ) -> usize
     where
-        Stream: WriteColor,
         Positions: Iterator<Item = (usize, usize)>,
     {
-        let mut y = data_lo;
         let mut nshared = 0;
+        segments.clear();
         for (lo, hi) in shared {
             if hi <= data_lo {
                 nshared += 1;
@@ -174,101 +2029,1081 @@ impl HunkBuffer {
             if hi <= lo {
                 continue;
             }
-            output(&data[y..lo], &highlight, out)?;
-            output(&data[lo..hi], &no_highlight, out)?;
+            segments.push((lo, hi));
+        }
+        nshared
+    }
+
+    fn paint_line<Stream>(
+        data: &[u8],
+        &(data_lo, line_end): &(usize, usize),
+        (no_highlight, highlight): (&ColorSpec, &ColorSpec),
+        segments: &[(usize, usize)],
+        (case_changed, number_changed, search_matches, masked): (
+            &[Span],
+            &[Span],
+            &[Span],
+            &[Span],
+        ),
+        config: &AppConfig,
+        out: &mut Stream,
+    ) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        // the carriage return is neither refined nor highlighted
+        let (data_hi, crlf) = split_crlf(data, (data_lo, line_end));
+        if crlf {
+            let line = (data_lo, data_hi);
+            let faces = (no_highlight, highlight);
+            let segments = segments
+                .iter()
+                .map(|&(lo, hi)| (lo, hi.min(data_hi)))
+                .filter(|(lo, hi)| lo < hi)
+                .collect::<Vec<_>>();
+            let spans = (case_changed, number_changed, search_matches, masked);
+            Self::paint_line(data, &line, faces, &segments, spans, config, out)?;
+            return output_crlf(config, out);
+        }
+        if is_too_different(config, data, (data_lo, data_hi), segments) {
+            let mut layout = Layout::new(config);
+            let line = (data_lo, data_hi);
+            return output_searched(
+                data,
+                line,
+                no_highlight,
+                search_matches,
+                &mut layout,
+                config,
+                out,
+            );
+        }
+        let mut y = data_lo;
+        let mut layout = Layout::new(config);
+        let mut output_laid_out = |lo, hi, face: &ColorSpec, out: &mut Stream| {
+            output_searched(
+                data,
+                (lo, hi),
+                face,
+                search_matches,
+                &mut layout,
+                config,
+                out,
+            )
+        };
+        // the matches of --ignore-token
+        let mut output_laid_out = |lo, hi, face: &ColorSpec, out: &mut Stream| {
+            let faces = (face, &config.masked_face);
+            output_spans((lo, hi), faces, masked, &mut output_laid_out, out)
+        };
+        let number_face = &config.number_changed_face;
+        for &(lo, hi) in segments {
+            // the numbers replaced by other numbers
+            let faces = (highlight, number_face);
+            output_spans((y, lo), faces, number_changed, &mut output_laid_out, out)?;
+            // the shared tokens that differ by case
+            let faces = (no_highlight, &config.case_changed_face);
+            output_spans((lo, hi), faces, case_changed, &mut output_laid_out, out)?;
             y = hi;
         }
-        if y < data_hi {
-            output(&data[y..data_hi], &highlight, out)?;
+        // the truncated end of the line is not refined
+        let cut = match Layout::new(config).truncation_index(&data[data_lo..data_hi]) {
+            Some(i) => (data_lo + i).max(y),
+            None => data_hi,
+        };
+        if y < cut {
+            let faces = (highlight, number_face);
+            output_spans((y, cut), faces, number_changed, &mut output_laid_out, out)?;
+        }
+        if cut < data_hi {
+            output_laid_out(cut, data_hi, no_highlight, out)?;
         }
-        Ok(nshared)
+        Ok(())
     }
 
     fn process<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
     where
         Stream: WriteColor,
     {
+        self.finish_header(out)?;
+        if self.lines.len() == 0 {
+            return Ok(());
+        }
+        let result = if self.count_only {
+            self.count_lines();
+            Ok(())
+        } else {
+            let start = now(self.config.profile);
+            let lcs = self.timings.lcs;
+            let result = self.refine(out).and_then(|()| self.check_apply(out));
+            // the time of the LCS is counted apart
+            self.timings.emit += elapsed(&start).saturating_sub(self.timings.lcs - lcs);
+            result
+        };
+        self.lines.clear();
+        self.gaps.clear();
+        self.input_faces.clear();
+        self.added_tokens.clear();
+        self.removed_tokens.clear();
+        result
+    }
+
+    /// Checks that the lines of the hunk, whose header is the line
+    /// `header_line` of the input, match the counts of its header. A
+    /// mismatch is reported, and the hunks of the file are not refined;
+    /// with `strict`, it is an error.
+    fn check_counts(&mut self, header_line: usize, strict: bool) -> io::Result<()> {
+        let header = match self.header {
+            Some(header) if 0 < self.lines.len() => header,
+            _ => return Ok(()),
+        };
+        let data = self.lines.data();
+        let (mut old, mut new) = (0, 0);
+        for (lo, hi) in self.lines.iter() {
+            match first_after_escape(&data[lo..hi]) {
+                Some(b'-') => old += 1,
+                Some(b'+') => new += 1,
+                Some(b'\\') => {}
+                _ => {
+                    old += 1;
+                    new += 1;
+                }
+            }
+        }
+        if (old, new) == (header.old_len, header.new_len) {
+            return Ok(());
+        }
+        let message = format!(
+            "malformed hunk at line {} of {}: its header counts {} old and {} new lines, \
+             it has {} old and {} new lines",
+            header_line,
+            self.path.as_deref().unwrap_or("the input"),
+            header.old_len,
+            header.new_len,
+            old,
+            new
+        );
+        if strict {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ParseError(message),
+            ));
+        }
+        eprintln!("{}; it is displayed unrefined", message);
+        self.malformed = true;
+        Ok(())
+    }
+
+    /// Writes how the hunk applies, with --apply-preview.
+    fn check_apply<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let (dir, header) = match (&self.config.apply_preview, &self.header) {
+            (Some((_, dir)), Some(header)) => (dir, header),
+            _ => return Ok(()),
+        };
+        let data = self.lines.data();
+        let lines = self.lines.iter().map(|(lo, hi)| &data[lo..hi]);
+        let paths = (self.old_path.as_deref(), self.path.as_deref());
+        let status = self.apply.check(dir, paths, header, lines);
+        if let apply::Status::Rejected(_) = status {
+            self.stats.rejected_hunks += 1;
+        }
+        apply::output_note(&status, &self.config, out)
+    }
+
+    fn count_lines(&mut self) {
+        let data = self.lines.data();
+        let file_stats = self.stats.current();
+        for (line_start, _) in self.lines.iter() {
+            match data[line_start] {
+                b'+' => file_stats.added_lines += 1,
+                b'-' => file_stats.removed_lines += 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// Writes each block of changed lines of the hunk merged, with the
+    /// removed and the added tokens inline, like `git diff --word-diff`.
+    fn word_diff<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let Self {
+            v,
+            diff_buffer,
+            added_tokens,
+            removed_tokens,
+            lines,
+            stats,
+            timings,
+            folds,
+            gaps,
+            config,
+            ..
+        } = self;
+        let data = lines.data();
+        let file_stats = stats.current();
+        file_stats.added_tokens += added_tokens.len();
+        file_stats.removed_tokens += removed_tokens.len();
+        // the tokens of the lines before the current block
+        let (mut removed_lo, mut added_lo) = (0, 0);
+        fold_lines(config, lines, gaps, folds);
+        let mut line_iter = lines.iter().enumerate().peekable();
+        while let Some((iline, (line_start, line_end))) = line_iter.next() {
+            match folds.get(iline) {
+                Some(Fold::Marker(nlines)) => {
+                    output_fold_marker(*nlines, config, out)?;
+                    continue;
+                }
+                Some(Fold::Hidden) => continue,
+                _ => (),
+            }
+            if data[line_start] == b'\\' {
+                let line = Layout::new(config).apply(&data[line_start..line_end]);
+                output(&line, &config.no_newline_face, out)?;
+                continue;
+            }
+            if data[line_start] != b'-' && data[line_start] != b'+' {
+                // the empty context lines of --lenient have no prefix
+                let prefix_len = if data[line_start] == b'\n' { 0 } else { 1 };
+                let mut layout = Layout::new(config);
+                layout.start_line(prefix_len);
+                let line = layout.apply(&data[line_start + prefix_len..line_end]);
+                output(&line, &ColorSpec::default(), out)?;
+                continue;
+            }
+            let mut block_end = line_end;
+            let mut next_start = line_start;
+            loop {
+                match data[next_start] {
+                    b'+' => file_stats.added_lines += 1,
+                    _ => file_stats.removed_lines += 1,
+                }
+                match line_iter.peek() {
+                    Some(&(_, (start, end))) if data[start] == b'-' || data[start] == b'+' => {
+                        next_start = start;
+                        block_end = end;
+                        line_iter.next();
+                    }
+                    _ => break,
+                }
+            }
+            let removed_hi =
+                removed_lo + removed_tokens[removed_lo..].partition_point(|tok| tok.lo < block_end);
+            let added_hi =
+                added_lo + added_tokens[added_lo..].partition_point(|tok| tok.lo < block_end);
+            let removed = &removed_tokens[removed_lo..removed_hi];
+            let added = &added_tokens[added_lo..added_hi];
+            let folded = config
+                .ignore_case
+                .then(|| tokenize::fold_case(data, removed, added));
+            let (diff_data, diff_removed, diff_added) = match &folded {
+                Some((data, removed, added)) => (&data[..], &removed[..], &added[..]),
+                None => (data, removed, added),
+            };
+            let cost = removed.len() * added.len();
+            let start = now(config.profile);
+            match config.max_refine_cost {
+                Some(max_cost) if max_cost < cost => diff_buffer.clear(),
+                _ => diff_tokens(
+                    config,
+                    diff_data,
+                    (diff_removed, diff_added),
+                    v,
+                    diff_buffer,
+                ),
+            }
+            if config.slider {
+                slider::slide(diff_buffer, diff_data, diff_removed, diff_added);
+            }
+            timings.lcs += elapsed(&start);
+            let end = Snake {
+                x0: removed.len() as isize,
+                y0: added.len() as isize,
+                len: 0,
+            };
+            let (mut x, mut y) = (0, 0);
+            let mut layout = Layout::new(config);
+            layout.start_line(1);
+            let shared_face = ColorSpec::default();
+            let mut output_tokens =
+                |toks: &[HashedSpan], face: &ColorSpec, out: &mut Stream| -> io::Result<()> {
+                    for tok in toks {
+                        let tok = &data[tok.lo..tok.hi];
+                        output_visible(tok, face, &mut layout, config, out)?;
+                        if tok.ends_with(b"\n") {
+                            layout.start_line(1);
+                        }
+                    }
+                    Ok(())
+                };
+            for snake in diff_buffer.iter().chain(Some(&end)) {
+                let (x0, y0) = (snake.x0 as usize, snake.y0 as usize);
+                let len = snake.len as usize;
+                output_tokens(&removed[x..x0], &config.removed_face, out)?;
+                output_tokens(&added[y..y0], &config.added_face, out)?;
+                for (k, tok) in added[y0..y0 + len].iter().enumerate() {
+                    let old = removed[x0 + k];
+                    let face = if data[old.lo..old.hi] == data[tok.lo..tok.hi] {
+                        &shared_face
+                    } else {
+                        &config.case_changed_face
+                    };
+                    output_tokens(std::slice::from_ref(tok), face, out)?;
+                }
+                x = x0 + len;
+                y = y0 + len;
+            }
+            removed_lo = removed_hi;
+            added_lo = added_hi;
+        }
+        Ok(())
+    }
+
+    fn refine<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        if self.config.word_diff {
+            return self.word_diff(out);
+        }
         let Self {
             v,
             diff_buffer,
             added_tokens,
             removed_tokens,
             lines,
+            input_faces,
+            segments,
+            case_changed,
+            number_changed,
+            search_matches,
+            masked,
+            indent_pairs,
+            relocated,
+            folds,
+            gaps,
+            chunks,
+            pending_header: _,
+            commit: _,
+            commit_sha,
+            mail: _,
+            signature: _,
+            binary_patch: _,
+            extended: _,
+            blame,
+            apply: _,
+            cache,
+            header,
+            function_context,
+            old_path,
+            path,
+            content_lang: _,
+            stats,
+            timings,
+            count_only: _,
+            malformed,
+            base_faces: _,
             config,
         } = self;
+        let refine_start = now(config.profile);
+        let lcs_before = timings.lcs;
         let data = lines.data();
-        let tokens = DiffInput {
-            removed: Tokenization::new(lines.data(), removed_tokens),
-            added: Tokenization::new(lines.data(), added_tokens),
+        if by_char(config, data, lines) {
+            tokenize::split_chars(data, removed_tokens);
+            tokenize::split_chars(data, added_tokens);
+        }
+        let folded = config
+            .ignore_case
+            .then(|| tokenize::fold_case(data, removed_tokens, added_tokens));
+        let (diff_data, diff_removed, diff_added) = match &folded {
+            Some((data, removed, added)) => (&data[..], &removed[..], &added[..]),
+            None => (data, &removed_tokens[..], &added_tokens[..]),
+        };
+        masked.clear();
+        if !config.ignore_tokens.is_empty() {
+            for (line_start, line_end) in lines.iter() {
+                if let b'-' | b'+' = data[line_start] {
+                    let line = (line_start, line_end);
+                    grep::match_spans(&config.ignore_tokens, data, line, masked);
+                }
+            }
+        }
+        let masked_tokens = (!masked.is_empty())
+            .then(|| tokenize::mask(diff_data, masked, diff_removed, diff_added));
+        let (diff_removed, diff_added) = match &masked_tokens {
+            Some((removed, added)) => (&removed[..], &added[..]),
+            None => (diff_removed, diff_added),
         };
-        diffr_lib::diff(&tokens, v, diff_buffer);
+        let cost = added_tokens.len() * removed_tokens.len();
+        let refine = match config.max_refine_cost {
+            Some(max_cost) if max_cost < cost => {
+                if config.debug && config.debug_format == DebugFormat::Text {
+                    eprintln!(
+                        "refinement skipped in {}: cost {} (+{} -{} tokens) exceeds {}",
+                        path.as_deref().unwrap_or("<unknown>"),
+                        cost,
+                        added_tokens.len(),
+                        removed_tokens.len(),
+                        max_cost
+                    );
+                }
+                false
+            }
+            _ => true,
+        };
+        let ignored = is_ignored(config, lines);
+        let skipped = match (ignored, refine) {
+            _ if *malformed => Some("malformed"),
+            (true, _) => Some("ignored"),
+            (false, false) => Some("max-refine-cost"),
+            (false, true) => None,
+        };
+        let refine = refine && !ignored && !*malformed;
+        let (mut cached, mut nshared) = (false, 0);
+        let file_stats = stats.current();
+        file_stats.added_tokens += added_tokens.len();
+        file_stats.removed_tokens += removed_tokens.len();
+        case_changed.clear();
+        number_changed.clear();
+        search_matches.clear();
+        if !config.grep.is_empty() {
+            for (line_start, line_end) in lines.iter() {
+                if let b'-' | b'+' = data[line_start] {
+                    grep::match_spans(&config.grep, data, (line_start, line_end), search_matches);
+                }
+            }
+        }
+        indent_pairs.clear();
+        if config.indent_changed {
+            indent::pair_lines(data, lines.iter(), indent_pairs);
+        }
+        relocated.clear();
+        if config.relocated {
+            relocated::find(data, lines.iter(), relocated);
+        }
+        if refine {
+            let start = now(config.profile);
+            let tokens = (diff_removed, diff_added);
+            let key = cache.key(config, diff_data, tokens);
+            let sizes = (diff_removed.len(), diff_added.len());
+            match &key {
+                Some(key) if cache.get(key, sizes, diff_buffer) => cached = true,
+                _ => {
+                    if config.refine_block {
+                        diff_blocks(config, lines, diff_data, tokens, v, diff_buffer);
+                    } else {
+                        diff_tokens(config, diff_data, tokens, v, diff_buffer);
+                    }
+                    if config.slider {
+                        slider::slide(diff_buffer, diff_data, diff_removed, diff_added);
+                    }
+                    if let Some(key) = &key {
+                        cache.put(key, diff_buffer);
+                    }
+                }
+            }
+            timings.lcs += elapsed(&start);
+            if config.ignore_case && !config.case_changed_face.is_none() {
+                case_changed_spans(
+                    data,
+                    diff_buffer,
+                    removed_tokens,
+                    added_tokens,
+                    case_changed,
+                );
+            }
+            if !config.number_changed_face.is_none() {
+                number_changed_spans(
+                    data,
+                    diff_buffer,
+                    removed_tokens,
+                    added_tokens,
+                    number_changed,
+                );
+            }
+            nshared = diff_buffer.iter().map(|s| s.len as usize).sum::<usize>();
+            file_stats.refined_added_tokens += added_tokens.len() - nshared;
+            file_stats.refined_removed_tokens += removed_tokens.len() - nshared;
+        }
         let mut ishared_added = 0;
         let mut ishared_removed = 0;
-        for (line_start, line_end) in lines.iter() {
+        let unpaired_unrefined =
+            config.pairing_threshold.is_some() && config.line_pairing && !config.refine_block && {
+                let count = |prefix| lines.iter().filter(|&(lo, _)| data[lo] == prefix).count();
+                count(b'-') != count(b'+')
+            };
+        let gutter_width = match (config.line_numbers, &header) {
+            (Some(style), Some(header)) => {
+                Some(header.gutter_width(style, config.line_number_width))
+            }
+            _ => None,
+        };
+        let (mut old_line, mut new_line) = match header {
+            Some(header) => (header.old_start, header.new_start),
+            None => (0, 0),
+        };
+        if let (Some(width), Some(context)) = (gutter_width, function_context) {
+            output_line_numbers(None, None, width, None, config, out)?;
+            output_breadcrumb(path.as_deref(), context, config, out)?;
+        }
+        fold_lines(config, lines, gaps, folds);
+        match config.split_hunks {
+            Some(min_lines) => chunks::split(data, lines.iter(), min_lines, chunks),
+            None => chunks.clear(),
+        }
+        let mut chunks = chunks.iter().peekable();
+        // the files unknown to git blame have no gutter
+        let blamed = config.blame
+            && old_path
+                .as_deref()
+                .is_some_and(|old_path| blame.is_known(old_path, config));
+        // the line whose annotations are written after it, and after
+        // its "\ No newline at end of file" marker
+        let mut annotated = None;
+        for (iline, (line_start, line_end)) in lines.iter().enumerate() {
             let first = data[line_start];
+            if first != b'\\' {
+                if let Some(line) = annotated.take() {
+                    output_annotations(path.as_deref(), line, gutter_width, config, out)?;
+                }
+            }
+            if let Some(chunk) = chunks.next_if(|chunk| chunk.start == iline) {
+                if let Some(width) = gutter_width {
+                    output_line_numbers(None, None, width, None, config, out)?;
+                }
+                output_chunk_header(old_line, new_line, chunk, config, out)?;
+            }
+            if let Some(&fold) = folds.get(iline).filter(|&&fold| fold != Fold::Shown) {
+                if let Fold::Marker(nlines) = fold {
+                    if let Some(width) = gutter_width {
+                        output_line_numbers(None, None, width, None, config, out)?;
+                    }
+                    output_symbol(false, config, out)?;
+                    output_fold_marker(nlines, config, out)?;
+                }
+                // the hidden lines are context lines
+                if first != b'\\' {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                continue;
+            }
+            let (old, new) = match first {
+                b'-' => (Some(old_line), None),
+                b'+' => (None, Some(new_line)),
+                b'\\' => (None, None),
+                _ => (Some(old_line), Some(new_line)),
+            };
+            if let Some(width) = gutter_width {
+                let url = hyperlink(config, path, commit_sha, new_line);
+                output_line_numbers(old, new, width, url, config, out)?;
+            }
+            if first != b'\\' {
+                annotated = new;
+            }
+            if blamed {
+                let annotation = match (first, old_path.as_deref(), &header) {
+                    (b'-', Some(old_path), Some(_)) => blame.annotation(old_path, old_line, config),
+                    _ => None,
+                };
+                blame::output_gutter(annotation, config, out)?;
+            }
+            old_line += old.map_or(0, |_| 1);
+            new_line += new.map_or(0, |_| 1);
+            if first != b'-' && first != b'+' {
+                output_symbol(false, config, out)?;
+            }
             match first {
                 b'-' | b'+' => {
                     let is_plus = first == b'+';
+                    if is_plus {
+                        file_stats.added_lines += 1;
+                    } else {
+                        file_stats.removed_lines += 1;
+                    }
                     let (nohighlight, highlight, toks, i) = if is_plus {
                         (
                             &config.added_face,
                             &config.refine_added_face,
-                            &tokens.added,
+                            diff_added,
                             &mut ishared_added,
                         )
                     } else {
                         (
                             &config.removed_face,
                             &config.refine_removed_face,
-                            &tokens.removed,
+                            diff_removed,
                             &mut ishared_removed,
                         )
                     };
-                    let shared = diff_buffer.iter().skip(*i).map(|s| {
-                        let x0 = if is_plus { s.y0 } else { s.x0 };
-                        let first = toks.nth_span(x0).lo;
-                        let last = toks.nth_span(x0 + s.len - 1).hi;
-                        (first, last)
-                    });
-                    *i += Self::paint_line(
-                        toks.data(),
-                        &(line_start, line_end),
-                        &nohighlight,
-                        &highlight,
-                        shared,
+                    let nohighlight = match input_faces.get(iline) {
+                        _ if ignored => &config.ignored_face,
+                        Some(face) if !face.is_none() => face,
+                        _ => nohighlight,
+                    };
+                    if let (Some(&Some(pair)), false) = (indent_pairs.get(iline), ignored) {
+                        let line = (line_start, line_end);
+                        let faces = (nohighlight, pair);
+                        output_symbol(false, config, out)?;
+                        output_indent_changed(data, line, faces, search_matches, config, out)?;
+                        continue;
+                    }
+                    if let (Some(true), false) = (relocated.get(iline), ignored) {
+                        let line = (line_start, line_end);
+                        output_symbol(false, config, out)?;
+                        output_relocated(data, line, nohighlight, search_matches, config, out)?;
+                        continue;
+                    }
+                    let line = (line_start, line_end);
+                    if !refine {
+                        output_plain_changed(data, line, nohighlight, search_matches, config, out)?;
+                        continue;
+                    }
+                    let shared = diff_buffer
+                        .iter()
+                        .skip(*i)
+                        .map(|s| Self::shared_span(s, toks, is_plus));
+                    *i += Self::shared_segments(shared, &line, segments);
+                    // the lines without counterpart share no token
+                    if unpaired_unrefined && segments.is_empty() {
+                        output_plain_changed(data, line, nohighlight, search_matches, config, out)?;
+                        continue;
+                    }
+                    output_symbol(!segments.is_empty(), config, out)?;
+                    Self::paint_line(
+                        data,
+                        &line,
+                        (nohighlight, highlight),
+                        segments,
+                        (case_changed, number_changed, search_matches, masked),
+                        config,
                         out,
                     )?;
                 }
-                _ => output(&data[line_start..line_end], &ColorSpec::default(), out)?,
+                b'\\' => {
+                    let line = Layout::new(config).apply(&data[line_start..line_end]);
+                    output(&line, &config.no_newline_face, out)?;
+                }
+                _ => {
+                    let (line_end, crlf) = split_crlf(data, (line_start, line_end));
+                    let mut layout = Layout::new(config);
+                    let line = &data[line_start..line_end];
+                    if config.keep_input_colors {
+                        output_raw(&layout.apply(line), out)?
+                    } else {
+                        output_visible(line, &ColorSpec::default(), &mut layout, config, out)?
+                    }
+                    if crlf {
+                        output_crlf(config, out)?;
+                    }
+                }
+            }
+        }
+        if let Some(line) = annotated {
+            output_annotations(path.as_deref(), line, gutter_width, config, out)?;
+        }
+        match (&config.recorder, *header) {
+            (Some(recorder), Some(header)) => {
+                let lcs = refine.then_some(&diff_buffer[..]);
+                recorder.record(hunks::Part::Hunk(hunks::refined(
+                    (header, old_path, path),
+                    lines,
+                    (diff_removed, diff_added),
+                    (lcs, masked),
+                    unpaired_unrefined,
+                    config,
+                )))?;
             }
+            // the lines after a malformed header are not a hunk
+            (Some(recorder), None) => recorder.record(hunks::Part::Lines(data.to_vec()))?,
+            (None, _) => {}
+        }
+        if config.debug {
+            let count = |prefix| lines.iter().filter(|&(lo, _)| data[lo] == prefix).count();
+            let mode = if config.refine_block {
+                "block"
+            } else if config.line_pairing {
+                "pairing"
+            } else {
+                "tokens"
+            };
+            let report = debug::HunkReport {
+                path: path.as_deref(),
+                header: header
+                    .as_ref()
+                    .map(|h| (h.old_start, h.old_len, h.new_start, h.new_len)),
+                lines: (count(b'-'), count(b'+')),
+                tokens: (removed_tokens.len(), added_tokens.len()),
+                cost,
+                shared: nshared,
+                algorithm: format!("{:?}", config.algorithm).to_lowercase(),
+                mode,
+                skipped,
+                cached,
+                lcs: timings.lcs.saturating_sub(lcs_before),
+                total: elapsed(&refine_start),
+            };
+            report.write(config.debug_format, &mut io::stderr())?;
         }
-        lines.clear();
-        added_tokens.clear();
-        removed_tokens.clear();
         Ok(())
     }
 
-    fn push_added(&mut self, line: &[u8]) {
-        self.push_aux(line, true)
+    /// Writes a line that is not part of the body of a hunk; `raw` is
+    /// the line as read, and `stripped` without escape codes.
+    fn output_header_line<Stream>(
+        &mut self,
+        raw: &[u8],
+        stripped: &[u8],
+        out: &mut Stream,
+    ) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        if let Some(recorder) = &self.config.recorder {
+            recorder.record(hunks::Part::Lines(stripped.to_vec()))?;
+        }
+        // the signature of a mail lasts until the next mail
+        if self.signature && !commit::is_commit_start(stripped) {
+            return Ok(());
+        }
+        self.signature = false;
+        if let Some(patch) = &mut self.binary_patch {
+            if patch.push(stripped) {
+                return Ok(());
+            }
+            self.finish_binary(out)?;
+        }
+        if let Some(commit) = &mut self.commit {
+            if commit.push(raw, stripped) {
+                return Ok(());
+            }
+            self.finish_commit(out)?;
+        }
+        if commit::is_commit_start(stripped) {
+            // the files of the previous commit are done
+            self.old_path = None;
+            self.path = None;
+            self.content_lang = None;
+            self.header = None;
+            self.commit_sha = commit::sha(stripped);
+            let commit = CommitHeader::new(raw, stripped);
+            self.mail = commit.is_mail();
+            self.commit = Some(commit);
+            return Ok(());
+        }
+        if self.mail && commit::is_signature(stripped) {
+            self.signature = true;
+            return Ok(());
+        }
+        // a rewritten line loses its input colors
+        let rewritten = headers::rewrite(stripped, &self.config);
+        let (raw, stripped) = match &rewritten {
+            Some(line) => (&line[..], &line[..]),
+            None => (raw, stripped),
+        };
+        match extended::parse(stripped) {
+            Some(line) if extended::is_summarized(&line, &self.config) => {
+                self.extended.push(&line);
+                return Ok(());
+            }
+            Some(line) => {
+                self.extended.write(&self.config, out)?;
+                return extended::output_line(raw, stripped, &line, &self.config, out);
+            }
+            None => self.extended.write(&self.config, out)?,
+        }
+        let link_line = if stripped.starts_with(b"@") {
+            self.stats.current().hunks += 1;
+            self.header = parse_hunk_header(stripped);
+            self.function_context =
+                split_function_context(stripped).map(|(_, context)| context.to_vec());
+            if self.header.is_none() {
+                self.stats.malformed_headers += 1;
+            }
+            match self.header {
+                Some(header) if self.config.line_numbers.is_none() => Some(header.new_start),
+                _ => None,
+            }
+        } else if let Some(paths) = stripped.strip_prefix(b"diff --git ") {
+            self.old_path = None;
+            self.path = parse_git_path(paths);
+            self.content_lang = None;
+            self.select_file_faces();
+            None
+        } else if bytes::is_binary_marker(stripped) {
+            let path = parse_binary_files_path(stripped).or_else(|| self.path.clone());
+            self.stats.start_file(path.clone().unwrap_or_default());
+            self.stats.current().binary = true;
+            match self.config.binary {
+                BinaryMode::Raw => None,
+                _ if stripped.starts_with(b"GIT binary patch") => {
+                    self.binary_patch = Some(binary::Patch::new(path));
+                    return Ok(());
+                }
+                _ => {
+                    let change = binary::marker_change(stripped);
+                    return binary::write_summary(path.as_deref(), change, &self.config, out);
+                }
+            }
+        } else if let Some(path) = stripped.strip_prefix(b"--- ") {
+            self.old_path = parse_old_path(path);
+            None
+        } else if let Some(path) = stripped.strip_prefix(b"+++ ") {
+            self.path = parse_new_path(path);
+            self.content_lang = None;
+            self.select_file_faces();
+            let display_path = self.path.as_ref().or(self.old_path.as_ref());
+            self.stats
+                .start_file(display_path.cloned().unwrap_or_default());
+            Some(1)
+        } else {
+            None
+        };
+        if self.config.merge_hunks.is_some() && self.header.is_some() && stripped.starts_with(b"@")
+        {
+            self.pending_header = Some((raw.to_vec(), stripped.to_vec(), link_line));
+            return Ok(());
+        }
+        self.write_header_line(raw, stripped, link_line, out)
     }
 
-    fn push_removed(&mut self, line: &[u8]) {
+    /// Sets the faces of the current file: the faces of the
+    /// configuration file whose glob matches its path apply on top of
+    /// the other faces.
+    fn select_file_faces(&mut self) {
+        if self.config.file_faces.is_empty() {
+            return;
+        }
+        let config = &mut self.config;
+        let base = self
+            .base_faces
+            .get_or_insert_with(|| cli_args::faces(config));
+        cli_args::set_faces(config, base);
+        let path = match self.path.as_ref().or(self.old_path.as_ref()) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        for file_faces in self.config.file_faces.clone() {
+            if filter::matches_path(&file_faces.glob, &path) {
+                let colors = file_faces.colors.iter().map(String::as_str);
+                // the faces are checked when they are read
+                ignore(cli_args::parse_color_args(&mut self.config, colors));
+            }
+        }
+    }
+
+    /// Writes the header line `raw`, hyperlinked to the line `link_line`
+    /// of the file if any.
+    fn write_header_line<Stream>(
+        &self,
+        raw: &[u8],
+        stripped: &[u8],
+        link_line: Option<usize>,
+        out: &mut Stream,
+    ) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        if let Some(marker) = &self.config.navigate {
+            if stripped.starts_with(b"@") || stripped.starts_with(b"+++ ") {
+                output(
+                    format!("{} ", marker).as_bytes(),
+                    &ColorSpec::default(),
+                    out,
+                )?;
+            }
+        }
+        let keep_input_colors = self.config.keep_input_colors;
+        let url =
+            link_line.and_then(|line| hyperlink(&self.config, &self.path, &self.commit_sha, line));
+        match split_function_context(stripped) {
+            Some(parts) if !keep_input_colors => {
+                output_hunk_header(parts, url.as_deref(), &self.config, out)
+            }
+            _ => match url {
+                Some(url) => output_hyperlinked(raw, &url, keep_input_colors, out),
+                None => output_unrefined(raw, keep_input_colors, out),
+            },
+        }
+    }
+
+    /// Writes the header of the hunk held by --merge-hunks, if any, with
+    /// the line ranges of the hunks merged into it.
+    fn finish_header<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        let (raw, stripped, link_line) = match self.pending_header.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let header = match self.header {
+            Some(header) if !self.gaps.is_empty() => header,
+            _ => return self.write_header_line(&raw, &stripped, link_line, out),
+        };
+        let mut merged = format!(
+            "@@ -{} +{} @@",
+            reslice::format_range(header.old_start, header.old_len),
+            reslice::format_range(header.new_start, header.new_len),
+        )
+        .into_bytes();
+        if let Some((_, context)) = split_function_context(&stripped) {
+            merged.push(b' ');
+            merged.extend_from_slice(context);
+        }
+        merged.push(b'\n');
+        self.write_header_line(&merged, &merged, link_line, out)
+    }
+
+    /// Merges the hunk of the header `stripped` into the current one,
+    /// with --merge-hunks, if they are separated by fewer unchanged
+    /// lines than its limit; these lines are pushed as empty context
+    /// lines, and folded. Returns true if the hunks are merged.
+    fn merge_hunk(&mut self, stripped: &[u8]) -> bool {
+        let (max, header, next) = match (self.config.merge_hunks, self.header) {
+            (Some(max), Some(header)) if self.pending_header.is_some() => {
+                match parse_hunk_header(stripped) {
+                    Some(next) => (max, header, next),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+        // an empty range starts at the line before it
+        let first = |start, len| start + usize::from(len == 0);
+        let old_first = first(header.old_start, header.old_len);
+        let new_first = first(header.new_start, header.new_len);
+        let gap_old = first(next.old_start, next.old_len).checked_sub(old_first + header.old_len);
+        let gap_new = first(next.new_start, next.new_len).checked_sub(new_first + header.new_len);
+        let gap = match (gap_old, gap_new) {
+            (Some(old), Some(new)) if old == new && old < max => old,
+            _ => return false,
+        };
+        self.gaps.push((self.lines.iter().count(), gap));
+        for _ in 0..gap {
+            self.push_context(b" \n");
+        }
+        self.header = Some(HunkHeader {
+            old_start: old_first,
+            old_len: first(next.old_start, next.old_len) + next.old_len - old_first,
+            new_start: new_first,
+            new_len: first(next.new_start, next.new_len) + next.new_len - new_first,
+        });
+        self.stats.current().hunks += 1;
+        true
+    }
+
+    /// Writes the placeholder of the binary patch being read, if any.
+    fn finish_binary<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        match self.binary_patch.take() {
+            Some(patch) => patch.write(&self.config, out),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the buffered commit header, if any.
+    fn finish_commit<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        match self.commit.take() {
+            Some(commit) => commit.write(&self.config, out),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the buffered summary of --compact-headers or
+    /// --rename-format=compact, if any.
+    fn finish_extended<Stream>(&mut self, out: &mut Stream) -> io::Result<()>
+    where
+        Stream: WriteColor,
+    {
+        self.extended.write(&self.config, out)
+    }
+
+    fn push_added(&mut self, line: &[u8]) {
+        self.push_aux(line, true)
+    }
+
+    fn push_removed(&mut self, line: &[u8]) {
         self.push_aux(line, false)
     }
 
+    fn push_context(&mut self, line: &[u8]) {
+        self.detect_lang(line);
+        if self.config.keep_input_colors {
+            self.input_faces.push(ColorSpec::default());
+            self.lines.append_line(line);
+        } else {
+            add_raw_line(&mut self.lines, line);
+        }
+    }
+
+    /// Detects the language of the file from the shebang or the
+    /// modeline of `line`, with --lang=auto and a path without known
+    /// extension.
+    fn detect_lang(&mut self, line: &[u8]) {
+        if self.config.lang != Some(Lang::Auto) || self.content_lang.is_some() || self.count_only {
+            return;
+        }
+        if Lang::Auto.resolve(self.path.as_deref()).is_some() {
+            return;
+        }
+        // a shebang is only read on the first line of the file
+        let first = self.lines.len() == 0
+            && self
+                .header
+                .is_some_and(|header| header.old_start <= 1 && header.new_start <= 1);
+        self.content_lang = lang::detect(line.get(1..).unwrap_or_default(), first);
+    }
+
     fn push_aux(&mut self, line: &[u8], added: bool) {
+        self.detect_lang(line);
+        if self.config.keep_input_colors {
+            self.input_faces.push(input_face(line));
+        }
         // XXX: don't tokenize the leading +/- character
         let ofs = self.lines.len() + 1;
         add_raw_line(&mut self.lines, line);
-        diffr_lib::tokenize(
-            &self.lines.data(),
-            ofs,
-            if added {
-                &mut self.added_tokens
-            } else {
-                &mut self.removed_tokens
-            },
-        );
+        if self.count_only {
+            return;
+        }
+        let lang = self
+            .config
+            .lang
+            .and_then(|lang| lang.resolve(self.path.as_deref()))
+            .or(self.content_lang);
+        let tokens = if added {
+            &mut self.added_tokens
+        } else {
+            &mut self.removed_tokens
+        };
+        // the truncated end of long lines is not refined
+        let mut data = self.lines.data();
+        let mut layout = Layout::new(&self.config);
+        layout.start_line(1);
+        if let Some(i) = layout.truncation_index(&data[ofs..]) {
+            data = &data[..ofs + i];
+        }
+        let time = now(self.config.profile);
+        let start = tokens.len();
+        if self.config.ascii_tokenize {
+            diffr_lib::tokenize(data, ofs, tokens);
+        } else {
+            tokenize::tokenize(data, ofs, tokens);
+        }
+        tokenize::drop_carriage_return(data, tokens, start);
+        if let Some(lang) = lang {
+            lang::merge_tokens(data, tokens, start, lang);
+        }
+        self.timings.tokenize += elapsed(&time);
     }
 }
 
@@ -276,13 +3111,742 @@ fn add_raw_line(dst: &mut LineSplit, line: &[u8]) {
     let mut i = 0;
     let len = line.len();
     while i < len {
-        i += skip_all_escape_code(&line[i..]);
-        let tok_len = skip_token(&line[i..]);
+        let escape_len = skip_all_escape_code(&line[i..]);
+        if escape_len != 0 {
+            i += escape_len;
+            continue;
+        }
+        // an unterminated escape code is kept as text
+        let tok_len = skip_token(&line[i..]).max(1);
         dst.append_line(&line[i..i + tok_len]);
         i += tok_len;
     }
 }
 
+/// Collects in `spans` the spans of the tokens shared by `snakes` that
+/// differ by case, sorted.
+fn case_changed_spans(
+    data: &[u8],
+    snakes: &[Snake],
+    removed: &[HashedSpan],
+    added: &[HashedSpan],
+    spans: &mut Vec<(usize, usize)>,
+) {
+    for snake in snakes {
+        let (x0, y0) = (snake.x0 as usize, snake.y0 as usize);
+        for k in 0..snake.len as usize {
+            let (old, new) = (removed[x0 + k], added[y0 + k]);
+            if data[old.lo..old.hi] != data[new.lo..new.hi] {
+                spans.push((old.lo, old.hi));
+                spans.push((new.lo, new.hi));
+            }
+        }
+    }
+    spans.sort_unstable();
+}
+
+/// Returns true if `token` is a number, such as "42", "0x1f" or
+/// "1e5".
+fn is_number(token: &[u8]) -> bool {
+    token.first().is_some_and(u8::is_ascii_digit)
+        && token
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Computes the spans of the numbers replaced by other numbers: the
+/// unshared tokens between two snakes, when both sides have as many
+/// and they are all numbers, for the number-changed face.
+fn number_changed_spans(
+    data: &[u8],
+    snakes: &[Snake],
+    removed: &[HashedSpan],
+    added: &[HashedSpan],
+    spans: &mut Vec<(usize, usize)>,
+) {
+    let end = Snake {
+        x0: removed.len() as isize,
+        y0: added.len() as isize,
+        len: 0,
+    };
+    let (mut x, mut y) = (0, 0);
+    for snake in snakes.iter().chain(Some(&end)) {
+        let (x0, y0) = (snake.x0 as usize, snake.y0 as usize);
+        let (old, new) = (&removed[x..x0], &added[y..y0]);
+        let is_number = |token: &HashedSpan| is_number(&data[token.lo..token.hi]);
+        if !old.is_empty() && old.len() == new.len() && old.iter().chain(new).all(is_number) {
+            spans.extend(old.iter().chain(new).map(|token| (token.lo, token.hi)));
+        }
+        x = x0 + snake.len as usize;
+        y = y0 + snake.len as usize;
+    }
+    spans.sort_unstable();
+}
+
+/// Writes `data[lo..hi]` with `face` through `output`, and its parts
+/// in the sorted `spans` with `span_face`.
+fn output_spans<Output, Stream>(
+    (lo, hi): (usize, usize),
+    (face, span_face): (&ColorSpec, &ColorSpec),
+    spans: &[(usize, usize)],
+    output: &mut Output,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Output: FnMut(usize, usize, &ColorSpec, &mut Stream) -> io::Result<()>,
+{
+    let mut x = lo;
+    let first = spans.partition_point(|&(_, span_hi)| span_hi <= lo);
+    for &(span_lo, span_hi) in &spans[first..] {
+        if hi <= span_lo {
+            break;
+        }
+        let (span_lo, span_hi) = (span_lo.max(lo), span_hi.min(hi));
+        output(x, span_lo, face, out)?;
+        output(span_lo, span_hi, span_face, out)?;
+        x = span_hi;
+    }
+    output(x, hi, face, out)
+}
+
+/// Writes `data[lo..hi]` laid out by `layout` and painted with `face`,
+/// except the matches of --grep in `search_matches`, painted with the
+/// 'search-match' face.
+fn output_searched<Stream>(
+    data: &[u8],
+    (lo, hi): (usize, usize),
+    face: &ColorSpec,
+    search_matches: &[Span],
+    layout: &mut Layout,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let faces = (face, &config.search_match_face);
+    let mut output_laid_out = |lo, hi, face: &ColorSpec, out: &mut Stream| {
+        output_visible(&data[lo..hi], face, layout, config, out)
+    };
+    output_spans((lo, hi), faces, search_matches, &mut output_laid_out, out)
+}
+
+/// Writes `buf` laid out by `layout` and painted with `face`; with
+/// --show-nonprinting, its nonprinting characters are escaped and
+/// painted with the 'nonprinting' face.
+fn output_visible<Stream>(
+    buf: &[u8],
+    face: &ColorSpec,
+    layout: &mut Layout,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let mut buf = buf;
+    let find = |buf| {
+        config
+            .show_nonprinting
+            .then(|| nonprinting::find(buf))
+            .flatten()
+    };
+    while let Some((lo, hi)) = find(buf) {
+        output_ruled(&buf[..lo], face, layout, config, out)?;
+        let escaped = nonprinting::escape(&buf[lo..hi]);
+        output(
+            &layout.apply(escaped.as_bytes()),
+            &config.nonprinting_face,
+            out,
+        )?;
+        buf = &buf[hi..];
+    }
+    output_ruled(buf, face, layout, config, out)
+}
+
+/// Writes `buf` laid out by `layout` and painted with `face`; with
+/// --ruler, the guide is drawn at the end of the line if it is short
+/// enough, and the end of the added lines beyond the guide is painted
+/// with the 'overlong' face, if set.
+fn output_ruled<Stream>(
+    buf: &[u8],
+    face: &ColorSpec,
+    layout: &mut Layout,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if config.ruler.is_none() {
+        return output(&layout.apply(buf), face, out);
+    }
+    let (content, newline) = match buf.strip_suffix(b"\n") {
+        Some(content) => (content, true),
+        None => (buf, false),
+    };
+    let overlong = match layout.overlong_index(content) {
+        Some(i) if !config.overlong_face.is_none() => i,
+        _ => content.len(),
+    };
+    output(&layout.apply(&content[..overlong]), face, out)?;
+    output(
+        &layout.apply(&content[overlong..]),
+        &config.overlong_face,
+        out,
+    )?;
+    if newline {
+        if let Some(padding) = layout.ruler_padding() {
+            out.write_all(" ".repeat(padding).as_bytes())?;
+            output("│".as_bytes(), &config.ruler_face, out)?;
+        }
+        out.write_all(&layout.apply(b"\n"))?;
+    }
+    Ok(())
+}
+
+/// Writes the changed line `lo..hi` of `data` without refinement: its
+/// +/- prefix and its content are painted with `face`.
+fn output_plain_changed<Stream>(
+    data: &[u8],
+    (lo, hi): (usize, usize),
+    face: &ColorSpec,
+    search_matches: &[(usize, usize)],
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    output_symbol(false, config, out)?;
+    let (hi, crlf) = split_crlf(data, (lo, hi));
+    let mut layout = Layout::new(config);
+    output_searched(
+        data,
+        (lo, hi),
+        face,
+        search_matches,
+        &mut layout,
+        config,
+        out,
+    )?;
+    if crlf {
+        output_crlf(config, out)?;
+    }
+    Ok(())
+}
+
+/// Writes the line `lo..hi` of `data` whose indentation only changed
+/// from or to the line `pair`: its +/- prefix is painted with `face`,
+/// and the rest with the 'indent-changed' face, followed on the added
+/// lines by the change of indentation, with --indent-delta.
+fn output_indent_changed<Stream>(
+    data: &[u8],
+    (lo, hi): (usize, usize),
+    (face, pair): (&ColorSpec, Span),
+    search_matches: &[Span],
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let content_hi = if data[hi - 1] == b'\n' { hi - 1 } else { hi };
+    let mut layout = Layout::new(config);
+    output(&layout.apply(&data[lo..lo + 1]), face, out)?;
+    let (content, indent_face) = ((lo + 1, content_hi), &config.indent_changed_face);
+    output_searched(
+        data,
+        content,
+        indent_face,
+        search_matches,
+        &mut layout,
+        config,
+        out,
+    )?;
+    if config.indent_delta && data[lo] == b'+' {
+        let delta = indent::delta(&data[pair.0..pair.1], &data[lo..hi]);
+        output(format!(" {}", delta).as_bytes(), indent_face, out)?;
+    }
+    out.write_all(&data[content_hi..hi])
+}
+
+/// Writes the line `lo..hi` of `data` moved within its hunk: its +/-
+/// prefix is painted with `face`, and the rest with the 'relocated'
+/// face.
+fn output_relocated<Stream>(
+    data: &[u8],
+    (lo, hi): (usize, usize),
+    face: &ColorSpec,
+    search_matches: &[Span],
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let (hi, crlf) = split_crlf(data, (lo, hi));
+    let mut layout = Layout::new(config);
+    output(&layout.apply(&data[lo..lo + 1]), face, out)?;
+    let (content, relocated_face) = ((lo + 1, hi), &config.relocated_face);
+    output_searched(
+        data,
+        content,
+        relocated_face,
+        search_matches,
+        &mut layout,
+        config,
+        out,
+    )?;
+    if crlf {
+        output_crlf(config, out)?;
+    }
+    Ok(())
+}
+
+/// Writes the symbol of --accessibility=symbols before a line of a
+/// hunk: '±' if the line is `modified`, that is, partly refined.
+fn output_symbol<Stream>(modified: bool, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    match (config.symbols, modified) {
+        (false, _) => Ok(()),
+        (true, true) => out.write_all("± ".as_bytes()),
+        (true, false) => out.write_all(b"  "),
+    }
+}
+
+/// Returns the end of the line `lo..hi` of `data` without its "\r\n"
+/// line end, and whether it has one.
+fn split_crlf(data: &[u8], (lo, hi): Span) -> (usize, bool) {
+    if data[lo..hi].ends_with(b"\r\n") {
+        (hi - 2, true)
+    } else {
+        (hi, false)
+    }
+}
+
+/// Writes a "\r\n" line end; with --show-cr, the carriage return is
+/// displayed as "^M" with the 'carriage-return' face.
+fn output_crlf<Stream>(config: &AppConfig, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if config.show_cr {
+        output(b"^M\n", &config.carriage_return_face, out)
+    } else {
+        out.write_all(b"\r\n")
+    }
+}
+
+/// Writes the annotations of the line `line` of the new version of
+/// `path`, with --annotations, after a blank gutter.
+fn output_annotations<Stream>(
+    path: Option<&str>,
+    line: usize,
+    gutter_width: Option<GutterWidth>,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let path = match path {
+        Some(path) if !config.annotations.is_empty() => path,
+        _ => return Ok(()),
+    };
+    let output_gutter = |out: &mut Stream| {
+        if let Some(width) = gutter_width {
+            output_line_numbers(None, None, width, None, config, out)?;
+        }
+        if config.blame {
+            blame::output_gutter(None, config, out)?;
+        }
+        output_symbol(false, config, out)
+    };
+    for annotation in config.annotations.get(path, line) {
+        annotations::output_callout(annotation, output_gutter, config, out)?;
+    }
+    Ok(())
+}
+
+/// Writes the header of `chunk`, whose first line is the line
+/// `old_line` of the old file and `new_line` of the new one.
+fn output_chunk_header<Stream>(
+    old_line: usize,
+    new_line: usize,
+    chunk: &Chunk,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let header = format!(
+        "@@ -{} +{} @@\n",
+        reslice::format_range(old_line, chunk.old_len),
+        reslice::format_range(new_line, chunk.new_len),
+    );
+    output(header.as_bytes(), &config.chunk_header_face, out)
+}
+
+/// Writes the marker of `nlines` context lines folded by
+/// --collapse-context.
+fn output_fold_marker<Stream>(nlines: usize, config: &AppConfig, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let lines = if nlines == 1 { "line" } else { "lines" };
+    let marker = format!("··· {} unchanged {} ···\n", nlines, lines);
+    output(marker.as_bytes(), &config.folded_face, out)
+}
+
+/// Parses the template of --line-numbers-format: "{old}" and "{new}"
+/// stand for the line numbers, followed after a colon by an alignment,
+/// '<', '^' or '>', and a width, as in "{old:<4}"; "{{" and "}}" are
+/// braces.
+fn parse_line_numbers_format(template: &str) -> Result<Vec<GutterPart>, String> {
+    let invalid = |message: String| {
+        format!(
+            "invalid {} '{}': {}",
+            cli_args::FLAG_LINE_NUMBERS_FORMAT,
+            template,
+            message
+        )
+    };
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            text.push_str(brace);
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            return Err(invalid("unmatched '}', written '}}' as text".to_owned()));
+        }
+        let end = rest
+            .find('}')
+            .ok_or_else(|| invalid("unclosed '{'".to_owned()))?;
+        let (name, spec) = rest[..end].split_once(':').unwrap_or((&rest[..end], ""));
+        let new = match name {
+            "old" => false,
+            "new" => true,
+            _ => {
+                let message = format!("got '{{{}}}', expected {{old}} or {{new}}", name);
+                return Err(invalid(message));
+            }
+        };
+        let (align, width) = match spec.chars().next() {
+            Some('<') => (Align::Left, &spec[1..]),
+            Some('^') => (Align::Center, &spec[1..]),
+            Some('>') => (Align::Right, &spec[1..]),
+            _ => (Align::Right, spec),
+        };
+        let width = match width {
+            "" => None,
+            width => Some(
+                width
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid width '{}'", width)))?,
+            ),
+        };
+        if !text.is_empty() {
+            parts.push(GutterPart::Text(std::mem::take(&mut text)));
+        }
+        parts.push(GutterPart::Number { new, align, width });
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(GutterPart::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Formats the line numbers `old` and `new` with the template of
+/// --line-numbers-format, as parsed by `parse_line_numbers_format`:
+/// the numbers, or blanks, are aligned on the right in columns of
+/// `width`, unless the template says otherwise.
+fn format_line_numbers(
+    template: &[GutterPart],
+    old: Option<usize>,
+    new: Option<usize>,
+    width: GutterWidth,
+) -> String {
+    let mut gutter = String::new();
+    for part in template {
+        let (is_new, align, column) = match part {
+            GutterPart::Text(text) => {
+                gutter.push_str(text);
+                continue;
+            }
+            GutterPart::Number { new, align, width } => (*new, *align, *width),
+        };
+        let (line, width) = if is_new {
+            (new, column.unwrap_or(width.new))
+        } else {
+            (old, column.unwrap_or(width.old))
+        };
+        let line = line.map_or_else(String::new, |line| line.to_string());
+        let line = match align {
+            Align::Left => format!("{:<width$}", line, width = width),
+            Align::Center => format!("{:^width$}", line, width = width),
+            Align::Right => format!("{:>width$}", line, width = width),
+        };
+        gutter.push_str(&line);
+    }
+    gutter
+}
+
+fn output_line_numbers<Stream>(
+    old: Option<usize>,
+    new: Option<usize>,
+    width: GutterWidth,
+    url: Option<String>,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if let Some(template) = &config.line_number_format {
+        let gutter = format_line_numbers(template, old, new, width);
+        if let Some(url) = &url {
+            out.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+        }
+        output(gutter.as_bytes(), &config.line_number_face, out)?;
+        if url.is_some() {
+            out.set_hyperlink(&HyperlinkSpec::close())?;
+        }
+        return Ok(());
+    }
+    let column = |line: Option<usize>, width| match line {
+        Some(line) => format!("{:>width$}", line, width = width),
+        None => " ".repeat(width),
+    };
+    let gutter = match width.old {
+        0 => column(new, width.new),
+        _ => format!("{} {}", column(old, width.old), column(new, width.new)),
+    };
+    let separator = &config.line_number_separator;
+    match url {
+        Some(url) => {
+            out.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+            output(gutter.as_bytes(), &config.line_number_face, out)?;
+            out.set_hyperlink(&HyperlinkSpec::close())?;
+            output(separator.as_bytes(), &config.line_number_face, out)
+        }
+        None => {
+            let gutter = gutter + separator;
+            output(gutter.as_bytes(), &config.line_number_face, out)
+        }
+    }
+}
+
+/// Extracts the path of the new file from the end of a "+++ " line.
+fn parse_new_path(buf: &[u8]) -> Option<String> {
+    parse_header_path(buf, b"b/")
+}
+
+/// Extracts the path of the old file from the end of a "--- " line.
+fn parse_old_path(buf: &[u8]) -> Option<String> {
+    parse_header_path(buf, b"a/")
+}
+
+/// Extracts the path of the new file from the end of a "diff --git "
+/// line.
+fn parse_git_path(buf: &[u8]) -> Option<String> {
+    let i = buf.windows(3).position(|w| w == b" b/")?;
+    parse_new_path(&buf[i + 1..])
+}
+
+/// Extracts the path of a file from a "Binary files A and B differ"
+/// line: the new one, or the old one if the file was deleted.
+fn parse_binary_files_path(buf: &[u8]) -> Option<String> {
+    let buf = buf.strip_prefix(b"Binary files ")?;
+    let end = buf.windows(7).rposition(|w| w == b" differ")?;
+    let buf = &buf[..end];
+    let i = buf.windows(5).rposition(|w| w == b" and ")?;
+    parse_new_path(&buf[i + 5..]).or_else(|| parse_old_path(&buf[..i]))
+}
+
+fn parse_header_path(buf: &[u8], prefix: &[u8]) -> Option<String> {
+    // the path may be followed by a timestamp
+    let path = buf.split(|&b| b == b'\t').next()?;
+    let path = match path.iter().rposition(|b| !b.is_ascii_whitespace()) {
+        Some(last) => &path[..=last],
+        None => return None,
+    };
+    if path == b"/dev/null" {
+        return None;
+    }
+    let path = path.strip_prefix(prefix).unwrap_or(path);
+    Some(String::from_utf8_lossy(path).into_owned())
+}
+
+/// Computes the url of a line of the given file, with --hyperlinks
+/// or --repo-url.
+fn hyperlink(
+    config: &AppConfig,
+    path: &Option<String>,
+    commit: &Option<String>,
+    line: usize,
+) -> Option<String> {
+    // the links of --repo-url are relative to the root of the repository
+    let (template, path) = match &config.repo_url {
+        Some(template) => (template, PathBuf::from(path.as_ref()?)),
+        None if config.hyperlinks => (
+            &config.hyperlink_format,
+            std::env::current_dir().ok()?.join(path.as_ref()?),
+        ),
+        None => return None,
+    };
+    let mut encoded = String::new();
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(b))
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    Some(
+        template
+            .replace("{path}", &encoded)
+            .replace("{line}", &line.to_string())
+            .replace("{commit}", commit.as_deref().unwrap_or("HEAD")),
+    )
+}
+
+/// Writes a line that is not refined as an OSC 8 hyperlink to `url`.
+fn output_hyperlinked<Stream>(
+    buf: &[u8],
+    url: &str,
+    keep_input_colors: bool,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let (line, newline) = match buf.strip_suffix(b"\n") {
+        Some(line) => (line, &b"\n"[..]),
+        None => (buf, &b""[..]),
+    };
+    out.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+    output_unrefined(line, keep_input_colors, out)?;
+    out.set_hyperlink(&HyperlinkSpec::close())?;
+    out.write_all(newline)
+}
+
+/// Writes the hunk header split in `header` and `context` by
+/// `split_function_context`, the function context being painted with
+/// the 'function-context' face, and linked to `url` if any.
+fn output_hunk_header<Stream>(
+    (header, context): (&[u8], &[u8]),
+    url: Option<&str>,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if let Some(url) = url {
+        out.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+    }
+    output(header, &ColorSpec::default(), out)?;
+    output(context, &config.function_context_face, out)?;
+    if url.is_some() {
+        out.set_hyperlink(&HyperlinkSpec::close())?;
+    }
+    out.write_all(b"\n")
+}
+
+/// Writes the breadcrumb of --line-numbers above a hunk: the path of
+/// the file, if any, and the function context of the hunk.
+fn output_breadcrumb<Stream>(
+    path: Option<&str>,
+    context: &[u8],
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if let Some(path) = path {
+        output(
+            format!("{} › ", path).as_bytes(),
+            &ColorSpec::default(),
+            out,
+        )?;
+    }
+    output(context, &config.function_context_face, out)?;
+    out.write_all(b"\n")
+}
+
+/// Writes a line that is not refined: verbatim if the input colors
+/// are kept, without its escape codes otherwise.
+fn output_unrefined<Stream>(buf: &[u8], keep_input_colors: bool, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if keep_input_colors {
+        return output_raw(buf, out);
+    }
+    let mut stripped = Vec::with_capacity(buf.len());
+    strip_escape_codes(buf, &mut stripped);
+    output(&stripped, &ColorSpec::default(), out)
+}
+
+/// Writes `buf` with its escape codes. On consoles that do not
+/// interpret escape codes, such as older Windows consoles, the colors
+/// of the SGR sequences are set through the console instead.
+fn output_raw<Stream>(buf: &[u8], out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    if !out.is_synchronous() {
+        return out.write_all(buf);
+    }
+    let mut face = ColorSpec::default();
+    let mut i = 0;
+    while i < buf.len() {
+        if let Some(nbytes) = skip_escape_code(&buf[i..]) {
+            if buf[i + 1] == b'[' && buf[i + nbytes - 1] == b'm' {
+                apply_sgr(&buf[i + 2..i + nbytes - 1], &mut face);
+            }
+            i += nbytes;
+            continue;
+        }
+        let len = skip_token(&buf[i..]).max(1);
+        output(&buf[i..i + len], &face, out)?;
+        i += len;
+    }
+    Ok(())
+}
+
+/// Appends the content of `buf` without its escape codes to `dst`.
+fn strip_escape_codes(buf: &[u8], dst: &mut Vec<u8>) {
+    let mut i = 0;
+    let len = buf.len();
+    while i < len {
+        let escape_len = skip_all_escape_code(&buf[i..]);
+        if escape_len != 0 {
+            i += escape_len;
+            continue;
+        }
+        // an unterminated escape code is kept as text
+        let tok_len = skip_token(&buf[i..]).max(1);
+        dst.extend_from_slice(&buf[i..i + tok_len]);
+        i += tok_len;
+    }
+}
+
 fn output<Stream>(buf: &[u8], colorspec: &ColorSpec, out: &mut Stream) -> io::Result<()>
 where
     Stream: WriteColor,
@@ -297,7 +3861,7 @@ where
         buf
     };
     out.set_color(colorspec)?;
-    out.write_all(&buf)?;
+    out.write_all(buf)?;
     out.reset()?;
     if ends_with_newline {
         out.write_all(b"\n")?;
@@ -305,63 +3869,304 @@ where
     Ok(())
 }
 
-/// Returns the number of bytes of escape code that start the slice.
-fn skip_all_escape_code(buf: &[u8]) -> usize {
-    // Skip one sequence
-    fn skip_escape_code(buf: &[u8]) -> Option<usize> {
-        if 2 <= buf.len() && &buf[..2] == b"\x1b[" {
-            // "\x1b[" + sequence body + "m" => 3 additional bytes
-            Some(index_of(&buf[2..], b'm')? + 3)
+/// Lays out the lines of hunks: replaces tabs by spaces, up to the
+/// next multiple of --tabs columns after the leading +/- character,
+/// and truncates the lines longer than --max-line-length.
+#[derive(Clone)]
+struct Layout<'a> {
+    tabs: Option<usize>,
+    max_line_length: Option<usize>,
+    marker: &'a str,
+    ruler: Option<usize>,
+    // the column in the line, the leading +/- character being at 0
+    column: usize,
+    truncated: bool,
+    // whether the leading character of the line is a '+'
+    added: bool,
+}
+
+impl<'a> Layout<'a> {
+    fn new(config: &'a AppConfig) -> Self {
+        Layout {
+            tabs: config.tabs,
+            max_line_length: config.max_line_length,
+            marker: &config.truncation_marker,
+            ruler: config.ruler,
+            column: 0,
+            truncated: false,
+            added: false,
+        }
+    }
+
+    /// Starts a new line, at `column`.
+    fn start_line(&mut self, column: usize) {
+        self.column = column;
+        self.truncated = false;
+        self.added = false;
+    }
+
+    /// Lays out the character `ch`, a UTF-8 sequence or a single byte,
+    /// in `dst`.
+    fn push(&mut self, ch: &[u8], dst: &mut Vec<u8>) {
+        if self.column == 0 {
+            self.added = ch == b"+";
+        }
+        let width = char_width(ch);
+        let is_full = match self.max_line_length {
+            Some(max) => 0 < width && max < self.column + width,
+            None => false,
+        };
+        if self.truncated || ch != b"\n" && is_full {
+            if !self.truncated && ch != b"\n" {
+                dst.extend_from_slice(self.marker.as_bytes());
+                self.truncated = true;
+            }
+            if ch == b"\n" {
+                dst.extend_from_slice(ch);
+            }
+            return;
+        }
+        match (ch, self.tabs) {
+            (b"\t", Some(width)) => {
+                let nspaces = width - self.column.saturating_sub(1) % width;
+                dst.resize(dst.len() + nspaces, b' ');
+                self.column += nspaces;
+            }
+            _ => {
+                dst.extend_from_slice(ch);
+                self.column += width;
+            }
+        }
+    }
+
+    /// Lays out `buf`, which follows what was already laid out in the
+    /// line.
+    fn apply<'b>(&mut self, buf: &'b [u8]) -> Cow<'b, [u8]> {
+        if self.tabs.is_none() && self.max_line_length.is_none() && self.ruler.is_none() {
+            return Cow::Borrowed(buf);
+        }
+        let mut laid_out = Vec::with_capacity(buf.len());
+        let mut i = 0;
+        while i < buf.len() {
+            let escape_len = skip_all_escape_code(&buf[i..]);
+            if escape_len != 0 {
+                laid_out.extend_from_slice(&buf[i..i + escape_len]);
+                i += escape_len;
+                continue;
+            }
+            let len = char_len(&buf[i..]);
+            self.push(&buf[i..i + len], &mut laid_out);
+            i += len;
+        }
+        Cow::Owned(laid_out)
+    }
+
+    /// Returns the index of the first byte of `buf` beyond the column
+    /// of --ruler, if the line is an added line.
+    fn overlong_index(&self, buf: &[u8]) -> Option<usize> {
+        let ruler = self.ruler?;
+        let mut layout = self.clone();
+        let mut scratch = vec![];
+        let mut i = 0;
+        while i < buf.len() {
+            if layout.added && ruler < layout.column {
+                return Some(i);
+            }
+            let len = char_len(&buf[i..]);
+            layout.push(&buf[i..i + len], &mut scratch);
+            scratch.clear();
+            i += len;
+        }
+        None
+    }
+
+    /// The number of spaces between the end of the line and the guide
+    /// of --ruler, if the line ends before the guide.
+    fn ruler_padding(&self) -> Option<usize> {
+        let ruler = self.ruler?;
+        (self.column <= ruler + 1).then(|| ruler + 1 - self.column)
+    }
+
+    /// Returns the index of the first byte of `buf` that is truncated,
+    /// `buf` being a line without escape codes.
+    fn truncation_index(&mut self, buf: &[u8]) -> Option<usize> {
+        self.max_line_length?;
+        let mut scratch = vec![];
+        let mut i = 0;
+        while i < buf.len() {
+            let len = char_len(&buf[i..]);
+            self.push(&buf[i..i + len], &mut scratch);
+            if self.truncated {
+                return Some(i);
+            }
+            scratch.clear();
+            i += len;
+        }
+        None
+    }
+}
+
+/// The number of bytes of the UTF-8 sequence that starts `buf`, or 1
+/// if it does not start with a valid sequence.
+fn char_len(buf: &[u8]) -> usize {
+    let len = match buf[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    match buf.get(..len).map(std::str::from_utf8) {
+        Some(Ok(_)) => len,
+        _ => 1,
+    }
+}
+
+/// The number of columns taken by the character `ch`, as returned by
+/// `char_len`: the wide characters take 2 columns, and a byte that is
+/// not valid UTF-8 takes 1, unless it continues a character that was
+/// already laid out.
+fn char_width(ch: &[u8]) -> usize {
+    match std::str::from_utf8(ch)
+        .ok()
+        .and_then(|ch| ch.chars().next())
+    {
+        // the width of a tab that is not expanded is not known
+        Some('\t') => 1,
+        Some(c) => c.width().unwrap_or(0),
+        None if ch[0] & 0xc0 == 0x80 => 0,
+        None => 1,
+    }
+}
+
+/// Returns the number of bytes of the CSI or OSC escape sequence that
+/// starts the slice, if any.
+fn skip_escape_code(buf: &[u8]) -> Option<usize> {
+    if 2 <= buf.len() && &buf[..2] == b"\x1b]" {
+        // "\x1b]" + sequence body + BEL or "\x1b\\"
+        let body_len = buf[2..]
+            .iter()
+            .position(|&b| b == b'\x07' || b == b'\x1b')?;
+        match buf.get(2 + body_len) {
+            Some(b'\x07') => Some(body_len + 3),
+            _ if buf.get(3 + body_len) == Some(&b'\\') => Some(body_len + 4),
+            _ => None,
+        }
+    } else if 2 <= buf.len() && &buf[..2] == b"\x1b[" {
+        // "\x1b[" + parameter and intermediate bytes + final byte
+        let body_len = buf[2..].iter().position(|b| !(0x20..=0x3f).contains(b))?;
+        if (0x40..=0x7e).contains(&buf[2 + body_len]) {
+            Some(body_len + 3)
         } else {
             None
         }
+    } else {
+        None
     }
+}
+
+/// Returns the number of bytes of escape code that start the slice.
+fn skip_all_escape_code(buf: &[u8]) -> usize {
     let mut buf = buf;
     let mut sum = 0;
-    while let Some(nbytes) = skip_escape_code(&buf) {
+    while let Some(nbytes) = skip_escape_code(buf) {
         buf = &buf[nbytes..];
         sum += nbytes
     }
     sum
 }
 
-/// Returns the first byte of the slice, after skipping the escape
-/// code bytes.
-fn first_after_escape(buf: &[u8]) -> Option<u8> {
-    let nbytes = skip_all_escape_code(&buf);
-    buf.iter().skip(nbytes).cloned().next()
+/// Computes the face set by the SGR escape codes that start the
+/// slice.
+fn input_face(buf: &[u8]) -> ColorSpec {
+    let mut face = ColorSpec::default();
+    let mut buf = buf;
+    while let Some(nbytes) = skip_escape_code(buf) {
+        if buf[nbytes - 1] == b'm' {
+            apply_sgr(&buf[2..nbytes - 1], &mut face);
+        }
+        buf = &buf[nbytes..];
+    }
+    face
 }
 
-/// Scan the slice looking for the given byte, returning the index of
-/// its first appearance.
-fn index_of(buf: &[u8], target: u8) -> Option<usize> {
-    let mut it = buf.iter().enumerate();
-    loop {
-        match it.next() {
-            Some((index, c)) => {
-                if *c == target {
-                    return Some(index);
-                }
-            }
-            None => return None,
+/// Updates `face` with the parameters of a SGR escape sequence.
+fn apply_sgr(params: &[u8], face: &mut ColorSpec) {
+    // an empty parameter means 0; unknown values are ignored
+    let mut codes = params.split(|&b| b == b';').map(|code| {
+        if code.is_empty() {
+            return 0;
+        }
+        std::str::from_utf8(code)
+            .ok()
+            .and_then(|code| code.parse::<u8>().ok())
+            .unwrap_or(u8::MAX)
+    });
+    while let Some(code) = codes.next() {
+        match code {
+            0 => *face = ColorSpec::default(),
+            1 => ignore(face.set_bold(true)),
+            2 => ignore(face.set_dimmed(true)),
+            3 => ignore(face.set_italic(true)),
+            4 => ignore(face.set_underline(true)),
+            9 => ignore(face.set_strikethrough(true)),
+            22 => ignore(face.set_bold(false).set_dimmed(false)),
+            23 => ignore(face.set_italic(false)),
+            24 => ignore(face.set_underline(false)),
+            29 => ignore(face.set_strikethrough(false)),
+            30..=37 => ignore(face.set_fg(Some(ansi_color(code - 30)))),
+            38 => ignore(face.set_fg(extended_color(&mut codes))),
+            39 => ignore(face.set_fg(None)),
+            40..=47 => ignore(face.set_bg(Some(ansi_color(code - 40)))),
+            48 => ignore(face.set_bg(extended_color(&mut codes))),
+            49 => ignore(face.set_bg(None)),
+            90..=97 => ignore(face.set_fg(Some(ansi_color(code - 90))).set_intense(true)),
+            100..=107 => ignore(face.set_bg(Some(ansi_color(code - 100))).set_intense(true)),
+            _ => (),
         }
     }
 }
 
+fn ansi_color(code: u8) -> Color {
+    match code {
+        0 => Color::Black,
+        1 => Red,
+        2 => Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        _ => White,
+    }
+}
+
+/// Parses the arguments of the 38 and 48 SGR codes.
+fn extended_color<Codes>(codes: &mut Codes) -> Option<Color>
+where
+    Codes: Iterator<Item = u8>,
+{
+    match codes.next()? {
+        5 => Some(Color::Ansi256(codes.next()?)),
+        2 => Some(Color::Rgb(codes.next()?, codes.next()?, codes.next()?)),
+        _ => None,
+    }
+}
+
+fn ignore<T>(_: T) {}
+
+/// Returns the first byte of the slice, after skipping the escape
+/// code bytes.
+fn first_after_escape(buf: &[u8]) -> Option<u8> {
+    if buf.first() != Some(&b'\x1b') {
+        return buf.first().cloned();
+    }
+    let nbytes = skip_all_escape_code(buf);
+    buf.iter().skip(nbytes).cloned().next()
+}
+
 /// Computes the number of bytes until either the next escape code, or
 /// the end of buf.
 fn skip_token(buf: &[u8]) -> usize {
-    match buf.len() {
-        0 => 0,
-        len => {
-            for i in 0..buf.len() - 1 {
-                if &buf[i..i + 2] == b"\x1b[" {
-                    return i;
-                }
-            }
-            len
-        }
-    }
+    scan::find_escape(buf).unwrap_or(buf.len())
 }
 
 #[cfg(test)]
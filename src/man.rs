@@ -0,0 +1,113 @@
+//! Rendering of the manual page of diffr, for --man-page.
+
+use std::io::{self, Write};
+
+/// The columns of indentation of the descriptions of the options in
+/// the help written by clap.
+const DESCRIPTION_INDENT: usize = 12;
+
+/// Escapes `text` for roff: the backslashes, the hyphens, and the
+/// control characters at the start of a line.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+/// The name of the section of `line`, such as "OPTIONS" for
+/// "OPTIONS:".
+fn section_name(line: &str) -> Option<&str> {
+    line.strip_suffix(':')
+        .filter(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase()))
+}
+
+/// Writes the lines of a section of options or arguments: each one is
+/// a tagged paragraph, and the lines indented in its description are
+/// written without filling.
+fn write_items<W: Write>(lines: &[&str], out: &mut W) -> io::Result<()> {
+    let mut nofill = false;
+    for line in lines {
+        let text = line.trim_start();
+        let indent = line.len() - text.len();
+        if text.is_empty() {
+            if !nofill {
+                writeln!(out, ".IP")?;
+            }
+            continue;
+        }
+        if indent < DESCRIPTION_INDENT {
+            if nofill {
+                writeln!(out, ".fi")?;
+                nofill = false;
+            }
+            writeln!(out, ".TP\n\\fB{}\\fR", escape(text.trim_end()))?;
+            continue;
+        }
+        let indented = DESCRIPTION_INDENT < indent;
+        if indented != nofill {
+            writeln!(out, "{}", if indented { ".nf" } else { ".fi" })?;
+            nofill = indented;
+        }
+        let margin = " ".repeat(indent - DESCRIPTION_INDENT);
+        writeln!(out, "{}", escape(&format!("{}{}", margin, text)))?;
+    }
+    if nofill {
+        writeln!(out, ".fi")?;
+    }
+    Ok(())
+}
+
+/// Writes `help`, the long help of diffr written by clap, as a manual
+/// page: the first line names the page, the about text is its
+/// description, the usage its synopsis, and the options and arguments
+/// are tagged paragraphs.
+pub fn write<W: Write>(help: &str, out: &mut W) -> io::Result<()> {
+    let mut lines = help.lines();
+    let title = lines.next().unwrap_or_default();
+    let author = lines.next().unwrap_or_default();
+    let mut about = vec![];
+    let mut sections: Vec<(&str, Vec<&str>)> = vec![];
+    for line in lines {
+        match (section_name(line), sections.last_mut()) {
+            (Some(name), _) => sections.push((name, vec![])),
+            (None, Some((_, section))) => section.push(line),
+            (None, None) => about.push(line),
+        }
+    }
+    let about = about
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    writeln!(out, ".TH DIFFR 1 \"\" \"{}\"", escape(title))?;
+    writeln!(out, ".SH NAME")?;
+    let summary = about.first().copied().unwrap_or_default();
+    let summary = summary.strip_prefix("diffr ").unwrap_or(summary);
+    writeln!(out, "diffr \\- {}", escape(summary))?;
+    for (name, lines) in &sections {
+        match *name {
+            "USAGE" => {
+                writeln!(out, ".SH SYNOPSIS\n.nf")?;
+                for line in lines {
+                    writeln!(out, "{}", escape(line.strip_prefix("    ").unwrap_or(line)))?;
+                }
+                writeln!(out, ".fi\n.SH DESCRIPTION")?;
+                for line in &about {
+                    writeln!(out, "{}", escape(line))?;
+                }
+            }
+            "ARGS" => {
+                writeln!(out, ".SH ARGUMENTS")?;
+                write_items(lines, out)?;
+            }
+            name => {
+                writeln!(out, ".SH {}", name)?;
+                write_items(lines, out)?;
+            }
+        }
+    }
+    writeln!(out, ".SH AUTHOR\n{}", escape(author))
+}
@@ -0,0 +1,69 @@
+//! Rendering of the output of diffr as Markdown, for --output.
+
+use std::io::{self, Write};
+
+use super::{html, pager};
+
+/// Returns true if `line` starts the header of a file, given the line
+/// after it.
+fn is_file_start(line: &[u8], next_line: Option<&Vec<u8>>) -> bool {
+    line.starts_with(b"diff ")
+        || line.starts_with(b"--- ") && next_line.is_some_and(|next| next.starts_with(b"+++ "))
+}
+
+/// Writes `rendered`, the output of diffr, as a code block per file.
+///
+/// With `inline_html`, the blocks are `<pre>` elements with the faces
+/// written as `<span>` elements; otherwise the blocks are fenced
+/// ```diff blocks without faces.
+pub fn write<W: Write>(rendered: &[u8], inline_html: bool, out: &mut W) -> io::Result<()> {
+    let (lines, plain) = pager::split_lines(rendered);
+    // the text before the first file, then the lines of each file
+    let mut starts = vec![0];
+    let mut in_header = false;
+    for (i, line) in plain.iter().enumerate() {
+        if in_header {
+            in_header = !line.starts_with(b"@@");
+        } else if is_file_start(line, plain.get(i + 1)) {
+            starts.push(i);
+            in_header = true;
+        }
+    }
+    starts.push(lines.len());
+    starts.dedup();
+    for (n, section) in starts.windows(2).enumerate() {
+        let (lo, hi) = (section[0], section[1]);
+        if 0 < n {
+            out.write_all(b"\n")?;
+        }
+        if inline_html {
+            out.write_all(b"<pre><code>")?;
+            for line in &lines[lo..hi] {
+                html::write_line(line, out)?;
+                out.write_all(b"\n")?;
+            }
+            out.write_all(b"</code></pre>\n")?;
+            continue;
+        }
+        // the fence is longer than the runs of backquotes of the block
+        let longest_run = plain[lo..hi]
+            .iter()
+            .flat_map(|line| line.split(|&b| b != b'`'))
+            .map(<[u8]>::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        let info = if is_file_start(&plain[lo], plain.get(lo + 1)) {
+            "diff"
+        } else {
+            ""
+        };
+        writeln!(out, "{}{}", fence, info)?;
+        for line in &plain[lo..hi] {
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+        writeln!(out, "{}", fence)?;
+    }
+    Ok(())
+}
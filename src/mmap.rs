@@ -0,0 +1,95 @@
+//! Mapping of the file of --input in memory, so that huge patches are
+//! read line by line without being copied in a buffer.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+/// A file mapped read-only in memory.
+pub struct Mmap {
+    #[cfg(unix)]
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    /// Maps `file` in memory. Returns None if it cannot be mapped, such
+    /// as a pipe, or on the platforms without mmap.
+    #[cfg(unix)]
+    pub fn map(file: &File) -> io::Result<Option<Self>> {
+        use std::convert::TryFrom;
+        use std::os::unix::io::AsRawFd;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        let len = match usize::try_from(metadata.len()) {
+            Ok(len) => len,
+            Err(_) => return Ok(None),
+        };
+        if len == 0 {
+            // mmap rejects empty mappings
+            return Ok(Some(Mmap {
+                ptr: std::ptr::null_mut(),
+                len,
+            }));
+        }
+        // SAFETY: the mapping is private and read-only; it is valid
+        // until it is unmapped by `drop`. As with any mapping, the file
+        // must not be truncated while it is read.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Ok(None);
+        }
+        // SAFETY: `ptr` is a mapping of `len` bytes; the advice only
+        // lets the kernel read ahead and drop the pages read.
+        unsafe {
+            libc::madvise(ptr, len, libc::MADV_SEQUENTIAL);
+        }
+        Ok(Some(Mmap { ptr, len }))
+    }
+
+    #[cfg(not(unix))]
+    pub fn map(_file: &File) -> io::Result<Option<Self>> {
+        Ok(None)
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    #[cfg(unix)]
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` is a readable mapping of `len` bytes.
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    #[cfg(not(unix))]
+    fn deref(&self) -> &[u8] {
+        &[]
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: `ptr` is a mapping of `len` bytes, unmapped once.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
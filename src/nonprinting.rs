@@ -0,0 +1,69 @@
+//! Detection of the control characters and of the invisible Unicode
+//! characters, for --show-nonprinting.
+
+use super::skip_all_escape_code;
+
+/// Returns true if the character `c` is invisible: the zero width
+/// characters, the byte order mark and the bidirectional controls.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00ad}'
+            | '\u{200b}'..='\u{200f}'
+            | '\u{202a}'..='\u{202e}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{feff}'
+    )
+}
+
+/// Returns the length of the nonprinting character that starts `buf`,
+/// if any. Tabs, line ends and escape codes are not nonprinting.
+fn nonprinting_len(buf: &[u8]) -> Option<usize> {
+    match buf[0] {
+        b'\t' | b'\n' => None,
+        b'\x1b' if skip_all_escape_code(buf) != 0 => None,
+        0..=0x1f | 0x7f => Some(1),
+        0..=0x7f => None,
+        first => {
+            let len = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let c = std::str::from_utf8(buf.get(..len)?).ok()?.chars().next()?;
+            (c.is_control() || is_invisible(c)).then_some(len)
+        }
+    }
+}
+
+/// Returns the span of the first nonprinting character of `buf`, if
+/// any.
+pub fn find(buf: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < buf.len() {
+        let escape_len = skip_all_escape_code(&buf[i..]);
+        if escape_len != 0 {
+            i += escape_len;
+            continue;
+        }
+        if let Some(len) = nonprinting_len(&buf[i..]) {
+            return Some((i, i + len));
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Displays the nonprinting character `c`: "^X" for the ASCII control
+/// characters, and "<U+XXXX>" for the others.
+pub fn escape(c: &[u8]) -> String {
+    match c {
+        &[b] if b < 0x20 => format!("^{}", (b + b'@') as char),
+        b"\x7f" => "^?".to_owned(),
+        _ => {
+            let c = std::str::from_utf8(c).ok().and_then(|c| c.chars().next());
+            format!("<U+{:04X}>", c.map_or(0, u32::from))
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! A minimal pager to browse the output of diffr.
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use std::io::{self, Write};
+
+use super::{ignore, strip_escape_codes};
+
+pub struct Pager<'a> {
+    // the rendered lines, with their escape codes
+    lines: Vec<&'a [u8]>,
+    // the same lines, without escape codes
+    plain: Vec<Vec<u8>>,
+    // indices of the lines starting a file or a hunk
+    pub boundaries: Vec<usize>,
+    pub top: usize,
+    pub height: usize,
+    pub search: Vec<u8>,
+    pub message: String,
+}
+
+/// Display `output` page by page on the terminal.
+pub fn run(output: &[u8]) -> io::Result<()> {
+    let mut pager = Pager::new(output);
+    with_terminal(|stdout| pager.event_loop(stdout))
+}
+
+/// Run `f` with the terminal in raw mode on an alternate screen.
+pub fn with_terminal<F>(f: F) -> io::Result<()>
+where
+    F: FnOnce(&mut io::Stdout) -> io::Result<()>,
+{
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(
+        stdout,
+        terminal::EnterAlternateScreen,
+        terminal::DisableLineWrap,
+        cursor::Hide
+    )?;
+    let result = f(&mut stdout);
+    execute!(
+        stdout,
+        cursor::Show,
+        terminal::EnableLineWrap,
+        terminal::LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+/// Split `output` in lines, and compute the same lines without escape
+/// codes.
+pub fn split_lines(output: &[u8]) -> (Vec<&[u8]>, Vec<Vec<u8>>) {
+    let lines = output
+        .split_inclusive(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\n").unwrap_or(line))
+        .collect::<Vec<_>>();
+    let plain = lines
+        .iter()
+        .map(|line| {
+            let mut stripped = vec![];
+            strip_escape_codes(line, &mut stripped);
+            stripped
+        })
+        .collect();
+    (lines, plain)
+}
+
+/// Returns the height of the terminal, minus the status line.
+pub fn page_height() -> io::Result<usize> {
+    let (_, rows) = terminal::size()?;
+    Ok(usize::from(rows.saturating_sub(1)).max(1))
+}
+
+pub fn is_boundary(line: &[u8], next_line: Option<&Vec<u8>>) -> bool {
+    line.starts_with(b"diff ")
+        || line.starts_with(b"@@")
+        || line.starts_with(b"--- ") && next_line.is_some_and(|next| next.starts_with(b"+++ "))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+impl<'a> Pager<'a> {
+    pub fn new(output: &'a [u8]) -> Self {
+        let (lines, plain) = split_lines(output);
+        let boundaries = (0..plain.len())
+            .filter(|&i| is_boundary(&plain[i], plain.get(i + 1)))
+            .collect();
+        Pager {
+            lines,
+            plain,
+            boundaries,
+            top: 0,
+            height: 1,
+            search: vec![],
+            message: String::new(),
+        }
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    fn scroll_down(&mut self, nlines: usize) {
+        self.top = (self.top + nlines).min(self.max_top());
+    }
+
+    fn scroll_up(&mut self, nlines: usize) {
+        self.top = self.top.saturating_sub(nlines);
+    }
+
+    fn next_boundary(&mut self) {
+        match self.boundaries.iter().find(|&&i| self.top < i) {
+            Some(&i) => self.top = i.min(self.max_top()),
+            None => self.message = "no next file or hunk".to_owned(),
+        }
+    }
+
+    fn previous_boundary(&mut self) {
+        match self.boundaries.iter().rev().find(|&&i| i < self.top) {
+            Some(&i) => self.top = i,
+            None => self.message = "no previous file or hunk".to_owned(),
+        }
+    }
+
+    pub fn search_forward(&mut self) {
+        let search = &self.search;
+        let found = (self.top + 1..self.plain.len()).find(|&i| contains(&self.plain[i], search));
+        match found {
+            Some(i) => self.top = i.min(self.max_top()),
+            None => {
+                self.message = format!("pattern not found: {}", String::from_utf8_lossy(search))
+            }
+        }
+    }
+
+    fn draw<Out: Write>(&self, out: &mut Out, prompt: Option<&[u8]>) -> io::Result<()> {
+        queue!(out, terminal::Clear(ClearType::All))?;
+        for (row, line) in self.lines[self.top..].iter().take(self.height).enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            out.write_all(line)?;
+            out.write_all(b"\x1b[0m")?;
+        }
+        queue!(out, cursor::MoveTo(0, self.height as u16))?;
+        match prompt {
+            Some(prompt) => {
+                out.write_all(b"/")?;
+                out.write_all(prompt)?;
+            }
+            None if !self.message.is_empty() => write!(out, "\x1b[7m{}\x1b[0m", self.message)?,
+            None if self.top == self.max_top() => out.write_all(b"\x1b[7m(END)\x1b[0m")?,
+            None => out.write_all(b":")?,
+        }
+        out.flush()
+    }
+
+    fn event_loop<Out: Write>(&mut self, out: &mut Out) -> io::Result<()> {
+        loop {
+            self.height = page_height()?;
+            self.top = self.top.min(self.max_top());
+            self.draw(out, None)?;
+            if let Some(key) = read_key()? {
+                self.message.clear();
+                if !self.handle_key(key, out)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Returns false when the user asked to quit.
+    pub fn handle_key<Out: Write>(&mut self, key: KeyEvent, out: &mut Out) -> io::Result<bool> {
+        let page = self.height;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(false)
+            }
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => self.scroll_down(1),
+            KeyCode::Char('k') | KeyCode::Up => self.scroll_up(1),
+            KeyCode::Char(' ') | KeyCode::Char('f') | KeyCode::PageDown => self.scroll_down(page),
+            KeyCode::Char('b') | KeyCode::PageUp => self.scroll_up(page),
+            KeyCode::Char('g') | KeyCode::Home => self.top = 0,
+            KeyCode::Char('G') | KeyCode::End => self.top = self.max_top(),
+            KeyCode::Char('n') => self.next_boundary(),
+            KeyCode::Char('p') => self.previous_boundary(),
+            KeyCode::Char('/') => {
+                if let Some(search) = self.read_search(out)? {
+                    // an empty search repeats the previous one
+                    if !search.is_empty() {
+                        self.search = search;
+                    }
+                    self.search_forward();
+                }
+            }
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn read_search<Out: Write>(&self, out: &mut Out) -> io::Result<Option<Vec<u8>>> {
+        let mut search = String::new();
+        loop {
+            self.draw(out, Some(search.as_bytes()))?;
+            if let Some(key) = read_key()? {
+                match key.code {
+                    KeyCode::Enter => return Ok(Some(search.into_bytes())),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Backspace => ignore(search.pop()),
+                    KeyCode::Char(c) => search.push(c),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+pub fn read_key() -> io::Result<Option<KeyEvent>> {
+    match event::read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release => Ok(Some(key)),
+        _ => Ok(None),
+    }
+}
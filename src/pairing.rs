@@ -0,0 +1,207 @@
+//! Pairing of the removed and added lines of a hunk before their
+//! refinement.
+//!
+//! Comparing all the removed tokens of a hunk with all its added
+//! tokens may match the tokens of a removed line with the tokens of an
+//! unrelated added line, for instance when lines are moved or when a
+//! line is replaced by several ones. The removed and added lines are
+//! first paired by similarity, keeping their order, and the tokens of
+//! each pair are compared together; the lines left between two pairs
+//! are compared as a block, as without pairing. With --pairing-threshold,
+//! the lines left in a hunk whose numbers of removed and added lines
+//! differ are not compared: they have no counterpart.
+
+use diffr_lib::{HashedSpan, Snake};
+
+use super::algorithm::{self, Algorithm};
+
+/// The minimal similarity of paired lines by default, as the
+/// proportion of the bytes of their non-blank tokens that they share.
+pub const DEFAULT_PAIRING_THRESHOLD: f64 = 0.5;
+
+/// The maximal number of pairs of lines scored; larger hunks are
+/// refined without pairing.
+const MAX_PAIRING_CELLS: usize = 1 << 20;
+
+/// The maximal product of the numbers of removed and added tokens of
+/// the hunks whose lines are paired: scoring all the pairs of lines
+/// compares each removed token with each added token.
+const MAX_PAIRING_COST: usize = 1 << 24;
+
+/// Splits `tokens` in lines, as ranges of tokens.
+fn split_lines(data: &[u8], tokens: &[HashedSpan]) -> Vec<(usize, usize)> {
+    let mut lines = vec![];
+    let mut lo = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.lo < token.hi && data[token.hi - 1] == b'\n' {
+            lines.push((lo, i + 1));
+            lo = i + 1;
+        }
+    }
+    if lo < tokens.len() {
+        lines.push((lo, tokens.len()));
+    }
+    lines
+}
+
+/// The hashes and the lengths of the non-blank tokens of a line.
+fn signature(data: &[u8], tokens: &[HashedSpan]) -> Vec<(u64, usize)> {
+    tokens
+        .iter()
+        .filter(|token| !data[token.lo..token.hi].iter().all(u8::is_ascii_whitespace))
+        .map(|token| (token.hash, token.hi - token.lo))
+        .collect()
+}
+
+/// The proportion of the bytes of two signatures that their longest
+/// common subsequence of tokens covers.
+fn similarity(a: &[(u64, usize)], b: &[(u64, usize)], row: &mut Vec<usize>) -> f64 {
+    let total = |tokens: &[(u64, usize)]| tokens.iter().map(|token| token.1).sum::<usize>();
+    let total = total(a) + total(b);
+    if a.is_empty() || b.is_empty() || total == 0 {
+        return 0.0;
+    }
+    // row[j]: the bytes of the common subsequence of the tokens of a
+    // read so far and the first j tokens of b
+    row.clear();
+    row.resize(b.len() + 1, 0);
+    for &(hash, len) in a {
+        let mut diagonal = 0;
+        for j in 1..=b.len() {
+            let above = row[j];
+            row[j] = if b[j - 1].0 == hash {
+                diagonal + len
+            } else {
+                above.max(row[j - 1])
+            };
+            diagonal = above;
+        }
+    }
+    2.0 * row[b.len()] as f64 / total as f64
+}
+
+/// Pairs the removed and added lines at least `threshold` similar,
+/// keeping their order, so that the total similarity of the pairs is
+/// maximal.
+fn pair_lines(
+    removed: &[Vec<(u64, usize)>],
+    added: &[Vec<(u64, usize)>],
+    threshold: f64,
+) -> Vec<(usize, usize)> {
+    let (n, m) = (removed.len(), added.len());
+    let mut row = vec![];
+    // score[i][j]: the best score pairing the first i removed lines
+    // and the first j added lines
+    let mut score = vec![0.0f64; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in 1..=n {
+        for j in 1..=m {
+            let similarity = similarity(&removed[i - 1], &added[j - 1], &mut row);
+            let mut best = score[at(i - 1, j)].max(score[at(i, j - 1)]);
+            if threshold <= similarity {
+                best = best.max(score[at(i - 1, j - 1)] + similarity);
+            }
+            score[at(i, j)] = best;
+        }
+    }
+    let mut pairs = vec![];
+    let (mut i, mut j) = (n, m);
+    while 0 < i && 0 < j {
+        if score[at(i, j)] == score[at(i - 1, j)] {
+            i -= 1;
+        } else if score[at(i, j)] == score[at(i, j - 1)] {
+            j -= 1;
+        } else {
+            pairs.push((i - 1, j - 1));
+            i -= 1;
+            j -= 1;
+        }
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// Computes the tokens shared by `removed` and `added` into `dst`,
+/// comparing the tokens of paired lines with `algorithm`. The lines are
+/// paired if they are at least `threshold` similar; without threshold,
+/// the default one is used, and the unpaired lines are always compared.
+pub fn diff(
+    algorithm: Algorithm,
+    threshold: Option<f64>,
+    data: &[u8],
+    (removed, added): (&[HashedSpan], &[HashedSpan]),
+    v: &mut Vec<isize>,
+    dst: &mut Vec<Snake>,
+) {
+    let removed_lines = split_lines(data, removed);
+    let added_lines = split_lines(data, added);
+    if removed_lines.len() <= 1 && added_lines.len() <= 1
+        || MAX_PAIRING_CELLS < removed_lines.len() * added_lines.len()
+        || MAX_PAIRING_COST < removed.len() * added.len()
+    {
+        return algorithm::diff(algorithm, data, (removed, added), v, dst);
+    }
+    let signatures = |lines: &[(usize, usize)], tokens: &[HashedSpan]| {
+        lines
+            .iter()
+            .map(|&(lo, hi)| signature(data, &tokens[lo..hi]))
+            .collect::<Vec<_>>()
+    };
+    let pairs = pair_lines(
+        &signatures(&removed_lines, removed),
+        &signatures(&added_lines, added),
+        threshold.unwrap_or(DEFAULT_PAIRING_THRESHOLD),
+    );
+    // the unpaired lines of an unbalanced hunk are left unrefined
+    let compare_unpaired = threshold.is_none() || removed_lines.len() == added_lines.len();
+
+    // the token ranges compared together: the pairs, and the blocks
+    // of lines between them
+    let start = |lines: &[(usize, usize)], i: usize| lines.get(i).map_or(lines_end(lines), |l| l.0);
+    let mut blocks = vec![];
+    let (mut i, mut j) = (0, 0);
+    for &(pi, pj) in &pairs {
+        if compare_unpaired {
+            blocks.push((
+                (start(&removed_lines, i), start(&removed_lines, pi)),
+                (start(&added_lines, j), start(&added_lines, pj)),
+            ));
+        }
+        blocks.push((removed_lines[pi], added_lines[pj]));
+        i = pi + 1;
+        j = pj + 1;
+    }
+    if compare_unpaired {
+        blocks.push((
+            (start(&removed_lines, i), removed.len()),
+            (start(&added_lines, j), added.len()),
+        ));
+    }
+
+    dst.clear();
+    let mut snakes = vec![];
+    for ((x0, x1), (y0, y1)) in blocks {
+        if x0 == x1 || y0 == y1 {
+            continue;
+        }
+        algorithm::diff(
+            algorithm,
+            data,
+            (&removed[x0..x1], &added[y0..y1]),
+            v,
+            &mut snakes,
+        );
+        for snake in &snakes {
+            algorithm::push(
+                dst,
+                x0 + snake.x0 as usize,
+                y0 + snake.y0 as usize,
+                snake.len as usize,
+            );
+        }
+    }
+}
+
+fn lines_end(lines: &[(usize, usize)]) -> usize {
+    lines.last().map_or(0, |line| line.1)
+}
@@ -0,0 +1,93 @@
+//! The default faces, for dark and light terminal backgrounds.
+//!
+//! The default faces are made for dark backgrounds: the white on green
+//! and white on red of the refined segments, and the yellow or cyan
+//! headers, are hard to read on light backgrounds. The light palette
+//! uses dark foregrounds, and pale backgrounds for the refined
+//! segments.
+//!
+//! The themes for color vision deficiencies replace the green and red
+//! of the added and removed lines by colors that remain distinct.
+
+use std::env;
+use termcolor::Color::Ansi256;
+
+use super::{color_spec, AppConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Default,
+    /// Blue and orange, for the deficiency of green cones.
+    Deuteranopia,
+    /// Blue and yellow, for the deficiency of red cones.
+    Protanopia,
+    /// Teal and red, for the deficiency of blue cones.
+    Tritanopia,
+}
+
+/// Guesses the background of the terminal from the COLORFGBG variable,
+/// set by some terminals such as rxvt or Konsole, as "fg;bg" or
+/// "fg;default;bg" where bg is the number of an ANSI color.
+pub fn detect() -> Option<Background> {
+    env::var("COLORFGBG")
+        .ok()
+        .and_then(|value| parse_colorfgbg(&value))
+}
+
+pub fn parse_colorfgbg(value: &str) -> Option<Background> {
+    let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+    match bg {
+        // white, and the bright colors but black
+        7 | 9..=15 => Some(Background::Light),
+        0..=6 | 8 => Some(Background::Dark),
+        _ => None,
+    }
+}
+
+/// Sets the default faces for a light background.
+pub fn set_light(config: &mut AppConfig) {
+    config.added_face = color_spec(Some(Ansi256(28)), None, false);
+    config.refine_added_face = color_spec(Some(Ansi256(22)), Some(Ansi256(194)), true);
+    config.removed_face = color_spec(Some(Ansi256(124)), None, false);
+    config.refine_removed_face = color_spec(Some(Ansi256(88)), Some(Ansi256(224)), true);
+    config.commit_face = color_spec(Some(Ansi256(130)), None, false);
+    config.mode_face = color_spec(Some(Ansi256(30)), None, false);
+    config.rename_face = color_spec(Some(Ansi256(30)), None, false);
+    config.submodule_face = color_spec(Some(Ansi256(90)), None, false);
+}
+
+/// Sets the faces of the added and removed lines of `theme`, for
+/// `background`.
+pub fn set_theme(config: &mut AppConfig, theme: Theme, background: Background) {
+    // the foreground of the lines, and the foreground and background
+    // of the refined segments, of the added then removed lines
+    let colors: [(u8, u8, u8); 2] = match (theme, background) {
+        (Theme::Default, _) => return,
+        (Theme::Deuteranopia, Background::Dark) => [(75, 15, 25), (214, 0, 214)],
+        (Theme::Deuteranopia, Background::Light) => [(25, 17, 153), (130, 52, 223)],
+        (Theme::Protanopia, Background::Dark) => [(75, 15, 25), (220, 0, 220)],
+        (Theme::Protanopia, Background::Light) => [(25, 17, 153), (136, 58, 229)],
+        (Theme::Tritanopia, Background::Dark) => [(44, 15, 30), (203, 15, 160)],
+        (Theme::Tritanopia, Background::Light) => [(30, 23, 159), (160, 88, 217)],
+    };
+    let [(added, refine_added_fg, refine_added_bg), (removed, refine_removed_fg, refine_removed_bg)] =
+        colors;
+    config.added_face = color_spec(Some(Ansi256(added)), None, false);
+    config.refine_added_face = color_spec(
+        Some(Ansi256(refine_added_fg)),
+        Some(Ansi256(refine_added_bg)),
+        true,
+    );
+    config.removed_face = color_spec(Some(Ansi256(removed)), None, false);
+    config.refine_removed_face = color_spec(
+        Some(Ansi256(refine_removed_fg)),
+        Some(Ansi256(refine_removed_bg)),
+        true,
+    );
+}
@@ -0,0 +1,47 @@
+//! Rendering of the output of diffr as a unified diff, for
+//! --output=patch.
+
+use std::io::{self, Write};
+
+use super::cli_args::FLAG_REDACT;
+use super::hunks::{LineKind, Part};
+use super::{AppConfig, OutputFormat};
+
+/// Checks that the diff rendered with `config` in `format` is not
+/// redacted if it is a patch, as the redacted patch would not apply.
+pub fn check_redact(config: &AppConfig, format: OutputFormat) -> Result<(), String> {
+    if format == OutputFormat::Patch && !config.redact.is_empty() {
+        return Err(format!(
+            "{} cannot be used with the patch format: the patch would not apply",
+            FLAG_REDACT
+        ));
+    }
+    Ok(())
+}
+
+/// Writes `parts` as the lines of the input, without the
+/// transformations that only change how the diff is displayed, so that
+/// the patch applies as the input does.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    for part in parts {
+        let hunk = match part {
+            Part::Lines(lines) => {
+                out.write_all(lines)?;
+                continue;
+            }
+            Part::Hunk(hunk) => hunk,
+        };
+        for line in &hunk.lines {
+            let prefix: &[u8] = match line.kind {
+                LineKind::Context => b" ",
+                LineKind::Removed => b"-",
+                LineKind::Added => b"+",
+                LineKind::NoNewline => b"\\ No newline at end of file",
+            };
+            out.write_all(prefix)?;
+            out.write_all(&line.content)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! The sample diff of --preview-colors.
+
+/// A diff painted with most of the faces: a commit header, extended
+/// header lines, refined hunks and a submodule.
+pub const SAMPLE_DIFF: &str = "\
+commit 3f2a1c4d5e6b7a8c9d0e1f2a3b4c5d6e7f8a9b0c
+Author: Jane Doe <jane@example.com>
+Date:   Mon Jan 1 12:00:00 2024 +0000
+
+    Rename the greeting helper
+
+diff --git a/src/greet.rs b/src/hello.rs
+old mode 100644
+new mode 100755
+similarity index 80%
+rename from src/greet.rs
+rename to src/hello.rs
+--- a/src/greet.rs
++++ b/src/hello.rs
+@@ -1,6 +1,6 @@
+ /// Greets the user.
+-fn greet(name: &str) -> String {
+-    format!(\"Hello, {}!\", name)
++fn hello(name: &str, excited: bool) -> String {
++    format!(\"Hello, {}{}\", name, if excited { \"!\" } else { \".\" })
+ }
+ 
+-// TODO: translate
+\\ No newline at end of file
++// TODO: Translate
+\\ No newline at end of file
+diff --git a/vendor/lib b/vendor/lib
+Submodule vendor/lib 1234567..89abcde:
+  > Update the library
+";
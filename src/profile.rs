@@ -0,0 +1,212 @@
+//! The profiles of the configuration file, for --profile.
+//!
+//! The configuration file is 'diffr/config' in the configuration
+//! directory of the XDG base directory specification, or of Windows.
+//! Each profile is a section of options, written as in DIFFR_OPTIONS:
+//!
+//! [profile.presentation]
+//! --theme deuteranopia
+//! --line-numbers aligned --max-line-length 100
+//!
+//! The sections 'faces.GLOB' set the faces of the files whose path
+//! matches GLOB, as in --path, written as the values of --colors:
+//!
+//! [faces.*.md]
+//! refine-added:italic refine-removed:italic
+//! [faces.Cargo.lock]
+//! added:dim removed:dim
+//!
+//! The section 'faces' sets the faces of all the files, as the table
+//! of the same name of a theme file written by --dump-theme:
+//!
+//! [faces]
+//! added = "none:foreground:green"
+//!
+//! The other sections, and the lines of a profile that do not start
+//! with an option, are errors.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::cli_args::{split_shell_words, FLAG_PROFILE};
+use super::theme_file;
+
+/// The environment variable naming the profile used without --profile.
+pub const ENV_PROFILE: &str = "DIFFR_PROFILE";
+
+/// The path of the configuration file.
+fn config_path() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let base = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| var("APPDATA").map(PathBuf::from))?;
+    Some(base.join("diffr").join("config"))
+}
+
+/// A section of the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section<'a> {
+    /// The options of the profile 'profile.NAME'.
+    Profile(&'a str),
+    /// The values of --colors of the section 'faces.GLOB'.
+    Faces(&'a str),
+    /// The faces of the section 'faces', as in a theme file.
+    Theme,
+}
+
+/// Splits the configuration `text` into its sections, with the words
+/// of their lines. The unknown sections, the lines outside of a
+/// section, and the lines of a profile that do not start with an
+/// option are errors.
+fn sections(text: &str) -> Result<Vec<(Section<'_>, Vec<String>)>, String> {
+    let mut sections: Vec<(Section, Vec<String>)> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let name = name.trim();
+            let section = if name == "faces" {
+                Section::Theme
+            } else if let Some(profile) = name.strip_prefix("profile.") {
+                Section::Profile(profile)
+            } else if let Some(glob) = name.strip_prefix("faces.") {
+                Section::Faces(glob)
+            } else {
+                return Err(format!("line {}: unknown section '{}'", i + 1, name));
+            };
+            sections.push((section, vec![]));
+            continue;
+        }
+        let (section, words) = match sections.last_mut() {
+            Some(last) => last,
+            None => return Err(format!("line {}: options outside of a section", i + 1)),
+        };
+        let line_words = match section {
+            Section::Theme => theme_file::parse_entry(line).map(|spec| vec![spec]),
+            _ => split_shell_words(line),
+        };
+        let line_words = line_words.map_err(|err| format!("line {}: {}", i + 1, err))?;
+        if let (Section::Profile(_), Some(word)) = (section, line_words.first()) {
+            if !word.starts_with('-') {
+                return Err(format!(
+                    "line {}: expected an option, got '{}'",
+                    i + 1,
+                    word
+                ));
+            }
+        }
+        words.extend(line_words);
+    }
+    Ok(sections)
+}
+
+/// Reads the options of the profile `name` in the configuration
+/// `text`; returns None if the profile is not defined.
+pub fn parse(text: &str, name: &str) -> Result<Option<Vec<String>>, String> {
+    let mut options: Option<Vec<String>> = None;
+    for (section, words) in sections(text)? {
+        if section == Section::Profile(name) {
+            options.get_or_insert_with(Vec::new).extend(words);
+        }
+    }
+    Ok(options)
+}
+
+/// The faces set for the files whose path matches a glob.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFaces {
+    pub glob: String,
+    /// The values of --colors.
+    pub colors: Vec<String>,
+}
+
+/// Reads the sections of file faces of the configuration `text`.
+pub fn parse_file_faces(text: &str) -> Result<Vec<FileFaces>, String> {
+    let file_faces = sections(text)?
+        .into_iter()
+        .filter_map(|(section, colors)| match section {
+            Section::Faces(glob) => Some(FileFaces {
+                glob: glob.to_owned(),
+                colors,
+            }),
+            _ => None,
+        })
+        .collect();
+    Ok(file_faces)
+}
+
+/// Reads the color_specs of the section 'faces' of the configuration
+/// `text`, such as "added:none:foreground:green".
+pub fn parse_faces(text: &str) -> Result<Vec<String>, String> {
+    let specs = sections(text)?
+        .into_iter()
+        .filter(|(section, _)| *section == Section::Theme)
+        .flat_map(|(_, specs)| specs)
+        .collect();
+    Ok(specs)
+}
+
+/// Reads the configuration file with `parse`, if there is one.
+fn read_config<T: Default>(parse: fn(&str) -> Result<T, String>) -> Result<T, String> {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Ok(T::default()),
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// The faces of the configuration file, if there is one.
+pub fn faces() -> Result<Vec<String>, String> {
+    read_config(parse_faces)
+}
+
+/// The file faces of the configuration file, if there is one.
+pub fn file_faces() -> Result<Vec<FileFaces>, String> {
+    read_config(parse_file_faces)
+}
+
+/// The profile selected by the last --profile of `args`, or by the
+/// DIFFR_PROFILE environment variable.
+fn selected(args: &[OsString]) -> Option<String> {
+    let mut name = None;
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == FLAG_PROFILE {
+            name = args.next().map(|name| name.into_owned());
+        } else if let Some(value) = arg
+            .strip_prefix(FLAG_PROFILE)
+            .and_then(|s| s.strip_prefix('='))
+        {
+            name = Some(value.to_owned());
+        }
+    }
+    name.or_else(|| env::var(ENV_PROFILE).ok().filter(|name| !name.is_empty()))
+}
+
+/// The options of the profile selected in `args`, if any.
+pub fn options(args: &[OsString]) -> Result<Vec<String>, String> {
+    let name = match selected(args) {
+        Some(name) => name,
+        None => return Ok(vec![]),
+    };
+    let path = config_path().ok_or("no configuration directory")?;
+    let text = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    match parse(&text, &name) {
+        Ok(Some(options)) => Ok(options),
+        Ok(None) => Err(format!("{}: no profile '{}'", path.display(), name)),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
@@ -0,0 +1,94 @@
+//! Rendering of the output of diffr as a list of locations, for
+//! --output=quickfix.
+
+use std::io::{self, Write};
+
+use super::hunks::{self, LineKind, Part};
+
+/// A block of changed lines of a hunk.
+#[derive(Debug, Default)]
+struct Region<'a> {
+    /// The line of the new file where the block starts.
+    line: usize,
+    removed: usize,
+    added: usize,
+    first_removed: Option<&'a [u8]>,
+    first_added: Option<&'a [u8]>,
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+impl Region<'_> {
+    /// A description of the change, followed by its first line: the
+    /// first added line, or the first removed line.
+    fn summary(&self) -> String {
+        let description = match (self.removed, self.added) {
+            (0, added) => format!("added {} line{}", added, plural(added)),
+            (removed, 0) => format!("removed {} line{}", removed, plural(removed)),
+            (removed, added) => {
+                format!("changed {} line{} into {}", removed, plural(removed), added)
+            }
+        };
+        let first = self.first_added.or(self.first_removed).unwrap_or_default();
+        let first = String::from_utf8_lossy(first.trim_ascii());
+        if first.is_empty() {
+            description
+        } else {
+            format!("{}: {}", description, first)
+        }
+    }
+}
+
+fn write_region<W: Write>(path: &str, region: &Region, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}:{}: {}", path, region.line.max(1), region.summary())
+}
+
+/// Writes the hunks of `parts` as a `path:line: summary` line per
+/// block of changed lines, with the line numbers of the new files, for
+/// the quickfix list of Vim and the compilation mode of Emacs.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    for hunk in hunks::hunks(parts) {
+        let path = hunk.path.as_ref().or(hunk.old_path.as_ref());
+        let path = path.map_or("<unknown>", String::as_str);
+        let mut new_line = hunk.header.new_start;
+        let mut region: Option<Region> = None;
+        for line in &hunk.lines {
+            match line.kind {
+                LineKind::Removed => {
+                    let region = region.get_or_insert_with(|| Region {
+                        line: new_line,
+                        ..Region::default()
+                    });
+                    region.removed += 1;
+                    region.first_removed.get_or_insert(&line.content);
+                }
+                LineKind::Added => {
+                    let region = region.get_or_insert_with(|| Region {
+                        line: new_line,
+                        ..Region::default()
+                    });
+                    region.added += 1;
+                    region.first_added.get_or_insert(&line.content);
+                    new_line += 1;
+                }
+                LineKind::Context => {
+                    if let Some(region) = region.take() {
+                        write_region(path, &region, out)?;
+                    }
+                    new_line += 1;
+                }
+                LineKind::NoNewline => (),
+            }
+        }
+        if let Some(region) = region {
+            write_region(path, &region, out)?;
+        }
+    }
+    Ok(())
+}
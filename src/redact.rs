@@ -0,0 +1,184 @@
+//! Redaction of the output, for --redact.
+//!
+//! The lines written are held with their faces until their end, so that
+//! the regexes match the text of a whole line, whatever the faces that
+//! paint its parts.
+
+use regex::bytes::Regex;
+use std::io::{self, Write};
+use termcolor::{ColorSpec, HyperlinkSpec, WriteColor};
+
+use diffr_lib::hash_slice;
+
+use super::grep;
+
+/// How the matches of --redact are replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactStyle {
+    /// A block per character, so that the layout is kept.
+    Block,
+    /// A hash of the match, the same for equal matches.
+    Hash,
+}
+
+/// The replacement of a character of a match, with the block style.
+const BLOCK: &str = "\u{2588}";
+
+/// A change of the faces, between the text of a line.
+enum Op {
+    Text(usize, usize),
+    Color(ColorSpec),
+    Reset,
+    Hyperlink(Option<Vec<u8>>),
+}
+
+/// The replacement of `matched`, with the hash style.
+pub fn hash_placeholder(matched: &[u8]) -> String {
+    format!("#{:08x}", hash_slice(matched) as u32)
+}
+
+/// A stream that replaces the matches of regexes in each line written.
+pub struct Redact<W> {
+    inner: W,
+    regexes: Vec<Regex>,
+    style: RedactStyle,
+    /// The text of the current line.
+    line: Vec<u8>,
+    ops: Vec<Op>,
+    /// The spans of the matches in the current line.
+    spans: Vec<(usize, usize)>,
+}
+
+impl<W: WriteColor> Redact<W> {
+    pub fn new(inner: W, regexes: Vec<Regex>, style: RedactStyle) -> Self {
+        Redact {
+            inner,
+            regexes,
+            style,
+            line: vec![],
+            ops: vec![],
+            spans: vec![],
+        }
+    }
+
+    /// Writes the current line, redacted.
+    fn write_line(&mut self) -> io::Result<()> {
+        let content = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+        self.spans.clear();
+        grep::find_spans(&self.regexes, content, 0, &mut self.spans);
+        let mut spans = self.spans.iter().peekable();
+        for op in self.ops.drain(..) {
+            let (mut lo, hi) = match op {
+                Op::Text(lo, hi) => (lo, hi),
+                Op::Color(spec) => {
+                    self.inner.set_color(&spec)?;
+                    continue;
+                }
+                Op::Reset => {
+                    self.inner.reset()?;
+                    continue;
+                }
+                Op::Hyperlink(uri) => {
+                    let link = match &uri {
+                        Some(uri) => HyperlinkSpec::open(uri),
+                        None => HyperlinkSpec::close(),
+                    };
+                    self.inner.set_hyperlink(&link)?;
+                    continue;
+                }
+            };
+            while lo < hi {
+                while spans.next_if(|&&(_, span_hi)| span_hi <= lo).is_some() {}
+                let (span_lo, span_hi) = match spans.peek() {
+                    Some(&&span) if span.0 < hi => span,
+                    _ => {
+                        self.inner.write_all(&self.line[lo..hi])?;
+                        break;
+                    }
+                };
+                if lo < span_lo {
+                    self.inner.write_all(&self.line[lo..span_lo])?;
+                    lo = span_lo;
+                }
+                let end = span_hi.min(hi);
+                match self.style {
+                    RedactStyle::Block => {
+                        // a block per character, and per invalid byte
+                        for chunk in self.line[lo..end].utf8_chunks() {
+                            let count = chunk.valid().chars().count() + chunk.invalid().len();
+                            self.inner.write_all(BLOCK.repeat(count).as_bytes())?;
+                        }
+                    }
+                    // the placeholder is written with the face of the
+                    // start of the match
+                    RedactStyle::Hash if lo == span_lo => {
+                        let placeholder = hash_placeholder(&self.line[span_lo..span_hi]);
+                        self.inner.write_all(placeholder.as_bytes())?;
+                    }
+                    RedactStyle::Hash => {}
+                }
+                lo = end;
+            }
+        }
+        self.line.clear();
+        Ok(())
+    }
+
+    /// Writes the last line, if it has no newline.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.ops.is_empty() {
+            self.write_line()?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: WriteColor> Write for Redact<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for part in buf.split_inclusive(|&b| b == b'\n') {
+            let lo = self.line.len();
+            self.line.extend_from_slice(part);
+            match self.ops.last_mut() {
+                Some(Op::Text(_, hi)) if *hi == lo => *hi = self.line.len(),
+                _ => self.ops.push(Op::Text(lo, self.line.len())),
+            }
+            if part.ends_with(b"\n") {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: WriteColor> WriteColor for Redact<W> {
+    fn supports_color(&self) -> bool {
+        self.inner.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.ops.push(Op::Color(spec.clone()));
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.ops.push(Op::Reset);
+        Ok(())
+    }
+
+    fn is_synchronous(&self) -> bool {
+        self.inner.is_synchronous()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> io::Result<()> {
+        self.ops.push(Op::Hyperlink(link.uri().map(<[u8]>::to_vec)));
+        Ok(())
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        self.inner.supports_hyperlinks()
+    }
+}
@@ -0,0 +1,41 @@
+//! Detection of the lines moved unchanged within their hunk, for
+//! --relocated.
+
+use std::collections::HashMap;
+
+use super::Span;
+
+/// The content of the changed line `line`, without its +/- prefix and
+/// its line end.
+fn content(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    &line[1..]
+}
+
+/// Pairs the removed and added lines of a hunk with the same content,
+/// as when blocks of lines are reordered. Collects in `relocated`
+/// whether each line is paired; the blank lines are never paired.
+pub fn find<Lines>(data: &[u8], lines: Lines, relocated: &mut Vec<bool>)
+where
+    Lines: Iterator<Item = Span>,
+{
+    relocated.clear();
+    let spans = lines.collect::<Vec<_>>();
+    relocated.resize(spans.len(), false);
+    let line = |i: usize| content(&data[spans[i].0..spans[i].1]);
+    let is_changed = |i: usize, prefix| {
+        data[spans[i].0] == prefix && !line(i).iter().all(u8::is_ascii_whitespace)
+    };
+    // the removed lines not paired yet, by content
+    let mut removed = HashMap::<&[u8], Vec<usize>>::new();
+    for i in (0..spans.len()).rev().filter(|&i| is_changed(i, b'-')) {
+        removed.entry(line(i)).or_default().push(i);
+    }
+    for j in (0..spans.len()).filter(|&j| is_changed(j, b'+')) {
+        if let Some(i) = removed.get_mut(line(j)).and_then(Vec::pop) {
+            relocated[i] = true;
+            relocated[j] = true;
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Re-slicing of the hunks of the input with a different number of
+//! context lines, for --context.
+//!
+//! With --resolve-context, the context lines missing from the input are
+//! read from the blobs of the file, named by the "index" line of its
+//! git header.
+
+use std::io::{self, BufRead, Read};
+use std::mem;
+
+use super::{first_after_escape, parse_hunk_header, strip_escape_codes, HunkHeader, HunkRemaining};
+
+/// Loads the blob of a hash, for --resolve-context.
+pub type LoadBlob = fn(&str) -> Option<Vec<u8>>;
+
+/// The lines of a blob of the file of the hunks, for --resolve-context.
+struct Blob {
+    /// Whether the blob is the new version of the file.
+    new: bool,
+    data: Vec<u8>,
+    lines: Vec<(usize, usize)>,
+}
+
+impl Blob {
+    fn new(new: bool, data: Vec<u8>) -> Self {
+        let mut lines = vec![];
+        let mut lo = 0;
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            lines.push((lo, lo + line.len()));
+            lo += line.len();
+        }
+        Blob { new, data, lines }
+    }
+}
+
+/// A re-sliced hunk not written yet, as the next hunk may continue it.
+struct Pending {
+    raw_header: Vec<u8>,
+    /// The first old and new lines of the hunk.
+    first: (usize, usize),
+    /// The lines of the hunk by kind, the "\ No newline at end of file"
+    /// markers with the line before them.
+    units: Vec<(u8, Vec<u8>)>,
+    /// Whether the hunk was merged with the hunks after it, so that
+    /// its header is rewritten.
+    merged: bool,
+}
+
+impl Pending {
+    /// The numbers of old and new lines of the hunk.
+    fn lens(&self) -> (usize, usize) {
+        let old = self.units.iter().filter(|unit| unit.0 != b'+').count();
+        let new = self.units.iter().filter(|unit| unit.0 != b'-').count();
+        (old, new)
+    }
+
+    /// Appends `next` to the hunk if it starts where the hunk ends, or
+    /// within its trailing context lines; returns it otherwise.
+    fn merge(&mut self, next: Pending) -> Option<Pending> {
+        let (old_len, new_len) = self.lens();
+        let overlap = (self.first.0 + old_len).checked_sub(next.first.0);
+        let mergeable = match overlap {
+            Some(overlap) => {
+                (self.first.1 + new_len).checked_sub(next.first.1) == Some(overlap)
+                    && overlap <= next.units.len()
+                    && next.units[..overlap].iter().all(|unit| unit.0 == b' ')
+            }
+            None => false,
+        };
+        if !mergeable {
+            return Some(next);
+        }
+        let overlap = overlap.unwrap_or_default();
+        self.units.extend(next.units.into_iter().skip(overlap));
+        self.merged = true;
+        None
+    }
+}
+
+/// The input, with its hunks re-sliced to `context` lines of context.
+pub struct Reslice<Input> {
+    input: Input,
+    context: usize,
+    lenient: bool,
+    /// Loads the blobs of the files, with --resolve-context.
+    load_blob: Option<LoadBlob>,
+    /// The hashes of the old and new blobs of the current file.
+    index: Option<(String, String)>,
+    blob: Option<Option<Blob>>,
+    /// The last old and new lines of the previous hunk of the file.
+    previous_end: (usize, usize),
+    // the current hunk: its header, as read and parsed, and its lines
+    // with their kinds and their spans in `data`
+    header: Option<(Vec<u8>, HunkHeader)>,
+    remaining: Option<HunkRemaining>,
+    data: Vec<u8>,
+    lines: Vec<(u8, usize, usize)>,
+    /// The last re-sliced hunk, merged with the next ones that overlap
+    /// it.
+    pending: Option<Pending>,
+    line: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<Input> Reslice<Input> {
+    pub fn new(input: Input, context: usize, lenient: bool) -> Self {
+        Reslice {
+            input,
+            context,
+            lenient,
+            load_blob: None,
+            index: None,
+            blob: None,
+            previous_end: (0, 0),
+            header: None,
+            remaining: None,
+            data: vec![],
+            lines: vec![],
+            pending: None,
+            line: vec![],
+            output: vec![],
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Adds the context lines missing from the input, read from the
+    /// blobs loaded by `load_blob`, for --resolve-context.
+    pub fn resolve_context(mut self, load_blob: LoadBlob) -> Self {
+        self.load_blob = Some(load_blob);
+        self
+    }
+
+    /// Follows the headers of the files, for the blobs of their hunks.
+    fn read_file_header(&mut self, stripped: &[u8]) {
+        if stripped.starts_with(b"diff ") {
+            self.index = None;
+            self.blob = None;
+            self.previous_end = (0, 0);
+        } else if let Some(index) = stripped.strip_prefix(b"index ") {
+            let index = String::from_utf8_lossy(index);
+            let hashes = index.split_whitespace().next().unwrap_or_default();
+            // the combined diffs have several old blobs
+            if let Some((old, new)) = hashes.split_once("..").filter(|_| !hashes.contains(',')) {
+                self.index = Some((old.to_owned(), new.to_owned()));
+            }
+        }
+    }
+
+    /// The blob of the current file: the new one if git knows it, as
+    /// the files of the working tree are not, or else the old one.
+    fn blob(&mut self) -> Option<&Blob> {
+        let (load_blob, (old, new)) = match (self.load_blob, &self.index) {
+            (Some(load_blob), Some(index)) => (load_blob, index),
+            _ => return None,
+        };
+        let is_known = |hash: &str| !hash.bytes().all(|b| b == b'0');
+        self.blob
+            .get_or_insert_with(|| {
+                let new = Some(new)
+                    .filter(|new| is_known(new))
+                    .and_then(|new| load_blob(new));
+                match new {
+                    Some(data) => Some(Blob::new(true, data)),
+                    None => Some(old)
+                        .filter(|old| is_known(old))
+                        .and_then(|old| load_blob(old))
+                        .map(|data| Blob::new(false, data)),
+                }
+            })
+            .as_ref()
+    }
+
+    /// Adds to the current hunk, of the header `header`, the context
+    /// lines of its blob that it misses, without reaching the previous
+    /// hunk of the file nor the hunk of the header `next`. Returns the
+    /// header of the extended hunk, if it is.
+    fn extend(&mut self, header: HunkHeader, next: Option<&HunkHeader>) -> Option<HunkHeader> {
+        let first_old = header.old_start + usize::from(header.old_len == 0);
+        let first_new = header.new_start + usize::from(header.new_len == 0);
+        let end = (
+            first_old + header.old_len - 1,
+            first_new + header.new_len - 1,
+        );
+        let previous_end = mem::replace(&mut self.previous_end, end);
+        let context = self.context;
+        let leading = self.lines.iter().take_while(|line| line.0 == b' ').count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| line.0 == b' ')
+            .count();
+        // a hunk with a "\ No newline at end of file" ends its file
+        let at_end = self.lines.iter().any(|line| line.0 == b'\\');
+        let blob = self.blob()?;
+        let (first, len, previous_end, next) = if blob.new {
+            let next = next.map(|next| next.new_start + usize::from(next.new_len == 0));
+            (first_new, header.new_len, previous_end.1, next)
+        } else {
+            let next = next.map(|next| next.old_start + usize::from(next.old_len == 0));
+            (first_old, header.old_len, previous_end.0, next)
+        };
+        let nlead = context
+            .saturating_sub(leading)
+            .min((first - 1).saturating_sub(previous_end));
+        let last = next.map_or(blob.lines.len(), |next| next - 1);
+        let ntrail = match at_end {
+            true => 0,
+            false => context
+                .saturating_sub(trailing)
+                .min(last.min(blob.lines.len()).saturating_sub(first - 1 + len)),
+        };
+        if nlead + ntrail == 0 {
+            return None;
+        }
+        let (mut lead, mut trail) = (vec![], vec![]);
+        context_lines(blob, first - 1 - nlead..first - 1, &mut lead);
+        context_lines(blob, first - 1 + len..first - 1 + len + ntrail, &mut trail);
+        self.previous_end = (end.0 + ntrail, end.1 + ntrail);
+        let (hunk, lines) = (mem::take(&mut self.data), mem::take(&mut self.lines));
+        for line in lead.split_inclusive(|&b| b == b'\n') {
+            self.push_hunk_line(line[0], line);
+        }
+        for (kind, lo, hi) in lines {
+            self.push_hunk_line(kind, &hunk[lo..hi]);
+        }
+        for line in trail.split_inclusive(|&b| b == b'\n') {
+            self.push_hunk_line(line[0], line);
+        }
+        Some(HunkHeader {
+            old_start: first_old - nlead,
+            old_len: header.old_len + nlead + ntrail,
+            new_start: first_new - nlead,
+            new_len: header.new_len + nlead + ntrail,
+        })
+    }
+
+    fn push_hunk_line(&mut self, kind: u8, line: &[u8]) {
+        let lo = self.data.len();
+        self.data.extend_from_slice(line);
+        self.lines.push((kind, lo, self.data.len()));
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        let first = first_after_escape(line);
+        if self.header.is_some() {
+            let kind = match &mut self.remaining {
+                Some(remaining) => remaining.count(first),
+                None => first,
+            };
+            match kind {
+                Some(kind @ b'+') | Some(kind @ b'-') | Some(kind @ b' ') | Some(kind @ b'\\') => {
+                    self.push_hunk_line(kind, line);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        let mut stripped = vec![];
+        strip_escape_codes(line, &mut stripped);
+        let header = parse_hunk_header(&stripped);
+        self.flush(header.as_ref());
+        match header {
+            Some(header) => {
+                self.header = Some((line.to_vec(), header));
+                self.remaining = if self.lenient {
+                    Some(HunkRemaining::from(header))
+                } else {
+                    None
+                };
+            }
+            None => {
+                self.write_pending();
+                self.read_file_header(&stripped);
+                self.output.extend_from_slice(line);
+            }
+        }
+    }
+
+    /// Writes the current hunk, re-sliced; `next` is the header of the
+    /// hunk after it, if any.
+    fn flush(&mut self, next: Option<&HunkHeader>) {
+        let (raw_header, header) = match self.header.take() {
+            Some(header) => header,
+            None => return,
+        };
+        let (raw_header, header) = match self.extend(header, next) {
+            Some(extended) => {
+                let mut extended_header = format!(
+                    "@@ -{} +{} @@",
+                    format_range(extended.old_start, extended.old_len),
+                    format_range(extended.new_start, extended.new_len),
+                )
+                .into_bytes();
+                extended_header.extend_from_slice(&function_context(&raw_header));
+                (extended_header, extended)
+            }
+            None => (raw_header, header),
+        };
+        let (data, lines) = (&self.data, &self.lines);
+        // the "\ No newline at end of file" markers stay with the
+        // line before them
+        let mut units: Vec<(u8, usize, usize)> = vec![];
+        for (i, &(kind, _, _)) in lines.iter().enumerate() {
+            match units.last_mut() {
+                Some(unit) if kind == b'\\' => unit.2 = i + 1,
+                _ => units.push((kind, i, i + 1)),
+            }
+        }
+        let mut groups: Vec<(usize, usize)> = vec![];
+        for (j, &(kind, _, _)) in units.iter().enumerate() {
+            if kind == b' ' || kind == b'\\' {
+                continue;
+            }
+            let lo = j.saturating_sub(self.context);
+            let hi = (j + 1 + self.context).min(units.len());
+            match groups.last_mut() {
+                Some(group) if lo <= group.1 => group.1 = hi,
+                _ => groups.push((lo, hi)),
+            }
+        }
+        let first_old = header.old_start + usize::from(header.old_len == 0);
+        let first_new = header.new_start + usize::from(header.new_len == 0);
+        let unit_lines = |units: &[(u8, usize, usize)]| {
+            units
+                .iter()
+                .map(|&(kind, start, end)| {
+                    let (lo, hi) = (lines[start].1, lines[end - 1].2);
+                    (kind, data[lo..hi].to_vec())
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut hunks = vec![];
+        if groups.is_empty() || groups == [(0, units.len())] {
+            hunks.push(Pending {
+                raw_header,
+                first: (first_old, first_new),
+                units: unit_lines(&units),
+                merged: false,
+            });
+        } else {
+            let rest = function_context(&raw_header);
+            let count = |units: &[(u8, usize, usize)]| {
+                let old = units.iter().filter(|unit| unit.0 != b'+').count();
+                let new = units.iter().filter(|unit| unit.0 != b'-').count();
+                (old, new)
+            };
+            for (lo, hi) in groups {
+                let (old_offset, new_offset) = count(&units[..lo]);
+                let (old_len, new_len) = count(&units[lo..hi]);
+                let mut new_header = format!(
+                    "@@ -{} +{} @@",
+                    format_range(first_old + old_offset, old_len),
+                    format_range(first_new + new_offset, new_len),
+                )
+                .into_bytes();
+                new_header.extend_from_slice(&rest);
+                hunks.push(Pending {
+                    raw_header: new_header,
+                    first: (first_old + old_offset, first_new + new_offset),
+                    units: unit_lines(&units[lo..hi]),
+                    merged: false,
+                });
+            }
+        }
+        self.data.clear();
+        self.lines.clear();
+        for hunk in hunks {
+            let hunk = match &mut self.pending {
+                Some(pending) => pending.merge(hunk),
+                None => Some(hunk),
+            };
+            if let Some(hunk) = hunk {
+                self.write_pending();
+                self.pending = Some(hunk);
+            }
+        }
+    }
+
+    /// Writes the pending hunk, with a new header if it was merged.
+    fn write_pending(&mut self) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        if pending.merged {
+            let (old_len, new_len) = pending.lens();
+            let (first_old, first_new) = pending.first;
+            let header = format!(
+                "@@ -{} +{} @@",
+                format_range(first_old, old_len),
+                format_range(first_new, new_len),
+            );
+            self.output.extend_from_slice(header.as_bytes());
+            self.output
+                .extend_from_slice(&function_context(&pending.raw_header));
+        } else {
+            self.output.extend_from_slice(&pending.raw_header);
+        }
+        for (_, unit) in pending.units {
+            self.output.extend_from_slice(&unit);
+        }
+    }
+}
+
+/// The end of the hunk header `raw_header` after its line ranges: the
+/// function name, if any, and the newline.
+fn function_context(raw_header: &[u8]) -> Vec<u8> {
+    let mut stripped = vec![];
+    strip_escape_codes(raw_header, &mut stripped);
+    match stripped[4..].windows(2).position(|w| w == b"@@") {
+        Some(i) => stripped[i + 6..].to_vec(),
+        None => b"\n".to_vec(),
+    }
+}
+
+/// Appends the lines `range` of `blob` to `dst` as context lines.
+fn context_lines(blob: &Blob, range: std::ops::Range<usize>, dst: &mut Vec<u8>) {
+    for &(lo, hi) in &blob.lines[range] {
+        dst.push(b' ');
+        dst.extend_from_slice(&blob.data[lo..hi]);
+        if !dst.ends_with(b"\n") {
+            dst.extend_from_slice(b"\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// Formats a line range of a hunk header, starting at the line
+/// `first`.
+pub fn format_range(first: usize, len: usize) -> String {
+    match len {
+        // an empty range starts at the line before it
+        0 => format!("{},0", first - 1),
+        1 => first.to_string(),
+        _ => format!("{},{}", first, len),
+    }
+}
+
+impl<Input: BufRead> Read for Reslice<Input> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<Input: BufRead> BufRead for Reslice<Input> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.output.len() && !self.eof {
+            self.output.clear();
+            self.pos = 0;
+            let mut line = mem::take(&mut self.line);
+            line.clear();
+            if self.input.read_until(b'\n', &mut line)? == 0 {
+                self.eof = true;
+                self.flush(None);
+                self.write_pending();
+            } else {
+                self.push_line(&line);
+            }
+            self.line = line;
+        }
+        Ok(&self.output[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
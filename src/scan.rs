@@ -0,0 +1,93 @@
+//! Scanning of the input for line ends and escape codes, with the
+//! vectorized searches of `memchr`.
+
+use memchr::{memchr, memchr_iter};
+use std::io::{self, BufRead};
+
+use super::{commit, skip_all_escape_code};
+
+/// Appends the next line of `input` to `buf`, including its newline
+/// if any. Returns the number of bytes read, 0 at the end of the input.
+pub fn read_line<Input: BufRead>(input: &mut Input, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut read = 0;
+    loop {
+        let (done, used) = {
+            let available = match input.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            match memchr(b'\n', available) {
+                Some(i) => {
+                    buf.extend_from_slice(&available[..=i]);
+                    (true, i + 1)
+                }
+                None => {
+                    buf.extend_from_slice(available);
+                    (available.is_empty(), available.len())
+                }
+            }
+        };
+        input.consume(used);
+        read += used;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+/// Counts the lines of `data`, the last one being counted even
+/// without a newline.
+pub fn count_lines(data: &[u8]) -> usize {
+    let newlines = memchr_iter(b'\n', data).count();
+    newlines + usize::from(data.last().is_some_and(|&b| b != b'\n'))
+}
+
+/// Finds the start of the first escape code of `buf`, that is, the
+/// first ESC byte followed by `[` or `]`.
+pub fn find_escape(buf: &[u8]) -> Option<usize> {
+    memchr_iter(b'\x1b', buf).find(|&i| matches!(buf.get(i + 1), Some(b'[') | Some(b']')))
+}
+
+/// The starts of the lines that may be part of a diff, of its file
+/// headers or of a commit header, after the escape codes that start a
+/// colored line.
+const DIFF_STARTS: &[&[u8]] = &[
+    b"@",
+    b"diff ",
+    b"--- ",
+    b"+++ ",
+    b"index ",
+    b"commit ",
+    b"From ",
+    b"-- ",
+    b"Binary files ",
+    b"GIT binary patch",
+    b"old mode ",
+    b"new mode ",
+    b"new file mode ",
+    b"deleted file mode ",
+    b"rename ",
+    b"copy ",
+    b"similarity index ",
+    b"dissimilarity index ",
+    b"Submodule ",
+];
+
+/// Returns the length of the complete lines that start `buf` and are
+/// not part of a diff, such as the message of a commit. In the header
+/// of a commit, `in_commit`, its metadata lines are part of it.
+pub fn passthrough_len(buf: &[u8], in_commit: bool) -> usize {
+    let mut start = 0;
+    for end in memchr_iter(b'\n', buf) {
+        let line = &buf[start..end];
+        let line = &line[skip_all_escape_code(line)..];
+        if DIFF_STARTS.iter().any(|prefix| line.starts_with(prefix))
+            || (in_commit && commit::is_meta(line))
+        {
+            break;
+        }
+        start = end + 1;
+    }
+    start
+}
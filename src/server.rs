@@ -0,0 +1,79 @@
+//! Rendering of diffs on request, for --serve.
+//!
+//! Each request is a line "FORMAT LENGTH", where FORMAT is a format of
+//! --output, followed by LENGTH bytes of diff. Each response is a line
+//! "ok LENGTH" followed by LENGTH bytes of output, or "error LENGTH"
+//! followed by LENGTH bytes of message. The diffs longer than
+//! `MAX_REQUEST_LEN` are skipped with an error. The server stops at the
+//! end of its input, or after a malformed request line.
+
+use std::io::{self, BufRead, Read, Write};
+use termcolor::ColorChoice;
+
+use super::{cli_args, patch, render_to, AppConfig};
+
+/// The maximal length of the diff of a request, so that a malformed
+/// length does not allocate an unbounded buffer.
+const MAX_REQUEST_LEN: usize = 8 << 20;
+
+fn respond<W: Write>(status: &str, body: &[u8], out: &mut W) -> io::Result<()> {
+    writeln!(out, "{} {}", status, body.len())?;
+    out.write_all(body)?;
+    out.flush()
+}
+
+/// Renders `diff` in `format`, with the options of `config`.
+fn render(config: &AppConfig, format: &str, diff: &[u8]) -> Result<Vec<u8>, String> {
+    let format = cli_args::parse_output_format(format)?;
+    patch::check_redact(config, format)?;
+    let config = AppConfig {
+        output: format,
+        ..config.clone()
+    };
+    let colors = config.color != ColorChoice::Never;
+    let mut body = vec![];
+    render_to(config, format, colors, &mut &diff[..], &mut body)
+        .map_err(|err| format!("io error: {}", err))?;
+    Ok(body)
+}
+
+/// Answers the requests read from `input` until its end.
+pub fn run<R, W>(config: &AppConfig, input: &mut R, out: &mut W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request = line.trim_end();
+        let parsed = request
+            .split_once(' ')
+            .and_then(|(format, len)| Some((format, len.parse::<usize>().ok()?)));
+        let (format, len) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                let message = format!("malformed request: '{}'", request);
+                return respond("error", message.as_bytes(), out);
+            }
+        };
+        if MAX_REQUEST_LEN < len {
+            io::copy(&mut input.by_ref().take(len as u64), &mut io::sink())?;
+            let message = format!(
+                "request too large: {} bytes, at most {}",
+                len, MAX_REQUEST_LEN
+            );
+            respond("error", message.as_bytes(), out)?;
+            continue;
+        }
+        let mut diff = vec![0; len];
+        input.read_exact(&mut diff)?;
+        match render(config, format, &diff) {
+            Ok(body) => respond("ok", &body, out)?,
+            Err(message) => respond("error", message.as_bytes(), out)?,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Rendering of the output of diffr as s-expressions, for
+//! --output=sexp.
+
+use std::io::{self, Write};
+
+use super::hunks::{self, LineKind, Part};
+
+/// Writes `text` as a string literal.
+fn write_string<W: Write>(text: &[u8], out: &mut W) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for &b in text {
+        if b == b'"' || b == b'\\' {
+            out.write_all(b"\\")?;
+        }
+        out.write_all(&[b])?;
+    }
+    out.write_all(b"\"")
+}
+
+fn write_path<W: Write>(key: &str, path: &Option<String>, out: &mut W) -> io::Result<()> {
+    write!(out, " :{} ", key)?;
+    match path {
+        Some(path) => write_string(path.as_bytes(), out),
+        None => out.write_all(b"nil"),
+    }
+}
+
+fn write_line<W: Write>(line: &hunks::Line, out: &mut W) -> io::Result<()> {
+    let name = match line.kind {
+        LineKind::Removed => "removed",
+        LineKind::Added => "added",
+        LineKind::Context => "context",
+        LineKind::NoNewline => return out.write_all(b"(no-newline)"),
+    };
+    write!(out, "({} ", name)?;
+    write_string(&line.content, out)?;
+    for (lo, hi) in &line.spans {
+        write!(out, " ({} . {})", lo, hi)?;
+    }
+    out.write_all(b")")
+}
+
+/// Writes the hunks of `parts` as a form per hunk:
+///
+/// (hunk :old-path "a.rs" :path "a.rs" :old-start 1 :old-len 1
+///  :new-start 1 :new-len 1
+///  :lines ((removed "a b" (2 . 3)) (added "a c" (2 . 3))))
+///
+/// written on a single line. The lines are `context`, `removed` and
+/// `added` lists with the content of the line, followed by the refined
+/// spans as pairs of character offsets in the content, and
+/// `(no-newline)` for the "\ No newline at end of file" lines.
+pub fn write<W: Write>(parts: &[Part], out: &mut W) -> io::Result<()> {
+    for hunk in hunks::hunks(parts) {
+        out.write_all(b"(hunk")?;
+        write_path("old-path", &hunk.old_path, out)?;
+        write_path("path", &hunk.path, out)?;
+        let header = hunk.header;
+        write!(
+            out,
+            " :old-start {} :old-len {} :new-start {} :new-len {} :lines (",
+            header.old_start, header.old_len, header.new_start, header.new_len
+        )?;
+        for (i, line) in hunk.lines.iter().enumerate() {
+            if 0 < i {
+                out.write_all(b" ")?;
+            }
+            write_line(line, out)?;
+        }
+        out.write_all(b"))\n")?;
+    }
+    Ok(())
+}
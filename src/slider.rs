@@ -0,0 +1,113 @@
+//! Post-processing of the refinement, sliding the refined spans to
+//! more natural boundaries.
+//!
+//! When some tokens are only removed (or only added), the LCS may
+//! place them at several equivalent positions: adding "g(x), " before
+//! "g(y)" can highlight "x), g(" in "g(x), g(y)". Among the equivalent
+//! positions, the one with balanced brackets and quotes, and
+//! surrounded by blanks, is chosen.
+
+use diffr_lib::{HashedSpan, Snake};
+
+fn is_blank(token: &[u8]) -> bool {
+    token.iter().all(|b| b.is_ascii_whitespace())
+}
+
+/// Returns true if the brackets and quotes of `tokens` match.
+fn is_balanced<'a, Tokens>(tokens: Tokens) -> bool
+where
+    Tokens: Iterator<Item = &'a [u8]>,
+{
+    let mut stack = vec![];
+    let mut quotes = [0usize; 3];
+    for &b in tokens.flatten() {
+        match b {
+            b'(' | b'[' | b'{' => stack.push(b),
+            b')' | b']' | b'}' => {
+                let open = match b {
+                    b')' => b'(',
+                    b']' => b'[',
+                    _ => b'{',
+                };
+                if stack.pop() != Some(open) {
+                    return false;
+                }
+            }
+            b'"' => quotes[0] += 1,
+            b'\'' => quotes[1] += 1,
+            b'`' => quotes[2] += 1,
+            _ => (),
+        }
+    }
+    stack.is_empty() && quotes.iter().all(|n| n % 2 == 0)
+}
+
+struct Run<'a> {
+    data: &'a [u8],
+    tokens: &'a [HashedSpan],
+}
+
+impl<'a> Run<'a> {
+    fn text(&self, i: usize) -> &'a [u8] {
+        let token = &self.tokens[i];
+        &self.data[token.lo..token.hi]
+    }
+
+    /// Scores the tokens lo..hi as a refined span.
+    fn score(&self, lo: usize, hi: usize) -> usize {
+        let mut score = 0;
+        if is_balanced((lo..hi).map(|i| self.text(i))) {
+            score += 2;
+        }
+        if lo == 0 || is_blank(self.text(lo - 1)) {
+            score += 1;
+        }
+        if hi == self.tokens.len() || is_blank(self.text(hi)) {
+            score += 1;
+        }
+        score
+    }
+}
+
+/// Slides the refined spans between the shared spans of `snakes`.
+pub fn slide(snakes: &mut Vec<Snake>, data: &[u8], removed: &[HashedSpan], added: &[HashedSpan]) {
+    for i in 1..snakes.len() {
+        let (before, after) = snakes.split_at_mut(i);
+        let (s1, s2) = (&mut before[i - 1], &mut after[0]);
+        let removed_gap = (s1.x0 + s1.len) as usize..s2.x0 as usize;
+        let added_gap = (s1.y0 + s1.len) as usize..s2.y0 as usize;
+        let (tokens, gap) = match (removed_gap.is_empty(), added_gap.is_empty()) {
+            (false, true) => (removed, removed_gap),
+            (true, false) => (added, added_gap),
+            _ => continue,
+        };
+        let run = Run { data, tokens };
+        let (lo, hi) = (gap.start, gap.end);
+        let mut min_shift = 0;
+        while min_shift < s1.len as usize
+            && run.text(lo - min_shift - 1) == run.text(hi - min_shift - 1)
+        {
+            min_shift += 1;
+        }
+        let mut max_shift = 0;
+        while max_shift < s2.len as usize && run.text(lo + max_shift) == run.text(hi + max_shift) {
+            max_shift += 1;
+        }
+        let mut best = (run.score(lo, hi), 0);
+        for shift in -(min_shift as isize)..=max_shift as isize {
+            let score = run.score(
+                (lo as isize + shift) as usize,
+                (hi as isize + shift) as usize,
+            );
+            if best.0 < score {
+                best = (score, shift);
+            }
+        }
+        let shift = best.1;
+        s1.len += shift;
+        s2.x0 += shift;
+        s2.y0 += shift;
+        s2.len -= shift;
+    }
+    snakes.retain(|snake| 0 < snake.len);
+}
@@ -0,0 +1,131 @@
+//! Reordering and splitting of the files of a diff, for --sort-files
+//! and --split-output.
+
+use std::cmp::Reverse;
+use std::path::Path;
+
+use super::{
+    commit, first_after_escape, parse_git_path, parse_hunk_header, parse_new_path, parse_old_path,
+    strip_escape_codes, HunkRemaining,
+};
+
+/// The order of the files of --sort-files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortFiles {
+    /// By path.
+    Path,
+    /// The files with the most changed lines first.
+    Changes,
+    /// Grouped by extension, in the order of the diff.
+    Extension,
+}
+
+/// The lines of a file of the diff, from its first header line.
+#[derive(Default)]
+pub struct Section {
+    pub lines: Vec<u8>,
+    pub path: Option<String>,
+    changes: usize,
+    has_hunks: bool,
+}
+
+impl Section {
+    /// The extension of the path, empty without extension.
+    fn extension(&self) -> String {
+        let path = Path::new(self.path.as_deref().unwrap_or_default());
+        let extension = path.extension().unwrap_or_default();
+        extension.to_string_lossy().into_owned()
+    }
+}
+
+/// A part of a diff: the lines of a file, or the lines outside of the
+/// files, such as the header of a commit of the output of git log.
+pub enum Part {
+    File(Section),
+    Other(Vec<u8>),
+}
+
+/// Splits the diff `data` in the parts of its files and the lines
+/// between them. Only the lines of a commit start a part between two
+/// files: the other lines after a file are part of it.
+pub fn split_files(data: &[u8]) -> Vec<Part> {
+    let mut parts = vec![];
+    let mut remaining: Option<HunkRemaining> = None;
+    let mut stripped = vec![];
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        if let (Some(remaining), Some(Part::File(section))) = (&mut remaining, parts.last_mut()) {
+            if let Some(kind) = remaining.count(first_after_escape(line).or(Some(b'\n'))) {
+                if kind == b'+' || kind == b'-' {
+                    section.changes += 1;
+                }
+                section.lines.extend_from_slice(line);
+                continue;
+            }
+        }
+        remaining = None;
+        stripped.clear();
+        strip_escape_codes(line, &mut stripped);
+        let starts_file = match parts.last() {
+            _ if stripped.starts_with(b"diff ") => true,
+            Some(Part::File(section)) => stripped.starts_with(b"--- ") && section.has_hunks,
+            _ => stripped.starts_with(b"--- "),
+        };
+        if starts_file {
+            parts.push(Part::File(Section::default()));
+        } else if commit::is_commit_start(&stripped) || parts.is_empty() {
+            parts.push(Part::Other(vec![]));
+        }
+        let section = match parts.last_mut() {
+            Some(Part::File(section)) => section,
+            Some(Part::Other(lines)) => {
+                lines.extend_from_slice(line);
+                continue;
+            }
+            None => unreachable!(),
+        };
+        if let Some(header) = parse_hunk_header(&stripped) {
+            section.has_hunks = true;
+            remaining = Some(HunkRemaining::from(header));
+        } else if let Some(paths) = stripped.strip_prefix(b"diff --git ") {
+            section.path = parse_git_path(paths);
+        } else if let Some(path) = stripped.strip_prefix(b"--- ") {
+            section.path = section.path.take().or_else(|| parse_old_path(path));
+        } else if let Some(path) = stripped.strip_prefix(b"+++ ") {
+            section.path = parse_new_path(path).or(section.path.take());
+        }
+        section.lines.extend_from_slice(line);
+    }
+    parts
+}
+
+/// Sorts `sections` by `order`, and appends them to `dst`.
+fn flush(sections: &mut Vec<Section>, order: SortFiles, dst: &mut Vec<u8>) {
+    // the sort is stable: the files keep their order otherwise
+    match order {
+        SortFiles::Path => sections.sort_by(|a, b| a.path.cmp(&b.path)),
+        SortFiles::Changes => sections.sort_by_key(|section| Reverse(section.changes)),
+        SortFiles::Extension => sections.sort_by_key(Section::extension),
+    }
+    for section in sections.drain(..) {
+        dst.extend_from_slice(&section.lines);
+    }
+}
+
+/// Reorders the files of the diff `data` by `order`. The lines before
+/// the first file stay first, and the files of each commit of the
+/// output of git log are sorted separately.
+pub fn sort_files(data: &[u8], order: SortFiles) -> Vec<u8> {
+    let mut sorted = Vec::with_capacity(data.len());
+    let mut sections = vec![];
+    for part in split_files(data) {
+        match part {
+            Part::File(section) => sections.push(section),
+            Part::Other(lines) => {
+                flush(&mut sections, order, &mut sorted);
+                sorted.extend_from_slice(&lines);
+            }
+        }
+    }
+    flush(&mut sections, order, &mut sorted);
+    sorted
+}
@@ -0,0 +1,115 @@
+//! Rendering of each file of a diff in its own file of a directory,
+//! for --split-output.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+
+use super::sort::{self, Part};
+use super::stats::DiffStats;
+use super::{html, render_to, AppConfig, OutputFormat};
+
+/// The name of the file of `path`, with `extension`: the characters
+/// other than letters, digits, '.', '-' and '_' are replaced by '_',
+/// and a number is added if the name is already used.
+fn file_name(path: Option<&str>, extension: &str, used: &mut HashSet<String>) -> String {
+    let sanitized = path
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let stem = match sanitized.trim_start_matches('.') {
+        "" => "diff".to_owned(),
+        _ => sanitized,
+    };
+    let mut name = format!("{}.{}", stem, extension);
+    let mut n = 1;
+    while !used.insert(name.clone()) {
+        n += 1;
+        name = format!("{}-{}.{}", stem, n, extension);
+    }
+    name
+}
+
+/// Groups the parts of a diff by file: the lines between two files,
+/// such as the header of a commit, go with the next file.
+fn files(parts: Vec<Part>) -> Vec<(Option<String>, Vec<u8>)> {
+    let mut files: Vec<(Option<String>, Vec<u8>)> = vec![];
+    let mut pending = vec![];
+    for part in parts {
+        match part {
+            Part::Other(lines) => pending.extend_from_slice(&lines),
+            Part::File(section) => {
+                pending.extend_from_slice(&section.lines);
+                files.push((section.path, std::mem::take(&mut pending)));
+            }
+        }
+    }
+    match files.last_mut() {
+        Some((_, lines)) => lines.extend_from_slice(&pending),
+        None if !pending.is_empty() => files.push((None, pending)),
+        None => (),
+    }
+    files
+}
+
+/// Writes the index page of the files of `entries`, with their name,
+/// their path and their numbers of added and removed lines.
+fn write_index<W: Write>(
+    entries: &[(String, String, usize, usize)],
+    out: &mut W,
+) -> io::Result<()> {
+    out.write_all(
+        b"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>diffr</title>\n</head>\n<body>\n<ul>\n",
+    )?;
+    for (name, path, added, removed) in entries {
+        out.write_all(b"<li><a href=\"")?;
+        html::write_escaped(name.as_bytes(), out)?;
+        out.write_all(b"\">")?;
+        html::write_escaped(path.as_bytes(), out)?;
+        writeln!(out, "</a> +{} -{}</li>", added, removed)?;
+    }
+    out.write_all(b"</ul>\n</body>\n</html>\n")
+}
+
+/// Renders each file of the diff from `input` in `format`, in its own
+/// file of the directory `dir`, with an index page in the HTML format.
+/// Returns the collected statistics.
+pub fn write<Input>(
+    config: &AppConfig,
+    format: OutputFormat,
+    dir: &str,
+    input: &mut Input,
+) -> io::Result<DiffStats>
+where
+    Input: BufRead,
+{
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+    fs::create_dir_all(dir)?;
+    let mut stats = DiffStats::default();
+    let mut used = HashSet::new();
+    used.insert("index.html".to_owned());
+    let mut entries = vec![];
+    for (path, lines) in files(sort::split_files(&data)) {
+        let name = file_name(path.as_deref(), format.extension(), &mut used);
+        let mut file = BufWriter::new(File::create(Path::new(dir).join(&name))?);
+        let colors = config.file_colors;
+        let file_stats = render_to(config.clone(), format, colors, &mut &lines[..], &mut file)?;
+        file.flush()?;
+        let total = file_stats.total();
+        let path = path.unwrap_or_else(|| name.clone());
+        entries.push((name, path, total.added_lines, total.removed_lines));
+        stats.append(file_stats);
+    }
+    if format == OutputFormat::Html {
+        let mut index = BufWriter::new(File::create(Path::new(dir).join("index.html"))?);
+        write_index(&entries, &mut index)?;
+        index.flush()?;
+    }
+    Ok(stats)
+}
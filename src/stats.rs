@@ -0,0 +1,339 @@
+//! Statistics about the processed diff.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+use termcolor::{ColorSpec, WriteColor};
+
+use super::{output, AppConfig};
+
+/// Counts for one file, or for the whole diff.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    pub files: usize,
+    pub hunks: usize,
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    pub added_tokens: usize,
+    pub removed_tokens: usize,
+    pub refined_added_tokens: usize,
+    pub refined_removed_tokens: usize,
+    pub binary: bool,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.files += other.files;
+        self.hunks += other.hunks;
+        self.added_lines += other.added_lines;
+        self.removed_lines += other.removed_lines;
+        self.added_tokens += other.added_tokens;
+        self.removed_tokens += other.removed_tokens;
+        self.refined_added_tokens += other.refined_added_tokens;
+        self.refined_removed_tokens += other.refined_removed_tokens;
+    }
+}
+
+/// The time spent in each phase of the processing, measured with
+/// --debug and --bench.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    pub tokenize: Duration,
+    pub lcs: Duration,
+    pub emit: Duration,
+    pub total: Duration,
+}
+
+impl Timings {
+    /// The time spent reading and parsing the input.
+    pub fn parse(&self) -> Duration {
+        self.total
+            .saturating_sub(self.tokenize + self.lcs + self.emit)
+    }
+
+    pub fn add(&mut self, other: &Timings) {
+        self.tokenize += other.tokenize;
+        self.lcs += other.lcs;
+        self.emit += other.emit;
+        self.total += other.total;
+    }
+
+    /// Divides the timings by `n`, to average several runs.
+    pub fn div(&self, n: u32) -> Timings {
+        Timings {
+            tokenize: self.tokenize / n,
+            lcs: self.lcs / n,
+            emit: self.emit / n,
+            total: self.total / n,
+        }
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let phases = [
+            ("parse", self.parse()),
+            ("tokenize", self.tokenize),
+            ("lcs", self.lcs),
+            ("emit", self.emit),
+            ("total", self.total),
+        ];
+        for (phase, duration) in &phases {
+            writeln!(
+                f,
+                "{} time (ms): {:.3}",
+                phase,
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The statistics of each file of the diff.
+#[derive(Debug, Default)]
+pub struct DiffStats {
+    pub files: Vec<(String, Stats)>,
+    /// The number of hunk headers that could not be parsed.
+    pub malformed_headers: usize,
+    /// The number of hunks that do not apply, with --apply-preview.
+    pub rejected_hunks: usize,
+    pub timings: Timings,
+}
+
+impl DiffStats {
+    pub fn start_file(&mut self, path: String) {
+        let stats = Stats {
+            files: 1,
+            ..Stats::default()
+        };
+        self.files.push((path, stats))
+    }
+
+    /// Get the statistics of the current file.
+    pub fn current(&mut self) -> &mut Stats {
+        if self.files.is_empty() {
+            self.start_file("<unknown>".to_owned());
+        }
+        let last = self.files.len() - 1;
+        &mut self.files[last].1
+    }
+
+    /// The exit status for --exit-code: 0 without changes, 1 with
+    /// changes, 2 if some hunk headers could not be parsed.
+    pub fn exit_code(&self) -> i32 {
+        if 0 < self.malformed_headers {
+            2
+        } else if self
+            .files
+            .iter()
+            .any(|(_, stats)| 0 < stats.hunks || stats.binary)
+        {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Appends the statistics of `other`, collected on the files that
+    /// follow.
+    pub fn append(&mut self, other: DiffStats) {
+        self.files.extend(other.files);
+        self.malformed_headers += other.malformed_headers;
+        self.rejected_hunks += other.rejected_hunks;
+        self.timings.add(&other.timings);
+    }
+
+    pub fn total(&self) -> Stats {
+        let mut total = Stats::default();
+        for (_, stats) in &self.files {
+            total.add(stats);
+        }
+        total
+    }
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, word)
+    } else {
+        format!("{} {}s", n, word)
+    }
+}
+
+fn describe(stats: &Stats) -> String {
+    format!(
+        "{}, +{} -{} lines, +{} -{} refined tokens (out of +{} -{})",
+        plural(stats.hunks, "hunk"),
+        stats.added_lines,
+        stats.removed_lines,
+        stats.refined_added_tokens,
+        stats.refined_removed_tokens,
+        stats.added_tokens,
+        stats.removed_tokens,
+    )
+}
+
+/// Writes a summary line for each file, then for the whole diff.
+pub fn output_stats<Stream>(stats: &DiffStats, out: &mut Stream) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let face = ColorSpec::default();
+    for (path, file_stats) in &stats.files {
+        let line = if file_stats.binary {
+            format!("{}: binary\n", path)
+        } else {
+            format!("{}: {}\n", path, describe(file_stats))
+        };
+        output(line.as_bytes(), &face, out)?;
+    }
+    let total = stats.total();
+    let line = format!(
+        "total: {}, {}\n",
+        plural(total.files, "file"),
+        describe(&total)
+    );
+    output(line.as_bytes(), &face, out)
+}
+
+/// The maximal width of the bars of the diffstat.
+const DIFFSTAT_WIDTH: usize = 50;
+
+/// Writes a histogram of the changed lines of each file, like `git
+/// diff --stat`.
+pub fn output_diffstat<Stream>(
+    stats: &DiffStats,
+    config: &AppConfig,
+    out: &mut Stream,
+) -> io::Result<()>
+where
+    Stream: WriteColor,
+{
+    let face = ColorSpec::default();
+    let path_width = stats
+        .files
+        .iter()
+        .map(|(path, _)| path.len())
+        .max()
+        .unwrap_or(0);
+    let changes = |stats: &Stats| stats.added_lines + stats.removed_lines;
+    let max_changes = stats
+        .files
+        .iter()
+        .map(|(_, stats)| changes(stats))
+        .max()
+        .unwrap_or(0);
+    let mut count_width = max_changes.to_string().len();
+    if stats.files.iter().any(|(_, stats)| stats.binary) {
+        count_width = count_width.max(3);
+    }
+    let scale = |n: usize| {
+        if max_changes <= DIFFSTAT_WIDTH || n == 0 {
+            n
+        } else {
+            (n * DIFFSTAT_WIDTH / max_changes).max(1)
+        }
+    };
+    for (path, file_stats) in &stats.files {
+        let count = if file_stats.binary {
+            "Bin".to_owned()
+        } else {
+            changes(file_stats).to_string()
+        };
+        let line = format!(
+            " {:path_width$} | {:>count_width$} ",
+            path,
+            count,
+            path_width = path_width,
+            count_width = count_width
+        );
+        output(line.as_bytes(), &face, out)?;
+        let added = "+".repeat(scale(file_stats.added_lines));
+        output(added.as_bytes(), &config.added_face, out)?;
+        let removed = "-".repeat(scale(file_stats.removed_lines));
+        output(removed.as_bytes(), &config.removed_face, out)?;
+        output(b"\n", &face, out)?;
+    }
+    let total = stats.total();
+    let line = format!(
+        " {} changed, {} insertion{}(+), {} deletion{}(-)\n",
+        plural(total.files, "file"),
+        total.added_lines,
+        if total.added_lines == 1 { "" } else { "s" },
+        total.removed_lines,
+        if total.removed_lines == 1 { "" } else { "s" },
+    );
+    output(line.as_bytes(), &face, out)
+}
+
+/// Writes the totals of `stats` and the time `elapsed` to the file
+/// `path`, for --metrics-file: in the text format of Prometheus if its
+/// extension is '.prom', and as a JSON object otherwise.
+pub fn write_metrics_file(stats: &DiffStats, elapsed: Duration, path: &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    if path.ends_with(".prom") {
+        write_prometheus(stats, elapsed, &mut out)?;
+    } else {
+        write_json(stats, elapsed, &mut out)?;
+    }
+    out.flush()
+}
+
+/// The metrics of --metrics-file: their names and descriptions, and
+/// their values.
+fn metrics(stats: &DiffStats) -> [(&'static str, &'static str, usize); 8] {
+    let total = stats.total();
+    [
+        ("files", "Files of the diff.", total.files),
+        ("hunks", "Hunks of the diff.", total.hunks),
+        ("added_lines", "Added lines.", total.added_lines),
+        ("removed_lines", "Removed lines.", total.removed_lines),
+        (
+            "added_tokens",
+            "Tokens of the added lines.",
+            total.added_tokens,
+        ),
+        (
+            "removed_tokens",
+            "Tokens of the removed lines.",
+            total.removed_tokens,
+        ),
+        (
+            "refined_added_tokens",
+            "Highlighted tokens of the added lines.",
+            total.refined_added_tokens,
+        ),
+        (
+            "refined_removed_tokens",
+            "Highlighted tokens of the removed lines.",
+            total.refined_removed_tokens,
+        ),
+    ]
+}
+
+fn write_json<W: Write>(stats: &DiffStats, elapsed: Duration, out: &mut W) -> io::Result<()> {
+    out.write_all(b"{")?;
+    for (name, _, value) in &metrics(stats) {
+        write!(out, "\"{}\":{},", name, value)?;
+    }
+    writeln!(out, "\"elapsed_us\":{}}}", elapsed.as_micros())
+}
+
+fn write_prometheus<W: Write>(stats: &DiffStats, elapsed: Duration, out: &mut W) -> io::Result<()> {
+    let mut write_metric = |name: &str, help: &str, value: &dyn Display| {
+        writeln!(out, "# HELP diffr_{} {}", name, help)?;
+        writeln!(out, "# TYPE diffr_{} gauge", name)?;
+        writeln!(out, "diffr_{} {}", name, value)
+    };
+    for (name, help, value) in &metrics(stats) {
+        write_metric(name, help, value)?;
+    }
+    let seconds = elapsed.as_secs_f64();
+    write_metric(
+        "elapsed_seconds",
+        "Time spent rendering the diff.",
+        &seconds,
+    )
+}
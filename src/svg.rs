@@ -0,0 +1,120 @@
+//! Rendering of the output of diffr as an SVG image, for --output.
+
+use std::io::{self, Write};
+use termcolor::ColorSpec;
+use unicode_width::UnicodeWidthStr;
+
+use super::{html, pager};
+
+const FONT_SIZE: usize = 14;
+/// The width of the cells of the monospace font, in tenths of pixels.
+const CELL_WIDTH: usize = 84;
+const LINE_HEIGHT: usize = 18;
+const MARGIN: usize = 8;
+const TAB_WIDTH: usize = 8;
+
+/// The text of a run starting at `column`, with tabs expanded, and
+/// its width in columns. The wide characters take 2 columns.
+fn expand(text: &[u8], column: usize) -> (Vec<u8>, usize) {
+    let mut expanded = vec![];
+    let mut width = 0;
+    for chunk in String::from_utf8_lossy(text).split_inclusive('\t') {
+        let (chunk, tab) = match chunk.strip_suffix('\t') {
+            Some(chunk) => (chunk, true),
+            None => (chunk, false),
+        };
+        expanded.extend_from_slice(chunk.as_bytes());
+        width += chunk.width();
+        if tab {
+            let spaces = TAB_WIDTH - (column + width) % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(b' ', spaces));
+            width += spaces;
+        }
+    }
+    (expanded, width)
+}
+
+/// The attributes of a `<tspan>` painted with `face`.
+fn text_attributes(face: &ColorSpec) -> String {
+    let mut attributes = String::new();
+    if let Some(fg) = face.fg() {
+        attributes += &format!(" fill=\"{}\"", html::css_color(fg, face.intense()));
+    }
+    if face.bold() {
+        attributes += " font-weight=\"bold\"";
+    }
+    if face.italic() {
+        attributes += " font-style=\"italic\"";
+    }
+    if face.dimmed() {
+        attributes += " opacity=\"0.6\"";
+    }
+    match (face.underline(), face.strikethrough()) {
+        (true, true) => attributes += " text-decoration=\"underline line-through\"",
+        (true, false) => attributes += " text-decoration=\"underline\"",
+        (false, true) => attributes += " text-decoration=\"line-through\"",
+        (false, false) => (),
+    }
+    attributes
+}
+
+/// Formats a length in tenths of pixels.
+fn px(tenths: usize) -> String {
+    format!("{}.{}", tenths / 10, tenths % 10)
+}
+
+/// Writes `rendered`, the output of diffr, as an SVG image of a
+/// terminal with a monospace font on a white background.
+pub fn write<W: Write>(rendered: &[u8], out: &mut W) -> io::Result<()> {
+    let (lines, _) = pager::split_lines(rendered);
+    let mut backgrounds = vec![];
+    let mut texts = vec![];
+    let mut max_columns = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let y = MARGIN + i * LINE_HEIGHT;
+        let mut column = 0;
+        let mut text = vec![];
+        for (face, run) in html::runs(line) {
+            let (run, width) = expand(run, column);
+            let x = 10 * MARGIN + column * CELL_WIDTH;
+            if let Some(bg) = face.bg() {
+                writeln!(
+                    backgrounds,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    px(x),
+                    y,
+                    px(width * CELL_WIDTH),
+                    LINE_HEIGHT,
+                    html::css_color(bg, face.intense())
+                )?;
+            }
+            write!(text, "<tspan x=\"{}\"{}>", px(x), text_attributes(&face))?;
+            html::write_escaped(&run, &mut text)?;
+            text.extend_from_slice(b"</tspan>");
+            column += width;
+        }
+        max_columns = max_columns.max(column);
+        if !text.is_empty() {
+            write!(texts, "<text y=\"{}\">", y + LINE_HEIGHT * 3 / 4 + 1)?;
+            texts.extend(text);
+            texts.extend_from_slice(b"</text>\n");
+        }
+    }
+    let width = 10 * 2 * MARGIN + max_columns * CELL_WIDTH;
+    let height = 2 * MARGIN + lines.len() * LINE_HEIGHT;
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"{}\" xml:space=\"preserve\">",
+        px(width),
+        height,
+        FONT_SIZE
+    )?;
+    writeln!(
+        out,
+        "<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>"
+    )?;
+    out.write_all(&backgrounds)?;
+    out.write_all(&texts)?;
+    writeln!(out, "</svg>")
+}
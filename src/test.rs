@@ -1,10 +1,44 @@
 use super::*;
+use diffr_lib::{DiffInput, EditKind, Tokenization};
+use std::io::{Read, Write};
 
 #[test]
 fn skip_all_escape_code_test() {
     assert_eq!(5, skip_all_escape_code(b"\x1b[42m@@@"));
     assert_eq!(10, skip_all_escape_code(b"\x1b[42m\x1b[33m@@@"));
-    assert_eq!(0, skip_all_escape_code(b"\x1b[42@@@"));
+    assert_eq!(0, skip_all_escape_code(b"\x1b[42\x1b@@@"));
+}
+
+#[test]
+fn skip_all_escape_code_csi_test() {
+    assert_eq!(3, skip_all_escape_code(b"\x1b[K+abc"));
+    assert_eq!(6, skip_all_escape_code(b"\x1b[2;3H@@@"));
+    assert_eq!(0, skip_all_escape_code(b"\x1b[2;3"));
+    assert_eq!(4, skip_all_escape_code(b"\x1b[2@abc"));
+    assert_eq!(5, skip_all_escape_code(b"\x1b[15~abc"));
+    assert_eq!(3, skip_all_escape_code(b"\x1b[`abc"));
+}
+
+#[test]
+fn skip_all_escape_code_osc_test() {
+    assert_eq!(14, skip_all_escape_code(b"\x1b]8;;url\x1b\\\x1b[0m@@"));
+    assert_eq!(9, skip_all_escape_code(b"\x1b]8;;url\x07@@"));
+    assert_eq!(0, skip_all_escape_code(b"\x1b]8;;url"));
+}
+
+#[test]
+fn input_face_test() {
+    assert_eq!(
+        color_spec(Some(Green), None, true),
+        input_face(b"\x1b[1;32m+abc")
+    );
+    assert_eq!(
+        Some(&Color::Ansi256(208)),
+        input_face(b"\x1b[38;5;208m-abc").fg()
+    );
+    assert!(input_face(b"\x1b[31m\x1b[m-abc").is_none());
+    assert!(input_face(b"\x1b[9;2m-abc").strikethrough());
+    assert!(!input_face(b"\x1b[2;22m-abc").dimmed());
 }
 
 #[test]
@@ -14,8 +48,6 @@ fn first_after_escape_test() {
     assert_eq!(None, first_after_escape(b"\x1b[42m"));
 }
 
-// TODO test index_of?
-
 #[test]
 fn skip_token_test() {
     assert_eq!(4, skip_token(b"abc\x1b"));
@@ -24,3 +56,1707 @@ fn skip_token_test() {
     assert_eq!(1, skip_token(b"\x1b"));
     assert_eq!(0, skip_token(b""));
 }
+
+#[test]
+fn scan_test() {
+    let mut input: &[u8] = b"+a\n\n-b";
+    let mut line = vec![];
+    assert_eq!(3, scan::read_line(&mut input, &mut line).unwrap());
+    assert_eq!(b"+a\n", &line[..]);
+    assert_eq!(1, scan::read_line(&mut input, &mut line).unwrap());
+    assert_eq!(b"+a\n\n", &line[..]);
+    line.clear();
+    assert_eq!(2, scan::read_line(&mut input, &mut line).unwrap());
+    assert_eq!(b"-b", &line[..]);
+    assert_eq!(0, scan::read_line(&mut input, &mut line).unwrap());
+
+    assert_eq!(3, scan::count_lines(b"+a\n\n-b"));
+    assert_eq!(2, scan::count_lines(b"+a\n\n"));
+    assert_eq!(0, scan::count_lines(b""));
+
+    assert_eq!(Some(3), scan::find_escape(b"a\x1bb\x1b]8;;\x07"));
+    assert_eq!(None, scan::find_escape(b"a\x1b"));
+
+    assert_eq!(
+        13,
+        scan::passthrough_len(b"    message\n\ndiff --git a/x b/x\n", false)
+    );
+    assert_eq!(
+        2,
+        scan::passthrough_len(b"a\n\x1b[33mcommit 1\x1b[m\n", false)
+    );
+    assert_eq!(
+        16,
+        scan::passthrough_len(b"\x1b[31ma\x1b[m\nb\x1b[1m\n\x1b[1m@@\n", false)
+    );
+    assert_eq!(2, scan::passthrough_len(b"a\nincomplete", false));
+    assert_eq!(0, scan::passthrough_len(b"@@ -1 +1 @@\n", false));
+    assert_eq!(10, scan::passthrough_len(b"Author: a\n", false));
+    assert_eq!(0, scan::passthrough_len(b"Author: a\n", true));
+}
+
+#[test]
+fn pager_test() {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    let output =
+        b"diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -1 +1 @@\n-a\n+b\n@@ -5 +5 @@\n-c\n+d\n";
+    let mut pager = pager::Pager::new(output);
+    pager.height = 3;
+    assert_eq!(vec![0, 1, 3, 6], pager.boundaries);
+    let press = |pager: &mut pager::Pager, c| {
+        let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        pager.message.clear();
+        pager.handle_key(key, &mut io::sink()).unwrap()
+    };
+    let mut tops = vec![];
+    for c in "jkk G g".chars() {
+        assert!(press(&mut pager, c));
+        tops.push(pager.top);
+    }
+    assert_eq!(vec![1, 0, 0, 3, 6, 6, 0], tops);
+
+    let mut tops = vec![];
+    for c in "nnn".chars() {
+        press(&mut pager, c);
+        tops.push(pager.top);
+    }
+    assert_eq!(vec![1, 3, 6], tops);
+    press(&mut pager, 'n');
+    assert_eq!("no next file or hunk", pager.message);
+    press(&mut pager, 'p');
+    assert_eq!(3, pager.top);
+    assert!(pager.message.is_empty());
+    assert!(!press(&mut pager, 'q'));
+
+    pager.top = 0;
+    pager.search = b"+d".to_vec();
+    pager.search_forward();
+    assert_eq!(6, pager.top);
+    pager.top = 0;
+    pager.search = b"zzz".to_vec();
+    pager.search_forward();
+    assert_eq!(0, pager.top);
+    assert_eq!("pattern not found: zzz", pager.message);
+}
+
+#[test]
+fn tui_test() {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use tui::Row::*;
+    let output = b"diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -1,2 +1,2 @@\n x\n-a\n+b\n\
+diff --git a/c b/c\n@@ -1 +1 @@\n-c\n+d\n";
+    let mut tui = tui::Tui::new(output);
+    let press = |tui: &mut tui::Tui, c| tui.handle_key(KeyEvent::new(c, KeyModifiers::NONE));
+    assert_eq!(
+        vec![
+            File(0),
+            Header(0, 1),
+            Header(0, 2),
+            Hunk(0, 0),
+            File(1),
+            Hunk(1, 0)
+        ],
+        tui.rows
+    );
+    for _ in 0..3 {
+        assert!(press(&mut tui, KeyCode::Char('j')));
+    }
+    press(&mut tui, KeyCode::Char(' '));
+    assert_eq!(3, tui.cursor);
+    assert_eq!(
+        vec![
+            Hunk(0, 0),
+            Context(0, 0, 1),
+            Body(0, 0, 5),
+            Body(0, 0, 6),
+            File(1)
+        ],
+        tui.rows[3..8]
+    );
+    // unfolding the context lines keeps the cursor on the hunk
+    press(&mut tui, KeyCode::Down);
+    press(&mut tui, KeyCode::Enter);
+    assert_eq!(3, tui.cursor);
+    assert_eq!(
+        vec![Hunk(0, 0), Body(0, 0, 4), Body(0, 0, 5), Body(0, 0, 6)],
+        tui.rows[3..7]
+    );
+    press(&mut tui, KeyCode::Char('h'));
+    assert_eq!(vec![Hunk(0, 0), File(1)], tui.rows[3..5]);
+    press(&mut tui, KeyCode::Char('C'));
+    assert_eq!(vec![File(0), File(1)], tui.rows);
+    assert_eq!(0, tui.cursor);
+    press(&mut tui, KeyCode::Char('E'));
+    assert_eq!(11, tui.rows.len());
+    press(&mut tui, KeyCode::End);
+    assert_eq!(10, tui.cursor);
+    press(&mut tui, KeyCode::Char('g'));
+    assert_eq!(0, tui.cursor);
+    assert!(!press(&mut tui, KeyCode::Char('q')));
+}
+
+#[test]
+fn parse_hunk_header_test() {
+    let header = HunkHeader {
+        old_start: 12,
+        old_len: 3,
+        new_start: 12,
+        new_len: 1,
+    };
+    assert_eq!(
+        Some(header),
+        parse_hunk_header(b"@@ -12,3 +12 @@ fn main() {")
+    );
+    assert_eq!(None, parse_hunk_header(b"@@ -12,3 12 @@"));
+    assert_eq!(None, parse_hunk_header(b"@@@ -1 -1 +1 @@@"));
+}
+
+#[test]
+fn gutter_width_test() {
+    let header = parse_hunk_header(b"@@ -1,3 +98,4 @@").unwrap();
+    let width = |old, new| GutterWidth { old, new };
+    assert_eq!(
+        width(3, 3),
+        header.gutter_width(LineNumberStyle::Compact, 0)
+    );
+    assert_eq!(
+        width(4, 4),
+        header.gutter_width(LineNumberStyle::Aligned, 0)
+    );
+    assert_eq!(width(1, 3), header.gutter_width(LineNumberStyle::Both, 0));
+    assert_eq!(width(2, 3), header.gutter_width(LineNumberStyle::Both, 2));
+    assert_eq!(
+        width(0, 3),
+        header.gutter_width(LineNumberStyle::RightOnly, 0)
+    );
+}
+
+#[test]
+fn format_line_numbers_test() {
+    let width = GutterWidth { old: 2, new: 3 };
+    let format = |template: &str, old, new| {
+        let template = parse_line_numbers_format(template).unwrap();
+        format_line_numbers(&template, old, new, width)
+    };
+    assert_eq!(" 9  10 | ", format("{old} {new} | ", Some(9), Some(10)));
+    assert_eq!("9    |   ", format("{old:<4} | {new:>2}", Some(9), None));
+    assert_eq!("{10} ", format("{{{new:<0}}} ", None, Some(10)));
+    assert_eq!(" 10  |", format("{new:^5}|", None, Some(10)));
+    assert_eq!("  1", format("{old:3}", Some(1), None));
+    let error = |template: &str| parse_line_numbers_format(template).unwrap_err();
+    assert!(error("{bogus}").ends_with("got '{bogus}', expected {old} or {new}"));
+    assert!(error("{old").ends_with("unclosed '{'"));
+    assert!(error("old}").ends_with("unmatched '}', written '}}' as text"));
+    assert!(error("{new:<x}").ends_with("invalid width 'x'"));
+}
+
+#[test]
+fn split_function_context_test() {
+    assert_eq!(
+        Some((&b"@@ -12,3 +12 @@ "[..], &b"fn main() {"[..])),
+        split_function_context(b"@@ -12,3 +12 @@ fn main() {\n")
+    );
+    assert_eq!(None, split_function_context(b"@@ -12,3 +12 @@\n"));
+    assert_eq!(None, split_function_context(b"@@ -12,3 +12 @@ \n"));
+    assert_eq!(None, split_function_context(b"@@ -12,3 12 @@ fn main() {"));
+}
+
+#[test]
+fn parse_new_path_test() {
+    assert_eq!(
+        Some("src/main.rs".to_owned()),
+        parse_new_path(b"b/src/main.rs\n")
+    );
+    assert_eq!(
+        Some("main.rs".to_owned()),
+        parse_new_path(b"main.rs\t2019-09-07 10:00:00.000000000 +0200\n")
+    );
+    assert_eq!(None, parse_new_path(b"/dev/null\n"));
+}
+
+#[test]
+fn matches_path_test() {
+    use filter::matches_path;
+    assert!(matches_path("*.rs", "src/main.rs"));
+    assert!(!matches_path("src/*.rs", "src/bin/main.rs"));
+    assert!(matches_path("src/**.rs", "src/bin/main.rs"));
+    assert!(matches_path("src/**/*.rs", "src/bin/main.rs"));
+    assert!(matches_path("src/**/*.rs", "src/main.rs"));
+    assert!(matches_path("**/*.rs", "main.rs"));
+    assert!(!matches_path("src/**/*.rs", "srcmain.rs"));
+    assert!(matches_path("Cargo.lo?k", "Cargo.lock"));
+    assert!(!matches_path("Cargo.lock", "Cargo.lock.orig"));
+}
+
+#[test]
+fn file_filter_generated_test() {
+    use filter::{Collapsed, FileFilter, Filtered};
+    let mut filter = FileFilter::new(&[], &[], &["*.min.js".to_owned()]);
+    let push = |filter: &mut FileFilter, line: &str| {
+        let filtered = filter.push(line.as_bytes(), line.as_bytes());
+        filtered
+            .into_iter()
+            .map(|item| match item {
+                Filtered::Line(line) => String::from_utf8(line).unwrap(),
+                Filtered::Skipped(path) => format!("skipped {}", path),
+                Filtered::Generated(Collapsed {
+                    path,
+                    added,
+                    removed,
+                }) => format!("generated {} {} {}", path, added, removed),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(push(&mut filter, "--- a/x.min.js").is_empty());
+    assert!(push(&mut filter, "+++ b/x.min.js").is_empty());
+    assert!(filter.is_skipping());
+    assert!(push(&mut filter, "@@ -1 +1,2 @@").is_empty());
+    for kind in [b'-', b'+', b'+'] {
+        filter.count(Some(kind));
+    }
+    // a line after the file ends it
+    assert_eq!(
+        vec!["generated x.min.js 2 1", "commit 2"],
+        push(&mut filter, "commit 2")
+    );
+    assert!(!filter.is_skipping());
+    assert!(push(&mut filter, "--- a/y.js").is_empty());
+    assert_eq!(
+        vec!["--- a/y.js", "+++ b/y.js"],
+        push(&mut filter, "+++ b/y.js")
+    );
+    assert!(filter.finish().is_empty());
+}
+
+#[test]
+fn tokenize_unicode_test() {
+    let check = |input: &str, expected: &[&str]| {
+        let mut tokens = vec![];
+        tokenize::tokenize(input.as_bytes(), 0, &mut tokens);
+        let actual = tokens
+            .iter()
+            .map(|span| &input[span.lo..span.hi])
+            .collect::<Vec<_>>();
+        assert_eq!(expected, &actual[..]);
+    };
+    check("foo.bar(x)", &["foo", ".", "bar", "(", "x", ")"]);
+    check("café au lait", &["café", " ", "au", " ", "lait"]);
+    check("日本語", &["日", "本", "語"]);
+    check("e\u{301}t\u{e9}", &["e\u{301}t\u{e9}"]);
+}
+
+#[test]
+fn tokenize_invalid_utf8_test() {
+    let input = b"a\xffb";
+    let mut tokens = vec![];
+    tokenize::tokenize(input, 0, &mut tokens);
+    let actual = tokens
+        .iter()
+        .map(|span| &input[span.lo..span.hi])
+        .collect::<Vec<_>>();
+    assert_eq!(vec![&b"a"[..], &b"\xff"[..], &b"b"[..]], actual);
+}
+
+#[test]
+fn parse_binary_files_path_test() {
+    let parse = |buf: &[u8]| parse_binary_files_path(buf);
+    assert_eq!(
+        Some("img.png".to_owned()),
+        parse(b"Binary files a/img.png and b/img.png differ")
+    );
+    assert_eq!(
+        Some("old.png".to_owned()),
+        parse(b"Binary files a/old.png and /dev/null differ\n")
+    );
+    assert_eq!(None, parse(b"Binary files differ"));
+    assert_eq!(Some("x y".to_owned()), parse_git_path(b"a/x y b/x y"));
+}
+
+#[test]
+fn layout_tabs_test() {
+    let expand = |buf: &[u8], tabs| {
+        let config = AppConfig {
+            tabs,
+            ..AppConfig::default()
+        };
+        let mut layout = Layout::new(&config);
+        let laid_out = layout.apply(buf).into_owned();
+        (String::from_utf8(laid_out).unwrap(), layout.column)
+    };
+    assert_eq!(("+a\tb".to_owned(), 0), expand(b"+a\tb", None));
+    assert_eq!(("+a   b".to_owned(), 6), expand(b"+a\tb", Some(4)));
+    assert_eq!(("+abcd    b".to_owned(), 10), expand(b"+abcd\tb", Some(4)));
+    assert_eq!(
+        ("+é   b".to_owned(), 6),
+        expand("+é\tb".as_bytes(), Some(4))
+    );
+    assert_eq!(
+        ("+字  b".to_owned(), 6),
+        expand("+字\tb".as_bytes(), Some(4))
+    );
+    assert_eq!(
+        ("\x1b[32m+\x1b[0m    b".to_owned(), 6),
+        expand(b"\x1b[32m+\x1b[0m\tb", Some(4))
+    );
+}
+
+#[test]
+fn layout_truncation_test() {
+    let config = AppConfig {
+        max_line_length: Some(4),
+        truncation_marker: ">".to_owned(),
+        ..AppConfig::default()
+    };
+    let truncate = |segments: &[&str]| {
+        let mut layout = Layout::new(&config);
+        let mut laid_out = vec![];
+        for segment in segments {
+            laid_out.extend_from_slice(&layout.apply(segment.as_bytes()));
+        }
+        String::from_utf8(laid_out).unwrap()
+    };
+    assert_eq!("+abc\n", truncate(&["+abc\n"]));
+    assert_eq!("+abc>\n", truncate(&["+abcd\n"]));
+    assert_eq!("+aéc>\n", truncate(&["+a", "éc", "def\n"]));
+    assert_eq!("+abc>\x1b[0m", truncate(&["+abcd\x1b[0m"]));
+    // the wide characters take 2 columns
+    assert_eq!("+a字>\n", truncate(&["+a字b\n"]));
+    assert_eq!("+ab>\n", truncate(&["+ab字\n"]));
+    let mut layout = Layout::new(&config);
+    assert_eq!(Some(4), layout.truncation_index(b"+abcd\n"));
+}
+
+#[test]
+fn layout_ruler_test() {
+    let config = AppConfig {
+        ruler: Some(3),
+        ..AppConfig::default()
+    };
+    let ruled = |line: &[u8]| {
+        let mut layout = Layout::new(&config);
+        let overlong = layout.overlong_index(line);
+        layout.apply(line);
+        (overlong, layout.ruler_padding())
+    };
+    assert_eq!((None, Some(2)), ruled(b"+a"));
+    assert_eq!((None, Some(0)), ruled(b"+abc"));
+    assert_eq!((Some(4), None), ruled(b"+abcde"));
+    assert_eq!((Some(5), None), ruled("+aéc!".as_bytes()));
+    assert_eq!((Some(5), None), ruled("+字c!".as_bytes()));
+    assert_eq!((None, Some(1)), ruled("+字".as_bytes()));
+    // only the added lines are overlong
+    assert_eq!((None, None), ruled(b"-abcde"));
+}
+
+#[test]
+fn parse_git_face_test() {
+    use git_config::parse_git_face;
+    assert_eq!(
+        Ok(color_spec(Some(Red), None, true)),
+        parse_git_face("bold red")
+    );
+    assert_eq!(
+        Ok(color_spec(
+            Some(Color::Ansi256(208)),
+            Some(Color::Rgb(0, 0x33, 0xff)),
+            false
+        )),
+        parse_git_face("208 #0033FF")
+    );
+    let face = parse_git_face("normal brightblack nodim ul").unwrap();
+    assert_eq!(None, face.fg());
+    assert_eq!(Some(&Color::Black), face.bg());
+    assert!(face.underline());
+    assert!(parse_git_face("red green blue").is_err());
+    assert!(parse_git_face("reed").is_err());
+    assert!(parse_git_face("bright#0033ff").is_ok());
+    assert!(parse_git_face("#0033f").is_err());
+}
+
+#[test]
+fn apply_git_config_test() {
+    let mut config = AppConfig::default();
+    let list = "user.name=someone
+color.diff.new=blue
+diffr.refine-removed=background:yellow
+";
+    assert_eq!(Ok(()), git_config::apply_git_config(&mut config, list));
+    assert_eq!(
+        color_spec(Some(Color::Blue), None, false),
+        config.added_face
+    );
+    assert_eq!(Some(&Color::Yellow), config.refine_removed_face.bg());
+    assert_eq!(
+        Ok(()),
+        git_config::apply_git_config(&mut config, "diffr.foo=bold")
+    );
+    assert!(git_config::apply_git_config(&mut config, "diffr.added=bolder").is_err());
+}
+
+#[test]
+fn theme_file_test() {
+    let text = "# shared theme
+name = 1
+[faces]
+added = \"foreground:blue\" # a comment
+'refine-removed' = 'background:10,20,30:bold'
+[meta]
+author = 'someone'
+";
+    assert_eq!(
+        Ok(vec![
+            "added:foreground:blue".to_owned(),
+            "refine-removed:background:10,20,30:bold".to_owned()
+        ]),
+        theme_file::parse(text)
+    );
+    assert!(theme_file::parse("[faces]\nadded = blue").is_err());
+    assert!(theme_file::parse("[faces]\nadded").is_err());
+
+    // a written theme gives the same faces to any configuration
+    let mut config = AppConfig {
+        added_face: color_spec(Some(Color::Rgb(1, 2, 3)), None, true),
+        ..AppConfig::default()
+    };
+    config
+        .removed_face
+        .set_italic(true)
+        .set_bg(Some(Color::Ansi256(52)));
+    let mut theme = vec![];
+    theme_file::write(&config, &mut theme).unwrap();
+    let specs = theme_file::parse(&String::from_utf8(theme).unwrap()).unwrap();
+    let mut loaded = AppConfig::default();
+    palette::set_light(&mut loaded);
+    cli_args::parse_color_args(&mut loaded, specs.iter().map(String::as_str)).unwrap();
+    assert_eq!(cli_args::faces(&config), cli_args::faces(&loaded));
+}
+
+#[test]
+fn split_shell_words_test() {
+    use cli_args::split_shell_words;
+    let split = |input| split_shell_words(input).unwrap();
+    assert_eq!(vec!["--tabs", "4"], split(" --tabs  4 "));
+    assert_eq!(
+        vec!["a b", "c\"d", "e'f", "g\\h"],
+        split(r#"'a b' c\"d "e'f" "g\h""#)
+    );
+    assert_eq!(vec!["", "ab"], split("'' a'b'"));
+    assert!(split_shell_words("'a").is_err());
+    assert!(split_shell_words("a\\").is_err());
+}
+
+#[test]
+fn profile_test() {
+    let text = "# comment
+[profile.review]
+--line-numbers --tabs 4
+
+[profile.presentation]
+--theme 'deuteranopia'
+[profile.review]
+--hyperlinks
+";
+    assert_eq!(
+        Ok(Some(vec![
+            "--line-numbers".to_owned(),
+            "--tabs".to_owned(),
+            "4".to_owned(),
+            "--hyperlinks".to_owned()
+        ])),
+        profile::parse(text, "review")
+    );
+    assert_eq!(
+        Ok(Some(vec!["--theme".to_owned(), "deuteranopia".to_owned()])),
+        profile::parse(text, "presentation")
+    );
+    assert_eq!(Ok(Some(vec![])), profile::parse("[profile.empty]", "empty"));
+    assert_eq!(Ok(None), profile::parse(text, "other"));
+    assert!(profile::parse("--tabs 4", "review").is_err());
+    assert!(profile::parse("[profile.review]\n'a", "review").is_err());
+    assert_eq!(
+        Err("line 3: unknown section 'profiles.review'".to_owned()),
+        profile::parse("[profile.a]\n--tabs 4\n[profiles.review]\n", "a")
+    );
+    assert_eq!(
+        Err("line 2: expected an option, got 'tabs'".to_owned()),
+        profile::parse("[profile.other]\ntabs 4\n[profile.review]\n", "review")
+    );
+}
+
+#[test]
+fn profile_file_faces_test() {
+    let text = "[profile.review]
+--hyperlinks
+[faces.*.md]
+refine-added:italic
+'refine-removed:italic'
+[faces.Cargo.lock]
+added:dim removed:dim
+";
+    let faces = |glob: &str, colors: &[&str]| profile::FileFaces {
+        glob: glob.to_owned(),
+        colors: colors.iter().map(|&color| color.to_owned()).collect(),
+    };
+    assert_eq!(
+        Ok(vec![
+            faces("*.md", &["refine-added:italic", "refine-removed:italic"]),
+            faces("Cargo.lock", &["added:dim", "removed:dim"]),
+        ]),
+        profile::parse_file_faces(text)
+    );
+    assert_eq!(
+        Ok(Some(vec!["--hyperlinks".to_owned()])),
+        profile::parse(text, "review")
+    );
+    assert!(profile::parse_file_faces("[faces.x]\n'a").is_err());
+    assert!(profile::parse_file_faces("[colors]\nadded:dim").is_err());
+    let text = "[faces]\nadded = \"none:dim\"\n[faces.*.md]\nadded:bold\n";
+    assert_eq!(
+        Ok(vec!["added:none:dim".to_owned()]),
+        profile::parse_faces(text)
+    );
+    assert_eq!(
+        Ok(vec![faces("*.md", &["added:bold"])]),
+        profile::parse_file_faces(text)
+    );
+    assert!(profile::parse_faces("[faces]\nadded:dim").is_err());
+    assert!(profile::parse_file_faces("added:dim\n[faces.x]").is_err());
+}
+
+/// A console which is colored through calls rather than escape codes,
+/// like the Windows console; faces are recorded as `[fg]`.
+#[derive(Default)]
+struct ConsoleRecorder {
+    text: Vec<u8>,
+}
+
+impl Write for ConsoleRecorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.text.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteColor for ConsoleRecorder {
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        if let Some(fg) = spec.fg() {
+            write!(self.text, "[{:?}]", fg)?;
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn is_synchronous(&self) -> bool {
+        true
+    }
+}
+
+fn render_on_console(config: AppConfig, input: &str) -> String {
+    let mut console = ConsoleRecorder::default();
+    process(config, &mut input.as_bytes(), &mut console).unwrap();
+    String::from_utf8(console.text).unwrap()
+}
+
+#[test]
+fn console_input_colors_test() {
+    let config = AppConfig {
+        keep_input_colors: true,
+        hyperlinks: true,
+        ..AppConfig::default()
+    };
+    let input = "\x1b[1;33mdiff --git a/x b/x\x1b[m
+\x1b[1m+++ b/x\x1b[0m
+@@ -1,2 +1,2 @@
+\x1b[36m a\x1b[m
+";
+    let expected = "[Yellow]diff --git a/x b/x
++++ b/x
+@@ -1,2 +1,2 @@
+[Cyan] a
+";
+    assert_eq!(expected, render_on_console(config, input));
+}
+
+#[test]
+fn console_faces_test() {
+    let input = "@@ -1 +1 @@
+-a b
++a c
+";
+    let expected = "@@ -1 +1 @@
+[White]-[Red]a [White]b[Red]
+[White]+[Green]a [White]c[Green]
+";
+    assert_eq!(expected, render_on_console(AppConfig::default(), input));
+}
+
+#[test]
+fn slide_test() {
+    let check = |removed: &str, added: &str, expected: &str| {
+        let data = format!("{}{}", removed, added);
+        let mut removed_tokens = vec![];
+        let mut added_tokens = vec![];
+        diffr_lib::tokenize(&data.as_bytes()[..removed.len()], 0, &mut removed_tokens);
+        diffr_lib::tokenize(data.as_bytes(), removed.len(), &mut added_tokens);
+        let input = DiffInput {
+            removed: Tokenization::new(data.as_bytes(), &removed_tokens),
+            added: Tokenization::new(data.as_bytes(), &added_tokens),
+        };
+        let mut snakes = vec![];
+        diffr_lib::diff(&input, &mut vec![], &mut snakes);
+        slider::slide(&mut snakes, data.as_bytes(), &removed_tokens, &added_tokens);
+        // the added tokens that are not shared
+        let mut refined = String::new();
+        let mut y = 0;
+        for snake in snakes.iter() {
+            for token in &added_tokens[y..snake.y0 as usize] {
+                refined += &data[token.lo..token.hi];
+            }
+            y = (snake.y0 + snake.len) as usize;
+        }
+        assert_eq!(expected, refined);
+    };
+    check("\"a\" \"b\"", "\"a\" \"c\" \"b\"", " \"c\"");
+    check("call(a, b)", "call(a, c, b)", "c, ");
+}
+
+#[test]
+fn hunk_remaining_test() {
+    let header = parse_hunk_header(b"@@ -1,2 +1,2 @@").unwrap();
+    let mut remaining = HunkRemaining::from(header);
+    assert_eq!(Some(b'-'), remaining.count(Some(b'-')));
+    assert_eq!(Some(b'+'), remaining.count(Some(b'+')));
+    assert_eq!(Some(b'\\'), remaining.count(Some(b'\\')));
+    assert_eq!(Some(b' '), remaining.count(Some(b'\n')));
+    assert_eq!(None, remaining.count(Some(b'-')));
+    assert_eq!(HunkRemaining { old: 0, new: 0 }, remaining);
+}
+
+#[test]
+fn git_diff_args_test() {
+    let config = AppConfig {
+        git_revisions: Some("main..topic".to_owned()),
+        git_paths: vec!["src".to_owned()],
+        context: Some(5),
+        include_paths: vec!["*.rs".to_owned()],
+        exclude_paths: vec!["src/test*.rs".to_owned()],
+        ..AppConfig::default()
+    };
+    assert_eq!(
+        vec![
+            "diff",
+            "--no-ext-diff",
+            "--no-color",
+            "-U5",
+            "main..topic",
+            "--",
+            "src",
+            ":(glob)**/*.rs",
+            ":(exclude,glob)src/test*.rs",
+        ],
+        git_diff::git_diff_args(&config)
+    );
+    let config = AppConfig {
+        git_revisions: Some("HEAD".to_owned()),
+        exclude_paths: vec!["*.lock".to_owned()],
+        ..AppConfig::default()
+    };
+    assert_eq!(
+        vec![
+            "diff",
+            "--no-ext-diff",
+            "--no-color",
+            "HEAD",
+            "--",
+            ".",
+            ":(exclude,glob)**/*.lock",
+        ],
+        git_diff::git_diff_args(&config)
+    );
+}
+
+#[test]
+fn reslice_test() {
+    let check = |context: usize, input: &str, expected: &str| {
+        let mut output = String::new();
+        let mut input = reslice::Reslice::new(input.as_bytes(), context, false);
+        input.read_to_string(&mut output).unwrap();
+        assert_eq!(expected, output);
+    };
+    let input = "@@ -10,2 +10,4 @@
+ a
++b
+ c
+\\ No newline at end of file
++d
+";
+    check(3, input, input);
+    check(
+        0,
+        input,
+        "@@ -10,0 +11 @@
++b
+@@ -11,0 +13 @@
++d
+",
+    );
+    check(1, input, input);
+    // the hunks that overlap once re-sliced are merged
+    check(
+        1,
+        "@@ -1,4 +1,4 @@ fn f
+ a
+-b
++B
+ c
+ d
+@@ -4,3 +4,3 @@ fn f
+ d
+-e
++E
+ f
+",
+        "@@ -1,6 +1,6 @@ fn f
+ a
+-b
++B
+ c
+ d
+-e
++E
+ f
+",
+    );
+}
+
+#[test]
+fn reslice_resolve_context_test() {
+    fn load_blob(hash: &str) -> Option<Vec<u8>> {
+        match hash {
+            "2222222" => Some(b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10".to_vec()),
+            _ => None,
+        }
+    }
+    let check = |input: &str, expected: &str| {
+        let mut output = String::new();
+        let input = reslice::Reslice::new(input.as_bytes(), 2, false);
+        let mut input = input.resolve_context(load_blob);
+        input.read_to_string(&mut output).unwrap();
+        assert_eq!(expected, output);
+    };
+    let header = "diff --git a/f b/f\nindex 1111111..2222222 100644\n--- a/f\n+++ b/f\n";
+    check(
+        &format!(
+            "{}@@ -3 +3 @@\n-3\n+three\n@@ -9 +9 @@\n-9\n+nine\n",
+            header
+        ),
+        &format!(
+            "{}@@ -1,5 +1,5 @@\n 1\n 2\n-3\n+three\n 4\n 5\n\
+             @@ -7,4 +7,4 @@\n 7\n 8\n-9\n+nine\n 10\n\\ No newline at end of file\n",
+            header
+        ),
+    );
+    // the hunks are not extended over the hunks around them, and
+    // merged when they meet
+    check(
+        &format!("{}@@ -3 +3 @@\n-3\n+three\n@@ -6 +6 @@\n-6\n+six\n", header),
+        &format!(
+            "{}@@ -1,8 +1,8 @@\n 1\n 2\n-3\n+three\n 4\n 5\n-6\n+six\n 7\n 8\n",
+            header
+        ),
+    );
+    // nor when git knows neither blob
+    let unknown = "diff --git a/f b/f\nindex 1111111..3333333 100644\n--- a/f\n+++ b/f\n\
+                   @@ -3 +3 @@\n-3\n+three\n";
+    check(unknown, unknown);
+}
+
+#[test]
+fn fold_context_test() {
+    use fold::Fold::*;
+    let check = |kinds: &str, max: usize, expected: &[Fold]| {
+        let mut folds = vec![];
+        fold::fold_context(kinds.bytes(), max, &mut folds);
+        assert_eq!(expected, &folds[..]);
+    };
+    check("   -   ", 3, &[Shown; 7]);
+    // a single hidden line is displayed rather than its marker
+    check("   -   ", 2, &[Shown; 7]);
+    check("    -", 2, &[Marker(2), Hidden, Shown, Shown, Shown]);
+    check(
+        "   -   ",
+        1,
+        &[Marker(2), Hidden, Shown, Shown, Shown, Marker(2), Hidden],
+    );
+    check(
+        "+     -",
+        2,
+        &[Shown, Shown, Marker(3), Hidden, Hidden, Shown, Shown],
+    );
+    check("-  \\", 0, &[Shown, Marker(2), Hidden, Hidden]);
+
+    let mut folds = vec![];
+    fold::hide_context(" -\\+ \\".bytes(), &mut folds);
+    assert_eq!(&[Hidden, Shown, Shown, Shown, Hidden, Hidden], &folds[..]);
+}
+
+#[test]
+fn fold_gaps_test() {
+    use fold::Fold::*;
+    let mut folds = vec![];
+    fold::fold_gaps("-   +".bytes(), &[(2, 2)], true, &mut folds);
+    assert_eq!(&[Shown, Shown, Marker(2), Hidden, Shown], &folds[..]);
+    // merged with the folded context lines before the gap
+    fold::fold_context("-    +".bytes(), 1, &mut folds);
+    fold::fold_gaps("-    +".bytes(), &[(3, 2)], true, &mut folds);
+    assert_eq!(
+        &[Shown, Marker(4), Hidden, Hidden, Hidden, Shown],
+        &folds[..]
+    );
+    folds.clear();
+    fold::fold_gaps("-  +".bytes(), &[(1, 2)], false, &mut folds);
+    assert_eq!(&[Shown, Hidden, Hidden, Shown], &folds[..]);
+}
+
+#[test]
+fn grep_match_spans_test() {
+    let regexes = [Regex::new("ab").unwrap(), Regex::new("b+c").unwrap()];
+    let data = b"+ab abbc x\n";
+    let mut spans = vec![];
+    grep::match_spans(&regexes, data, (0, data.len()), &mut spans);
+    assert_eq!(vec![(1, 3), (4, 8)], spans);
+    assert!(grep::is_match(&regexes, b"-xbc\n"));
+    assert!(!grep::is_match(&regexes, b"-a b\n"));
+}
+
+#[test]
+fn indent_test() {
+    let data = "-foo(a);\n-bar(b);\n+if x {\n+    foo(a);\n+\tbar(c);\n+}\n";
+    let mut lines = LineSplit::default();
+    for line in data.split_inclusive('\n') {
+        lines.append_line(line.as_bytes());
+    }
+    let mut pairs = vec![];
+    indent::pair_lines(lines.data(), lines.iter(), &mut pairs);
+    let line = |span: Option<Span>| span.map(|(lo, hi)| &data[lo..hi]);
+    let pairs = pairs.into_iter().map(line).collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            Some("+    foo(a);\n"),
+            None,
+            None,
+            Some("-foo(a);\n"),
+            None,
+            None
+        ],
+        pairs
+    );
+
+    assert_eq!("→ +4 spaces", indent::delta(b"-x", b"+    x"));
+    assert_eq!("→ -1 tab", indent::delta(b"-\t\tx", b"+\tx"));
+    assert_eq!("→ 2 spaces to 1 tab", indent::delta(b"-  x", b"+\tx"));
+}
+
+#[test]
+fn relocated_test() {
+    let data = "-a();\n-\n-b();\n+b();\n+\n+c();\n+a();\n";
+    let mut lines = LineSplit::default();
+    for line in data.split_inclusive('\n') {
+        lines.append_line(line.as_bytes());
+    }
+    let mut relocated = vec![];
+    relocated::find(lines.data(), lines.iter(), &mut relocated);
+    // the blank lines are not paired
+    assert_eq!(vec![true, false, true, true, false, false, true], relocated);
+}
+
+#[test]
+fn headers_test() {
+    let config = AppConfig {
+        timestamps: headers::Timestamps::Strip,
+        strip_prefix: Some(2),
+        ..AppConfig::default()
+    };
+    let rewrite = |line: &str| headers::rewrite(line.as_bytes(), &config).map(String::from_utf8);
+    assert_eq!(
+        Some(Ok("--- c.rs\n".to_owned())),
+        rewrite("--- a/b/c.rs\t2024-01-01 10:00:00 +0100\n")
+    );
+    assert_eq!(
+        Some(Ok("+++ c.rs".to_owned())),
+        rewrite("+++ c.rs\t2024-01-01")
+    );
+    assert_eq!(None, rewrite("--- /dev/null\n"));
+    assert_eq!(None, rewrite("@@ -1 +1 @@\n"));
+}
+
+#[test]
+fn drop_carriage_return_test() {
+    let src = b"a b\r\n";
+    let mut tokens = vec![];
+    tokenize::tokenize(src, 0, &mut tokens);
+    tokenize::drop_carriage_return(src, &mut tokens, 0);
+    let tokens = tokens.iter().map(|t| &src[t.lo..t.hi]).collect::<Vec<_>>();
+    assert_eq!(vec![&b"a"[..], b" ", b"b", b"\n"], tokens);
+}
+
+#[test]
+fn mask_tokens_test() {
+    let data = b"-id_1a2b x\n+id_9f8e y\n";
+    let mut removed = vec![];
+    let mut added = vec![];
+    tokenize::tokenize(&data[..11], 1, &mut removed);
+    tokenize::tokenize(data, 12, &mut added);
+    let (removed, added) = tokenize::mask(data, &[(4, 8), (15, 19)], &removed, &added);
+    let hashes = |tokens: &[HashedSpan]| tokens.iter().map(|t| t.hash).collect::<Vec<_>>();
+    let (removed, added) = (hashes(&removed), hashes(&added));
+    // the identifiers differ only by their masked parts
+    assert_eq!(removed[0], added[0]);
+    assert_eq!(removed[1], added[1]);
+    assert_ne!(removed[2], added[2]);
+}
+
+#[test]
+fn debug_report_test() {
+    let report = debug::HunkReport {
+        path: Some("a b.rs"),
+        header: Some((1, 2, 1, 3)),
+        lines: (2, 3),
+        tokens: (9, 14),
+        cost: 126,
+        shared: 7,
+        algorithm: "myers".to_owned(),
+        mode: "pairing",
+        skipped: None,
+        cached: true,
+        lcs: Duration::from_micros(12),
+        total: Duration::from_micros(40),
+    };
+    let mut text = vec![];
+    report.write(debug::DebugFormat::Text, &mut text).unwrap();
+    assert_eq!(
+        "hunk path=\"a b.rs\" header=\"-1,2 +1,3\" lines=-2+3 tokens=-9+14 cost=126 shared=7 \
+         algorithm=myers mode=pairing skipped=none cached=true lcs_us=12 total_us=40\n",
+        String::from_utf8(text).unwrap()
+    );
+    let mut json = vec![];
+    report.write(debug::DebugFormat::Json, &mut json).unwrap();
+    assert!(String::from_utf8(json)
+        .unwrap()
+        .starts_with("{\"path\":\"a b.rs\",\"header\":\"-1,2 +1,3\",\"removed_lines\":2,"));
+}
+
+#[test]
+fn split_chars_test() {
+    let src = "ab é\u{301}=".as_bytes();
+    let mut tokens = vec![];
+    tokenize::tokenize(src, 0, &mut tokens);
+    tokenize::split_chars(src, &mut tokens);
+    let tokens = tokens.iter().map(|t| &src[t.lo..t.hi]).collect::<Vec<_>>();
+    // the combining accent stays with its letter
+    assert_eq!(
+        vec![&b"a"[..], b"b", b" ", "é\u{301}".as_bytes(), b"="],
+        tokens
+    );
+}
+
+#[test]
+fn mail_start_test() {
+    let mail = b"From fec866d36f4aa2ea22531810185c166bb540b857 Mon Sep 17 00:00:00 2001\n";
+    assert!(commit::is_commit_start(mail));
+    assert_eq!(
+        Some("fec866d36f4aa2ea22531810185c166bb540b857".to_owned()),
+        commit::sha(mail)
+    );
+    assert_eq!(Some("abc".to_owned()), commit::sha(b"commit abc (HEAD)\n"));
+    // the "From:" header and the quoted lines of a message
+    assert!(!commit::is_commit_start(b"From: A <a@b>\n"));
+    assert!(!commit::is_commit_start(b"From here on\n"));
+    assert!(commit::is_signature(b"-- \n"));
+    assert!(!commit::is_signature(b"-- a\n"));
+}
+
+#[test]
+fn json_parse_test() {
+    let value = json::parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\n\u00e9"} "#).unwrap();
+    assert_eq!(Some("x\né"), value.get("b").and_then(|b| b.as_str()));
+    match value.get("a") {
+        Some(json::Value::Array(items)) => {
+            assert_eq!(Some(1), items[0].as_usize());
+            assert_eq!(None, items[1].as_usize());
+            assert_eq!(json::Value::Bool(true), items[2]);
+            assert_eq!(json::Value::Null, items[3]);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(json::parse("[1,]").is_err());
+    assert!(json::parse("[1] 2").is_err());
+    assert!(json::parse("\"a").is_err());
+}
+
+#[test]
+fn json_event_stream_test() {
+    let line = |kind, content: &str, spans| hunks::Line {
+        kind,
+        content: content.as_bytes().to_vec(),
+        spans,
+    };
+    let hunk = hunks::Hunk {
+        old_path: None,
+        path: None,
+        header: HunkHeader {
+            old_start: 3,
+            old_len: 1,
+            new_start: 3,
+            new_len: 2,
+        },
+        lines: vec![
+            line(hunks::LineKind::Removed, "a b", vec![(2, 3)]),
+            line(hunks::LineKind::Added, "c", vec![]),
+            line(hunks::LineKind::Added, "a", vec![]),
+        ],
+        edits: vec![],
+    };
+    let mut out = vec![];
+    let mut stream = json::EventStream::new(&mut out);
+    // the header of the hunk is written from the hunk
+    let header = hunks::Part::Lines(b"@@ -3 +3,2 @@\n".to_vec());
+    stream.write_part(&header).unwrap();
+    stream.write_part(&hunks::Part::Hunk(hunk)).unwrap();
+    stream.finish().unwrap();
+    let events = String::from_utf8(out).unwrap();
+    let events = events
+        .lines()
+        .map(|line| json::parse(line).unwrap())
+        .collect::<Vec<_>>();
+    let field = |i: usize, key| events[i].get(key).cloned();
+    let name = |i: usize| field(i, "event").unwrap();
+    let names = (0..events.len()).map(name).collect::<Vec<_>>();
+    let expected = [
+        "file-start",
+        "hunk-start",
+        "line",
+        "span",
+        "line",
+        "line",
+        "file-end",
+    ];
+    assert_eq!(
+        expected
+            .iter()
+            .map(|name| json::Value::String(name.to_string()))
+            .collect::<Vec<_>>(),
+        names
+    );
+    assert_eq!(Some(json::Value::Null), field(0, "path"));
+    assert_eq!(Some(3), field(2, "old_line").and_then(|n| n.as_usize()));
+    assert_eq!(Some(2), field(3, "start").and_then(|n| n.as_usize()));
+    assert_eq!(Some(4), field(5, "new_line").and_then(|n| n.as_usize()));
+    assert_eq!(
+        Some("a"),
+        field(5, "content").as_ref().and_then(|c| c.as_str())
+    );
+}
+
+#[test]
+fn annotations_test() {
+    let annotations = annotations::Annotations::parse(
+        r#"[{"path": "./src/a.rs", "new_line": 2, "severity": "Error", "message": "e"},
+            {"path": "src/a.rs", "new_line": 2, "message": "n"}]"#,
+    )
+    .unwrap();
+    let found = annotations.get("src/a.rs", 2);
+    assert_eq!(2, found.len());
+    assert_eq!(annotations::Severity::Error, found[0].severity);
+    assert_eq!(annotations::Severity::Note, found[1].severity);
+    assert!(annotations.get("src/a.rs", 1).is_empty());
+    assert!(
+        annotations::Annotations::parse(r#"[{"path": "a", "new_line": 0, "message": "m"}]"#)
+            .is_err()
+    );
+    assert!(annotations::Annotations::parse(r#"{"path": "a"}"#).is_err());
+}
+
+#[test]
+fn annotations_sarif_test() {
+    let cwd = std::env::current_dir().unwrap();
+    let sarif = format!(
+        r#"{{"version": "2.1.0", "runs": [{{"results": [
+            {{"ruleId": "R1", "level": "error", "message": {{"text": "e"}},
+              "locations": [{{"physicalLocation": {{"artifactLocation": {{"uri": "src/a%20b.rs"}},
+                                                  "region": {{"startLine": 3, "endLine": 4}}}}}}]}},
+            {{"message": {{"text": "w"}},
+              "locations": [{{"physicalLocation": {{"artifactLocation": {{"uri": "file://{}/src/c.rs"}},
+                                                  "region": {{"startLine": 1}}}}}}]}},
+            {{"level": "none", "message": {{"text": "no region"}},
+              "locations": [{{"physicalLocation": {{"artifactLocation": {{"uri": "src/c.rs"}}}}}}]}}
+        ]}}]}}"#,
+        cwd.display()
+    );
+    let annotations = annotations::Annotations::parse_sarif(&sarif).unwrap();
+    let found = annotations.get("src/a b.rs", 3);
+    assert_eq!(1, found.len());
+    assert_eq!(annotations::Severity::Error, found[0].severity);
+    assert_eq!("e [R1]", found[0].message);
+    assert!(annotations.get("src/a b.rs", 4).is_empty());
+    let found = annotations.get("src/c.rs", 1);
+    assert_eq!(1, found.len());
+    assert_eq!(annotations::Severity::Warning, found[0].severity);
+    assert!(annotations::Annotations::parse_sarif("[]").is_err());
+}
+
+#[test]
+fn chunks_split_test() {
+    fn split(hunk: &str, min_lines: usize) -> Vec<chunks::Chunk> {
+        let data = hunk.as_bytes();
+        let mut lines = vec![];
+        let mut lo = 0;
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            lines.push((lo, lo + line.len()));
+            lo += line.len();
+        }
+        let mut chunks = vec![];
+        chunks::split(data, lines, min_lines, &mut chunks);
+        chunks
+    }
+    let chunk = |start, old_len, new_len| chunks::Chunk {
+        start,
+        old_len,
+        new_len,
+    };
+    let hunk = " a {\n-b\n+c\n }\n \n d {\n+e\n }\n f\n";
+    assert_eq!(vec![chunk(5, 2, 3), chunk(8, 1, 1)], split(hunk, 2));
+    // the chunks are at least 6 lines long
+    assert_eq!(vec![chunk(8, 1, 1)], split(hunk, 6));
+    assert_eq!(Vec::<chunks::Chunk>::new(), split(hunk, 9));
+    // a block that closes the one before the hunk
+    assert_eq!(vec![chunk(3, 1, 1)], split("-a\n+b\n }\n c\n", 1));
+    // nor before a "\ No newline at end of file"
+    assert_eq!(Vec::<chunks::Chunk>::new(), split(" }\n\\ No newline\n", 1));
+}
+
+#[test]
+fn nonprinting_test() {
+    assert_eq!(None, nonprinting::find(b"a\tb\n"));
+    assert_eq!(None, nonprinting::find(b"\x1b[31ma\x1b[0m\n"));
+    assert_eq!(Some((1, 2)), nonprinting::find(b"a\x1bb"));
+    assert_eq!(Some((3, 6)), nonprinting::find("éa\u{200b}".as_bytes()));
+    assert_eq!(Some((0, 2)), nonprinting::find("\u{85}".as_bytes()));
+    assert_eq!("^[", nonprinting::escape(b"\x1b"));
+    assert_eq!("^M", nonprinting::escape(b"\r"));
+    assert_eq!("^?", nonprinting::escape(b"\x7f"));
+    assert_eq!("<U+FEFF>", nonprinting::escape("\u{feff}".as_bytes()));
+}
+
+#[test]
+fn mapped_spans_test() {
+    let line = hunks::Line {
+        kind: hunks::LineKind::Added,
+        content: "é\t漢字 ab".as_bytes().to_vec(),
+        spans: vec![(0, 1), (2, 4), (5, 7)],
+    };
+    let (bytes, columns) = line.mapped_spans();
+    assert_eq!(vec![(0, 2), (3, 9), (10, 12)], bytes);
+    assert_eq!(vec![(0, 1), (8, 12), (13, 15)], columns);
+}
+
+#[test]
+fn refined_hunk_test() {
+    let mut lines = LineSplit::default();
+    let (mut removed, mut added) = (vec![], vec![]);
+    for line in [
+        " fn f() {\n",
+        "-    let a = 1;\n",
+        "-    g(a);\n",
+        "-    h();\n",
+        "+    let b = 1;\n",
+        "+    g(b, c);\n",
+        "+    i(d);\n",
+    ] {
+        let ofs = lines.len() + 1;
+        lines.append_line(line.as_bytes());
+        match line.as_bytes()[0] {
+            b'-' => tokenize::tokenize(lines.data(), ofs, &mut removed),
+            b'+' => tokenize::tokenize(lines.data(), ofs, &mut added),
+            _ => (),
+        }
+    }
+    let mut lcs = vec![];
+    let tokens = (&removed[..], &added[..]);
+    algorithm::diff(
+        Algorithm::Myers,
+        lines.data(),
+        tokens,
+        &mut vec![],
+        &mut lcs,
+    );
+    let header = HunkHeader {
+        old_start: 1,
+        old_len: 4,
+        new_start: 1,
+        new_len: 4,
+    };
+    let config = AppConfig::default();
+    let hunk = hunks::refined(
+        (header, &None, &None),
+        &lines,
+        tokens,
+        (Some(&lcs), &[]),
+        false,
+        &config,
+    );
+    let spans = hunk
+        .lines
+        .iter()
+        .map(|line| &line.spans[..])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            &[][..],
+            &[(8, 9)],
+            &[(6, 7)],
+            &[(4, 5)],
+            &[(8, 9)],
+            &[(6, 10)],
+            &[(4, 5), (6, 7)]
+        ],
+        spans
+    );
+    let edit = |kind, old: &str, old_range, new: &str, new_range| hunks::TextEdit {
+        kind,
+        old_range,
+        old: old.as_bytes().to_vec(),
+        new_range,
+        new: new.as_bytes().to_vec(),
+    };
+    assert_eq!(
+        vec![
+            edit(
+                EditKind::Replace,
+                "a",
+                Some(((1, 8), (1, 9))),
+                "b",
+                Some(((4, 8), (4, 9)))
+            ),
+            edit(
+                EditKind::Replace,
+                "a",
+                Some(((2, 6), (2, 7))),
+                "b, c",
+                Some(((5, 6), (5, 10)))
+            ),
+            edit(
+                EditKind::Replace,
+                "h",
+                Some(((3, 4), (3, 5))),
+                "i",
+                Some(((6, 4), (6, 5)))
+            ),
+            edit(EditKind::Insert, "", None, "d", Some(((6, 6), (6, 7)))),
+        ],
+        hunk.edits
+    );
+    // the text of an edit spans the lines of its side between its ends
+    let line = |kind, content: &str| hunks::Line {
+        kind,
+        content: content.as_bytes().to_vec(),
+        spans: vec![],
+    };
+    let mut hunk = hunks::Hunk {
+        lines: vec![
+            line(hunks::LineKind::Removed, "ab"),
+            line(hunks::LineKind::Added, "x"),
+            line(hunks::LineKind::Removed, "cd"),
+        ],
+        edits: vec![],
+        ..hunk
+    };
+    hunk.push_edit(EditKind::Delete, Some(((0, 1), (2, 1))), None);
+    assert_eq!(
+        vec![edit(
+            EditKind::Delete,
+            "b\nc",
+            Some(((0, 1), (2, 1))),
+            "",
+            None
+        )],
+        hunk.edits
+    );
+}
+
+#[test]
+fn cache_test() {
+    let dir = std::env::temp_dir().join(format!("diffr_cache_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = AppConfig::default();
+    let data = b"-a b\n+a c\n";
+    let mut removed = vec![];
+    let mut added = vec![];
+    tokenize::tokenize(data, 1, &mut removed);
+    tokenize::tokenize(data, 6, &mut added);
+    let tokens = (&removed[..], &added[..]);
+    let sizes = (removed.len(), added.len());
+    let snakes = vec![Snake {
+        x0: 0,
+        y0: 0,
+        len: 2,
+    }];
+    let mut cache = cache::Cache::with_dir(Some(dir.clone()), 1 << 20);
+    let key = cache.key(&config, data, tokens).unwrap();
+    let mut found = vec![];
+    assert!(!cache.get(&key, sizes, &mut found));
+    cache.put(&key, &snakes);
+    assert!(cache.get(&key, sizes, &mut found));
+    let spans = |snakes: &[Snake]| {
+        snakes
+            .iter()
+            .map(|s| (s.x0, s.y0, s.len))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(spans(&snakes), spans(&found));
+    // the entries that do not fit the tokens are ignored
+    assert!(!cache.get(&key, (1, 1), &mut found));
+    let ignore_case = AppConfig {
+        ignore_case: true,
+        ..AppConfig::default()
+    };
+    assert_ne!(Some(key.clone()), cache.key(&ignore_case, data, tokens));
+    cache.evict();
+    assert!(cache.get(&key, sizes, &mut found));
+    let mut cache = cache::Cache::with_dir(Some(dir.clone()), 0);
+    cache.put(&key, &snakes);
+    cache.evict();
+    assert!(!cache.get(&key, sizes, &mut found));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sort_files_test() {
+    let diff = b"commit 1
+--- b.c
++++ b.c
+@@ -1 +1 @@
+-a
++b
+--- a.h
++++ a.h
+@@ -1 +1,2 @@
+--- a
++b
++c
+commit 2
+--- d.c
++++ d.c
+@@ -1 +1 @@
+-a
++b
+--- c.c
++++ c.c
+@@ -1 +1 @@
+-a
++b
+";
+    let sorted = sort::sort_files(diff, sort::SortFiles::Path);
+    let paths = |sorted: &[u8]| {
+        sorted
+            .split(|&b| b == b'\n')
+            .filter(|line| line.starts_with(b"+++ ") || line.starts_with(b"commit "))
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect::<Vec<_>>()
+    };
+    let expected = [
+        "commit 1", "+++ a.h", "+++ b.c", "commit 2", "+++ c.c", "+++ d.c",
+    ];
+    assert_eq!(expected, &paths(&sorted)[..]);
+    assert_eq!(diff.len(), sorted.len());
+    let sorted = sort::sort_files(diff, sort::SortFiles::Changes);
+    let expected = [
+        "commit 1", "+++ a.h", "+++ b.c", "commit 2", "+++ d.c", "+++ c.c",
+    ];
+    assert_eq!(expected, &paths(&sorted)[..]);
+    let sorted = sort::sort_files(diff, sort::SortFiles::Extension);
+    let expected = [
+        "commit 1", "+++ b.c", "+++ a.h", "commit 2", "+++ d.c", "+++ c.c",
+    ];
+    assert_eq!(expected, &paths(&sorted)[..]);
+}
+
+#[test]
+fn lang_test() {
+    let check = |lang: Lang, line: &str, expected: &[&str]| {
+        let mut tokens = vec![];
+        tokenize::tokenize(line.as_bytes(), 0, &mut tokens);
+        lang::merge_tokens(line.as_bytes(), &mut tokens, 0, lang);
+        let tokens = tokens.iter().map(|t| &line[t.lo..t.hi]).collect::<Vec<_>>();
+        assert_eq!(expected, &tokens[..]);
+    };
+    check(Lang::Rust, "a::b->c", &["a", "::", "b", "->", "c"]);
+    check(Lang::Rust, "0..=n", &["0", "..=", "n"]);
+    check(Lang::Python, "a**b", &["a", "**", "b"]);
+    check(Lang::C, "p->x++", &["p", "->", "x", "++"]);
+    check(Lang::Json, r#""a \" b":1"#, &[r#""a \" b""#, ":", "1"]);
+    check(Lang::Json, "\"open", &["\"", "open"]);
+    check(Lang::Prose, "don't re-run", &["don't", " ", "re-run"]);
+
+    assert_eq!(Some(Lang::Rust), Lang::Auto.resolve(Some("src/main.rs")));
+    assert_eq!(None, Lang::Auto.resolve(Some("Makefile")));
+    assert_eq!(Some(Lang::C), Lang::C.resolve(None));
+
+    let detect = |line: &str, first| lang::detect(line.as_bytes(), first);
+    assert_eq!(Some(Lang::Python), detect("#!/usr/bin/env python3\n", true));
+    assert_eq!(
+        Some(Lang::C),
+        detect("#!/usr/bin/env -S node --flag\n", true)
+    );
+    assert_eq!(Some(Lang::Python), detect("#!/usr/bin/python\n", true));
+    assert_eq!(None, detect("#!/usr/bin/python\n", false));
+    assert_eq!(None, detect("#!/bin/sh\n", true));
+    assert_eq!(
+        Some(Lang::Rust),
+        detect("// -*- mode: rust; indent-tabs-mode: nil -*-\n", false)
+    );
+    assert_eq!(Some(Lang::Python), detect("# -*- python -*-\n", false));
+    assert_eq!(Some(Lang::Json), detect("// vim: set ft=json:\n", false));
+    assert_eq!(Some(Lang::Prose), detect("vi: filetype=markdown\n", false));
+    assert_eq!(None, detect("let envim: x = ft=json;\n", false));
+}
+
+#[test]
+fn algorithm_test() {
+    let shared = |algorithm, removed: &str, added: &str| {
+        let data = format!("{}{}", removed, added);
+        let (mut removed_tokens, mut added_tokens) = (vec![], vec![]);
+        tokenize::tokenize(&data.as_bytes()[..removed.len()], 0, &mut removed_tokens);
+        tokenize::tokenize(data.as_bytes(), removed.len(), &mut added_tokens);
+        let mut snakes = vec![];
+        algorithm::diff(
+            algorithm,
+            data.as_bytes(),
+            (&removed_tokens, &added_tokens),
+            &mut vec![],
+            &mut snakes,
+        );
+        snakes
+            .iter()
+            .map(|s| {
+                let (x0, x1) = (s.x0 as usize, (s.x0 + s.len) as usize);
+                data[removed_tokens[x0].lo..removed_tokens[x1 - 1].hi].to_owned()
+            })
+            .collect::<Vec<_>>()
+    };
+    let (removed, added) = ("foo(a, b) + bar(c, d)", "bar(c, d) + foo(a, b)");
+    assert_eq!(
+        vec!["(", ", ", ") + ", "(", ", ", ")"],
+        shared(Algorithm::Myers, removed, added)
+    );
+    // the common suffix is matched first
+    assert_eq!(
+        vec!["bar(c, d", ")"],
+        shared(Algorithm::Patience, removed, added)
+    );
+    assert_eq!(
+        vec!["bar(c, d", ")"],
+        shared(Algorithm::Histogram, removed, added)
+    );
+
+    assert_eq!(vec!["x y"], shared(Algorithm::Histogram, "x y", "x y"));
+    assert!(shared(Algorithm::Patience, "", "a").is_empty());
+}
+
+#[test]
+fn html_write_line_test() {
+    let mut out = vec![];
+    html::write_line(b"\x1b[1;31m<a>\x1b[0m & \x1b[38;5;196mb\x1b[0m", &mut out).unwrap();
+    assert_eq!(
+        "<span style=\"color:#cd0000;font-weight:bold\">&lt;a&gt;</span> &amp; \
+         <span style=\"color:#ff0000\">b</span>",
+        String::from_utf8(out).unwrap()
+    );
+}
+
+#[test]
+fn html_links_test() {
+    let mut out = vec![];
+    let line = b"\x1b]8;;https://x/a?b&c\x1b\\1\x1b]8;;\x07 | \x1b]8;id=2;u\x07\x1b[31m-a\x1b[0m";
+    html::write_line(line, &mut out).unwrap();
+    assert_eq!(
+        "<a href=\"https://x/a?b&amp;c\">1</a> | \
+         <a href=\"u\"><span style=\"color:#cd0000\">-a</span></a>",
+        String::from_utf8(out).unwrap()
+    );
+}
+
+#[test]
+fn binary_patch_test() {
+    let mut patch = binary::Patch::new(Some("logo.png".to_owned()));
+    let lines: &[&[u8]] = &[
+        b"literal 13\n",
+        b"UcmeAS@N?(olHy`uU}Rzj01~4CumAu6\n",
+        b"\n",
+        b"literal 2048\n",
+        b"RcmeAS@N?(olHy`u000e}0jdB1\n",
+        b"\n",
+    ];
+    assert!(lines.iter().all(|line| patch.push(line)));
+    assert!(!patch.push(b"diff --git a/x b/x\n"));
+    let mut out = termcolor::NoColor::new(vec![]);
+    patch.write(&AppConfig::default(), &mut out).unwrap();
+    assert_eq!(
+        "binary file logo.png: 2.0 KiB -> 13 B\n",
+        String::from_utf8(out.into_inner()).unwrap()
+    );
+
+    let mut patch = binary::Patch::new(None);
+    assert!(patch.push(b"delta 7\n"));
+    assert!(!patch.push(b"\n"));
+
+    assert_eq!("1023 B", binary::format_size(1023));
+    assert_eq!("1.5 MiB", binary::format_size(3 << 19));
+    assert_eq!(
+        "added",
+        binary::marker_change(b"Binary files /dev/null and b/x differ")
+    );
+    assert_eq!(
+        "deleted",
+        binary::marker_change(b"Binary files a/x and /dev/null differ\n")
+    );
+    assert_eq!("", binary::base64(b""));
+    assert_eq!("Zm9vYg==", binary::base64(b"foob"));
+    assert_eq!("Zm9vYmE=", binary::base64(b"fooba"));
+    assert_eq!("Zm9vYmFy", binary::base64(b"foobar"));
+}
+
+#[test]
+fn markdown_test() {
+    let mut out = vec![];
+    let rendered = "log ```\ndiff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n";
+    markdown::write(rendered.as_bytes(), false, &mut out).unwrap();
+    assert_eq!(
+        "````\nlog ```\n````\n\n```diff\ndiff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n```\n",
+        String::from_utf8(out).unwrap()
+    );
+}
+
+#[test]
+fn exit_code_test() {
+    let exit_code = |input: &str| {
+        let stats = process_diff(
+            AppConfig::default(),
+            &mut input.as_bytes(),
+            &mut NoColor::new(vec![]),
+            false,
+        )
+        .unwrap();
+        stats.exit_code()
+    };
+    assert_eq!(0, exit_code("diff --git a/f b/f\nindex 0..1\n"));
+    assert_eq!(1, exit_code("@@ -1 +1 @@\n-a\n+b\n"));
+    assert_eq!(2, exit_code("@@ -1 +1 @@\n-a\n+b\n@@ -x +y @@\n"));
+}
+
+#[test]
+fn compact_paths_test() {
+    let compact = |from: &str, to: &str| {
+        String::from_utf8(extended::compact_paths(from.as_bytes(), to.as_bytes())).unwrap()
+    };
+    assert_eq!("old.sh => new.sh", compact("old.sh", "new.sh"));
+    assert_eq!(
+        "src/{a => b}/lib.rs",
+        compact("src/a/lib.rs", "src/b/lib.rs")
+    );
+    assert_eq!("src/{a.rs => b.rs}", compact("src/a.rs", "src/b.rs"));
+    assert_eq!("{a => b}/lib.rs", compact("a/lib.rs", "b/lib.rs"));
+    assert_eq!("a/{b => c/b}", compact("a/b", "a/c/b"));
+    assert_eq!("dir/{a => b}/", compact("dir/a/", "dir/b/"));
+}
+
+#[test]
+fn parse_colorfgbg_test() {
+    use palette::{parse_colorfgbg, Background::*};
+    assert_eq!(Some(Light), parse_colorfgbg("0;15"));
+    assert_eq!(Some(Light), parse_colorfgbg("0;default;7"));
+    assert_eq!(Some(Dark), parse_colorfgbg("15;0"));
+    assert_eq!(Some(Dark), parse_colorfgbg("7;8"));
+    assert_eq!(None, parse_colorfgbg("0;default"));
+    assert_eq!(None, parse_colorfgbg(""));
+}
+
+#[test]
+fn is_number_test() {
+    assert!(is_number(b"42"));
+    assert!(is_number(b"0x1f"));
+    assert!(is_number(b"1_000"));
+    assert!(!is_number(b"v2"));
+    assert!(!is_number(b""));
+}
+
+#[test]
+fn blame_porcelain_test() {
+    let porcelain = "\
+1111111111111111111111111111111111111111 1 1 2
+author Jane Doe
+author-mail <jane@example.com>
+filename a.rs
+\tfn main() {
+1111111111111111111111111111111111111111 2 2
+\t}
+2222222222222222222222222222222222222222 5 3 1
+author John Roe
+filename a.rs
+\t
+";
+    let annotation = |hash: &str, author: &str| {
+        Some(blame::Annotation {
+            hash: hash.to_owned(),
+            author: author.to_owned(),
+        })
+    };
+    assert_eq!(
+        vec![
+            annotation("1111111", "Jane Doe"),
+            annotation("1111111", "Jane Doe"),
+            annotation("2222222", "John Roe"),
+        ],
+        blame::parse_porcelain(porcelain)
+    );
+}
+
+#[test]
+fn timings_test() {
+    let ms = std::time::Duration::from_millis;
+    let mut timings = Timings {
+        tokenize: ms(1),
+        lcs: ms(2),
+        emit: ms(3),
+        total: ms(10),
+    };
+    assert_eq!(ms(4), timings.parse());
+    timings.add(&timings.clone());
+    assert_eq!(ms(8), timings.div(2).parse() * 2);
+    assert!(timings.to_string().starts_with("parse time (ms): 8.000\n"));
+}
+
+#[test]
+fn watch_is_watched_test() {
+    let root = std::path::Path::new("/repo");
+    let is_watched = |path: &str| watch::is_watched(root, std::path::Path::new(path));
+    assert!(is_watched("/repo/a.txt"));
+    assert!(is_watched("/repo/src/main.rs"));
+    assert!(is_watched("/repo/.git/index"));
+    assert!(is_watched("/repo/.git/HEAD"));
+    assert!(!is_watched("/repo/.git/objects/ab"));
+    assert!(!is_watched("/repo/.cache/a.txt"));
+    assert!(!is_watched("/repo/src/.a.swp"));
+    assert!(!is_watched("/other/a.txt"));
+}
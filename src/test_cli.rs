@@ -1,6 +1,11 @@
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 use StringTest::*;
 
 enum StringTest {
@@ -22,29 +27,23 @@ impl StringTest {
         match self {
             Empty => assert!(
                 actual.is_empty(),
-                format!(
-                    "{}: expected empty, got\n\n{}",
-                    quote_or_empty(prefix),
-                    quote_or_empty(actual)
-                )
+                "{}: expected empty, got\n\n{}",
+                quote_or_empty(prefix),
+                quote_or_empty(actual)
             ),
             AtLeast(exp) => assert!(
                 actual.contains(exp),
-                format!(
-                    "{}: expected at least\n\n{}\n\ngot\n\n{}",
-                    prefix,
-                    quote_or_empty(exp),
-                    quote_or_empty(actual)
-                )
+                "{}: expected at least\n\n{}\n\ngot\n\n{}",
+                prefix,
+                quote_or_empty(exp),
+                quote_or_empty(actual)
             ),
             Exactly(exp) => assert!(
                 actual.trim() == exp.trim(),
-                format!(
-                    "{}: expected\n\n{}\n\ngot\n\n{}",
-                    prefix,
-                    quote_or_empty(exp),
-                    quote_or_empty(actual)
-                )
+                "{}: expected\n\n{}\n\ngot\n\n{}",
+                prefix,
+                quote_or_empty(exp),
+                quote_or_empty(actual)
             ),
         }
     }
@@ -52,6 +51,7 @@ impl StringTest {
 
 struct ProcessTest {
     args: &'static [&'static str],
+    input: &'static str,
     out: StringTest,
     err: StringTest,
     is_success: bool,
@@ -77,10 +77,21 @@ fn test_cli(descr: ProcessTest) {
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
     cmd.stdin(Stdio::piped());
+    cmd.env_remove("DIFFR_OPTIONS");
+    cmd.env_remove("CLICOLOR_FORCE");
+    cmd.env_remove("NO_COLOR");
+    cmd.env_remove("COLORFGBG");
     for arg in descr.args {
-        cmd.arg(&*arg);
+        cmd.arg(arg);
+    }
+    let mut child = cmd.spawn().expect("spawn");
+    {
+        let mut stdin = child.stdin.take().expect("stdin");
+        // diffr may exit, or read another input, before reading this one
+        if let Err(err) = stdin.write_all(descr.input.as_bytes()) {
+            assert_eq!(std::io::ErrorKind::BrokenPipe, err.kind(), "{}", err);
+        }
     }
-    let child = cmd.spawn().expect("spawn");
     let output = child.wait_with_output().expect("wait_with_output");
     fn string_of_status(code: bool) -> &'static str {
         if code {
@@ -88,14 +99,12 @@ fn test_cli(descr: ProcessTest) {
         } else {
             "failure"
         }
-    };
+    }
     assert!(
         descr.is_success == output.status.success(),
-        format!(
-            "unexpected status: expected {} got {}",
-            string_of_status(descr.is_success),
-            string_of_status(output.status.success()),
-        )
+        "unexpected status: expected {} got {}",
+        string_of_status(descr.is_success),
+        string_of_status(output.status.success()),
     );
     descr
         .out
@@ -105,22 +114,475 @@ fn test_cli(descr: ProcessTest) {
         .test(&String::from_utf8_lossy(&output.stderr), "stderr");
 }
 
+/// Runs diffr with `args` on `input`, and returns its output, or None
+/// if it does not exit within `timeout`.
+fn output_within(args: &[&str], input: Vec<u8>, timeout: Duration) -> Option<String> {
+    let mut child = Command::new(diffr_path())
+        .args(args)
+        .env_remove("DIFFR_OPTIONS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn");
+    let mut stdin = child.stdin.take().expect("stdin");
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let mut stdout = child.stdout.take().expect("stdout");
+    let reader = thread::spawn(move || {
+        let mut out = vec![];
+        let _ = stdout.read_to_end(&mut out);
+        out
+    });
+    let start = Instant::now();
+    while child.try_wait().expect("try_wait").is_none() {
+        if timeout < start.elapsed() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let _ = writer.join();
+    let out = reader.join().expect("reader");
+    Some(String::from_utf8_lossy(&out).into_owned())
+}
+
+#[test]
+fn exit_code_no_changes() {
+    test_cli(ProcessTest {
+        args: &["--exit-code"],
+        input: "diff --git a/f b/f\n",
+        out: AtLeast("diff --git a/f b/f"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn exit_code_changes() {
+    test_cli(ProcessTest {
+        args: &["--exit-code"],
+        input: "@@ -1 +1 @@\n-a\n+b\n",
+        out: AtLeast("@@ -1 +1 @@"),
+        err: Empty,
+        is_success: false,
+    })
+}
+
+#[test]
+fn quiet_stats() {
+    test_cli(ProcessTest {
+        args: &["--quiet", "--stats"],
+        input: "--- a/f
++++ b/f
+@@ -1 +1 @@
+-a b
++a c
+",
+        out: Exactly(
+            "\x1b[0mf: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +4 -4)\x1b[0m
+\x1b[0mtotal: 1 file, 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +4 -4)\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_file_html() {
+    let path = "target/test_output_file.html";
+    test_cli(ProcessTest {
+        args: &["--output-file", "target/test_output_file.html"],
+        input: "@@ -1 +1 @@
+-a <b>
++a <c>
+",
+        out: Empty,
+        err: Empty,
+        is_success: true,
+    });
+    let page = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains(
+        "&lt;</span><span style=\"color:#e5e5e5;background-color:#00cd00;font-weight:bold\">c</span>"
+    ));
+}
+
+#[test]
+fn tee() {
+    let path = "target/test_tee.txt";
+    test_cli(ProcessTest {
+        args: &["--tee", "target/test_tee.txt", "--color=never"],
+        input: "@@ -1 +1 @@
+-a
++b
+",
+        out: Exactly("@@ -1 +1 @@\n-a\n+b\n"),
+        err: Empty,
+        is_success: true,
+    });
+    let text = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!("@@ -1 +1 @@\n-a\n+b\n", text);
+}
+
+const GIT_LOG: &str = "commit 0123456789abcdef0123456789abcdef01234567 (HEAD -> main)
+Author: A U Thor <author@example.com>
+Date:   Mon Jan 1 00:00:00 2024 +0000
+
+    Fix the frobnicator
+
+    Details of the fix.
+
+diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1 +1 @@
+-a
++b
+commit 89abcdef0123456789abcdef0123456789abcdef
+Author: B <b@example.com>
+Date:   Sun Dec 31 00:00:00 2023 +0000
+
+    Initial commit
+";
+
+#[test]
+fn commit_faces() {
+    test_cli(ProcessTest {
+        args: &["--colors", "commit-meta:foreground:blue"],
+        input: GIT_LOG,
+        out: AtLeast(
+            "\x1b[0m\x1b[33mcommit 89abcdef0123456789abcdef0123456789abcdef\x1b[0m
+\x1b[0m\x1b[34mAuthor: B <b@example.com>\x1b[0m
+\x1b[0m\x1b[34mDate:   Sun Dec 31 00:00:00 2023 +0000\x1b[0m
+",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn commit_format_short() {
+    test_cli(ProcessTest {
+        args: &["--commit-format", "short", "--color=never"],
+        input: GIT_LOG,
+        out: AtLeast(
+            "commit 89abcdef0123456789abcdef0123456789abcdef
+Author: B <b@example.com>
+
+    Initial commit
+",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn commit_format_oneline() {
+    test_cli(ProcessTest {
+        args: &["--commit-format", "oneline", "--color=never"],
+        input: GIT_LOG,
+        out: Exactly(
+            "0123456 (HEAD -> main) Fix the frobnicator
+diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1 +1 @@
+-a
++b
+89abcde Initial commit
+",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+const EXTENDED_HEADERS: &str = "diff --git a/old.sh b/new.sh
+old mode 100644
+new mode 100755
+similarity index 90%
+rename from old.sh
+rename to new.sh
+diff --git a/sub b/sub
+Submodule sub 1234567..89abcde:
+  > Fix things
+";
+
+#[test]
+fn extended_headers() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: EXTENDED_HEADERS,
+        out: Exactly(
+            "\x1b[0mdiff --git a/old.sh b/new.sh\x1b[0m
+\x1b[0m\x1b[36mold mode 100644\x1b[0m
+\x1b[0m\x1b[36mnew mode 100755\x1b[0m
+\x1b[0m\x1b[36msimilarity index 90%\x1b[0m
+\x1b[0m\x1b[36mrename from old.sh\x1b[0m
+\x1b[0m\x1b[36mrename to new.sh\x1b[0m
+\x1b[0mdiff --git a/sub b/sub\x1b[0m
+\x1b[0m\x1b[35mSubmodule sub 1234567..89abcde:\x1b[0m
+\x1b[0m  > Fix things\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn compact_headers() {
+    test_cli(ProcessTest {
+        args: &["--compact-headers", "--color=never"],
+        input: EXTENDED_HEADERS,
+        out: Exactly(
+            "diff --git a/old.sh b/new.sh
+rename old.sh => new.sh (90%), mode 100644 => 100755
+diff --git a/sub b/sub
+Submodule sub 1234567..89abcde:
+  > Fix things",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn rename_format_compact() {
+    test_cli(ProcessTest {
+        args: &["--rename-format=compact", "--color=never"],
+        input: "diff --git a/src/a/lib.rs b/src/b/lib.rs
+similarity index 95%
+rename from src/a/lib.rs
+rename to src/b/lib.rs
+old mode 100644
+new mode 100755
+",
+        out: Exactly(
+            "diff --git a/src/a/lib.rs b/src/b/lib.rs
+rename src/{a => b}/lib.rs (95%)
+old mode 100644
+new mode 100755",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn light_background() {
+    test_cli(ProcessTest {
+        args: &["--light"],
+        input: "@@ -1 +1 @@\n-foo bar\n+foo baz\n",
+        out: AtLeast(
+            "\x1b[0m\x1b[38;5;28mfoo \x1b[0m\x1b[0m\x1b[1m\x1b[38;5;22m\x1b[48;5;194mbaz\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn light_background_colors() {
+    test_cli(ProcessTest {
+        args: &["--light", "--colors", "added:foreground:blue"],
+        input: "@@ -1 +1 @@\n-foo bar\n+foo baz\n",
+        out: AtLeast("\x1b[0m\x1b[34mfoo \x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn help_colors() {
+    test_cli(ProcessTest {
+        args: &["--help-colors", "--colors", "added:foreground:blue"],
+        input: "",
+        out: AtLeast("    \x1b[0m\x1b[34madded\x1b[0m\n    \x1b[0m\x1b[1m\x1b[37m\x1b[42mrefine-added\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn help_colors_examples() {
+    test_cli(ProcessTest {
+        args: &["--help-colors", "--color=never"],
+        input: "",
+        out: AtLeast("    refine-added:background:blue:bold  sample\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn preview_colors() {
+    test_cli(ProcessTest {
+        args: &[
+            "--preview-colors",
+            "--colors",
+            "refine-added:background:blue",
+        ],
+        input: "",
+        out: AtLeast("\x1b[0m\x1b[32mfn \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[44mhello\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn preview_colors_compact() {
+    test_cli(ProcessTest {
+        args: &["--preview-colors", "--compact-headers", "--color=never"],
+        input: "",
+        out: AtLeast(
+            "diff --git a/src/greet.rs b/src/hello.rs
+rename src/greet.rs => src/hello.rs (80%), mode 100644 => 100755
+--- a/src/greet.rs",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn refine_block() {
+    test_cli(ProcessTest {
+        args: &["--refine-block"],
+        input: "@@ -1,3 +1,3 @@
+ ctx
+-// The quick brown fox jumps over
+-// the lazy dog.
++// The quick brown fox
++// jumps over the lazy cat.
+",
+        out: AtLeast(
+            "\x1b[0m\x1b[1m\x1b[37m\x1b[42m+// \x1b[0m\x1b[0m\x1b[32mjumps over\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m \x1b[0m\x1b[0m\x1b[32mthe lazy\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m cat\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn ignore_matching_lines() {
+    test_cli(ProcessTest {
+        args: &["--ignore-matching-lines", "^version = \"[\\d.]+\"$"],
+        input: "@@ -1,2 +1,2 @@
+-version = \"1.2.3\"
++version = \"1.2.4\"
+ ctx
+@@ -10 +10 @@
+-foo
++bar
+",
+        out: Exactly(
+            "\x1b[0m@@ -1,2 +1,2 @@\x1b[0m
+\x1b[0m\x1b[2m-version = \"1.2.3\"\x1b[0m
+\x1b[0m\x1b[2m+version = \"1.2.4\"\x1b[0m
+\x1b[0m ctx\x1b[0m
+\x1b[0m@@ -10 +10 @@\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[41m-foo\x1b[0m\x1b[0m\x1b[31m\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[42m+bar\x1b[0m\x1b[0m\x1b[32m\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn ignore_matching_lines_invalid() {
+    test_cli(ProcessTest {
+        args: &["--ignore-matching-lines", "(ab"],
+        input: "",
+        out: Empty,
+        err: AtLeast("unclosed group"),
+        is_success: false,
+    })
+}
+
+#[test]
+fn number_changed_face() {
+    test_cli(ProcessTest {
+        args: &["--colors", "number-changed:foreground:yellow"],
+        input: "@@ -1,2 +1,2 @@
+-version = \"1.2.3\"
+-call(foo, 10)
++version = \"1.2.4\"
++call(bar, 12)
+",
+        out: Exactly(
+            "\x1b[0m@@ -1,2 +1,2 @@\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mversion = \"1.2.\x1b[0m\x1b[0m\x1b[33m3\x1b[0m\x1b[0m\x1b[31m\"\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mcall(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mfoo\x1b[0m\x1b[0m\x1b[31m, \x1b[0m\x1b[0m\x1b[33m10\x1b[0m\x1b[0m\x1b[31m)\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mversion = \"1.2.\x1b[0m\x1b[0m\x1b[33m4\x1b[0m\x1b[0m\x1b[32m\"\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mcall(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mbar\x1b[0m\x1b[0m\x1b[32m, \x1b[0m\x1b[0m\x1b[33m12\x1b[0m\x1b[0m\x1b[32m)\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn blame_unknown_file() {
+    test_cli(ProcessTest {
+        args: &["--blame", "--color=never"],
+        input: "--- a/no/such/file
++++ b/no/such/file
+@@ -1 +1 @@
+-foo
++bar
+",
+        out: Exactly(
+            "--- a/no/such/file
++++ b/no/such/file
+@@ -1 +1 @@
+-foo
++bar",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
 #[test]
 fn debug_flag() {
     test_cli(ProcessTest {
         args: &["--debug"],
+        input: "",
         out: Empty,
         err: AtLeast("hunk processing time (ms):"),
         is_success: true,
     })
 }
 
+#[test]
+fn debug_json() {
+    test_cli(ProcessTest {
+        args: &["--debug=json", "--max-refine-cost", "10"],
+        input: "@@ -1 +1 @@
+-foo bar baz
++foo bar qux
+",
+        out: AtLeast("-foo bar baz"),
+        err: AtLeast("{\"path\":null,\"header\":\"-1,1 +1,1\",\"removed_lines\":1,\"added_lines\":1,\"removed_tokens\":6,\"added_tokens\":6,\"cost\":36,\"shared_tokens\":0,\"algorithm\":\"myers\",\"mode\":\"pairing\",\"skipped\":\"max-refine-cost\",\"cached\":false,"),
+        is_success: true,
+    })
+}
+
 #[test]
 fn color_invalid_face_name() {
     test_cli(ProcessTest {
         args: &["--colors", "notafacename"],
+        input: "",
         out: Empty,
-        err: Exactly("unexpected face name: got 'notafacename', expected added|refine-added|removed|refine-removed"),
+        err: Exactly("unexpected face name: got 'notafacename', expected added|refine-added|removed|refine-removed|line-number|no-newline|folded|case-changed|commit|commit-meta|mode|rename|submodule|ignored|number-changed|blame|apply-warning|apply-rejected|search-match|indent-changed|carriage-return|nonprinting|function-context|binary|masked|relocated|mail-header|message|ruler|overlong|annotation-error|annotation-warning|annotation-note|chunk-header"),
         is_success: false,
     })
 }
@@ -129,6 +591,7 @@ fn color_invalid_face_name() {
 fn color_only_face_name() {
     test_cli(ProcessTest {
         args: &["--colors", "added"],
+        input: "",
         out: Empty,
         err: Exactly(""),
         is_success: true,
@@ -139,8 +602,9 @@ fn color_only_face_name() {
 fn color_invalid_attribute_name() {
     test_cli(ProcessTest {
         args: &["--colors", "added:bar"],
+        input: "",
         out: Empty,
-        err: Exactly("unexpected attribute name: got 'bar', expected foreground|background|bold|nobold|intense|nointense|underline|nounderline|none"),
+        err: Exactly("unexpected attribute name: got 'bar', expected foreground|background|bold|nobold|intense|nointense|underline|nounderline|dim|nodim|italic|noitalic|strikethrough|nostrikethrough|none"),
         is_success: false,
     })
 }
@@ -149,6 +613,7 @@ fn color_invalid_attribute_name() {
 fn color_invalid_color_value_name() {
     test_cli(ProcessTest {
         args: &["--colors", "added:foreground:baz"],
+        input: "",
         out: Empty,
         err: Exactly("unexpected color value: unrecognized color name 'baz'. Choose from: black, blue, green, red, cyan, magenta, yellow, white"),
         is_success: false,
@@ -159,6 +624,7 @@ fn color_invalid_color_value_name() {
 fn color_invalid_color_value_ansi() {
     test_cli(ProcessTest {
         args: &["--colors", "added:foreground:777"],
+        input: "",
         out: Empty,
         err: AtLeast("unexpected color value: unrecognized ansi256 color number"),
         is_success: false,
@@ -169,6 +635,7 @@ fn color_invalid_color_value_ansi() {
 fn color_invalid_color_value_rgb() {
     test_cli(ProcessTest {
         args: &["--colors", "added:foreground:0,0,777"],
+        input: "",
         out: Empty,
         err: AtLeast("unexpected color value: unrecognized RGB color triple"),
         is_success: false,
@@ -179,6 +646,7 @@ fn color_invalid_color_value_rgb() {
 fn color_invalid_color_not_done() {
     test_cli(ProcessTest {
         args: &["--colors", "added:foreground"],
+        input: "",
         out: Empty,
         err: Exactly("error parsing color: missing color value for face 'added'"),
         is_success: false,
@@ -189,6 +657,7 @@ fn color_invalid_color_not_done() {
 fn color_ok() {
     test_cli(ProcessTest {
         args: &["--colors", "added:foreground:0"],
+        input: "",
         out: Empty,
         err: Exactly(""),
         is_success: true,
@@ -204,8 +673,2834 @@ fn color_ok_multiple() {
             "--colors",
             "removed:background:red",
         ],
+        input: "",
         out: Empty,
         err: Exactly(""),
         is_success: true,
     })
 }
+
+#[test]
+fn input_colors_stripped() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: "\x1b[1mdiff --git a/x b/x\x1b[m\n",
+        out: Exactly("\x1b[0mdiff --git a/x b/x\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn input_colors_kept() {
+    test_cli(ProcessTest {
+        args: &["--keep-input-colors"],
+        input: "\x1b[1mdiff --git a/x b/x\x1b[m\n",
+        out: Exactly("\x1b[1mdiff --git a/x b/x\x1b[m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn internal_pager_not_a_terminal() {
+    test_cli(ProcessTest {
+        args: &["--pager", "internal"],
+        input: "diff --git a/x b/x\n",
+        out: Exactly("\x1b[0mdiff --git a/x b/x\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn tui_not_a_terminal() {
+    test_cli(ProcessTest {
+        args: &["--tui"],
+        input: "diff --git a/x b/x\n",
+        out: Exactly("\x1b[0mdiff --git a/x b/x\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn line_numbers() {
+    test_cli(ProcessTest {
+        args: &[
+            "--line-numbers",
+            "--colors",
+            "added:none",
+            "--colors",
+            "removed:none",
+        ],
+        input: "@@ -9,2 +9,2 @@\n a\n-b\n+c\n",
+        out: Exactly(
+            "\x1b[0m@@ -9,2 +9,2 @@\x1b[0m
+\x1b[0m 9  9 | \x1b[0m\x1b[0m a\x1b[0m
+\x1b[0m10    | \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41m-b\x1b[0m\x1b[0m\x1b[0m
+\x1b[0m   10 | \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m+c\x1b[0m\x1b[0m\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn line_numbers_styles() {
+    let input = "@@ -1,3 +98,4 @@\n a\n-b\n+c\n+d\n e\n";
+    test_cli(ProcessTest {
+        args: &["--line-numbers", "both", "--color=never"],
+        input,
+        out: Exactly(
+            "@@ -1,3 +98,4 @@\n1  98 |  a\n2     | -b\n   99 | +c\n  100 | +d\n3 101 |  e",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--line-numbers", "right-only", "--color=never"],
+        input,
+        out: Exactly("@@ -1,3 +98,4 @@\n 98 |  a\n    | -b\n 99 | +c\n100 | +d\n101 |  e"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &[
+            "--line-numbers",
+            "both",
+            "--line-numbers-width",
+            "2",
+            "--color=never",
+        ],
+        input,
+        out: Exactly(
+            "@@ -1,3 +98,4 @@\n 1  98 |  a\n 2     | -b\n    99 | +c\n   100 | +d\n 3 101 |  e",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--line-numbers-format", "{old:<2}{new} │ ", "--color=never"],
+        input,
+        out: Exactly(
+            "@@ -1,3 +98,4 @@\n1  98 │  a\n2     │ -b\n   99 │ +c\n  100 │ +d\n3 101 │  e",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &[
+            "--line-numbers-format",
+            "{new}",
+            "--line-numbers-separator",
+            ":",
+        ],
+        input,
+        out: Empty,
+        err: AtLeast("cannot be used with"),
+        is_success: false,
+    });
+    for template in ["{bogus}", "{old"] {
+        let output = Command::new(diffr_path())
+            .args(["--line-numbers-format", template])
+            .env_remove("DIFFR_OPTIONS")
+            .stdin(Stdio::null())
+            .output()
+            .expect("output");
+        assert_eq!(Some(2), output.status.code());
+        let err = String::from_utf8_lossy(&output.stderr);
+        assert!(err.starts_with("invalid --line-numbers-format"), "{}", err);
+    }
+}
+
+#[test]
+fn hyperlinks_format() {
+    test_cli(ProcessTest {
+        args: &["--hyperlinks", "--hyperlink-format", "editor:{line}"],
+        input: "+++ b/x\n@@ -9 +9 @@\n",
+        out: Exactly(
+            "\x1b]8;;editor:1\x1b\\\x1b[0m+++ b/x\x1b[0m\x1b]8;;\x1b\\
+\x1b]8;;editor:9\x1b\\\x1b[0m@@ -9 +9 @@\x1b[0m\x1b]8;;\x1b\\",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn repo_url() {
+    test_cli(ProcessTest {
+        args: &[
+            "--output",
+            "html",
+            "--repo-url",
+            "https://example.com/blob/{commit}/{path}#L{line}",
+            "--line-numbers",
+        ],
+        input: "commit 0123abc
+diff --git a/src/a b.rs b/src/a b.rs
+--- a/src/a b.rs
++++ b/src/a b.rs
+@@ -3 +3 @@
+ x
+",
+        out: AtLeast("<a href=\"https://example.com/blob/0123abc/src/a%20b.rs#L3\">3 3</a> |  x"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn binary_placeholders() {
+    let input = "diff --git a/img.png b/img.png
+GIT binary patch
+literal 13
+UcmeAS@N?(olHy`uU}Rzj01~4CumAu6
+
+literal 10
+RcmeAS@N?(olHy`u000e}0jdB1
+
+diff --git a/a.bin b/a.bin
+Binary files /dev/null and b/a.bin differ
+";
+    test_cli(ProcessTest {
+        args: &["--color", "never"],
+        input,
+        out: Exactly(
+            "diff --git a/img.png b/img.png
+binary file img.png: 10 B -> 13 B
+diff --git a/a.bin b/a.bin
+binary file a.bin: added",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--color", "never", "--binary", "raw"],
+        input,
+        out: AtLeast("literal 13\nUcmeAS@N?(olHy`uU}Rzj01~4CumAu6\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+/// Runs diffr with --emit-test-expectation on the diff `path`.
+fn emit_test_expectation(path: &Path) -> String {
+    let output = Command::new(diffr_path())
+        .arg("--emit-test-expectation")
+        .arg("--input")
+        .arg(path)
+        .env_remove("DIFFR_OPTIONS")
+        .output()
+        .expect("output");
+    assert!(output.status.success(), "{}: failure", path.display());
+    String::from_utf8(output.stdout).expect("utf-8 output")
+}
+
+/// Compares the refinement of each tests/golden/NAME.diff to
+/// tests/golden/NAME.expected. With DIFFR_BLESS set, the expectations
+/// are written instead, to be reviewed with git diff.
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden");
+    let bless = env::var_os("DIFFR_BLESS").is_some();
+    let mut paths = fs::read_dir(&dir)
+        .expect("read_dir")
+        .map(|entry| entry.expect("entry").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "diff")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no golden test in {}", dir.display());
+    let mut failures = vec![];
+    for path in paths {
+        let actual = emit_test_expectation(&path);
+        let expected_path = path.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &actual).expect("write");
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!(
+                "{}: expected\n\n{}\ngot\n\n{}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\n\nrun with DIFFR_BLESS=1 to accept the changes",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn output_annotated() {
+    test_cli(ProcessTest {
+        args: &["--output", "annotated", "--line-numbers"],
+        input: "--- a/x.rs
++++ b/x.rs
+@@ -1,2 +1,2 @@
+-x = f(a, b);
++y = f(a, c);
+ end
+",
+        out: Exactly(
+            "--- a/x.rs
++++ b/x.rs
+@@ -1,2 +1,2 @@
+-[-x-] = f(a, [-b-]);
++{+y+} = f(a, {+c+});
+ end",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn exclude_path() {
+    test_cli(ProcessTest {
+        args: &["--exclude", "*.lock", "--colors", "added:none"],
+        input: "diff --git a/Cargo.lock b/Cargo.lock
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -1 +1 @@
++x
+diff --git a/a.rs b/a.rs
+",
+        out: Exactly(
+            "\x1b[0mskipped: Cargo.lock\x1b[0m
+\x1b[0mdiff --git a/a.rs b/a.rs\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn generated_files() {
+    let input = "diff --git a/Cargo.lock b/Cargo.lock
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -1,2 +1,2 @@
+ a
+-b
++c
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1 +1,2 @@
+-x
++y
++z
+";
+    test_cli(ProcessTest {
+        args: &["--colors", "folded:none", "--color=always"],
+        input,
+        out: AtLeast("\x1b[0mgenerated: Cargo.lock +1 -1\x1b[0m\n\x1b[0mdiff --git a/a.rs b/a.rs"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--generated", "*.rs", "--color=never"],
+        input,
+        out: Exactly("generated: Cargo.lock +1 -1\ngenerated: a.rs +2 -1"),
+        err: Empty,
+        is_success: true,
+    });
+    for args in [
+        &["--expand-generated", "--color=never"][..],
+        &["--output", "patch"][..],
+    ] {
+        test_cli(ProcessTest {
+            args,
+            input,
+            out: Exactly(input),
+            err: Empty,
+            is_success: true,
+        });
+    }
+}
+
+#[test]
+fn stats() {
+    test_cli(ProcessTest {
+        args: &["--stats"],
+        input: "--- a/x.rs
++++ b/x.rs
+@@ -1 +1 @@
+-a b c
++a d c
+",
+        out: AtLeast(
+            "\x1b[0mx.rs: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +6 -6)\x1b[0m
+\x1b[0mtotal: 1 file, 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +6 -6)\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn diffstat() {
+    test_cli(ProcessTest {
+        args: &["--diffstat"],
+        input: "--- a/x.rs
++++ b/x.rs
+@@ -1 +1,2 @@
+-a b c
++a d c
++e
+",
+        out: AtLeast(
+            "\x1b[0m x.rs | 3 \x1b[0m\x1b[0m\x1b[32m++\x1b[0m\x1b[0m\x1b[31m-\x1b[0m\x1b[0m\x1b[0m
+\x1b[0m 1 file changed, 2 insertions(+), 1 deletion(-)\x1b[0m
+\x1b[0m--- a/x.rs\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn refine_threshold() {
+    test_cli(ProcessTest {
+        args: &["--refine-threshold", "50"],
+        input: "@@ -1,2 +1,2 @@
+-foo bar baz
+-hello world
++foo bar qux
++goodbye all
+",
+        out: AtLeast("\x1b[0m\x1b[31m-hello world\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn refine_threshold_invalid() {
+    test_cli(ProcessTest {
+        args: &["--refine-threshold", "150"],
+        input: "",
+        out: Empty,
+        err: AtLeast("'150' is not a percentage between 0 and 100"),
+        is_success: false,
+    })
+}
+
+#[test]
+fn max_refine_cost() {
+    test_cli(ProcessTest {
+        args: &["--max-refine-cost", "10", "--debug"],
+        input: "@@ -1 +1 @@
+-foo bar baz
++foo bar qux
+",
+        out: AtLeast("\x1b[0m\x1b[31m-foo bar baz\x1b[0m\n\x1b[0m\x1b[32m+foo bar qux\x1b[0m\n"),
+        err: AtLeast("refinement skipped in <unknown>: cost 36 (+6 -6 tokens) exceeds 10"),
+        is_success: true,
+    })
+}
+
+#[test]
+fn unicode_tokenize() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1 +1 @@
+-café
++cafè
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[41m-café\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn invalid_bytes_hex() {
+    // the input is not valid UTF-8, so it is written as bytes
+    let mut child = Command::new(diffr_path())
+        .args(["--invalid-bytes", "hex"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .env_remove("DIFFR_OPTIONS")
+        .env_remove("NO_COLOR")
+        .spawn()
+        .expect("spawn");
+    let mut stdin = child.stdin.take().expect("stdin");
+    stdin
+        .write_all(b"@@ -1 +1 @@\n-a\xffb\n+a\xffc\n")
+        .expect("write_all");
+    drop(stdin);
+    let output = child.wait_with_output().expect("wait_with_output");
+    assert!(output.status.success());
+    assert_eq!("", String::from_utf8_lossy(&output.stderr));
+    let out = String::from_utf8(output.stdout).expect("utf-8 output");
+    AtLeast("\x1b[0m\x1b[31ma\\xff\x1b[0m").test(&out, "stdout");
+    AtLeast("\x1b[0m\x1b[32ma\\xff\x1b[0m").test(&out, "stdout");
+}
+
+#[test]
+fn binary_files_stats() {
+    test_cli(ProcessTest {
+        args: &["--stats"],
+        input: "diff --git a/img.png b/img.png
+index 1234567..89abcde 100644
+Binary files a/img.png and b/img.png differ
+",
+        out: AtLeast("\x1b[0mimg.png: binary\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn tabs() {
+    test_cli(ProcessTest {
+        args: &["--tabs", "4"],
+        input: "@@ -1 +1 @@
+ a\tb
+",
+        out: AtLeast("\x1b[0m a   b\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn word_diff() {
+    test_cli(ProcessTest {
+        args: &["--word-diff"],
+        input: "@@ -1,2 +1,2 @@
+ ctx
+-foo bar
++foo qux
+",
+        out: AtLeast(
+            "\x1b[0mctx\x1b[0m
+\x1b[0mfoo\x1b[0m\x1b[0m \x1b[0m\x1b[0m\x1b[31mbar\x1b[0m\x1b[0m\x1b[32mqux\x1b[0m\x1b[0m\x1b[0m
+",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn color_strikethrough() {
+    test_cli(ProcessTest {
+        args: &["--colors", "removed:strikethrough:dim"],
+        input: "@@ -1 +1 @@
+-a b
++a c
+",
+        out: AtLeast("\x1b[0m\x1b[2m\x1b[9m\x1b[31ma \x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn color_never() {
+    test_cli(ProcessTest {
+        args: &["--color", "never", "--hyperlinks"],
+        input: "\x1b[32m+++ b/x\x1b[0m
+@@ -1 +1 @@
+-a b
++a c
+",
+        out: Exactly("+++ b/x\n@@ -1 +1 @@\n-a b\n+a c"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn color_auto_not_a_terminal() {
+    test_cli(ProcessTest {
+        args: &["--color", "auto"],
+        input: "@@ -1 +0,0 @@
+-a
+",
+        out: Exactly("@@ -1 +0,0 @@\n-a"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn max_line_length() {
+    test_cli(ProcessTest {
+        args: &["--max-line-length", "4", "--truncation-marker", "..."],
+        input: "@@ -1 +1 @@
+ abcdef
+",
+        out: AtLeast("\x1b[0m abc...\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn no_newline_marker() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1 +1 @@
+-a b
+\\ No newline at end of file
++a c
+",
+        out: Exactly(
+            "\x1b[0m@@ -1 +1 @@\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31ma \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mb\x1b[0m\x1b[0m\x1b[31m\x1b[0m
+\x1b[0m\x1b[2m\\ No newline at end of file\x1b[0m
+\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32ma \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mc\x1b[0m\x1b[0m\x1b[32m\x1b[0m",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn lenient_headers() {
+    test_cli(ProcessTest {
+        args: &["--lenient", "--stats"],
+        input: "Index: x.c
+===================================================================
+--- x.c\t(revision 1)
++++ x.c\t(working copy)
+@@ -1 +1 @@
+-a b
++a c
+--- y.c
++++ y.c
+@@ -1,2 +1,2 @@
+-p
++q
+
+",
+        out: AtLeast("x.c: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +4 -4)"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn git_revisions() {
+    let dir = env::temp_dir().join(format!("diffr_test_git_revisions_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=diffr",
+                "-c",
+                "user.email=diffr@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .status()
+            .expect("git");
+        assert!(status.success(), "git {:?}", args);
+    };
+    git(&["init", "-q"]);
+    fs::write(dir.join("src/a.rs"), "let x = 1;\nfn f() {}\nlet z = 3;\n").unwrap();
+    fs::write(dir.join("b.txt"), "b\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "first"]);
+    fs::write(dir.join("src/a.rs"), "let y = 1;\nfn f() {}\nlet z = 3;\n").unwrap();
+    fs::write(dir.join("b.txt"), "c\n").unwrap();
+    git(&["commit", "-q", "-a", "-m", "second"]);
+    let args = [
+        "--git",
+        "HEAD~1..HEAD",
+        "--context",
+        "1",
+        "--output",
+        "annotated",
+    ];
+    let output = Command::new(diffr_path())
+        .args(args)
+        .args(["--", "src"])
+        .env_remove("DIFFR_OPTIONS")
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("spawn");
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    let out = String::from_utf8_lossy(&output.stdout);
+    // the diff of the paths after "--", with the context of --context
+    assert!(
+        out.contains(
+            "+++ b/src/a.rs\n@@ -1,2 +1,2 @@\n-let [-x-] = 1;\n+let {+y+} = 1;\n fn f() {}\n"
+        ),
+        "{}",
+        out
+    );
+    assert!(!out.contains("b.txt"), "{}", out);
+}
+
+#[test]
+fn context_reslice() {
+    test_cli(ProcessTest {
+        args: &["--color", "never", "--context", "1"],
+        input: "@@ -1,9 +1,9 @@ fn main
+ c1
+ c2
+-a
++b
+ c3
+ c4
+ c5
+-d
++e
+ c6
+",
+        out: Exactly(
+            "@@ -2,3 +2,3 @@ fn main
+ c2
+-a
++b
+ c3
+@@ -6,3 +6,3 @@ fn main
+ c5
+-d
++e
+ c6",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn collapse_context() {
+    test_cli(ProcessTest {
+        args: &["--color", "never", "--collapse-context=2", "--line-numbers"],
+        input: "@@ -1,9 +1,9 @@
+ c1
+ c2
+ c3
+-a
++b
+ c4
+ c5
+ c6
+ c7
+ c8
+",
+        out: Exactly(
+            "@@ -1,9 +1,9 @@
+ 1  1 |  c1
+ 2  2 |  c2
+ 3  3 |  c3
+ 4    | -a
+    4 | +b
+ 5  5 |  c4
+ 6  6 |  c5
+      | ··· 3 unchanged lines ···",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn lang_json() {
+    test_cli(ProcessTest {
+        args: &["--lang", "json"],
+        input: "@@ -1 +1 @@
+-\"some value\"
++\"some other\"
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\"some other\"\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn lang_shebang() {
+    test_cli(ProcessTest {
+        args: &["--lang", "auto"],
+        input: "--- a/tool
++++ b/tool
+@@ -1,2 +1,2 @@
+ #!/usr/bin/env python3
+-x = a<=b
++x = a>=b
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[42m>=\x1b[0m\x1b[0m\x1b[32mb\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn ignore_case() {
+    test_cli(ProcessTest {
+        args: &["--ignore-case"],
+        input: "@@ -1 +1 @@
+-SELECT a
++select b
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mselect \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mb\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn algorithm_myers() {
+    test_cli(ProcessTest {
+        args: &["--algorithm", "myers"],
+        input: "@@ -1 +1 @@
+-foo(a, b) + bar(c, d)
++bar(c, d) + foo(a, b)
+",
+        out: Exactly("\x1b[0m@@ -1 +1 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-foo\x1b[0m\x1b[0m\x1b[31m(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41ma\x1b[0m\x1b[0m\x1b[31m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mb\x1b[0m\x1b[0m\x1b[31m) + \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mbar\x1b[0m\x1b[0m\x1b[31m(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mc\x1b[0m\x1b[0m\x1b[31m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41md\x1b[0m\x1b[0m\x1b[31m)\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+bar\x1b[0m\x1b[0m\x1b[32m(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mc\x1b[0m\x1b[0m\x1b[32m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42md\x1b[0m\x1b[0m\x1b[32m) + \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mfoo\x1b[0m\x1b[0m\x1b[32m(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42ma\x1b[0m\x1b[0m\x1b[32m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mb\x1b[0m\x1b[0m\x1b[32m)\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn algorithm_patience() {
+    test_cli(ProcessTest {
+        args: &["--algorithm", "patience"],
+        input: "@@ -1 +1 @@
+-foo(a, b) + bar(c, d)
++bar(c, d) + foo(a, b)
+",
+        out: Exactly("\x1b[0m@@ -1 +1 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-foo(a, b) + \x1b[0m\x1b[0m\x1b[31mbar(c, d)\x1b[0m\x1b[0m\x1b[31m\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mbar(c, d)\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m + foo(a, b)\x1b[0m\x1b[0m\x1b[32m\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn algorithm_histogram() {
+    test_cli(ProcessTest {
+        args: &["--algorithm", "histogram"],
+        input: "@@ -1 +1 @@
+-let x = compute(alpha, beta);
++let total = 0; let x = compute(beta, alpha);
+",
+        out: Exactly("\x1b[0m@@ -1 +1 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mlet \x1b[0m\x1b[0m\x1b[31mx = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41malpha\x1b[0m\x1b[0m\x1b[31m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mbeta\x1b[0m\x1b[0m\x1b[31m);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mlet \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mtotal = 0; let \x1b[0m\x1b[0m\x1b[32mx = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mbeta\x1b[0m\x1b[0m\x1b[32m, \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42malpha\x1b[0m\x1b[0m\x1b[32m);\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn line_pairing() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1,2 +1,2 @@
+-let alpha = compute(one);
+-print(total);
++print(total, extra);
++let alpha = compute(two);
+",
+        out: Exactly("\x1b[0m@@ -1,2 +1,2 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mlet alpha = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mone\x1b[0m\x1b[0m\x1b[31m);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-print(total);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+print(total, extra);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mlet alpha = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mtwo\x1b[0m\x1b[0m\x1b[32m);\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn no_line_pairing() {
+    test_cli(ProcessTest {
+        args: &["--no-line-pairing"],
+        input: "@@ -1,2 +1,2 @@
+-let alpha = compute(one);
+-print(total);
++print(total, extra);
++let alpha = compute(two);
+",
+        out: Exactly("\x1b[0m@@ -1,2 +1,2 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mlet alpha = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mone);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-print(total\x1b[0m\x1b[0m\x1b[31m);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+print(total, extra);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mlet alpha = compute(\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mtwo\x1b[0m\x1b[0m\x1b[32m);\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn line_pairing_shared_content() {
+    // the removed line is paired with the line that contains it, not
+    // with the line that has the most punctuation in common
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1,2 +1,5 @@
+ atty = \"0.2\"
+-diffr-lib = \"0.1.2\"
++crossterm = \"0.28\"
++diffr-lib = { version = \"0.1.2\", path = \"diffr-lib\" }
++unicode-segmentation = \"1\"
++unicode-width = \"0.1\"
+",
+        out: Exactly("\x1b[0m@@ -1,2 +1,5 @@\x1b[0m\n\x1b[0m atty = \"0.2\"\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mdiffr-lib\x1b[0m\x1b[0m\x1b[31m = \"0.1.2\"\x1b[0m\x1b[0m\x1b[31m\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+crossterm = \"0.28\"\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mdiffr-lib\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m = { version\x1b[0m\x1b[0m\x1b[32m = \"0.1.2\"\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42m, path = \"diffr-lib\" }\x1b[0m\x1b[0m\x1b[32m\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+unicode-segmentation = \"1\"\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+unicode-width = \"0.1\"\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn pairing_threshold() {
+    test_cli(ProcessTest {
+        args: &["--pairing-threshold", "0.5"],
+        input: "@@ -1 +1,2 @@
+-let x = compute(a, b);
++let y = compute(a, b);
++totally unrelated stuff here
+",
+        out: Exactly("\x1b[0m@@ -1 +1,2 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mlet \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mx\x1b[0m\x1b[0m\x1b[31m = compute(a, b);\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mlet \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42my\x1b[0m\x1b[0m\x1b[32m = compute(a, b);\x1b[0m\n\x1b[0m\x1b[32m+totally unrelated stuff here\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--pairing-threshold", "2"],
+        input: "",
+        out: Empty,
+        err: AtLeast("'2' is not a score between 0 and 1"),
+        is_success: false,
+    })
+}
+
+#[test]
+fn ignore_token() {
+    test_cli(ProcessTest {
+        args: &["--ignore-token", "[0-9a-f]{7}"],
+        input: "@@ -1 +1 @@
+-rev 1a2b3c4 old
++rev 9f8e7d6 new
+",
+        out: Exactly("\x1b[0m@@ -1 +1 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mrev \x1b[0m\x1b[0m\x1b[2m1a2b3c4\x1b[0m\x1b[0m\x1b[31m \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mold\x1b[0m\x1b[0m\x1b[31m\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mrev \x1b[0m\x1b[0m\x1b[2m9f8e7d6\x1b[0m\x1b[0m\x1b[32m \x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[42mnew\x1b[0m\x1b[0m\x1b[32m\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn refine_auto() {
+    test_cli(ProcessTest {
+        args: &["--refine", "auto"],
+        input: "@@ -1 +1 @@
+-let colour = 1;
++let color = 1;
+",
+        out: Exactly("\x1b[0m@@ -1 +1 @@\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[41m-\x1b[0m\x1b[0m\x1b[31mlet colo\x1b[0m\x1b[0m\x1b[1m\x1b[37m\x1b[41mu\x1b[0m\x1b[0m\x1b[31mr = 1;\x1b[0m\n\x1b[0m\x1b[1m\x1b[37m\x1b[42m+\x1b[0m\x1b[0m\x1b[32mlet colo\x1b[0m\x1b[0m\x1b[32mr = 1;\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    });
+    // the lines longer than --refine-char-width are refined by word
+    test_cli(ProcessTest {
+        args: &["--refine", "auto", "--refine-char-width", "10"],
+        input: "@@ -1 +1 @@
+-let colour = 1;
++let color = 1;
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[41mcolour\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn ignore_case_face() {
+    test_cli(ProcessTest {
+        args: &[
+            "--ignore-case",
+            "--colors",
+            "case-changed:foreground:yellow",
+        ],
+        input: "@@ -1 +1 @@
+-SELECT a
++select b
+",
+        out: AtLeast("\x1b[0m\x1b[33mselect\x1b[0m\x1b[0m\x1b[32m \x1b[0m"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_markdown() {
+    test_cli(ProcessTest {
+        args: &["--output", "markdown"],
+        input: "--- a/x
++++ b/x
+@@ -1 +1 @@
+-a b
++a c
+",
+        out: Exactly("```diff\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a b\n+a c\n```"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_svg() {
+    test_cli(ProcessTest {
+        args: &["--output", "svg"],
+        input: "@@ -1 +1 @@
+-a <b>
++a <c>
+",
+        out: AtLeast("<tspan x=\"16.4\" fill=\"#cd0000\">a &lt;</tspan><tspan x=\"41.6\" fill=\"#e5e5e5\" font-weight=\"bold\">b</tspan>"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_svg_wide_characters() {
+    test_cli(ProcessTest {
+        args: &["--output", "svg"],
+        input: "@@ -1 +1 @@
+-字a
++字b
+",
+        out: AtLeast("<tspan x=\"16.4\" fill=\"#cd0000\">字</tspan><tspan x=\"33.2\" fill=\"#e5e5e5\" font-weight=\"bold\">a</tspan>"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_quickfix() {
+    test_cli(ProcessTest {
+        args: &["--output", "quickfix", "--line-numbers"],
+        input: "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3,6 +3,5 @@ fn main() {
+ a
+-b
++B
+ c
+ d
+-e
+-f
++  g
+@@ -20,2 +20,3 @@
+ x
++y
+ z
+--- a/gone.txt
++++ /dev/null
+@@ -1,2 +0,0 @@
+-one
+-two
+",
+        out: Exactly(
+            "src/lib.rs:4: changed 1 line into 1: B
+src/lib.rs:7: changed 2 lines into 1: g
+src/lib.rs:21: added 1 line: y
+gone.txt:1: removed 2 lines: one",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_sexp() {
+    test_cli(ProcessTest {
+        args: &["--output", "sexp", "--colors", "added:foreground:blue"],
+        input: "--- a/x
++++ b/x
+@@ -1,2 +1,2 @@
+ \"é\"
+-a b
++a c
+\\ No newline at end of file
+",
+        out: Exactly(
+            "(hunk :old-path \"x\" :path \"x\" :old-start 1 :old-len 2 :new-start 1 :new-len 2 \
+             :lines ((context \"\\\"é\\\"\") (removed \"a b\" (2 . 3)) (added \"a c\" (2 . 3)) (no-newline)))",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_structured_faces() {
+    // the structured outputs describe the refinement, whatever its faces
+    let input = "--- a/x
++++ b/x
+@@ -1,2 +1,2 @@
+ a
+-let x = 1;
++let y = 1;
+";
+    let faces: &[&[&str]] = &[
+        &[
+            "--colors",
+            "refine-added:nobold",
+            "--colors",
+            "refine-removed:nobold",
+        ],
+        &["--theme", "deuteranopia"],
+    ];
+    for format in ["sexp", "json", "json-stream", "annotated", "quickfix"] {
+        let timeout = Duration::from_secs(10);
+        let args = ["--output", format];
+        let expected = output_within(&args, input.into(), timeout).expect("exit");
+        assert!(expected.contains("y"), "{}", expected);
+        for faces in faces {
+            let args = [&args[..], faces].concat();
+            let actual = output_within(&args, input.into(), timeout).expect("exit");
+            assert_eq!(expected, actual, "{:?}", args);
+        }
+    }
+}
+
+#[test]
+fn output_sexp_ruler() {
+    test_cli(ProcessTest {
+        args: &["--output", "sexp", "--ruler", "3"],
+        input: "--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+-a
++b
+ c
+",
+        out: Exactly(
+            "(hunk :old-path \"f\" :path \"f\" :old-start 1 :old-len 2 :new-start 1 :new-len 2 \
+             :lines ((removed \"a\" (0 . 1)) (added \"b\" (0 . 1)) (context \"c\")))",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_sexp_display_options() {
+    test_cli(ProcessTest {
+        args: &["--output", "sexp", "--indent-changed", "--line-numbers"],
+        input: "--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+-a
++  a
+ c
+",
+        out: Exactly(
+            "(hunk :old-path \"f\" :path \"f\" :old-start 1 :old-len 2 :new-start 1 :new-len 2 \
+             :lines ((removed \"a\") (added \"  a\" (0 . 2)) (context \"c\")))",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_sexp_merge_hunks() {
+    test_cli(ProcessTest {
+        args: &["--output", "sexp", "--merge-hunks", "20"],
+        input: "--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+-a
++b
+ c
+@@ -10,2 +10,2 @@
+-x
++y
+ z
+",
+        out: Exactly(
+            "(hunk :old-path \"f\" :path \"f\" :old-start 1 :old-len 2 :new-start 1 :new-len 2 \
+             :lines ((removed \"a\" (0 . 1)) (added \"b\" (0 . 1)) (context \"c\")))
+(hunk :old-path \"f\" :path \"f\" :old-start 10 :old-len 2 :new-start 10 :new-len 2 \
+             :lines ((removed \"x\" (0 . 1)) (added \"y\" (0 . 1)) (context \"z\")))",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_quickfix_merge_hunks() {
+    test_cli(ProcessTest {
+        args: &["--output", "quickfix", "--merge-hunks", "20"],
+        input: "--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+-a
++b
+ c
+@@ -10,2 +10,2 @@
+-x
++y
+ z
+",
+        out: Exactly("f:1: changed 1 line into 1: b\nf:10: changed 1 line into 1: y"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_json() {
+    test_cli(ProcessTest {
+        args: &["--output", "json"],
+        input: "--- a/x
++++ b/x
+@@ -1 +1 @@
+-a\tb
++a\tc
+",
+        out: Exactly(
+            "[
+{\"old_path\":\"x\",\"path\":\"x\",\"old_start\":1,\"old_len\":1,\"new_start\":1,\"new_len\":1,\"lines\":[\
+{\"kind\":\"removed\",\"content\":\"a\\tb\",\"refined\":[[2,3]],\
+\"refined_bytes\":[[2,3]],\"refined_columns\":[[8,9]]},\
+{\"kind\":\"added\",\"content\":\"a\\tc\",\"refined\":[[2,3]],\
+\"refined_bytes\":[[2,3]],\"refined_columns\":[[8,9]]}],\
+\"edits\":[{\"op\":\"replace\",\"old\":\"b\",\"old_range\":[[0,2],[0,3]],\
+\"new\":\"c\",\"new_range\":[[1,2],[1,3]]}]}
+]",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_json_edits() {
+    // the edits follow the refinement, here by character and without
+    // the ignored tokens
+    test_cli(ProcessTest {
+        args: &[
+            "--output",
+            "json",
+            "--refine",
+            "char",
+            "--ignore-token",
+            "[a-f0-9]{6}",
+        ],
+        input: "--- a/x
++++ b/x
+@@ -1 +1 @@
+-id=abc123 colour
++id=def456 color
+",
+        out: AtLeast(
+            "\"edits\":[{\"op\":\"delete\",\"old\":\"u\",\"old_range\":[[0,14],[0,15]],\
+\"new\":\"\",\"new_range\":null}]}",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn output_json_stream() {
+    test_cli(ProcessTest {
+        args: &["--output", "json-stream"],
+        input: "diff --git a/x b/x
+--- a/x
++++ b/x
+@@ -1,2 +1,2 @@
+-a\tb
++a\tc
+ d
+diff --git a/y b/y
+new file mode 100644
+",
+        out: Exactly(
+            "{\"event\":\"file-start\",\"old_path\":\"x\",\"path\":\"x\"}
+{\"event\":\"hunk-start\",\"old_start\":1,\"old_len\":2,\"new_start\":1,\"new_len\":2}
+{\"event\":\"line\",\"kind\":\"removed\",\"old_line\":1,\"new_line\":null,\"content\":\"a\\tb\"}
+{\"event\":\"span\",\"start\":2,\"end\":3,\"start_byte\":2,\"end_byte\":3,\"start_column\":8,\"end_column\":9}
+{\"event\":\"line\",\"kind\":\"added\",\"old_line\":null,\"new_line\":1,\"content\":\"a\\tc\"}
+{\"event\":\"span\",\"start\":2,\"end\":3,\"start_byte\":2,\"end_byte\":3,\"start_column\":8,\"end_column\":9}
+{\"event\":\"line\",\"kind\":\"context\",\"old_line\":2,\"new_line\":2,\"content\":\"d\"}
+{\"event\":\"file-end\",\"path\":\"x\"}",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn serve() {
+    test_cli(ProcessTest {
+        args: &["--serve", "--color=never"],
+        input: "terminal 22
+@@ -1 +1 @@
+-a b
++a c
+sexp 22
+@@ -1 +1 @@
+-a b
++a c
+bogus 0
+",
+        out: Exactly(
+            "ok 22
+@@ -1 +1 @@
+-a b
++a c
+ok 134
+(hunk :old-path nil :path nil :old-start 1 :old-len 1 :new-start 1 :new-len 1 \
+:lines ((removed \"a b\" (2 . 3)) (added \"a c\" (2 . 3))))
+error 109
+got 'bogus', expected terminal|markdown|markdown-html|svg|html|quickfix|sexp|json|json-stream|annotated|patch",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn serve_malformed() {
+    test_cli(ProcessTest {
+        args: &["--serve"],
+        input: "terminal\n",
+        out: Exactly("error 29\nmalformed request: 'terminal'"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn redact_patch() {
+    for args in [
+        &["--output", "patch", "--redact", "hello"][..],
+        &[
+            "--output-file",
+            "target/test_redact.patch",
+            "--redact",
+            "hello",
+        ][..],
+    ] {
+        test_cli(ProcessTest {
+            args,
+            input: "",
+            out: Empty,
+            err: Exactly(
+                "--redact cannot be used with the patch format: the patch would not apply",
+            ),
+            is_success: false,
+        });
+    }
+    test_cli(ProcessTest {
+        args: &["--serve", "--redact", "hello"],
+        input: "patch 0\n",
+        out: Exactly(
+            "error 72\n--redact cannot be used with the patch format: the patch would not apply",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn serve_too_large() {
+    test_cli(ProcessTest {
+        args: &["--serve", "--color=never"],
+        input: "terminal 99999999
+@@ -1 +1 @@
+",
+        out: Exactly("error 50\nrequest too large: 99999999 bytes, at most 8388608"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn watch_without_command() {
+    test_cli(ProcessTest {
+        args: &["--watch"],
+        input: "",
+        out: Empty,
+        err: Exactly("--watch needs a command after '--', or --git"),
+        is_success: false,
+    })
+}
+
+#[test]
+fn watch_renders_again() {
+    let dir = env::temp_dir().join(format!("diffr_test_watch_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("input.diff"),
+        "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+b\n",
+    )
+    .unwrap();
+    let mut child = Command::new(diffr_path())
+        .args(["--color=never", "--watch", "--", "cat", "input.diff"])
+        .env_remove("DIFFR_OPTIONS")
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn");
+    let mut stdout = child.stdout.take().expect("stdout");
+    let (sender, chunks) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        while let Ok(len @ 1..) = stdout.read(&mut buf) {
+            if sender.send(buf[..len].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    // the output read until it contains `expected`, within 10 seconds
+    let mut out = vec![];
+    let mut read_until = |expected: &str| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !String::from_utf8_lossy(&out).contains(expected) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match chunks.recv_timeout(timeout) {
+                Ok(chunk) => out.extend(chunk),
+                Err(_) => return None,
+            }
+        }
+        Some(String::from_utf8_lossy(&out).into_owned())
+    };
+    let first = read_until("+b");
+    // the change of the file renders the diff again
+    fs::write(
+        dir.join("input.diff"),
+        "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+c\n",
+    )
+    .unwrap();
+    let second = first.as_ref().and_then(|_| read_until("+c"));
+    let _ = child.kill();
+    let _ = child.wait();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(first.is_some(), "no first rendering");
+    let second = second.expect("no second rendering");
+    assert!(2 <= second.matches("\x1b[H\x1b[2J").count(), "{:?}", second);
+}
+
+#[test]
+fn paths_without_git() {
+    test_cli(ProcessTest {
+        args: &["--", "src"],
+        input: "",
+        out: Empty,
+        err: Exactly("the arguments after '--' need --git or --watch"),
+        is_success: false,
+    })
+}
+
+#[test]
+fn conflicts() {
+    let path = "target/test_conflicts.rs";
+    std::fs::write(
+        path,
+        "fn main() {
+<<<<<<< HEAD
+    let x = 1;
+||||||| base
+    let x = 0;
+=======
+    let x = 2;
+>>>>>>> topic
+}
+",
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--conflicts",
+            "target/test_conflicts.rs",
+            "--stats",
+            "--color=never",
+        ],
+        input: "",
+        out: Exactly(
+            " fn main() {
+<<<<<<< HEAD
+-    let x = 1;
+||||||| base
+     let x = 0;
+=======
++    let x = 2;
+>>>>>>> topic
+ }
+target/test_conflicts.rs: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +10 -10)
+total: 1 file, 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +10 -10)",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn three_way() {
+    let (ours, theirs) = ("target/test_ours.diff", "target/test_theirs.diff");
+    std::fs::write(
+        ours,
+        "--- a/f.rs
++++ b/f.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    let x = 1;
++    let x = 2;
+     let y = 3;
+",
+    )
+    .unwrap();
+    std::fs::write(
+        theirs,
+        "--- a/f.rs
++++ b/f.rs
+@@ -2,3 +2,3 @@
+     let x = 1;
+     let y = 3;
+-    let z = 4;
++    let z = 5;
+",
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--three-way",
+            "target/test_ours.diff",
+            "target/test_theirs.diff",
+            "--color=never",
+        ],
+        input: "",
+        out: Exactly(
+            "three-way f.rs
+ fn main() {
+<<<<<<< ours
+-    let x = 2;
+||||||| base
+     let x = 1;
+=======
++    let x = 1;
+>>>>>>> theirs (unchanged)
+     let y = 3;
+<<<<<<< ours (unchanged)
+-    let z = 4;
+||||||| base
+     let z = 4;
+=======
++    let z = 5;
+>>>>>>> theirs",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    std::fs::remove_file(ours).unwrap();
+    std::fs::remove_file(theirs).unwrap();
+}
+
+#[test]
+fn bench() {
+    test_cli(ProcessTest {
+        args: &["--bench", "Cargo.toml", "--bench-runs", "2"],
+        input: "",
+        out: AtLeast("average of 2 runs:\nparse time (ms): "),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn apply_preview() {
+    let dir = "target/test_apply_preview";
+    let patch = "target/test_apply_preview.diff";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/f.txt", dir), "x\na\nb\nc\nd\ne\nf\ng\n").unwrap();
+    std::fs::write(
+        patch,
+        "--- a/f.txt
++++ b/f.txt
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+@@ -5,3 +5,3 @@
+ D
+-e
++E
+ f
+@@ -8,1 +8,1 @@
+-z
++Z
+",
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--apply-preview",
+            "target/test_apply_preview.diff",
+            "target/test_apply_preview",
+            "--color=never",
+        ],
+        input: "",
+        out: Exactly(
+            "--- a/f.txt
++++ b/f.txt
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+applies with offset +1
+@@ -5,3 +5,3 @@
+ D
+-e
++E
+ f
+applies with fuzz 1
+@@ -8,1 +8,1 @@
+-z
++Z
+rejected: the context does not match",
+        ),
+        err: Empty,
+        is_success: false,
+    });
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(patch).unwrap();
+}
+
+#[test]
+fn navigate() {
+    test_cli(ProcessTest {
+        args: &["--navigate", "--color=never"],
+        input: "--- a/f
++++ b/f
+@@ -1 +1 @@
+-a
++b
+",
+        out: Exactly(
+            "--- a/f
+Δ +++ b/f
+Δ @@ -1 +1 @@
+-a
++b",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn navigate_prompt() {
+    test_cli(ProcessTest {
+        args: &["--navigate-prompt", "--navigate-marker", ">>"],
+        input: "",
+        out: Exactly(r"-R --pattern=^>> -Ps>> n/N\: next/previous file or hunk\.$"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn grep() {
+    let input = "diff --git a/x b/x
+--- a/x
++++ b/x
+@@ -1 +1 @@
+-safe a
++safe b
+@@ -5 +5 @@
+-unsafe a
++unsafe { b }
+diff --git a/y b/y
+--- a/y
++++ b/y
+@@ -1 +1 @@
+-q
++r
+";
+    test_cli(ProcessTest {
+        args: &["--grep", "unsafe", "--color=never"],
+        input,
+        out: Exactly(
+            "diff --git a/x b/x
+--- a/x
++++ b/x
+@@ -5 +5 @@
+-unsafe a
++unsafe { b }",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--grep", "unsafe"],
+        input,
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[30m\x1b[43munsafe\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn only_changes() {
+    test_cli(ProcessTest {
+        args: &["--only-changes", "--line-numbers", "--color=never"],
+        input: "--- a/f
++++ b/f
+@@ -1,5 +1,5 @@
+ a
+ b
+-c
++C
+ d
+ e
+",
+        out: Exactly(
+            "--- a/f
++++ b/f
+@@ -1,5 +1,5 @@
+3   | -c
+  3 | +C",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn relocated() {
+    test_cli(ProcessTest {
+        args: &["--relocated"],
+        input: "@@ -1,2 +1,2 @@
+-first();
+-second();
++second();
++first();
+",
+        out: Exactly("\x1b[0m@@ -1,2 +1,2 @@\x1b[0m\n\x1b[0m\x1b[31m-\x1b[0m\x1b[0m\x1b[2mfirst();\x1b[0m\n\x1b[0m\x1b[31m-\x1b[0m\x1b[0m\x1b[2msecond();\x1b[0m\n\x1b[0m\x1b[32m+\x1b[0m\x1b[0m\x1b[2msecond();\x1b[0m\n\x1b[0m\x1b[32m+\x1b[0m\x1b[0m\x1b[2mfirst();\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn indent_changed() {
+    test_cli(ProcessTest {
+        args: &["--indent-changed", "--indent-delta", "--color=never"],
+        input: "@@ -1,2 +1,3 @@
+-foo(a);
+-bar(b);
++if x {
++    foo(a);
++    bar(c);
+",
+        out: Exactly(
+            "@@ -1,2 +1,3 @@
+-foo(a);
+-bar(b);
++if x {
++    foo(a); → +4 spaces
++    bar(c);",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn accessibility_symbols() {
+    test_cli(ProcessTest {
+        args: &["--accessibility", "symbols", "--color=never"],
+        input: "@@ -1,3 +1,2 @@
+ a
+-foo(b)
++foo(c)
+-x
+",
+        out: Exactly(
+            "@@ -1,3 +1,2 @@
+   a
+± -foo(b)
+± +foo(c)
+  -x",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--accessibility", "symbols"],
+        input: "@@ -1 +1 @@
+-foo(b)
++foo(c)
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[4m\x1b[37m\x1b[42mc\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn theme() {
+    test_cli(ProcessTest {
+        args: &["--theme", "deuteranopia", "--dark"],
+        input: "@@ -1 +1 @@
+-foo(b)
++foo(c)
+",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[38;5;15m\x1b[48;5;25mc\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn man_page() {
+    test_cli(ProcessTest {
+        args: &["--man-page"],
+        input: "",
+        out: AtLeast(".SH NAME\ndiffr \\- adds word\\-level diff on top of unified diffs.\n"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--man-page"],
+        input: "",
+        out: AtLeast(
+            ".TP
+\\fB\\-\\-man\\-page\\fR
+Write the manual page of diffr, instead of reading the input.
+.IP
+The page is written in the roff format read by man, from this help:
+.IP
+.nf
+    diffr \\-\\-man\\-page > diffr.1
+    man ./diffr.1
+.fi
+",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn theme_file() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write(
+        "target/test_theme.toml",
+        "[faces]\nadded = \"none:foreground:blue:italic\"\n",
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--theme-file",
+            "target/test_theme.toml",
+            "--colors",
+            "removed:underline",
+            "--dump-theme",
+        ],
+        input: "",
+        out: AtLeast(
+            "# A theme of diffr, to load with --theme-file, or to paste in the configuration file.
+[faces]
+added = \"none:foreground:blue:italic\"
+refine-added = \"none:foreground:white:background:green:bold\"
+removed = \"none:foreground:red:underline\"
+",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    std::fs::write("target/test_theme_bad.toml", "[faces]\nadded = \"blue\"\n").unwrap();
+    test_cli(ProcessTest {
+        args: &["--theme-file", "target/test_theme_bad.toml"],
+        input: "",
+        out: Empty,
+        err: Exactly(
+            "target/test_theme_bad.toml: unexpected attribute name: got 'blue', expected \
+foreground|background|bold|nobold|intense|nointense|underline|nounderline|dim|nodim|\
+italic|noitalic|strikethrough|nostrikethrough|none",
+        ),
+        is_success: false,
+    });
+}
+
+#[test]
+fn timestamps_strip_prefix() {
+    test_cli(ProcessTest {
+        args: &[
+            "--timestamps",
+            "normalize",
+            "--strip-prefix",
+            "1",
+            "--color=never",
+        ],
+        input: "--- a/src/x.c\t2024-01-01 10:00:00.123 +0100
++++ b/src/x.c\t2024-01-02 10:00:00.456 +0100
+@@ -1 +1 @@
+-a
++b
+",
+        out: Exactly(
+            "--- src/x.c\t1970-01-01 00:00:00.000000000 +0000
++++ src/x.c\t1970-01-01 00:00:00.000000000 +0000
+@@ -1 +1 @@
+-a
++b",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn crlf() {
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1 +1 @@\n-foo b\r\n+foo c\n",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[37m\x1b[41mb\x1b[0m\r\n"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--show-cr", "--color=never"],
+        input: "@@ -1,2 +1,2 @@\n ctx\r\n-foo b\r\n+foo b\n",
+        out: Exactly("@@ -1,2 +1,2 @@\n ctx^M\n-foo b^M\n+foo b"),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn show_nonprinting() {
+    test_cli(ProcessTest {
+        args: &["--show-nonprinting", "--color=never"],
+        input: "@@ -1,2 +1,2 @@\n a\u{7}b\n-foo bar\n+foo\u{200b} bar\u{202e}\n",
+        out: Exactly("@@ -1,2 +1,2 @@\n a^Gb\n-foo bar\n+foo<U+200B> bar<U+202E>"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--show-nonprinting", "--word-diff", "--color=always"],
+        input: "@@ -1 +1 @@\n-a\n+\u{feff}a\n",
+        out: AtLeast("\x1b[0m\x1b[1m\x1b[33m<U+FEFF>\x1b[0m"),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn function_context() {
+    test_cli(ProcessTest {
+        args: &["--colors=function-context:foreground:blue"],
+        input: "@@ -1 +1 @@ fn main() {\n-a\n+b\n",
+        out: AtLeast("\x1b[0m@@ -1 +1 @@ \x1b[0m\x1b[0m\x1b[34mfn main() {\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &[],
+        input: "@@ -1 +1 @@ fn main() {\n-a\n+b\n",
+        out: AtLeast("\x1b[0m@@ -1 +1 @@ \x1b[0m\x1b[0mfn main() {\x1b[0m\n"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--line-numbers", "--color=never"],
+        input: "--- a/x.rs\n+++ b/x.rs\n@@ -1 +1 @@ fn main() {\n-a\n+b\n",
+        out: Exactly(
+            "--- a/x.rs\n+++ b/x.rs\n@@ -1 +1 @@ fn main() {\n    | x.rs › fn main() {\n1   | -a\n  1 | +b",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn input_file() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write("target/test_input.diff", "@@ -1 +1 @@\n-a b\n+a c\n").unwrap();
+    std::fs::write("target/test_input_empty.diff", "").unwrap();
+    test_cli(ProcessTest {
+        args: &["--input", "target/test_input.diff", "--color=never"],
+        input: "@@ -1 +1 @@\n-ignored\n",
+        out: Exactly("@@ -1 +1 @@\n-a b\n+a c"),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--input", "target/test_input_empty.diff"],
+        input: "",
+        out: Empty,
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--input", "target/test_input_missing.diff"],
+        input: "",
+        out: Empty,
+        err: AtLeast("io error"),
+        is_success: false,
+    });
+}
+
+#[test]
+fn format_patch() {
+    test_cli(ProcessTest {
+        args: &["--commit-format", "oneline", "--color=never"],
+        input: "From fec866d36f4aa2ea22531810185c166bb540b857 Mon Sep 17 00:00:00 2001
+From: A <a@b>
+Date: Fri, 16 Oct 2026 02:32:52 +0000
+Subject: [PATCH] Change b to c
+
+Body line.
+---
+ f | 2 +-
+
+diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+ a
+-b
++c
+-- 
+2.39.5
+
+",
+        out: Exactly(
+            "fec866d [PATCH] Change b to c
+---
+ f | 2 +-
+
+diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+ a
+-b
++c",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn redact() {
+    let input = "diff --git a/acme.rs b/acme.rs
+--- a/acme.rs
++++ b/acme.rs
+@@ -1 +1 @@
+-let acme_key = 1;
++let acme_key = 2;
+";
+    test_cli(ProcessTest {
+        args: &["--redact", r"acme\w*", "--color=never"],
+        input,
+        out: Exactly(
+            "diff --git a/████.rs b/████.rs
+--- a/████.rs
++++ b/████.rs
+@@ -1 +1 @@
+-let ████████ = 1;
++let ████████ = 2;",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    // the equal matches have the same hash
+    test_cli(ProcessTest {
+        args: &[
+            "--redact",
+            r"acme\w*",
+            "--redact-style",
+            "hash",
+            "--color=never",
+        ],
+        input,
+        out: Exactly(
+            "diff --git a/#3a8aef94.rs b/#3a8aef94.rs
+--- a/#3a8aef94.rs
++++ b/#3a8aef94.rs
+@@ -1 +1 @@
+-let #7f8045b8 = 1;
++let #7f8045b8 = 2;",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn ruler() {
+    test_cli(ProcessTest {
+        args: &["--ruler", "10", "--color=never"],
+        input: "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+ ctx
+-let a = 1;
++let abcdefgh = 2;
+",
+        out: Exactly(
+            "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+ ctx       │
+-let a = 1;│
++let abcdefgh = 2;",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn merge_hunks() {
+    test_cli(ProcessTest {
+        args: &["--merge-hunks", "5", "--line-numbers", "--color=never"],
+        input: "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+@@ -8,3 +8,3 @@
+ h
+-i
++I
+ j
+@@ -30,2 +30,2 @@
+-x
++X
+ y
+",
+        out: Exactly(
+            "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,10 +1,10 @@
+ 1  1 |  a
+ 2    | -b
+    2 | +B
+ 3  3 |  c
+      | ··· 4 unchanged lines ···
+ 8  8 |  h
+ 9    | -i
+    9 | +I
+10 10 |  j
+@@ -30,2 +30,2 @@
+30    | -x
+   30 | +X
+31 31 |  y",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn metrics_file() {
+    std::fs::create_dir_all("target").unwrap();
+    let input = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a b\n+a c\n";
+    for args in [
+        &[
+            "--metrics-file",
+            "target/test_metrics.json",
+            "--color=never",
+        ],
+        &[
+            "--metrics-file",
+            "target/test_metrics.prom",
+            "--color=never",
+        ],
+    ] {
+        test_cli(ProcessTest {
+            args,
+            input,
+            out: AtLeast("+a c"),
+            err: Empty,
+            is_success: true,
+        });
+    }
+    let json = std::fs::read_to_string("target/test_metrics.json").unwrap();
+    assert!(json.starts_with(
+        "{\"files\":1,\"hunks\":1,\"added_lines\":1,\"removed_lines\":1,\"added_tokens\":4,\
+         \"removed_tokens\":4,\"refined_added_tokens\":1,\"refined_removed_tokens\":1,\
+         \"elapsed_us\":"
+    ));
+    let prometheus = std::fs::read_to_string("target/test_metrics.prom").unwrap();
+    assert!(prometheus.contains("# TYPE diffr_hunks gauge\ndiffr_hunks 1\n"));
+    assert!(prometheus.contains("\ndiffr_elapsed_seconds "));
+}
+
+#[test]
+fn annotations() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write(
+        "target/test_annotations.json",
+        r#"[{"path": "f", "new_line": 2, "severity": "warning", "message": "unused: `c`\nremove it"},
+            {"path": "f", "new_line": 1, "message": "kept"},
+            {"path": "g", "new_line": 2, "message": "other file"}]"#,
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--annotations",
+            "target/test_annotations.json",
+            "--color=never",
+        ],
+        input: "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+        out: Exactly(
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n └ note: kept\n-b\n+c\n └ warning: unused: `c`\n   remove it",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--annotations", "target/test_annotations_missing.json"],
+        input: "",
+        out: Empty,
+        err: AtLeast("test_annotations_missing.json"),
+        is_success: false,
+    });
+}
+
+#[test]
+fn annotations_sexp() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write(
+        "target/test_annotations_sexp.json",
+        r#"[{"path": "f", "new_line": 1, "severity": "warning", "message": "unused"}]"#,
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--annotations",
+            "target/test_annotations_sexp.json",
+            "--output",
+            "sexp",
+        ],
+        input: "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n-a\n+b\n c\n",
+        out: Exactly(
+            "(hunk :old-path \"f\" :path \"f\" :old-start 1 :old-len 2 :new-start 1 :new-len 2 \
+             :lines ((removed \"a\" (0 . 1)) (added \"b\" (0 . 1)) (context \"c\")))",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn annotations_sarif() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write(
+        "target/test_annotations.sarif",
+        r#"{"version": "2.1.0", "runs": [{"tool": {"driver": {"name": "lint"}}, "results": [
+            {"ruleId": "unused", "level": "warning", "message": {"text": "unused: `c`"},
+             "locations": [{"physicalLocation": {"artifactLocation": {"uri": "f"},
+                                                 "region": {"startLine": 2}}}]}]}]}"#,
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "--annotations-sarif",
+            "target/test_annotations.sarif",
+            "--color=never",
+        ],
+        input: "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+        out: Exactly(
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n └ warning: unused: `c` [unused]",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn output_patch() {
+    const PATCH: &str = "\
+diff --git a/src/a.rs b/src/b.rs
+similarity index 80%
+rename from src/a.rs
+rename to src/b.rs
+index 1111111..2222222 100644
+--- a/src/a.rs
++++ b/src/b.rs
+@@ -1,3 +1,3 @@ fn main() {
+ let a = 1;
+-let b = \"secret\";\t
++let b = \"public\";
+ let c = 3;
+@@ -10 +10 @@
+-end
++end
+\\ No newline at end of file
+";
+    for args in [
+        &["--output", "patch"][..],
+        &[
+            "--output",
+            "patch",
+            "--color=always",
+            "--line-numbers",
+            "--merge-hunks",
+            "10",
+            "--strip-prefix",
+            "1",
+            "--ruler",
+            "8",
+        ],
+    ] {
+        test_cli(ProcessTest {
+            args,
+            input: PATCH,
+            out: Exactly(PATCH),
+            err: Empty,
+            is_success: true,
+        });
+    }
+}
+
+#[test]
+fn output_patch_filtered() {
+    let input = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1 @@
+-a
++b
+diff --git a/b.lock b/b.lock
+--- a/b.lock
++++ b/b.lock
+@@ -1 +1 @@
+-x
++y
+";
+    // the files left out are not summarized in the patch
+    let filtered = &input[..input.find("diff --git a/b.lock").unwrap()];
+    for args in [
+        &["--output", "patch", "--exclude", "*.lock"][..],
+        &["--output", "patch", "--path", "*.txt"],
+    ] {
+        test_cli(ProcessTest {
+            args,
+            input,
+            out: Exactly(filtered),
+            err: Empty,
+            is_success: true,
+        });
+    }
+    // the filtered patch applies, outside of the repository of diffr
+    let dir = env::temp_dir().join(format!("diffr_test_patch_filtered_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "a\n").unwrap();
+    fs::write(dir.join("b.lock"), "x\n").unwrap();
+    let args = ["--output", "patch", "--exclude", "*.lock"];
+    let patch = output_within(&args, input.into(), Duration::from_secs(10)).expect("exit");
+    fs::write(dir.join("filtered.patch"), patch).unwrap();
+    let status = Command::new("git")
+        .args(["apply", "filtered.patch"])
+        .current_dir(&dir)
+        .status()
+        .expect("git apply");
+    let applied = (
+        fs::read_to_string(dir.join("a.txt")).unwrap(),
+        fs::read_to_string(dir.join("b.lock")).unwrap(),
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success());
+    assert_eq!(("b\n".to_string(), "x\n".to_string()), applied);
+}
+
+#[test]
+fn split_hunks() {
+    let input = "--- a/f\n+++ b/f\n@@ -1,7 +1,7 @@\n fn a() {\n-    x\n+    y\n }\n \n fn b() {\n\
+                 -    1\n+    2\n }\n";
+    test_cli(ProcessTest {
+        args: &["--split-hunks", "3", "--color=never"],
+        input,
+        out: Exactly(
+            "--- a/f\n+++ b/f\n@@ -1,7 +1,7 @@\n fn a() {\n-    x\n+    y\n }\n \n\
+             @@ -5,3 +5,3 @@\n fn b() {\n-    1\n+    2\n }",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+    // the hunks are not split in the patches
+    test_cli(ProcessTest {
+        args: &["--split-hunks", "3", "--output", "patch"],
+        input,
+        out: Exactly(input),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn malformed_hunks() {
+    // the first hunk of a counts 2 removed lines, and has 1
+    let input = "\
+diff --git a/a b/a
+--- a/a
++++ b/a
+@@ -1,2 +1 @@
+-a b
++a c
+@@ -10 +10 @@
+-d e
++d f
+diff --git a/b b/b
+--- a/b
++++ b/b
+@@ -1 +1 @@
+-a b
++a c
+";
+    test_cli(ProcessTest {
+        args: &["--output", "annotated"],
+        input,
+        out: Exactly(
+            "diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -1,2 +1 @@\n-a b\n+a c\n\
+             @@ -10 +10 @@\n-d [-e-]\n+d {+f+}\n\
+             diff --git a/b b/b\n--- a/b\n+++ b/b\n@@ -1 +1 @@\n-a [-b-]\n+a {+c+}",
+        ),
+        err: AtLeast(
+            "malformed hunk at line 4 of a: its header counts 2 old and 1 new lines, \
+             it has 1 old and 1 new lines",
+        ),
+        is_success: true,
+    });
+    test_cli(ProcessTest {
+        args: &["--strict", "--color=never"],
+        input,
+        out: AtLeast("+++ b/a"),
+        err: AtLeast("malformed hunk at line 4 of a"),
+        is_success: false,
+    });
+    // with --strict, the malformed hunk is a parse error
+    let mut child = Command::new(diffr_path())
+        .arg("--strict")
+        .env_remove("DIFFR_OPTIONS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn");
+    let mut stdin = child.stdin.take().expect("stdin");
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().expect("wait");
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!(
+        "parse error: malformed hunk at line 4 of a: its header counts 2 old and 1 new lines, \
+         it has 1 old and 1 new lines\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn input_files() {
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write(
+        "target/test_inputs_1.patch",
+        "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a b\n+a c\n",
+    )
+    .unwrap();
+    std::fs::write(
+        "target/test_inputs_2.patch",
+        "--- a/g\n+++ b/g\n@@ -1 +1 @@\n-d\n+e\n",
+    )
+    .unwrap();
+    test_cli(ProcessTest {
+        args: &[
+            "target/test_inputs_1.patch",
+            "target/test_inputs_2.patch",
+            "--stats",
+            "--color=never",
+        ],
+        input: "",
+        out: Exactly(
+            "==> target/test_inputs_1.patch <==
+--- a/f
++++ b/f
+@@ -1 +1 @@
+-a b
++a c
+==> target/test_inputs_2.patch <==
+--- a/g
++++ b/g
+@@ -1 +1 @@
+-d
++e
+f: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +4 -4)
+g: 1 hunk, +1 -1 lines, +1 -1 refined tokens (out of +2 -2)
+total: 2 files, 2 hunks, +2 -2 lines, +2 -2 refined tokens (out of +6 -6)",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn closed_stdout() {
+    let input = (0..20000)
+        .map(|i| format!("@@ -{0} +{0} @@\n-a {0}\n+b {0}\n", i + 1))
+        .collect::<String>();
+    for args in [&["--color=always"][..], &["--output=json"]] {
+        let mut child = Command::new(diffr_path())
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env_remove("DIFFR_OPTIONS")
+            .spawn()
+            .expect("spawn");
+        // the reader quits before reading anything, like a pager
+        drop(child.stdout.take());
+        let mut stdin = child.stdin.take().expect("stdin");
+        let input = input.clone();
+        let writer = std::thread::spawn(move || {
+            // diffr may stop reading before the end of its input
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        let output = child.wait_with_output().expect("wait_with_output");
+        writer.join().unwrap();
+        assert!(output.status.success(), "{:?}: {:?}", args, output.status);
+        assert_eq!("", String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn sort_files() {
+    test_cli(ProcessTest {
+        args: &["--sort-files", "changes", "--color=never"],
+        input: "--- a
++++ a
+@@ -1 +1 @@
+-a
++b
+--- b
++++ b
+@@ -1,2 +1,2 @@
+-a
+-b
++c
++d
+",
+        out: Exactly(
+            "--- b
++++ b
+@@ -1,2 +1,2 @@
+-a
+-b
++c
++d
+--- a
++++ a
+@@ -1 +1 @@
+-a
++b",
+        ),
+        err: Empty,
+        is_success: true,
+    });
+}
+
+#[test]
+fn split_output() {
+    let dir = "target/test_split_output";
+    let _ = std::fs::remove_dir_all(dir);
+    test_cli(ProcessTest {
+        args: &[
+            "--split-output",
+            "target/test_split_output",
+            "--output",
+            "html",
+            "--color=never",
+        ],
+        input: "commit 1
+diff --git a/src/a.rs b/src/a.rs
+--- a/src/a.rs
++++ b/src/a.rs
+@@ -1 +1 @@
+-a
++b
+diff --git a/<b> c b/<b> c
+--- a/<b> c
++++ b/<b> c
+@@ -1 +1,2 @@
+-a
++b
++c
+",
+        out: Empty,
+        err: Empty,
+        is_success: true,
+    });
+    let read = |name: &str| std::fs::read_to_string(format!("{}/{}", dir, name)).unwrap();
+    let index = read("index.html");
+    assert!(index.contains("<li><a href=\"src_a.rs.html\">src/a.rs</a> +1 -1</li>"));
+    assert!(index.contains("<li><a href=\"_b__c.html\">&lt;b&gt; c</a> +2 -1</li>"));
+    let a = read("src_a.rs.html");
+    assert!(a.contains("commit 1</span>\ndiff --git a/src/a.rs b/src/a.rs\n"));
+    assert!(!a.contains("&lt;b&gt;"));
+    assert!(read("_b__c.html").starts_with("<!DOCTYPE html>"));
+}
+
+#[test]
+fn profile() {
+    let dir = Path::new("target/test_profile");
+    fs::create_dir_all(dir.join("diffr")).unwrap();
+    fs::write(
+        dir.join("diffr").join("config"),
+        "[profile.plain]\n--color never\n--line-numbers\n",
+    )
+    .unwrap();
+    fs::write(dir.join("x.diff"), "@@ -1 +1 @@\n-a\n+b\n").unwrap();
+    let run = |args: &[&str], profile: Option<&str>| {
+        let mut cmd = Command::new(diffr_path());
+        cmd.args(args)
+            .arg("--input")
+            .arg(dir.join("x.diff"))
+            .env("XDG_CONFIG_HOME", dir)
+            .env_remove("DIFFR_OPTIONS")
+            .env_remove("DIFFR_PROFILE");
+        if let Some(profile) = profile {
+            cmd.env("DIFFR_PROFILE", profile);
+        }
+        let output = cmd.output().expect("output");
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+    let expected = "@@ -1 +1 @@\n1   | -a\n  1 | +b\n";
+    let expected = (true, expected.to_owned(), String::new());
+    assert_eq!(expected, run(&["--profile", "plain"], None));
+    assert_eq!(expected, run(&[], Some("plain")));
+    let (success, _, err) = run(&["--profile=missing"], Some("plain"));
+    assert!(!success);
+    assert!(err.contains("no profile 'missing'"), "{}", err);
+}
+
+#[test]
+fn file_faces() {
+    let dir = Path::new("target/test_file_faces");
+    fs::create_dir_all(dir.join("diffr")).unwrap();
+    let run = |config: &str| {
+        fs::write(dir.join("diffr").join("config"), config).unwrap();
+        let mut cmd = Command::new(diffr_path());
+        let mut child = cmd
+            .env("XDG_CONFIG_HOME", dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn");
+        let input = "diff --git a/a.md b/a.md
+--- a/a.md
++++ b/a.md
+@@ -1 +1 @@
+-a
++b
+diff --git a/b.rs b/b.rs
+--- a/b.rs
++++ b/b.rs
+@@ -1 +1 @@
+-a
++b
+";
+        // diffr exits without reading its input on an invalid configuration
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        let output = child.wait_with_output().expect("output");
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+    let (success, out, err) = run("[faces.*.md]\nadded:foreground:blue removed:italic\n");
+    assert!(success, "{}", err);
+    let (md, rs) = out.split_at(out.find("diff --git a/b.rs").unwrap());
+    // the faces of the lines paint the end of the refined lines
+    assert!(md.contains("\x1b[3m\x1b[31m"), "{:?}", md);
+    assert!(md.contains("\x1b[34m"), "{:?}", md);
+    assert!(
+        !rs.contains("\x1b[3m") && !rs.contains("\x1b[34m"),
+        "{:?}",
+        rs
+    );
+    assert!(rs.contains("\x1b[32m"), "{:?}", rs);
+    let (success, _, err) = run("[faces.*.md]\nadded:blue\n");
+    assert!(!success);
+    assert!(err.starts_with("in the faces of *.md: unexpected attribute name"));
+}
+
+#[test]
+fn config_faces() {
+    let dir = Path::new("target/test_config_faces");
+    fs::create_dir_all(dir.join("diffr")).unwrap();
+    let run = |config: &str, args: &[&str]| {
+        fs::write(dir.join("diffr").join("config"), config).unwrap();
+        let output = Command::new(diffr_path())
+            .args(args)
+            .arg("--dump-theme")
+            .env("XDG_CONFIG_HOME", dir)
+            .env_remove("DIFFR_OPTIONS")
+            .env_remove("DIFFR_PROFILE")
+            .output()
+            .expect("output");
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+    // the output of --dump-theme is read back from the configuration
+    let (success, theme, err) = run("", &["--colors", "added:foreground:blue"]);
+    assert!(success, "{}", err);
+    assert_eq!((true, theme.clone(), String::new()), run(&theme, &[]));
+    let (success, out, err) = run(&theme, &["--colors", "added:italic"]);
+    assert!(success, "{}", err);
+    assert!(
+        out.contains("\nadded = \"none:foreground:blue:italic\"\n"),
+        "{}",
+        out
+    );
+    let (success, _, err) = run("[faces]\nbogus = \"none\"\n", &[]);
+    assert!(!success);
+    assert!(
+        err.starts_with("in the faces of the configuration file"),
+        "{}",
+        err
+    );
+    let (success, _, err) = run("[theme]\nadded = \"none\"\n", &[]);
+    assert!(!success);
+    assert!(err.contains("line 1: unknown section 'theme'"), "{}", err);
+}
+
+#[test]
+fn passthrough() {
+    let input = "commit 1
+Author: a
+
+    message
+    +not a hunk
+diff --git a/x b/x
+--- a/x
++++ b/x
+@@ -1 +1 @@
+-a
++b
+commit 2
+
+    other message
+";
+    let keep: &'static [&'static str] = &["--color", "never", "--keep-input-colors"];
+    for args in [&["--color", "never"], keep] {
+        test_cli(ProcessTest {
+            args,
+            input,
+            out: Exactly(input),
+            err: Empty,
+            is_success: true,
+        })
+    }
+}
+
+#[test]
+fn passthrough_colors() {
+    // the colored lines outside of the diff lose their colors, as when
+    // written line by line
+    let mut input = vec![];
+    let mut expected = String::new();
+    for i in 0..100_000 {
+        input.extend_from_slice(format!("\x1b[33mline {}\x1b[m\n", i).as_bytes());
+        expected.push_str(&format!("\x1b[0mline {}\x1b[0m\n", i));
+    }
+    input.extend_from_slice(b"\x1b[1mdiff --git a/x b/x\x1b[m\n");
+    let timeout = Duration::from_secs(60);
+    let out = output_within(&["--color", "always"], input, timeout).expect("exit");
+    assert!(out.starts_with(&expected), "{:?}", &out[..100]);
+    assert!(out[expected.len()..].contains("diff --git a/x b/x"));
+    // they are written at once, before the diff
+    test_cli(ProcessTest {
+        args: &["--color", "always", "--debug"],
+        input: "\x1b[33mcommit 1\x1b[m
+Author: a
+
+\x1b[31m    message\x1b[m
+    more
+
+\x1b[1mdiff --git a/x b/x\x1b[m
+",
+        out: AtLeast("\x1b[0m    message\x1b[0m\n\x1b[0m    more\x1b[0m\n"),
+        err: AtLeast("lines written as read: 4"),
+        is_success: true,
+    })
+}
+
+#[test]
+fn passthrough_filtered() {
+    // the skipped files follow the header of their commit
+    test_cli(ProcessTest {
+        args: &["--color", "never", "--exclude", "x"],
+        input: "commit 1
+
+    message
+
+diff --git a/x b/x
+--- a/x
++++ b/x
+@@ -1 +1 @@
+-a
++b
+",
+        out: Exactly(
+            "commit 1
+
+    message
+
+skipped: x",
+        ),
+        err: Empty,
+        is_success: true,
+    })
+}
+
+#[test]
+fn truncated_escape_code() {
+    // the unterminated escape codes are written as text
+    for input in [&b"x\x1b[12;\nfoo\n"[..], b"\x1b["] {
+        let timeout = Duration::from_secs(10);
+        let out = output_within(&[], input.to_vec(), timeout);
+        let out = out.expect("diffr does not hang");
+        assert!(out.contains("\x1b["), "{:?}", out);
+    }
+}
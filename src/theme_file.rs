@@ -0,0 +1,149 @@
+//! Theme files, for --theme-file and --dump-theme.
+//!
+//! A theme file is a TOML file where the faces are the keys of the
+//! 'faces' table, and their values are written with the syntax of
+//! --colors:
+//!
+//! [faces]
+//! added = "none:foreground:green"
+//! refine-added = "none:foreground:white:background:green:bold"
+//!
+//! The keys of the other tables, such as a name or an author, are
+//! ignored.
+
+use std::fs;
+use std::io::{self, Write};
+use termcolor::{Color, ColorSpec};
+
+use super::cli_args::{self, FLAG_THEME_FILE};
+use super::AppConfig;
+
+/// The name of `color` in a color_spec, if it has one.
+fn color_name(color: &Color) -> Option<String> {
+    let name = match color {
+        Color::Black => "black",
+        Color::Blue => "blue",
+        Color::Green => "green",
+        Color::Red => "red",
+        Color::Cyan => "cyan",
+        Color::Magenta => "magenta",
+        Color::Yellow => "yellow",
+        Color::White => "white",
+        Color::Ansi256(n) => return Some(n.to_string()),
+        Color::Rgb(r, g, b) => return Some(format!("{},{},{}", r, g, b)),
+        _ => return None,
+    };
+    Some(name.to_owned())
+}
+
+/// The attributes of a color_spec that sets `face` whatever the face
+/// was before, such as "none:foreground:green:bold".
+fn face_spec(face: &ColorSpec) -> String {
+    let mut spec = vec!["none".to_owned()];
+    let colors = [("foreground", face.fg()), ("background", face.bg())];
+    for (attribute, color) in colors {
+        if let Some(color) = color.and_then(color_name) {
+            spec.push(format!("{}:{}", attribute, color));
+        }
+    }
+    let flags = [
+        ("bold", face.bold()),
+        ("intense", face.intense()),
+        ("underline", face.underline()),
+        ("dim", face.dimmed()),
+        ("italic", face.italic()),
+        ("strikethrough", face.strikethrough()),
+    ];
+    spec.extend(
+        flags
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| name.to_string()),
+    );
+    spec.join(":")
+}
+
+/// Writes the faces of `config` as a theme file.
+pub fn write<W: Write>(config: &AppConfig, out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "# A theme of diffr, to load with {}, or to paste in the configuration file.",
+        FLAG_THEME_FILE
+    )?;
+    writeln!(out, "[faces]")?;
+    for (name, face) in cli_args::faces(config) {
+        writeln!(out, "{} = \"{}\"", name, face_spec(&face))?;
+    }
+    Ok(())
+}
+
+/// Parses a TOML string, quoted with '"' or '\''.
+fn parse_string(value: &str) -> Option<String> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        return literal.strip_suffix('\'').map(str::to_owned);
+    }
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '"' => string.push('"'),
+                '\\' => string.push('\\'),
+                't' => string.push('\t'),
+                _ => return None,
+            },
+            '"' => return None,
+            c => string.push(c),
+        }
+    }
+    Some(string)
+}
+
+/// Parses the line `key = "value"` of a table of faces as the
+/// color_spec of the face, such as "added:none:foreground:green".
+pub fn parse_entry(line: &str) -> Result<String, String> {
+    let (key, value) = line.split_once('=').ok_or("expected 'key = value'")?;
+    let value = value.trim();
+    // a comment may follow a value that has no '#'
+    let value = match value.rfind('#') {
+        Some(end) if parse_string(value).is_none() => value[..end].trim_end(),
+        _ => value,
+    };
+    let value = parse_string(value).ok_or("expected a string")?;
+    Ok(format!(
+        "{}:{}",
+        key.trim().trim_matches(|c| c == '"' || c == '\''),
+        value
+    ))
+}
+
+/// Parses the theme file `text` as the color_specs of its faces, such
+/// as "added:none:foreground:green".
+pub fn parse(text: &str) -> Result<Vec<String>, String> {
+    let mut specs = vec![];
+    let mut in_faces = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(table) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            in_faces = table.trim() == "faces";
+            continue;
+        }
+        if !in_faces {
+            continue;
+        }
+        specs.push(parse_entry(line).map_err(|err| format!("line {}: {}", i + 1, err))?);
+    }
+    Ok(specs)
+}
+
+/// Applies the faces of the theme file at `path`.
+pub fn read(path: &str, config: &mut AppConfig) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let specs = parse(&text).map_err(|err| format!("{}: {}", path, err))?;
+    cli_args::parse_color_args(config, specs.iter().map(String::as_str))
+        .map_err(|err| format!("{}: {}", path, err))
+}
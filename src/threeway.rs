@@ -0,0 +1,233 @@
+//! Display of two diffs of the same base, for --three-way.
+//!
+//! The changes of both diffs are merged by the lines of the base they
+//! replace; each region of the base changed by either side is written
+//! in the diff3 style of conflict markers, and displayed as by
+//! --conflicts, with our side refined against theirs.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead};
+use termcolor::{ColorSpec, WriteColor};
+
+use super::{
+    conflicts, output, parse_hunk_header, parse_new_path, parse_old_path, scan, stats, AppConfig,
+    DiffStats, HunkRemaining,
+};
+
+/// The number of unchanged lines of the base displayed around the
+/// changed regions, when they are known.
+const CONTEXT: usize = 3;
+
+/// The replacement of the lines `lo..hi` of the base, numbered from 1,
+/// by `lines`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Change {
+    lo: usize,
+    hi: usize,
+    lines: Vec<Vec<u8>>,
+}
+
+/// The changes of a file, and the lines of the base read in its hunks.
+#[derive(Debug, Default)]
+struct FileDiff {
+    changes: Vec<Change>,
+    base: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Parses a unified diff in the changes of each file, by path.
+fn parse_diff<Input: BufRead>(input: &mut Input) -> io::Result<Vec<(String, FileDiff)>> {
+    let mut files: Vec<(String, FileDiff)> = vec![];
+    let mut old_path = None;
+    let mut remaining: Option<HunkRemaining> = None;
+    let mut old_line = 0;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if scan::read_line(input, &mut line)? == 0 {
+            break;
+        }
+        let kind = match &mut remaining {
+            Some(remaining) => remaining.count(line.first().copied()),
+            None => None,
+        };
+        let file = match (kind, files.last_mut()) {
+            (Some(_), Some((_, file))) => file,
+            _ => {
+                remaining = None;
+                if let Some(header) = parse_hunk_header(&line) {
+                    remaining = Some(HunkRemaining::from(header));
+                    old_line = header.old_start.max(1);
+                } else if let Some(path) = line.strip_prefix(b"--- ") {
+                    old_path = parse_old_path(path);
+                } else if let Some(path) = line.strip_prefix(b"+++ ") {
+                    let path = parse_new_path(path).or_else(|| old_path.clone());
+                    files.push((path.unwrap_or_default(), FileDiff::default()));
+                }
+                continue;
+            }
+        };
+        let content = line.get(1..).unwrap_or_default().to_vec();
+        let last = file
+            .changes
+            .last_mut()
+            .filter(|change| change.hi == old_line);
+        match kind {
+            Some(b'-') => {
+                match last {
+                    Some(change) => change.hi += 1,
+                    None => file.changes.push(Change {
+                        lo: old_line,
+                        hi: old_line + 1,
+                        lines: vec![],
+                    }),
+                }
+                file.base.insert(old_line, content);
+                old_line += 1;
+            }
+            Some(b'+') => match last {
+                Some(change) => change.lines.push(content),
+                None => file.changes.push(Change {
+                    lo: old_line,
+                    hi: old_line,
+                    lines: vec![content],
+                }),
+            },
+            Some(b' ') => {
+                file.base.insert(old_line, content);
+                old_line += 1;
+            }
+            // "\ No newline at end of file"
+            _ => (),
+        }
+    }
+    Ok(files)
+}
+
+/// The lines of the base `lo..hi` with the `changes` within them
+/// applied.
+fn apply(lo: usize, hi: usize, changes: &[Change], base: &BTreeMap<usize, Vec<u8>>) -> Vec<u8> {
+    let mut text = vec![];
+    let mut changes = changes
+        .iter()
+        .filter(|change| lo <= change.lo && change.hi <= hi)
+        .peekable();
+    let mut i = lo;
+    loop {
+        match changes.peek() {
+            Some(change) if change.lo == i => {
+                for line in &change.lines {
+                    text.extend_from_slice(line);
+                }
+                i = change.hi;
+                changes.next();
+                continue;
+            }
+            _ => (),
+        }
+        if hi <= i {
+            break;
+        }
+        text.extend_from_slice(base.get(&i).map_or(&b"\n"[..], |line| &line[..]));
+        i += 1;
+    }
+    text
+}
+
+/// Writes the file with the regions changed by `ours` or `theirs`
+/// between conflict markers, and the lines of the base around them.
+fn merge(ours: &FileDiff, theirs: &FileDiff) -> Vec<u8> {
+    let mut base = ours.base.clone();
+    base.extend(theirs.base.clone());
+    let mut changes = ours
+        .changes
+        .iter()
+        .chain(&theirs.changes)
+        .collect::<Vec<_>>();
+    changes.sort_by_key(|change| (change.lo, change.hi));
+    // the changed regions of the base, merging the touching changes
+    let mut regions: Vec<(usize, usize)> = vec![];
+    for change in changes {
+        match regions.last_mut() {
+            Some(region) if change.lo <= region.1 => region.1 = region.1.max(change.hi),
+            _ => regions.push((change.lo, change.hi)),
+        }
+    }
+    let mut text = vec![];
+    // the end of the last line written
+    let mut written = 1;
+    for (i, &(lo, hi)) in regions.iter().enumerate() {
+        let next = regions.get(i + 1).map_or(usize::MAX, |region| region.0);
+        let before = lo.saturating_sub(CONTEXT).max(written);
+        for line in before..lo {
+            if let Some(line) = base.get(&line) {
+                text.extend_from_slice(line);
+            }
+        }
+        let label = |diff: &FileDiff| {
+            let changed = diff
+                .changes
+                .iter()
+                .any(|change| lo <= change.lo && change.hi <= hi);
+            if changed {
+                ""
+            } else {
+                " (unchanged)"
+            }
+        };
+        text.extend_from_slice(format!("<<<<<<< ours{}\n", label(ours)).as_bytes());
+        text.extend_from_slice(&apply(lo, hi, &ours.changes, &base));
+        text.extend_from_slice(b"||||||| base\n");
+        text.extend_from_slice(&apply(lo, hi, &[], &base));
+        text.extend_from_slice(b"=======\n");
+        text.extend_from_slice(&apply(lo, hi, &theirs.changes, &base));
+        text.extend_from_slice(format!(">>>>>>> theirs{}\n", label(theirs)).as_bytes());
+        let after = (hi + CONTEXT).min(next);
+        for line in hi..after {
+            if let Some(line) = base.get(&line) {
+                text.extend_from_slice(line);
+            }
+        }
+        written = after;
+    }
+    text
+}
+
+/// Displays the diffs of the files of --three-way, against the same
+/// base.
+pub fn process<Output>(config: AppConfig, out: &mut Output) -> io::Result<DiffStats>
+where
+    Output: WriteColor,
+{
+    let (ours_path, theirs_path) = config.three_way.clone().unwrap_or_default();
+    let ours = parse_diff(&mut &fs::read(ours_path)?[..])?;
+    let mut theirs = parse_diff(&mut &fs::read(theirs_path)?[..])?;
+    let mut files = vec![];
+    for (path, ours) in ours {
+        let theirs = match theirs.iter().position(|(other, _)| *other == path) {
+            Some(i) => theirs.remove(i).1,
+            None => FileDiff::default(),
+        };
+        files.push((path, ours, theirs));
+    }
+    for (path, theirs) in theirs {
+        files.push((path, FileDiff::default(), theirs));
+    }
+    let mut stats = DiffStats::default();
+    for (path, ours, theirs) in files {
+        let header = format!("three-way {}\n", path);
+        output(header.as_bytes(), &ColorSpec::default(), out)?;
+        let file_config = AppConfig {
+            conflicts: Some(path),
+            stats: false,
+            ..config.clone()
+        };
+        let merged = merge(&ours, &theirs);
+        let file_stats = conflicts::process(file_config, &mut &merged[..], out)?;
+        stats.files.extend(file_stats.files);
+    }
+    if config.stats {
+        stats::output_stats(&stats, out)?;
+    }
+    Ok(stats)
+}
@@ -0,0 +1,184 @@
+//! Unicode aware tokenization of the lines of a hunk.
+
+use diffr_lib::{classify_byte, hash_slice, HashedSpan, TokenKind};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Tokenize the ASCII bytes `src[lo..hi]`, like `diffr_lib::tokenize`.
+fn tokenize_ascii(src: &[u8], lo: usize, hi: usize, tokens: &mut Vec<HashedSpan>) {
+    let mut push = |lo: usize, hi: usize| {
+        if lo < hi {
+            tokens.push(HashedSpan {
+                lo,
+                hi,
+                hash: hash_slice(&src[lo..hi]),
+            })
+        }
+    };
+    let mut start = lo;
+    let mut kind = TokenKind::Other;
+    for (i, &b) in src.iter().enumerate().take(hi).skip(lo) {
+        let oldkind = kind;
+        kind = classify_byte(b);
+        if kind != oldkind || oldkind == TokenKind::Other {
+            push(start, i);
+            start = i
+        }
+    }
+    push(start, hi);
+}
+
+/// Tokenize the valid UTF-8 text `text`, found at `ofs` in `src`.
+fn tokenize_text(src: &[u8], ofs: usize, text: &str, tokens: &mut Vec<HashedSpan>) {
+    for (i, segment) in text.split_word_bound_indices() {
+        let lo = ofs + i;
+        let hi = lo + segment.len();
+        if segment.is_ascii() {
+            // keep the finer tokenization of punctuation in code
+            tokenize_ascii(src, lo, hi, tokens);
+        } else {
+            tokens.push(HashedSpan {
+                lo,
+                hi,
+                hash: hash_slice(&src[lo..hi]),
+            })
+        }
+    }
+}
+
+/// Tokenize data from `src` from the position `ofs` into `tokens`.
+///
+/// Non-ASCII text is split at the word boundaries of Unicode (UAX
+/// #29), which never split a character or a grapheme cluster. Bytes
+/// that are not valid UTF-8 are tokens of their own.
+pub fn tokenize(src: &[u8], ofs: usize, tokens: &mut Vec<HashedSpan>) {
+    let mut lo = ofs;
+    for chunk in src[ofs..].utf8_chunks() {
+        tokenize_text(src, lo, chunk.valid(), tokens);
+        lo += chunk.valid().len();
+        tokenize_ascii(src, lo, lo + chunk.invalid().len(), tokens);
+        lo += chunk.invalid().len();
+    }
+}
+
+/// The size of the tokens refined, for --refine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Word,
+    Char,
+    /// Characters in the hunks whose changed lines are all short, words
+    /// otherwise.
+    Auto,
+}
+
+/// Splits the tokens of `data` in `tokens` in their characters, that
+/// is, their grapheme clusters; the bytes that are not valid UTF-8 are
+/// kept as they are.
+pub fn split_chars(data: &[u8], tokens: &mut Vec<HashedSpan>) {
+    let words = std::mem::take(tokens);
+    for word in words {
+        let text = match std::str::from_utf8(&data[word.lo..word.hi]) {
+            Ok(text) if 1 < text.len() => text,
+            _ => {
+                tokens.push(word);
+                continue;
+            }
+        };
+        for (i, grapheme) in text.grapheme_indices(true) {
+            let lo = word.lo + i;
+            let hi = lo + grapheme.len();
+            tokens.push(HashedSpan {
+                lo,
+                hi,
+                hash: hash_slice(&data[lo..hi]),
+            });
+        }
+    }
+}
+
+/// Computes the tokens `removed` and `added` of `data` in ASCII lower
+/// case, for --ignore-case. Returns the data and the tokens.
+pub fn fold_case(
+    data: &[u8],
+    removed: &[HashedSpan],
+    added: &[HashedSpan],
+) -> (Vec<u8>, Vec<HashedSpan>, Vec<HashedSpan>) {
+    let folded = data.to_ascii_lowercase();
+    let rehash = |tokens: &[HashedSpan]| {
+        tokens
+            .iter()
+            .map(|token| HashedSpan {
+                hash: hash_slice(&folded[token.lo..token.hi]),
+                ..*token
+            })
+            .collect()
+    };
+    let (removed, added) = (rehash(removed), rehash(added));
+    (folded, removed, added)
+}
+
+/// Computes the tokens `removed` and `added` of `data` with the parts
+/// inside the sorted `masked` spans hashed as the same placeholder, for
+/// --ignore-token. Returns the tokens.
+pub fn mask(
+    data: &[u8],
+    masked: &[(usize, usize)],
+    removed: &[HashedSpan],
+    added: &[HashedSpan],
+) -> (Vec<HashedSpan>, Vec<HashedSpan>) {
+    let rehash = |tokens: &[HashedSpan]| {
+        tokens
+            .iter()
+            .map(|token| {
+                let first = masked.partition_point(|&(_, hi)| hi <= token.lo);
+                let overlapping = masked[first..].iter().take_while(|&&(lo, _)| lo < token.hi);
+                let mut s = DefaultHasher::new();
+                let mut x = token.lo;
+                for &(lo, hi) in overlapping {
+                    if x < lo {
+                        s.write(&data[x..lo]);
+                    }
+                    s.write_u8(0);
+                    x = hi;
+                }
+                if x == token.lo {
+                    return *token;
+                }
+                if x < token.hi {
+                    s.write(&data[x..token.hi]);
+                }
+                HashedSpan {
+                    hash: s.finish(),
+                    ..*token
+                }
+            })
+            .collect()
+    };
+    (rehash(removed), rehash(added))
+}
+
+/// Removes the carriage return of a "\r\n" line end from the tokens
+/// `tokens[start..]` of the line at the end of `src`, so that the lines
+/// with different line ends are diffed as the same.
+pub fn drop_carriage_return(src: &[u8], tokens: &mut Vec<HashedSpan>, start: usize) {
+    if !src.ends_with(b"\r\n") {
+        return;
+    }
+    let cr = src.len() - 2;
+    let first = start + tokens[start..].partition_point(|token| token.hi <= cr);
+    for token in tokens.split_off(first) {
+        for (lo, hi) in [
+            (token.lo, token.hi.min(cr)),
+            (token.lo.max(cr + 1), token.hi),
+        ] {
+            if lo < hi {
+                tokens.push(HashedSpan {
+                    lo,
+                    hi,
+                    hash: hash_slice(&src[lo..hi]),
+                });
+            }
+        }
+    }
+}
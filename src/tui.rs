@@ -0,0 +1,314 @@
+//! A read-only review interface where files and hunks are folded.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+use std::io::{self, Write};
+use std::ops::Range;
+
+use super::pager::{page_height, read_key, split_lines, with_terminal};
+
+struct Hunk {
+    header: usize,
+    body: Range<usize>,
+    expanded: bool,
+    show_context: bool,
+}
+
+struct File {
+    header: Range<usize>,
+    hunks: Vec<Hunk>,
+    expanded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    File(usize),
+    // file index, line index
+    Header(usize, usize),
+    Hunk(usize, usize),
+    // file index, hunk index, line index
+    Body(usize, usize, usize),
+    // file index, hunk index, number of folded lines
+    Context(usize, usize, usize),
+}
+
+pub struct Tui<'a> {
+    lines: Vec<&'a [u8]>,
+    plain: Vec<Vec<u8>>,
+    files: Vec<File>,
+    pub rows: Vec<Row>,
+    pub cursor: usize,
+    top: usize,
+    height: usize,
+}
+
+/// Browse `output` with files and hunks displayed as a tree.
+pub fn run(output: &[u8]) -> io::Result<()> {
+    let mut tui = Tui::new(output);
+    if tui.rows.is_empty() {
+        return Ok(());
+    }
+    with_terminal(|stdout| tui.event_loop(stdout))
+}
+
+fn is_hunk_body(line: &[u8]) -> bool {
+    matches!(
+        line.first(),
+        Some(b'+') | Some(b'-') | Some(b' ') | Some(b'\\')
+    )
+}
+
+fn parse_files(plain: &[Vec<u8>]) -> Vec<File> {
+    let mut files: Vec<File> = vec![];
+    let mut in_hunk = false;
+    for (i, line) in plain.iter().enumerate() {
+        match files.last_mut() {
+            Some(file) if line.starts_with(b"@@") => {
+                file.hunks.push(Hunk {
+                    header: i,
+                    body: i + 1..i + 1,
+                    expanded: false,
+                    show_context: false,
+                });
+                in_hunk = true;
+            }
+            Some(file) if in_hunk && is_hunk_body(line) => {
+                if let Some(hunk) = file.hunks.last_mut() {
+                    hunk.body.end = i + 1;
+                }
+            }
+            Some(file) if file.hunks.is_empty() && !line.starts_with(b"diff ") => {
+                file.header.end = i + 1;
+            }
+            _ => {
+                files.push(File {
+                    header: i..i + 1,
+                    hunks: vec![],
+                    expanded: true,
+                });
+                in_hunk = false;
+            }
+        }
+    }
+    files
+}
+
+impl<'a> Tui<'a> {
+    pub fn new(output: &'a [u8]) -> Self {
+        let (lines, plain) = split_lines(output);
+        let files = parse_files(&plain);
+        let mut tui = Tui {
+            lines,
+            plain,
+            files,
+            rows: vec![],
+            cursor: 0,
+            top: 0,
+            height: 1,
+        };
+        tui.rows = tui.compute_rows();
+        tui
+    }
+
+    fn compute_rows(&self) -> Vec<Row> {
+        let mut rows = vec![];
+        for (ifile, file) in self.files.iter().enumerate() {
+            rows.push(Row::File(ifile));
+            if !file.expanded {
+                continue;
+            }
+            for line in file.header.start + 1..file.header.end {
+                rows.push(Row::Header(ifile, line));
+            }
+            for (ihunk, hunk) in file.hunks.iter().enumerate() {
+                rows.push(Row::Hunk(ifile, ihunk));
+                if !hunk.expanded {
+                    continue;
+                }
+                let mut nfolded = 0;
+                for line in hunk.body.clone() {
+                    if !hunk.show_context && self.plain[line].starts_with(b" ") {
+                        nfolded += 1;
+                        continue;
+                    }
+                    if nfolded != 0 {
+                        rows.push(Row::Context(ifile, ihunk, nfolded));
+                        nfolded = 0;
+                    }
+                    rows.push(Row::Body(ifile, ihunk, line));
+                }
+                if nfolded != 0 {
+                    rows.push(Row::Context(ifile, ihunk, nfolded));
+                }
+            }
+        }
+        rows
+    }
+
+    /// Apply `f` to the tree and move the cursor to `target`.
+    fn update<F>(&mut self, target: Row, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(self);
+        self.rows = self.compute_rows();
+        self.cursor = self.rows.iter().position(|&row| row == target).unwrap_or(0);
+    }
+
+    fn set_expanded(&mut self, row: Row, expanded: bool) {
+        let (ifile, ihunk) = match row {
+            Row::File(ifile) | Row::Header(ifile, _) => (ifile, None),
+            Row::Hunk(ifile, ihunk)
+            | Row::Body(ifile, ihunk, _)
+            | Row::Context(ifile, ihunk, _) => (ifile, Some(ihunk)),
+        };
+        let target = match ihunk {
+            Some(ihunk) => Row::Hunk(ifile, ihunk),
+            None => Row::File(ifile),
+        };
+        self.update(target, |tui| {
+            let file = &mut tui.files[ifile];
+            match (row, ihunk) {
+                (Row::Header(..), _) | (Row::Body(..), _) if expanded => (),
+                (Row::Context(..), Some(ihunk)) if expanded => {
+                    file.hunks[ihunk].show_context = true
+                }
+                (_, Some(ihunk)) => {
+                    let hunk = &mut file.hunks[ihunk];
+                    hunk.expanded = expanded;
+                    hunk.show_context = false;
+                }
+                (_, None) => file.expanded = expanded,
+            }
+        })
+    }
+
+    fn toggle(&mut self) {
+        let row = self.rows[self.cursor];
+        let expanded = match row {
+            Row::File(ifile) => self.files[ifile].expanded,
+            Row::Hunk(ifile, ihunk) => self.files[ifile].hunks[ihunk].expanded,
+            Row::Context(..) => false,
+            Row::Header(..) | Row::Body(..) => true,
+        };
+        self.set_expanded(row, !expanded)
+    }
+
+    fn set_all_expanded(&mut self, expanded: bool) {
+        let target = match self.rows[self.cursor] {
+            Row::File(ifile) | Row::Header(ifile, _) => Row::File(ifile),
+            Row::Hunk(ifile, ihunk)
+            | Row::Body(ifile, ihunk, _)
+            | Row::Context(ifile, ihunk, _) => {
+                if expanded {
+                    Row::Hunk(ifile, ihunk)
+                } else {
+                    Row::File(ifile)
+                }
+            }
+        };
+        self.update(target, |tui| {
+            for file in &mut tui.files {
+                file.expanded = expanded;
+                for hunk in &mut file.hunks {
+                    hunk.expanded = expanded;
+                }
+            }
+        })
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let max = self.rows.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + delta).max(0).min(max) as usize;
+    }
+
+    fn draw<Out: Write>(&mut self, out: &mut Out) -> io::Result<()> {
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.top + self.height <= self.cursor {
+            self.top = self.cursor + 1 - self.height;
+        }
+        queue!(out, terminal::Clear(ClearType::All))?;
+        let visible = self
+            .rows
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(self.height);
+        for (row_index, (irow, row)) in visible.enumerate() {
+            queue!(out, cursor::MoveTo(0, row_index as u16))?;
+            if irow == self.cursor {
+                out.write_all(b"\x1b[7m>\x1b[0m")?;
+            } else {
+                out.write_all(b" ")?;
+            }
+            match *row {
+                Row::File(ifile) => {
+                    let file = &self.files[ifile];
+                    out.write_all(if file.expanded { b"- " } else { b"+ " })?;
+                    out.write_all(self.lines[file.header.start])?;
+                }
+                Row::Header(_, line) => {
+                    out.write_all(b"  ")?;
+                    out.write_all(self.lines[line])?;
+                }
+                Row::Hunk(ifile, ihunk) => {
+                    let hunk = &self.files[ifile].hunks[ihunk];
+                    out.write_all(if hunk.expanded { b"  - " } else { b"  + " })?;
+                    out.write_all(self.lines[hunk.header])?;
+                }
+                Row::Body(_, _, line) => {
+                    out.write_all(b"    ")?;
+                    out.write_all(self.lines[line])?;
+                }
+                Row::Context(_, _, nfolded) => {
+                    let plural = if nfolded == 1 { "" } else { "s" };
+                    write!(
+                        out,
+                        "    \x1b[2m... {} unchanged line{} ...",
+                        nfolded, plural
+                    )?;
+                }
+            }
+            out.write_all(b"\x1b[0m")?;
+        }
+        queue!(out, cursor::MoveTo(0, self.height as u16))?;
+        out.write_all(b"\x1b[7m[space] fold/unfold  [E/C] expand/collapse all  [q] quit\x1b[0m")?;
+        out.flush()
+    }
+
+    fn event_loop<Out: Write>(&mut self, out: &mut Out) -> io::Result<()> {
+        loop {
+            self.height = page_height()?;
+            self.draw(out)?;
+            if let Some(key) = read_key()? {
+                if !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Returns false when the user asked to quit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let page = self.height as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('j') | KeyCode::Down => self.move_cursor(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_cursor(-1),
+            KeyCode::PageDown => self.move_cursor(page),
+            KeyCode::PageUp => self.move_cursor(-page),
+            KeyCode::Char('g') | KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('G') | KeyCode::End => self.move_cursor(self.rows.len() as isize),
+            KeyCode::Char(' ') | KeyCode::Enter | KeyCode::Tab => self.toggle(),
+            KeyCode::Char('l') | KeyCode::Right => self.set_expanded(self.rows[self.cursor], true),
+            KeyCode::Char('h') | KeyCode::Left => self.set_expanded(self.rows[self.cursor], false),
+            KeyCode::Char('E') => self.set_all_expanded(true),
+            KeyCode::Char('C') => self.set_all_expanded(false),
+            _ => (),
+        }
+        true
+    }
+}
@@ -0,0 +1,92 @@
+//! Rendering of a diff again when the files change, for --watch.
+
+use std::io::{self, BufReader, Write};
+use std::path::{Component, Path};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{Event, RecursiveMode, Watcher};
+
+use super::{git_diff, render, AppConfig};
+
+/// The delay after a change during which the following changes, such
+/// as the ones of a file saved in several writes, are gathered.
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// The files of git whose changes are watched, such as the index that
+/// changes when files are staged.
+const GIT_FILES: [&str; 2] = ["index", "HEAD"];
+
+/// Returns true if a change of `path` changes the diff of the files
+/// under `root`: the files that are not hidden nor in a hidden
+/// directory, and the files of git of `GIT_FILES`.
+pub fn is_watched(root: &Path, path: &Path) -> bool {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
+    let hidden = |component: Component| component.as_os_str().to_string_lossy().starts_with('.');
+    !relative.components().any(hidden)
+        || GIT_FILES
+            .iter()
+            .any(|file| relative == Path::new(".git").join(file))
+}
+
+/// Runs the command of --watch, or git diff, and renders its output.
+fn render_once(config: &AppConfig) -> io::Result<()> {
+    let mut command = config.watch.clone().unwrap_or_default();
+    if command.is_empty() {
+        command.push("git".to_owned());
+        command.extend(git_diff::git_diff_args(config));
+    }
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            io::Error::new(err.kind(), format!("could not run {}: {}", command[0], err))
+        })?;
+    let mut input = BufReader::new(child.stdout.take().expect("piped stdout"));
+    render(config.clone(), &mut input)?;
+    // a failure of the command is reported on its standard error
+    child.wait()?;
+    Ok(())
+}
+
+/// Renders the diff, then renders it again on a clear screen each time
+/// the files of the repository, or of the current directory, change.
+pub fn run(config: AppConfig) -> io::Result<()> {
+    let root = git_diff::repository_root().unwrap_or_else(|| ".".to_owned());
+    let root = Path::new(&root).canonicalize()?;
+    let watch_error = |err: notify::Error| {
+        io::Error::other(format!("could not watch {}: {}", root.display(), err))
+    };
+    let (sender, changes) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(watch_error)?;
+    loop {
+        {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(b"\x1b[H\x1b[2J")?;
+            stdout.flush()?;
+        }
+        render_once(&config)?;
+        loop {
+            let event = match changes.recv() {
+                Ok(event) => event.map_err(watch_error)?,
+                Err(_) => return Ok(()),
+            };
+            // the files read, such as by git diff, are not changed
+            let changed = !event.kind.is_access();
+            if changed && event.paths.iter().any(|path| is_watched(&root, path)) {
+                break;
+            }
+        }
+        std::thread::sleep(SETTLE_DELAY);
+        while changes.try_recv().is_ok() {}
+    }
+}